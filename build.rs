@@ -0,0 +1,11 @@
+fn main() {
+    println!("cargo:rerun-if-changed=proto");
+
+    // protox compiles the protocol without needing protoc installed
+    let files = protox::compile(["admin.proto"], ["proto"]).unwrap();
+
+    tonic_prost_build::configure()
+        .build_client(false)
+        .compile_fds(files)
+        .unwrap();
+}
@@ -0,0 +1,154 @@
+//! Masking or blocking unwanted words in what players see.
+//!
+//! After [filter](crate::filter) decides that a player sees an event, its
+//! text runs through [CENSORS], which may mask parts of it or block it. The
+//! built-in censors match the regexes in the `censor_mask` and
+//! `censor_block` fields, one per line, of the system object for the whole
+//! server and of each player for themselves, which `@censor` edits. Matches
+//! of mask patterns are replaced with asterisks, and events matching block
+//! patterns aren't shown at all. Patterns ignore case.
+//!
+//! Other censors, like ones calling out to a moderation service, may be
+//! added to [CENSORS].
+
+use std::{
+    collections::HashMap,
+    sync::{Mutex, OnceLock},
+};
+
+use regex::{Regex, RegexBuilder};
+
+use crate::{event::Event, State, Value, SYSTEM_OBJECT};
+
+/// A stage that checks the text of an event for a player, returning the
+/// text to show, which may be masked, or [None] to block the event.
+pub type Censor = fn(&State, usize, &str) -> Option<String>;
+
+/// The censors that the text of every event passes through, in order.
+pub const CENSORS: &[Censor] = &[server_patterns, player_patterns];
+
+/// The field holding the patterns that are masked.
+pub const MASK_FIELD: &str = "censor_mask";
+
+/// The field holding the patterns that block events.
+pub const BLOCK_FIELD: &str = "censor_block";
+
+/// The longest pattern that may be censored.
+pub const MAX_PATTERN_LEN: usize = 1024;
+
+/// The most memory that a compiled pattern may use.
+const MAX_REGEX_SIZE: usize = 1 << 20;
+
+/// The most compiled patterns that are cached at once.
+const MAX_CACHED_REGEXES: usize = 256;
+
+/// Runs an event that a player sees through the censors, returning it with
+/// its text masked, or [None] if it is blocked.
+pub fn apply(state: &State, player: usize, mut event: Event) -> Option<Event> {
+    let text = match &mut event {
+        Event::Announce { message, .. }
+        | Event::Emit { message, .. }
+        | Event::Say { message, .. }
+        | Event::Page { message, .. }
+        | Event::Notice { message, .. } => message,
+        Event::Emote { action, .. } => action,
+        Event::Social {
+            to_actor,
+            to_target,
+            to_others,
+            ..
+        } => {
+            for text in [to_actor, to_target, to_others] {
+                *text = censor(state, player, text)?;
+            }
+
+            return Some(event);
+        }
+        _ => return Some(event),
+    };
+
+    *text = censor(state, player, text)?;
+    Some(event)
+}
+
+/// Runs text through every censor.
+pub fn censor(state: &State, player: usize, text: &str) -> Option<String> {
+    CENSORS.iter().try_fold(text.to_string(), |text, censor| {
+        censor(state, player, &text)
+    })
+}
+
+/// Lists the patterns in a field of an object.
+pub fn patterns(state: &State, id: usize, field: &str) -> Vec<String> {
+    match state.get(id, field) {
+        Some(Value::String(patterns)) => patterns
+            .lines()
+            .filter(|line| !line.is_empty())
+            .map(str::to_string)
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Compiles a pattern, reusing the cached compilation if the pattern has
+/// been compiled before.
+pub fn compile(pattern: &str) -> Result<Regex, String> {
+    static CACHE: OnceLock<Mutex<HashMap<String, Regex>>> = OnceLock::new();
+
+    if pattern.len() > MAX_PATTERN_LEN {
+        return Err(format!("pattern is longer than {MAX_PATTERN_LEN} bytes"));
+    }
+
+    let mut cache = CACHE.get_or_init(Default::default).lock().unwrap();
+    if let Some(regex) = cache.get(pattern) {
+        return Ok(regex.clone());
+    }
+
+    let regex = RegexBuilder::new(pattern)
+        .case_insensitive(true)
+        .size_limit(MAX_REGEX_SIZE)
+        .build()
+        .map_err(|err| format!("invalid regex: {err}"))?;
+
+    if cache.len() >= MAX_CACHED_REGEXES {
+        cache.clear();
+    }
+
+    cache.insert(pattern.to_string(), regex.clone());
+    Ok(regex)
+}
+
+/// Masks and blocks text with the patterns on an object.
+fn object_patterns(state: &State, id: usize, text: &str) -> Option<String> {
+    let regexes = |field| {
+        let patterns = patterns(state, id, field).into_iter();
+        patterns.filter_map(|pattern| compile(&pattern).ok())
+    };
+
+    for regex in regexes(BLOCK_FIELD) {
+        if regex.is_match(text) {
+            return None;
+        }
+    }
+
+    let mut text = text.to_string();
+    for regex in regexes(MASK_FIELD) {
+        let masked = regex.replace_all(&text, |caps: &regex::Captures| {
+            "*".repeat(caps[0].chars().count())
+        });
+
+        text = masked.into_owned();
+    }
+
+    Some(text)
+}
+
+/// Applies the server's patterns, from the system object.
+fn server_patterns(state: &State, _player: usize, text: &str) -> Option<String> {
+    object_patterns(state, SYSTEM_OBJECT, text)
+}
+
+/// Applies the player's own patterns.
+fn player_patterns(state: &State, player: usize, text: &str) -> Option<String> {
+    object_patterns(state, player, text)
+}
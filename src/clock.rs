@@ -0,0 +1,109 @@
+//! Timestamps and durations.
+//!
+//! Times are stored as integers counting seconds since the UNIX epoch, and
+//! durations as integers counting seconds, so that they fit in any field.
+
+/// The number of seconds in a minute.
+pub const MINUTE: i64 = 60;
+
+/// The number of seconds in an hour.
+pub const HOUR: i64 = 60 * MINUTE;
+
+/// The number of seconds in a day.
+pub const DAY: i64 = 24 * HOUR;
+
+/// Formats a UNIX timestamp as a UTC date and time.
+pub fn format_time(timestamp: i64) -> String {
+    let days = timestamp.div_euclid(DAY);
+    let secs = timestamp.rem_euclid(DAY);
+    let (year, month, day) = civil_from_days(days);
+
+    format!(
+        "{year:04}-{month:02}-{day:02} {:02}:{:02}:{:02} UTC",
+        secs / HOUR,
+        secs % HOUR / MINUTE,
+        secs % MINUTE
+    )
+}
+
+/// Formats a UNIX timestamp as an RFC 3339 date and time in UTC, like
+/// `2023-09-30T12:34:56Z`, as Atom feeds use.
+pub fn format_rfc3339(timestamp: i64) -> String {
+    let (year, month, day) = civil_from_days(timestamp.div_euclid(DAY));
+    let secs = timestamp.rem_euclid(DAY);
+
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}Z",
+        secs / HOUR,
+        secs % HOUR / MINUTE,
+        secs % MINUTE
+    )
+}
+
+/// Formats a UNIX timestamp as an RFC 2822 date and time in UTC, like
+/// `Sat, 30 Sep 2023 12:34:56 +0000`, as RSS feeds use.
+pub fn format_rfc2822(timestamp: i64) -> String {
+    const WEEKDAYS: [&str; 7] = ["Thu", "Fri", "Sat", "Sun", "Mon", "Tue", "Wed"];
+    const MONTHS: [&str; 12] = [
+        "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+    ];
+
+    let days = timestamp.div_euclid(DAY);
+    let secs = timestamp.rem_euclid(DAY);
+    let (year, month, day) = civil_from_days(days);
+
+    // the UNIX epoch was a Thursday
+    format!(
+        "{}, {day:02} {} {year:04} {:02}:{:02}:{:02} +0000",
+        WEEKDAYS[days.rem_euclid(7) as usize],
+        MONTHS[month as usize - 1],
+        secs / HOUR,
+        secs % HOUR / MINUTE,
+        secs % MINUTE
+    )
+}
+
+/// Formats a duration in seconds like `2d 3h 4m 5s`, leaving out any units
+/// that are zero.
+pub fn format_duration(duration: i64) -> String {
+    if duration == 0 {
+        return "0s".to_string();
+    }
+
+    let sign = if duration < 0 { "-" } else { "" };
+    let mut rest = duration.unsigned_abs();
+    let mut parts = Vec::new();
+
+    for (unit, secs) in [("d", DAY), ("h", HOUR), ("m", MINUTE), ("s", 1)] {
+        let count = rest / secs as u64;
+        rest %= secs as u64;
+
+        if count > 0 {
+            parts.push(format!("{count}{unit}"));
+        }
+    }
+
+    format!("{sign}{}", parts.join(" "))
+}
+
+/// Converts a count of days since the UNIX epoch into a year, month, and day
+/// on the proleptic Gregorian calendar.
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    // shift the epoch to 0000-03-01 so that leap days fall at year's end
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * month_index + 2) / 5 + 1) as u32;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    } as u32;
+
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
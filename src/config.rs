@@ -0,0 +1,119 @@
+//! Settings read from a file, which can be changed without a restart.
+//!
+//! When [CONFIG_VAR] names a file, each of its `field = value` lines sets
+//! that field on the system object, like `http_rate_limit = 20` or
+//! `idle_timeout = 3600`. Values are integers, `true` or `false`, or else
+//! strings, which may be wrapped in double quotes. Blank lines and lines
+//! starting with `#` are skipped.
+//!
+//! The `motd_file` setting names a file whose contents become the `motd`,
+//! so that a message of the day spanning several lines can be kept on disk.
+//!
+//! The file is read when the server starts and again whenever it gets
+//! `SIGHUP`, so that operators can change rate limits, timeouts and the
+//! MOTD without disconnecting everyone. Lines that can't be applied are
+//! reported and skipped, leaving the rest of the file to take effect.
+
+use std::sync::Arc;
+
+use tokio::signal::unix::{signal, SignalKind};
+
+use crate::{State, Value, SYSTEM_OBJECT};
+
+/// The environment variable naming the settings file.
+pub const CONFIG_VAR: &str = "MARCIEMOO_CONFIG";
+
+/// The setting naming a file to read the `motd` from.
+pub const MOTD_FILE: &str = "motd_file";
+
+/// Parses a setting's value.
+fn parse_value(val: &str) -> Value {
+    if let Ok(val) = val.parse() {
+        return Value::Integer(val);
+    }
+
+    match val {
+        "true" => Value::Bool(true),
+        "false" => Value::Bool(false),
+        val => {
+            let unquoted = val.strip_prefix('"').and_then(|val| val.strip_suffix('"'));
+            Value::String(unquoted.unwrap_or(val).to_string())
+        }
+    }
+}
+
+/// Applies one line of the settings file, returning why not if it can't be.
+fn apply_line(state: &State, line: &str) -> Result<(), String> {
+    let Some((key, val)) = line.split_once('=') else {
+        return Err("expected field = value".to_string());
+    };
+
+    let (key, val) = (key.trim(), val.trim());
+    if key.is_empty() || !key.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+        return Err(format!("{key:?} is not a field name"));
+    }
+
+    if key == MOTD_FILE {
+        let motd = std::fs::read_to_string(val).map_err(|err| format!("{val}: {err}"))?;
+        let motd = Value::String(motd.trim_end().to_string());
+        return state
+            .set(SYSTEM_OBJECT, "motd", motd)
+            .map_err(|err| err.to_string());
+    }
+
+    state
+        .set(SYSTEM_OBJECT, key, parse_value(val))
+        .map_err(|err| err.to_string())
+}
+
+/// Reads the settings file into the system object, if there is one.
+pub fn load(state: &State) {
+    let Some(path) = std::env::var(CONFIG_VAR)
+        .ok()
+        .filter(|path| !path.is_empty())
+    else {
+        return;
+    };
+
+    let src = match std::fs::read_to_string(&path) {
+        Ok(src) => src,
+        Err(err) => {
+            eprintln!("could not read the settings in {path}: {err}");
+            return;
+        }
+    };
+
+    let lines = src
+        .lines()
+        .enumerate()
+        .map(|(num, line)| (num + 1, line.trim()));
+    for (num, line) in lines.filter(|(_, line)| !line.is_empty() && !line.starts_with('#')) {
+        if let Err(err) = apply_line(state, line) {
+            eprintln!("skipped line {num} of {path}: {err}");
+        }
+    }
+
+    eprintln!("Loaded settings from {path}");
+}
+
+/// Reads the settings file again every time the server gets `SIGHUP`.
+pub async fn reload_on_hangup(state: Arc<State>) {
+    let mut hangups = match signal(SignalKind::hangup()) {
+        Ok(hangups) => hangups,
+        Err(err) => {
+            eprintln!("not reloading settings on SIGHUP: {err}");
+            return;
+        }
+    };
+
+    let shutdown = state.shutdown_token();
+    loop {
+        tokio::select! {
+            _ = shutdown.cancelled() => return,
+            Some(()) = hangups.recv() => {
+                let state = state.clone();
+                let _ = tokio::task::spawn_blocking(move || load(&state)).await;
+            }
+        }
+    }
+}
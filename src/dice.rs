@@ -0,0 +1,94 @@
+//! Dice notation and random rolls.
+//!
+//! Dice are written like `3d6`, `d20`, or `2d8+3`: an optional number of
+//! dice, a `d`, the number of sides on each die, and an optional modifier
+//! that is added to the total.
+
+use std::fmt::Display;
+
+use rand::Rng;
+
+/// The most dice that may be rolled at once.
+pub const MAX_DICE: u32 = 100;
+
+/// The most sides that a die may have.
+pub const MAX_SIDES: u32 = 1000;
+
+/// The largest modifier, either way, that may be added to a roll.
+pub const MAX_MODIFIER: i64 = 1_000_000;
+
+/// A number of dice to roll and a modifier to add to their total.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Dice {
+    pub count: u32,
+    pub sides: u32,
+    pub modifier: i64,
+}
+
+impl Display for Dice {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}d{}", self.count, self.sides)?;
+
+        match self.modifier {
+            0 => Ok(()),
+            modifier if modifier > 0 => write!(f, "+{modifier}"),
+            modifier => write!(f, "{modifier}"),
+        }
+    }
+}
+
+impl Dice {
+    /// Parses dice notation, returning `None` if it is invalid or rolls more
+    /// dice or sides, or adds a larger modifier, than allowed.
+    pub fn parse(notation: &str) -> Option<Self> {
+        let notation = notation.trim().to_lowercase();
+        let (count, rest) = notation.split_once('d')?;
+
+        let count = match count {
+            "" => 1,
+            count => count.parse().ok()?,
+        };
+
+        let (sides, modifier) = match rest.find(['+', '-']) {
+            Some(at) => (&rest[..at], rest[at..].parse().ok()?),
+            None => (rest, 0),
+        };
+
+        let sides = sides.parse().ok()?;
+
+        if !(1..=MAX_DICE).contains(&count)
+            || !(1..=MAX_SIDES).contains(&sides)
+            || !(-MAX_MODIFIER..=MAX_MODIFIER).contains(&modifier)
+        {
+            return None;
+        }
+
+        Some(Self {
+            count,
+            sides,
+            modifier,
+        })
+    }
+
+    /// Rolls every die, returning each die's result.
+    pub fn roll(&self) -> Vec<i64> {
+        let mut rng = rand::thread_rng();
+        (0..self.count)
+            .map(|_| rng.gen_range(1..=self.sides) as i64)
+            .collect()
+    }
+
+    /// Adds up the results of a roll and this dice's modifier.
+    pub fn total(&self, rolls: &[i64]) -> i64 {
+        rolls.iter().sum::<i64>() + self.modifier
+    }
+}
+
+/// Picks a random integer between `min` and `max`, inclusive.
+pub fn random(min: i64, max: i64) -> Option<i64> {
+    if min > max {
+        return None;
+    }
+
+    Some(rand::thread_rng().gen_range(min..=max))
+}
@@ -0,0 +1,69 @@
+//! Equipment, which players wear in slots like their head or hands.
+//!
+//! An item's `slot` field names the slot that it is worn in. Wearing it with
+//! `wear` sets an `equip-<slot>` field on the player to the item, and an item
+//! that is worn can't be moved until it is taken off with `remove`.
+//!
+//! The slots are listed in the system object's `equipment_slots` field,
+//! separated by commas, or are [DEFAULT_SLOTS] if it isn't set.
+
+use crate::{State, Value, SYSTEM_OBJECT};
+
+/// The slots that players have if the system object doesn't list them.
+pub const DEFAULT_SLOTS: &[&str] = &[
+    "head", "neck", "body", "arms", "hands", "finger", "waist", "legs", "feet",
+];
+
+/// The field holding the slot that an item is worn in.
+pub const SLOT_FIELD: &str = "slot";
+
+/// Gets the field on a player that holds what they wear in a slot.
+pub fn slot_key(slot: &str) -> String {
+    format!("equip-{slot}")
+}
+
+/// Lists the slots that players have, in the order they are shown.
+pub fn slots(state: &State) -> Vec<String> {
+    let Some(Value::String(slots)) = state.get(SYSTEM_OBJECT, "equipment_slots") else {
+        return DEFAULT_SLOTS.iter().map(ToString::to_string).collect();
+    };
+
+    let slots = slots.split(',').map(|slot| slot.trim().to_lowercase());
+    slots.filter(|slot| !slot.is_empty()).collect()
+}
+
+/// Gets the slot that an item is worn in, if it names one.
+pub fn slot_of(state: &State, item: usize) -> Option<String> {
+    let slot = state
+        .get(item, SLOT_FIELD)?
+        .as_string()?
+        .trim()
+        .to_lowercase();
+    Some(slot).filter(|slot| !slot.is_empty())
+}
+
+/// Gets what a player wears in a slot. Items that have since left the
+/// player aren't worn any more.
+pub fn worn_in(state: &State, player: usize, slot: &str) -> Option<usize> {
+    let item = state.get(player, &slot_key(slot))?.as_id()?;
+    Some(item).filter(|item| state.location(*item) == Some(player))
+}
+
+/// Lists what a player wears, by slot.
+pub fn equipment(state: &State, player: usize) -> Vec<(String, Option<usize>)> {
+    let slots = slots(state).into_iter();
+    slots
+        .map(|slot| {
+            let item = worn_in(state, player, &slot);
+            (slot, item)
+        })
+        .collect()
+}
+
+/// Tests if an item is being worn by the player carrying it.
+pub fn is_worn(state: &State, item: usize) -> bool {
+    match (state.location(item), slot_of(state, item)) {
+        (Some(player), Some(slot)) => worn_in(state, player, &slot) == Some(item),
+        _ => false,
+    }
+}
@@ -0,0 +1,156 @@
+use serde::{Deserialize, Serialize};
+
+/// Something that happened in the world.
+///
+/// Every event is published on the [State](crate::State)'s event channel, so
+/// connections, scripts, and bridges all share one subscription point.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event {
+    /// A player connected.
+    Connect { player: usize },
+
+    /// A player disconnected.
+    Disconnect { player: usize },
+
+    /// A wizard disconnected a player.
+    Boot { player: usize },
+
+    /// A server-wide announcement, made by the server itself if it has no
+    /// source.
+    Announce {
+        source: Option<usize>,
+        message: String,
+    },
+
+    /// A message for every object in a room, from the object that emitted
+    /// it, if any.
+    Emit {
+        room: usize,
+        source: Option<usize>,
+        message: String,
+    },
+
+    /// An object said something.
+    Say {
+        speaker: usize,
+        name: String,
+        message: String,
+    },
+
+    /// An object acted out something, like `Marcie waves.`.
+    Emote {
+        actor: usize,
+        name: String,
+        action: String,
+    },
+
+    /// An object acted out a [social](crate::social), which reads differently
+    /// to its actor and its target than to everyone else.
+    Social {
+        actor: usize,
+        name: String,
+        target: Option<usize>,
+        to_actor: String,
+        to_target: String,
+        to_others: String,
+    },
+
+    /// An object sent a private message to another.
+    Page {
+        from: usize,
+        name: String,
+        to: usize,
+        message: String,
+    },
+
+    /// A message from the server for one player, like a friend connecting.
+    Notice { to: usize, message: String },
+
+    /// An object moved from one location to another.
+    Move {
+        object: usize,
+        from: Option<usize>,
+        to: usize,
+    },
+
+    /// An object was created.
+    Create { object: usize },
+
+    /// An object was destroyed.
+    Destroy { object: usize },
+
+    /// Day broke in the world, on an in-game day as counted by the
+    /// [world clock](crate::world_clock).
+    Dawn { day: i64 },
+
+    /// Night fell in the world, on an in-game day as counted by the
+    /// [world clock](crate::world_clock).
+    Dusk { day: i64 },
+}
+
+impl Event {
+    /// Renders this event as a line of text for connected players, if it is
+    /// meant to be seen by them.
+    pub fn render(&self) -> Option<String> {
+        match self {
+            Event::Boot { .. } => Some("You have been booted by a wizard.".to_string()),
+            Event::Announce { message, .. } => Some(message.to_owned()),
+            Event::Emit { message, .. } => Some(message.to_owned()),
+            Event::Say { name, message, .. } => Some(format!("{name} says: {message}")),
+            Event::Emote { name, action, .. } => Some(format!("{name} {action}")),
+            Event::Social { to_others, .. } => Some(to_others.to_owned()),
+            Event::Page { name, message, .. } => Some(format!("{name} pages: {message}")),
+            Event::Notice { message, .. } => Some(message.to_owned()),
+            _ => None,
+        }
+    }
+
+    /// Renders this event as a line of text for one player, which differs
+    /// from [Event::render] for the players involved in a social.
+    pub fn render_for(&self, viewer: usize) -> Option<String> {
+        match self {
+            Event::Social {
+                actor, to_actor, ..
+            } if *actor == viewer => Some(to_actor.to_owned()),
+            Event::Social {
+                target: Some(target),
+                to_target,
+                ..
+            } if *target == viewer => Some(to_target.to_owned()),
+            _ => self.render(),
+        }
+    }
+
+    /// Gets the room that this event is confined to, if it is not meant for
+    /// the whole server.
+    pub fn room(&self) -> Option<usize> {
+        match self {
+            Event::Emit { room, .. } => Some(*room),
+            _ => None,
+        }
+    }
+
+    /// Gets the object that caused this event, if it was caused by one.
+    pub fn source(&self) -> Option<usize> {
+        match self {
+            Event::Announce { source, .. } => *source,
+            Event::Emit { source, .. } => *source,
+            Event::Say { speaker, .. } => Some(*speaker),
+            Event::Emote { actor, .. } => Some(*actor),
+            Event::Social { actor, .. } => Some(*actor),
+            Event::Page { from, .. } => Some(*from),
+            _ => None,
+        }
+    }
+
+    /// Gets the one object that this event is addressed to, if it is private.
+    pub fn recipient(&self) -> Option<usize> {
+        match self {
+            Event::Boot { player } => Some(*player),
+            Event::Page { to, .. } => Some(*to),
+            Event::Notice { to, .. } => Some(*to),
+            _ => None,
+        }
+    }
+}
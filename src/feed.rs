@@ -0,0 +1,188 @@
+//! Feeds of server announcements over HTTP, so that players can follow the
+//! world's news without connecting.
+//!
+//! The feeds are served on the address named by [FEED_BIND_VAR], as Atom at
+//! `/atom.xml` and as RSS at `/rss.xml`. The server's own announcements and
+//! those that wizards make with `@announce` are kept in the database as they
+//! are made, so the feeds list the latest [FEED_LENGTH] of them across
+//! restarts. What verbs announce isn't kept.
+//!
+//! The feeds link to themselves at [FEED_URL_VAR] if it is set, or else at
+//! the host that each request was made to.
+
+use std::sync::Arc;
+
+use axum::{
+    extract::State as Extract,
+    http::{header, HeaderMap, HeaderValue},
+    response::IntoResponse,
+    routing::get,
+    Router,
+};
+use tokio::net::TcpListener;
+
+use crate::{
+    clock::{format_rfc2822, format_rfc3339},
+    news::Announcement,
+    now, State,
+};
+
+/// The environment variable naming the address to serve feeds on, like
+/// `0.0.0.0:8080`.
+pub const FEED_BIND_VAR: &str = "MARCIEMOO_FEED_BIND";
+
+/// The environment variable naming the URL that the feeds are reached at,
+/// like `https://moo.example.org`.
+pub const FEED_URL_VAR: &str = "MARCIEMOO_FEED_URL";
+
+/// How many announcements the feeds list.
+pub const FEED_LENGTH: usize = 20;
+
+/// How many characters of an announcement make up its title.
+const TITLE_LENGTH: usize = 60;
+
+/// Serves the feeds until the server shuts down.
+pub async fn serve(state: Arc<State>, bind: String) {
+    let listener = match TcpListener::bind(&bind).await {
+        Ok(listener) => listener,
+        Err(err) => {
+            eprintln!("could not serve feeds on {bind}: {err}");
+            return;
+        }
+    };
+
+    eprintln!("Serving feeds on {bind}");
+    let shutdown = state.shutdown_token();
+
+    let app = Router::new()
+        .route("/atom.xml", get(atom))
+        .route("/rss.xml", get(rss))
+        .with_state(state);
+
+    let served = axum::serve(listener, app)
+        .with_graceful_shutdown(shutdown.cancelled_owned())
+        .await;
+
+    if let Err(err) = served {
+        eprintln!("the feeds failed: {err}");
+    }
+}
+
+/// Gets the base URL that feeds are reached at, escaped for XML, from
+/// [FEED_URL_VAR] or else the request's `Host`.
+fn base_url(headers: &HeaderMap) -> String {
+    if let Some(url) = std::env::var(FEED_URL_VAR)
+        .ok()
+        .filter(|url| !url.is_empty())
+    {
+        return escape(url.trim_end_matches('/'));
+    }
+
+    let host = headers
+        .get(header::HOST)
+        .and_then(|host| host.to_str().ok());
+    escape(&format!("http://{}", host.unwrap_or("localhost")))
+}
+
+async fn atom(Extract(state): Extract<Arc<State>>, headers: HeaderMap) -> impl IntoResponse {
+    let base = base_url(&headers);
+    let name = escape(&state.server_name());
+    let announcements = state.recent_announcements(FEED_LENGTH);
+    let updated = announcements.first().map_or(now(), |news| news.at);
+
+    let mut feed = format!(
+        "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n\
+         <feed xmlns=\"http://www.w3.org/2005/Atom\">\n\
+         <title>{name} announcements</title>\n\
+         <id>{base}/atom.xml</id>\n\
+         <link rel=\"self\" href=\"{base}/atom.xml\"/>\n\
+         <updated>{}</updated>\n\
+         <author><name>{name}</name></author>\n",
+        format_rfc3339(updated as i64),
+    );
+
+    for news in &announcements {
+        feed.push_str(&format!(
+            "<entry>\n\
+             <title>{}</title>\n\
+             <id>{base}/atom.xml#{}</id>\n\
+             <updated>{}</updated>\n\
+             <content type=\"text\">{}</content>\n\
+             </entry>\n",
+            escape(&title(news)),
+            news.id,
+            format_rfc3339(news.at as i64),
+            escape(&news.message),
+        ));
+    }
+
+    feed.push_str("</feed>\n");
+    xml("application/atom+xml; charset=utf-8", feed)
+}
+
+async fn rss(Extract(state): Extract<Arc<State>>, headers: HeaderMap) -> impl IntoResponse {
+    let base = base_url(&headers);
+    let name = escape(&state.server_name());
+
+    let mut feed = format!(
+        "<?xml version=\"1.0\" encoding=\"utf-8\"?>\n\
+         <rss version=\"2.0\">\n\
+         <channel>\n\
+         <title>{name} announcements</title>\n\
+         <link>{base}/rss.xml</link>\n\
+         <description>Announcements made on {name}</description>\n"
+    );
+
+    for news in state.recent_announcements(FEED_LENGTH) {
+        feed.push_str(&format!(
+            "<item>\n\
+             <title>{}</title>\n\
+             <description>{}</description>\n\
+             <guid isPermaLink=\"false\">{}</guid>\n\
+             <pubDate>{}</pubDate>\n\
+             </item>\n",
+            escape(&title(&news)),
+            escape(&news.message),
+            news.id,
+            format_rfc2822(news.at as i64),
+        ));
+    }
+
+    feed.push_str("</channel>\n</rss>\n");
+    xml("application/rss+xml; charset=utf-8", feed)
+}
+
+fn xml(content_type: &'static str, body: String) -> impl IntoResponse {
+    (
+        [(header::CONTENT_TYPE, HeaderValue::from_static(content_type))],
+        body,
+    )
+}
+
+/// Titles an announcement with its first line, shortened if it is long.
+fn title(news: &Announcement) -> String {
+    let line = news.message.lines().next().unwrap_or_default();
+    match line.char_indices().nth(TITLE_LENGTH) {
+        Some((end, _)) => format!("{}...", &line[..end]),
+        None => line.to_string(),
+    }
+}
+
+/// Escapes text for XML.
+fn escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&apos;"),
+            // XML 1.0 can't hold most control characters, even escaped
+            c if c.is_control() && !matches!(c, '\t' | '\n' | '\r') => {}
+            c => out.push(c),
+        }
+    }
+
+    out
+}
@@ -0,0 +1,46 @@
+//! Deciding which events each connected player sees.
+//!
+//! Every connection hears every [Event], and runs it through [FILTERS]
+//! before rendering it. An event is delivered only if every filter passes it.
+
+use crate::{event::Event, State, Value};
+
+/// A rule deciding whether a player sees an event.
+pub type Filter = fn(&State, usize, &Event) -> bool;
+
+/// The filters that every event passes through, in order.
+pub const FILTERS: &[Filter] = &[in_room, addressed, not_gagged];
+
+/// Tests if an event should be delivered to a player.
+pub fn delivers(state: &State, player: usize, event: &Event) -> bool {
+    FILTERS.iter().all(|filter| filter(state, player, event))
+}
+
+/// Gets the field on a player that marks another object as gagged.
+pub fn gag_key(id: usize) -> String {
+    format!("gag-{id}")
+}
+
+/// Passes events confined to a room only to the players in it.
+fn in_room(state: &State, player: usize, event: &Event) -> bool {
+    match event.room() {
+        Some(room) => state.location(player) == Some(room),
+        None => true,
+    }
+}
+
+/// Passes private events only to the player that they are addressed to.
+fn addressed(_state: &State, player: usize, event: &Event) -> bool {
+    match event.recipient() {
+        Some(to) => to == player,
+        None => true,
+    }
+}
+
+/// Drops events caused by objects that the player has gagged.
+fn not_gagged(state: &State, player: usize, event: &Event) -> bool {
+    match event.source() {
+        Some(source) => !matches!(state.get(player, &gag_key(source)), Some(Value::Bool(true))),
+        None => true,
+    }
+}
@@ -0,0 +1,40 @@
+//! Friends lists, which tell players when the people they follow connect.
+//!
+//! Friendship is one-way: listing someone as a friend with `@friend add`
+//! sets a `friend-<id>` field on the player, like gags do, and the player is
+//! then sent a [Notice](Event::Notice) whenever that friend connects.
+
+use crate::{event::Event, locale, State, Value};
+
+/// Gets the field on a player that marks another object as their friend.
+pub fn friend_key(id: usize) -> String {
+    format!("friend-{id}")
+}
+
+/// Lists the friends of a player, in order of their IDs.
+pub fn friends(state: &State, player: usize) -> Vec<usize> {
+    let mut friends: Vec<usize> = state
+        .show(player)
+        .into_iter()
+        .filter(|(_, val)| matches!(val, Value::Bool(true)))
+        .filter_map(|(key, _)| key.strip_prefix("friend-")?.parse().ok())
+        .collect();
+
+    friends.sort();
+    friends
+}
+
+/// Tells the connected players who count a player as a friend that the
+/// player has connected.
+pub fn notify_connect(state: &State, player: usize) {
+    let name = state.display_name(player);
+
+    for to in state.connected() {
+        let friended = state.get(to, &friend_key(player));
+        if to != player && matches!(friended, Some(Value::Bool(true))) {
+            let args = [("name", name.clone())];
+            let message = locale::text(state, to, "friend_connected", &args);
+            state.publish(Event::Notice { to, message });
+        }
+    }
+}
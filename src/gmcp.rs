@@ -0,0 +1,67 @@
+//! GMCP, the Generic MUD Communication Protocol.
+//!
+//! Clients that negotiate GMCP are sent packets of JSON alongside the text
+//! of the world, which graphical clients use to draw maps and chat windows.
+//! Each packet is a package name like `Room.Info` followed by its data. The
+//! server sends:
+//!
+//! - `Room.Info`: the `num` and `name` of the room that the player is in,
+//!   whenever they move
+//! - `Comm.Channel.Text`: the `channel`, `talker`, and `text` of everything
+//!   said, emoted, paged, and announced to the player
+//!
+//! Verbs send their own packets to the invoking player with `gmcp_send`.
+
+use serde_json::{json, Value as Json};
+
+use crate::{event::Event, telnet, State};
+
+/// Encodes a packet as a telnet subnegotiation.
+pub fn encode(package: &str, data: &Json) -> Vec<u8> {
+    let message = match data {
+        Json::Null => package.to_string(),
+        data => format!("{package} {data}"),
+    };
+
+    telnet::subnegotiate(telnet::GMCP, message.as_bytes())
+}
+
+/// Checks that a package name is one word, like `Char.Vitals`.
+pub fn is_package(package: &str) -> bool {
+    !package.is_empty()
+        && package
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '.' || c == '_' || c == '-')
+}
+
+/// Describes a room for `Room.Info`.
+pub fn room_info(state: &State, room: usize) -> Json {
+    json!({
+        "num": room,
+        "name": state.name(room).unwrap_or_default(),
+    })
+}
+
+/// Gets the packet that tells a player about an event, if there is one.
+pub fn packet(state: &State, player: usize, event: &Event) -> Option<Vec<u8>> {
+    let channel = |channel: &str, talker: &str| {
+        let data = json!({
+            "channel": channel,
+            "talker": talker,
+            "text": event.render_for(player).unwrap_or_default(),
+        });
+
+        Some(encode("Comm.Channel.Text", &data))
+    };
+
+    match event {
+        Event::Move { object, to, .. } if *object == player => {
+            Some(encode("Room.Info", &room_info(state, *to)))
+        }
+        Event::Say { name, .. } => channel("say", name),
+        Event::Emote { name, .. } | Event::Social { name, .. } => channel("emote", name),
+        Event::Page { name, .. } => channel("page", name),
+        Event::Announce { .. } => channel("announce", ""),
+        _ => None,
+    }
+}
@@ -0,0 +1,296 @@
+//! The gRPC admin API, defined in `proto/admin.proto`.
+//!
+//! It is served on the address named by [GRPC_BIND_VAR], and only to callers
+//! that present the token named by [GRPC_TOKEN_VAR], since every call acts
+//! with a wizard's powers.
+
+use std::{net::SocketAddr, sync::Arc};
+
+use tonic::{transport::Server, Request, Response, Status};
+
+use crate::{event::Event, lastlog::SessionEvent, script, FieldError, State, Value, SYSTEM_OBJECT};
+
+use proto::{
+    admin_server::{Admin, AdminServer},
+    value, BootRequest, BootResponse, CheckpointRequest, CheckpointResponse, Connection,
+    CreateObjectRequest, DestroyObjectRequest, DestroyObjectResponse, Field, GetObjectRequest,
+    ListConnectionsRequest, ListConnectionsResponse, Object, RunScriptRequest, RunScriptResponse,
+    SetFieldRequest, UnsetFieldRequest,
+};
+
+#[allow(clippy::all)]
+pub mod proto {
+    tonic::include_proto!("marciemoo.admin");
+}
+
+/// The environment variable naming the address to serve the admin API on,
+/// like `127.0.0.1:50051`.
+pub const GRPC_BIND_VAR: &str = "MARCIEMOO_GRPC_BIND";
+
+/// The environment variable holding the token that callers must present.
+/// The API isn't served without one.
+pub const GRPC_TOKEN_VAR: &str = "MARCIEMOO_GRPC_TOKEN";
+
+/// Serves the admin API until the server shuts down.
+pub async fn serve(state: Arc<State>, bind: String) {
+    let addr: SocketAddr = match bind.parse() {
+        Ok(addr) => addr,
+        Err(err) => {
+            eprintln!("could not serve the admin API on {bind}: {err}");
+            return;
+        }
+    };
+
+    let Some(token) = std::env::var(GRPC_TOKEN_VAR)
+        .ok()
+        .filter(|token| !token.is_empty())
+    else {
+        eprintln!("not serving the admin API, since {GRPC_TOKEN_VAR} is not set");
+        return;
+    };
+
+    let expected = format!("Bearer {token}");
+    let check_token = move |request: Request<()>| {
+        let given = request.metadata().get("authorization");
+        match given.and_then(|given| given.to_str().ok()) {
+            Some(given) if tokens_match(given, &expected) => Ok(request),
+            _ => Err(Status::unauthenticated("missing or wrong admin token")),
+        }
+    };
+
+    eprintln!("Serving the admin API on {addr}");
+    let shutdown = state.shutdown_token();
+    let service = AdminServer::with_interceptor(AdminService { state }, check_token);
+
+    let served = Server::builder()
+        .add_service(service)
+        .serve_with_shutdown(addr, shutdown.cancelled_owned())
+        .await;
+
+    if let Err(err) = served {
+        eprintln!("the admin API failed: {err}");
+    }
+}
+
+/// Compares tokens in time that doesn't depend on where they differ, so that
+/// timing them can't reveal the token.
+pub fn tokens_match(given: &str, expected: &str) -> bool {
+    given.len() == expected.len()
+        && given
+            .bytes()
+            .zip(expected.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+struct AdminService {
+    state: Arc<State>,
+}
+
+impl AdminService {
+    /// Gets an object and its fields, if it exists.
+    fn object(&self, id: u64) -> Result<Response<Object>, Status> {
+        let id = self.existing(id)?;
+        let fields = self.state.show(id).into_iter();
+        let fields = fields.map(|(key, val)| Field {
+            key,
+            value: Some(encode_value(val)),
+        });
+
+        Ok(Response::new(Object {
+            id: id as u64,
+            fields: fields.collect(),
+        }))
+    }
+
+    /// Checks that an object exists, converting its ID.
+    fn existing(&self, id: u64) -> Result<usize, Status> {
+        usize::try_from(id)
+            .ok()
+            .filter(|id| self.state.exists(*id))
+            .ok_or_else(|| Status::not_found(format!("no object #{id}")))
+    }
+}
+
+#[tonic::async_trait]
+impl Admin for AdminService {
+    async fn list_connections(
+        &self,
+        _request: Request<ListConnectionsRequest>,
+    ) -> Result<Response<ListConnectionsResponse>, Status> {
+        let connections = self.state.connected().into_iter().map(|player| {
+            let last = self.state.recent_connections(Some(player), 1).pop();
+            let last = last.filter(|entry| matches!(entry.event, SessionEvent::Connect));
+
+            Connection {
+                player: player as u64,
+                name: self.state.name(player).unwrap_or_default(),
+                address: last
+                    .as_ref()
+                    .map(|entry| entry.addr.clone())
+                    .unwrap_or_default(),
+                connected_at: last.map(|entry| entry.at).unwrap_or_default(),
+            }
+        });
+
+        Ok(Response::new(ListConnectionsResponse {
+            connections: connections.collect(),
+        }))
+    }
+
+    async fn boot(&self, request: Request<BootRequest>) -> Result<Response<BootResponse>, Status> {
+        let player = self.existing(request.into_inner().player)?;
+        if !self.state.is_connected(player) {
+            return Err(Status::failed_precondition(format!(
+                "#{player} is not connected"
+            )));
+        }
+
+        self.state.publish(Event::Boot { player });
+        Ok(Response::new(BootResponse {}))
+    }
+
+    async fn create_object(
+        &self,
+        request: Request<CreateObjectRequest>,
+    ) -> Result<Response<Object>, Status> {
+        let owner = match request.into_inner().owner {
+            Some(owner) => Some(self.existing(owner)?),
+            None => None,
+        };
+
+        let id = self.state.create();
+        if let Some(owner) = owner {
+            self.state
+                .set(id, "owner", Value::Integer(owner as i64))
+                .map_err(field_error)?;
+        }
+
+        self.object(id as u64)
+    }
+
+    async fn get_object(
+        &self,
+        request: Request<GetObjectRequest>,
+    ) -> Result<Response<Object>, Status> {
+        self.object(request.into_inner().id)
+    }
+
+    async fn set_field(
+        &self,
+        request: Request<SetFieldRequest>,
+    ) -> Result<Response<Object>, Status> {
+        let request = request.into_inner();
+        let id = self.existing(request.id)?;
+        let value = request
+            .value
+            .and_then(|value| value.value)
+            .ok_or_else(|| Status::invalid_argument("missing value"))?;
+
+        self.state
+            .set(id, &request.key, decode_value(value))
+            .map_err(field_error)?;
+
+        self.object(request.id)
+    }
+
+    async fn unset_field(
+        &self,
+        request: Request<UnsetFieldRequest>,
+    ) -> Result<Response<Object>, Status> {
+        let request = request.into_inner();
+        let id = self.existing(request.id)?;
+        self.state.unset(id, &request.key);
+        self.object(request.id)
+    }
+
+    async fn destroy_object(
+        &self,
+        request: Request<DestroyObjectRequest>,
+    ) -> Result<Response<DestroyObjectResponse>, Status> {
+        let id = self.existing(request.into_inner().id)?;
+        if id == SYSTEM_OBJECT {
+            return Err(Status::invalid_argument("cannot destroy the system object"));
+        }
+
+        if !self.state.destroy(id) {
+            return Err(Status::not_found(format!("no object #{id}")));
+        }
+
+        Ok(Response::new(DestroyObjectResponse {}))
+    }
+
+    async fn run_script(
+        &self,
+        request: Request<RunScriptRequest>,
+    ) -> Result<Response<RunScriptResponse>, Status> {
+        let request = request.into_inner();
+        let wizard = self.state.console_player();
+        let object = match request.object {
+            Some(object) => self.existing(object)?,
+            None => wizard,
+        };
+
+        // scripts may run for a long time, so they get a thread of their own
+        let state = self.state.clone();
+        let output = tokio::task::spawn_blocking(move || {
+            let host = script::Host::new(state, object, wizard);
+            script::run(&host, "grpc", &request.source)
+        })
+        .await
+        .map_err(|err| Status::internal(err.to_string()))?;
+
+        for announcement in &output.announcements {
+            self.state.announce_from(object, announcement);
+        }
+
+        let room = self.state.location(object).unwrap_or(object);
+        for message in &output.emits {
+            self.state.emit_from(room, object, message);
+        }
+
+        Ok(Response::new(RunScriptResponse {
+            messages: output.messages,
+        }))
+    }
+
+    async fn checkpoint(
+        &self,
+        _request: Request<CheckpointRequest>,
+    ) -> Result<Response<CheckpointResponse>, Status> {
+        let bytes_flushed = self
+            .state
+            .checkpoint()
+            .await
+            .map_err(|err| Status::internal(err.to_string()))?;
+
+        Ok(Response::new(CheckpointResponse {
+            bytes_flushed: bytes_flushed as u64,
+        }))
+    }
+}
+
+fn encode_value(val: Value) -> proto::Value {
+    let value = match val {
+        Value::String(val) => value::Value::String(val),
+        Value::Integer(val) => value::Value::Integer(val),
+        Value::Bool(val) => value::Value::Bool(val),
+    };
+
+    proto::Value { value: Some(value) }
+}
+
+fn decode_value(value: value::Value) -> Value {
+    match value {
+        value::Value::String(val) => Value::String(val),
+        value::Value::Integer(val) => Value::Integer(val),
+        value::Value::Bool(val) => Value::Bool(val),
+    }
+}
+
+fn field_error(err: FieldError) -> Status {
+    match err {
+        FieldError::NoSuchObject => Status::not_found(err.to_string()),
+        _ => Status::invalid_argument(err.to_string()),
+    }
+}
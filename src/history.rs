@@ -0,0 +1,86 @@
+//! The history of what was said in the world, kept in its own tree of the
+//! database so that players can catch up on what they missed with `@recap`.
+
+use serde::{Deserialize, Serialize};
+use sled::{Db, Tree};
+
+use crate::event::Event;
+
+/// How many events the history keeps before dropping the oldest.
+pub const HISTORY_LENGTH: usize = 200;
+
+/// One event in the history.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct HistoryEntry {
+    pub event: Event,
+
+    /// When it happened, in seconds since the UNIX epoch.
+    pub at: u64,
+}
+
+/// Tests if an event is kept in the history, which holds what was said on
+/// the server's channels and in its rooms.
+pub fn is_kept(event: &Event) -> bool {
+    matches!(
+        event,
+        Event::Announce { .. }
+            | Event::Emit { .. }
+            | Event::Say { .. }
+            | Event::Emote { .. }
+            | Event::Social { .. }
+    )
+}
+
+pub struct ChatHistory {
+    db: Db,
+    tree: Tree,
+}
+
+impl ChatHistory {
+    pub fn open(db: &Db) -> Self {
+        Self {
+            db: db.clone(),
+            tree: db.open_tree("history").unwrap(),
+        }
+    }
+
+    /// Records an event, if it is one that is kept.
+    pub fn record(&self, event: &Event) {
+        if !is_kept(event) {
+            return;
+        }
+
+        let entry = HistoryEntry {
+            event: event.clone(),
+            at: crate::now(),
+        };
+
+        // generated IDs only increase, so keys are in chronological order
+        let key = self.db.generate_id().unwrap().to_be_bytes();
+        let entry = serde_json::to_vec(&entry).unwrap();
+        self.tree.insert(key, entry).unwrap();
+
+        while self.tree.len() > HISTORY_LENGTH {
+            self.tree.pop_min().unwrap();
+        }
+    }
+
+    /// Lists up to `limit` of the latest entries, oldest first, with their
+    /// events passed through a function that may change or drop them.
+    pub fn recent(&self, limit: usize, map: impl Fn(Event) -> Option<Event>) -> Vec<HistoryEntry> {
+        let mut entries: Vec<_> = self
+            .tree
+            .iter()
+            .rev()
+            .filter_map(|entry| serde_json::from_slice::<HistoryEntry>(&entry.unwrap().1).ok())
+            .filter_map(|entry| {
+                let event = map(entry.event)?;
+                Some(HistoryEntry { event, ..entry })
+            })
+            .take(limit)
+            .collect();
+
+        entries.reverse();
+        entries
+    }
+}
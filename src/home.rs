@@ -0,0 +1,72 @@
+//! Homes, which players return to with the `home` command.
+//!
+//! A player's `home` field names their home, which `@sethome` sets to where
+//! they are standing. Players without one are at home in the starting room.
+//!
+//! If the system object's `home_delay` is a number of seconds, players who
+//! disconnect are sent home once they have been gone for that long. The
+//! bodies of players who were still away when the server stopped are left
+//! where they are.
+
+use std::{sync::Arc, time::Duration};
+
+use crate::{State, Value, SYSTEM_OBJECT};
+
+/// The field holding a player's home.
+pub const HOME_FIELD: &str = "home";
+
+/// Gets where a player's home is, if they have one to go to.
+pub fn home_of(state: &State, player: usize) -> Option<usize> {
+    let home = state.get(player, HOME_FIELD).and_then(|home| home.as_id());
+    let home = home.filter(|home| state.exists(*home));
+    home.or_else(|| state.starting_room())
+}
+
+/// Gets how long disconnected players are left before being sent home.
+fn delay(state: &State) -> Option<Duration> {
+    let delay = state.get(SYSTEM_OBJECT, "home_delay")?.as_integer()?;
+    let delay = u64::try_from(delay).ok().filter(|delay| *delay > 0)?;
+    Some(Duration::from_secs(delay))
+}
+
+/// Sends a player who has just disconnected home after the `home_delay`,
+/// unless they have come back by then.
+pub fn send_home_later(state: &Arc<State>, player: usize) {
+    if !state.has_password(player) || state.is_console(player) {
+        return;
+    }
+
+    let Some(delay) = delay(state) else {
+        return;
+    };
+
+    let state = state.clone();
+    let left = state.get(player, "last_disconnected");
+    let shutdown = state.shutdown_token();
+
+    tokio::spawn(async move {
+        tokio::select! {
+            _ = tokio::time::sleep(delay) => {}
+            _ = shutdown.cancelled() => return,
+        }
+
+        // players who came back since, even if they've left again, are
+        // waited on by the task for their latest disconnection instead
+        let still_gone = matches!(
+            (state.get(player, "last_disconnected"), left),
+            (Some(Value::Integer(now)), Some(Value::Integer(then))) if now == then
+        );
+
+        if !still_gone || state.is_connected(player) {
+            return;
+        }
+
+        let Some(home) = home_of(&state, player) else {
+            return;
+        };
+
+        if state.location(player) != Some(home) {
+            let _ = state.move_object(player, home);
+        }
+    });
+}
@@ -0,0 +1,178 @@
+//! Fetching pages from the web with `http_get`, so that in-world tools can
+//! pull in outside data like the weather or a news feed.
+//!
+//! Verbs may only fetch from the domains in the system object's
+//! `http_domains` field, separated by commas, and from their subdomains. Only
+//! wizards may change the list, and nothing may be fetched until they do.
+//! Each object may fetch `http_rate_limit` pages a minute, as set on the
+//! system object, so that verbs can't flood the sites they fetch from.
+//!
+//! Redirects aren't followed, since they could lead off the allow-list, and
+//! pages larger than [MAX_BODY_LEN] aren't fetched at all.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    fmt::Display,
+    sync::{Mutex, OnceLock},
+    time::Duration,
+};
+
+use reqwest::{redirect, Client, Url};
+
+use crate::{clock, now, State, Value, SYSTEM_OBJECT};
+
+/// The field holding the domains that verbs may fetch from.
+pub const DOMAINS_FIELD: &str = "http_domains";
+
+/// How many pages each object may fetch a minute if the system object
+/// doesn't say.
+pub const DEFAULT_RATE_LIMIT: usize = 10;
+
+/// The most bytes that may be fetched in a page.
+pub const MAX_BODY_LEN: usize = 1 << 20;
+
+/// How long a fetch may take before it is given up on.
+pub const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The reasons that [get] can fail.
+#[derive(Debug)]
+pub enum FetchError {
+    InvalidUrl,
+    NotAllowed { domain: String },
+    RateLimited,
+    TimedOut,
+    TooLarge,
+    Status(u16),
+    Failed(String),
+}
+
+impl Display for FetchError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FetchError::InvalidUrl => write!(f, "not an HTTP or HTTPS URL"),
+            FetchError::NotAllowed { domain } => {
+                write!(f, "{domain} is not on the list of allowed domains")
+            }
+            FetchError::RateLimited => write!(f, "too many pages fetched in the last minute"),
+            FetchError::TimedOut => write!(f, "the page took too long to fetch"),
+            FetchError::TooLarge => write!(f, "the page is too large"),
+            FetchError::Status(status) => write!(f, "the page was not fetched (status {status})"),
+            FetchError::Failed(err) => write!(f, "the page could not be fetched: {err}"),
+        }
+    }
+}
+
+/// Lists the domains that verbs may fetch from.
+pub fn domains(state: &State) -> Vec<String> {
+    let Some(Value::String(domains)) = state.get(SYSTEM_OBJECT, DOMAINS_FIELD) else {
+        return Vec::new();
+    };
+
+    let domains = domains
+        .split(',')
+        .map(|domain| domain.trim().to_lowercase());
+    let domains = domains.map(|domain| domain.trim_end_matches('.').to_string());
+    domains.filter(|domain| !domain.is_empty()).collect()
+}
+
+/// Tests if a host is one of the allowed domains or a subdomain of one.
+pub fn is_allowed(state: &State, host: &str) -> bool {
+    let host = host.trim_end_matches('.').to_lowercase();
+    domains(state).iter().any(|domain| {
+        host == *domain
+            || host
+                .strip_suffix(domain.as_str())
+                .is_some_and(|sub| sub.ends_with('.'))
+    })
+}
+
+/// Gets how many pages each object may fetch a minute.
+fn rate_limit(state: &State) -> usize {
+    let limit = state.get(SYSTEM_OBJECT, "http_rate_limit");
+    let limit = limit.and_then(|limit| limit.as_integer());
+    let limit = limit.and_then(|limit| usize::try_from(limit).ok());
+    limit.unwrap_or(DEFAULT_RATE_LIMIT)
+}
+
+/// Counts a fetch against an object's rate limit, returning false if it has
+/// already fetched as many pages as it may this minute.
+fn take_fetch(state: &State, object: usize) -> bool {
+    static FETCHES: OnceLock<Mutex<HashMap<usize, VecDeque<u64>>>> = OnceLock::new();
+    let mut fetches = FETCHES.get_or_init(Default::default).lock().unwrap();
+
+    // forgetting objects that haven't fetched lately keeps the map small
+    let since = now().saturating_sub(clock::MINUTE as u64);
+    fetches.retain(|_, times| {
+        while times.front().is_some_and(|time| *time <= since) {
+            times.pop_front();
+        }
+
+        !times.is_empty()
+    });
+
+    let times = fetches.entry(object).or_default();
+    if times.len() >= rate_limit(state) {
+        return false;
+    }
+
+    times.push_back(now());
+    true
+}
+
+/// Gets the client that every page is fetched with.
+fn client() -> &'static Client {
+    static CLIENT: OnceLock<Client> = OnceLock::new();
+    CLIENT.get_or_init(|| {
+        Client::builder()
+            .redirect(redirect::Policy::none())
+            .timeout(TIMEOUT)
+            .build()
+            .expect("failed to create HTTP client")
+    })
+}
+
+/// Fetches a page for a verb running on an object, returning its body.
+pub async fn get(state: &State, object: usize, url: &str) -> Result<String, FetchError> {
+    let url = Url::parse(url).map_err(|_| FetchError::InvalidUrl)?;
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err(FetchError::InvalidUrl);
+    }
+
+    let host = url.host_str().ok_or(FetchError::InvalidUrl)?;
+    if !is_allowed(state, host) {
+        let domain = host.to_string();
+        return Err(FetchError::NotAllowed { domain });
+    }
+
+    if !take_fetch(state, object) {
+        return Err(FetchError::RateLimited);
+    }
+
+    let failed = |err: reqwest::Error| match err.is_timeout() {
+        true => FetchError::TimedOut,
+        false => FetchError::Failed(err.without_url().to_string()),
+    };
+
+    let mut response = client().get(url).send().await.map_err(failed)?;
+    if !response.status().is_success() {
+        return Err(FetchError::Status(response.status().as_u16()));
+    }
+
+    if response
+        .content_length()
+        .is_some_and(|len| len > MAX_BODY_LEN as u64)
+    {
+        return Err(FetchError::TooLarge);
+    }
+
+    let mut body = Vec::new();
+    while let Some(chunk) = response.chunk().await.map_err(failed)? {
+        if body.len() + chunk.len() > MAX_BODY_LEN {
+            return Err(FetchError::TooLarge);
+        }
+
+        body.extend_from_slice(&chunk);
+    }
+
+    Ok(String::from_utf8_lossy(&body).into_owned())
+}
@@ -0,0 +1,558 @@
+//! A gateway for IRC clients.
+//!
+//! IRC users log in with their player's password as the server password,
+//! either alone with their name as their nickname, or as `name:password`.
+//! They are joined to [CHANNEL], where everything that is said and emoted
+//! in the world is relayed, along with announcements as notices. Private
+//! messages to a nickname page the player with that name, and private
+//! messages to [SERVICE] run commands, whose output comes back as notices.
+//!
+//! Behind the gateway, each IRC connection is a [User] speaking the
+//! [jsonl](crate::jsonl) protocol, so it runs commands and sees events like any other connection.
+
+use std::{net::SocketAddr, sync::Arc, time::Duration};
+
+use serde_json::{json, Value as Json};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader},
+    net::{TcpListener, TcpStream},
+};
+
+use crate::{State, User, DEFAULT_MAX_LINE_LENGTH};
+
+/// The environment variable naming an address to listen for IRC clients
+/// on, like `0.0.0.0:6667`.
+pub const IRC_BIND_VAR: &str = "MARCIEMOO_IRC_BIND";
+
+/// The channel that the world's chat is relayed to.
+pub const CHANNEL: &str = "#say";
+
+/// The nickname that runs commands sent to it.
+pub const SERVICE: &str = "MOO";
+
+/// How long a client has to log in before it is disconnected.
+const REGISTRATION_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// The longest line that IRC allows, including its CRLF.
+const MAX_IRC_LINE: usize = 512;
+
+/// The ID of requests made by chatting, whose output isn't sent back, since
+/// IRC clients already show what they sent.
+const CHAT_REQUEST: &str = "chat";
+
+/// The ID of requests sent to [SERVICE], whose output is sent back.
+const SERVICE_REQUEST: &str = "service";
+
+/// Accepts IRC connections until the server shuts down.
+pub async fn listen(state: Arc<State>, bind: String) {
+    let listener = match TcpListener::bind(&bind).await {
+        Ok(listener) => listener,
+        Err(err) => {
+            eprintln!("could not listen for IRC on {bind}: {err}");
+            return;
+        }
+    };
+
+    eprintln!("Listening for IRC on {bind}");
+    let shutdown = state.shutdown_token();
+
+    loop {
+        tokio::select! {
+            incoming = listener.accept() => match incoming {
+                Ok((conn, addr)) => accept(state.clone(), conn, addr),
+                Err(err) => {
+                    eprintln!("could not accept a connection: {err}");
+                    tokio::time::sleep(Duration::from_millis(100)).await;
+                }
+            },
+            _ = shutdown.cancelled() => break,
+        }
+    }
+}
+
+fn accept(state: Arc<State>, conn: TcpStream, addr: SocketAddr) {
+    tokio::spawn(async move {
+        eprintln!("IRC connection from {addr}");
+
+        let (rx, tx) = tokio::io::split(conn);
+        let mut lines = Lines {
+            reader: BufReader::new(rx),
+            line: Vec::new(),
+        };
+
+        let mut client = Client {
+            state,
+            tx,
+            nick: "*".to_string(),
+            player: 0,
+        };
+
+        let registered =
+            tokio::time::timeout(REGISTRATION_TIMEOUT, client.register(&mut lines, addr));
+        if let Ok(Some(player)) = registered.await {
+            client.player = player;
+            client.run(lines, addr).await;
+        }
+
+        eprintln!("{addr} disconnected");
+    });
+}
+
+/// A message from an IRC client, like `PRIVMSG #say :hello`.
+#[derive(Debug)]
+struct Message {
+    command: String,
+    params: Vec<String>,
+}
+
+impl Message {
+    /// Parses a line, ignoring any prefix that the client sent.
+    fn parse(line: &str) -> Option<Self> {
+        let mut rest = line.trim_end_matches(['\r', '\n']);
+
+        if rest.starts_with(':') {
+            rest = rest.split_once(' ')?.1;
+        }
+
+        let (rest, trailing) = match rest.split_once(" :") {
+            Some((rest, trailing)) => (rest, Some(trailing)),
+            None => (rest, None),
+        };
+
+        let mut words = rest.split(' ').filter(|word| !word.is_empty());
+        let command = words.next()?.to_ascii_uppercase();
+        let mut params: Vec<String> = words.map(str::to_string).collect();
+        params.extend(trailing.map(str::to_string));
+
+        Some(Self { command, params })
+    }
+
+    fn param(&self, index: usize) -> Option<&str> {
+        self.params.get(index).map(String::as_str)
+    }
+}
+
+/// Gets the nickname that a player goes by on IRC, which has no spaces.
+pub fn nick(state: &State, player: usize) -> String {
+    let name = state.name(player).unwrap_or_default();
+    let nick: String = name
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() || "-_[]{}\\`^|".contains(c) {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+
+    // nicknames can't start with a digit
+    if nick.is_empty() || nick.starts_with(|c: char| c.is_ascii_digit()) {
+        format!("Player{player}")
+    } else {
+        nick
+    }
+}
+
+/// Finds the connected player who goes by a nickname.
+fn find_nick(state: &State, target: &str) -> Option<usize> {
+    state
+        .connected()
+        .into_iter()
+        .find(|player| nick(state, *player).eq_ignore_ascii_case(target))
+}
+
+/// Reads the messages that a client sends.
+struct Lines<R> {
+    reader: BufReader<R>,
+
+    /// The part of the next line that has been read, which is kept when a
+    /// read is interrupted.
+    line: Vec<u8>,
+}
+
+impl<R: AsyncRead + Unpin> Lines<R> {
+    /// Reads the next message, or nothing once the client has disconnected.
+    /// Lines that aren't messages are read as [None].
+    async fn next(&mut self) -> Option<Option<Message>> {
+        let left = MAX_IRC_LINE.saturating_sub(self.line.len()).max(1);
+        let mut limited = (&mut self.reader).take(left as u64);
+
+        match limited.read_until(b'\n', &mut self.line).await {
+            Ok(0) | Err(_) => None,
+            Ok(_) => {
+                let line = std::mem::take(&mut self.line);
+                Some(Message::parse(&String::from_utf8_lossy(&line)))
+            }
+        }
+    }
+}
+
+struct Client<W> {
+    state: Arc<State>,
+    tx: W,
+    nick: String,
+    player: usize,
+}
+
+impl<W: AsyncWrite + Unpin> Client<W> {
+    async fn send(&mut self, line: &str) -> std::io::Result<()> {
+        self.tx.write_all(format!("{line}\r\n").as_bytes()).await
+    }
+
+    /// Sends a numeric reply from the server.
+    async fn reply(&mut self, numeric: &str, text: &str) -> std::io::Result<()> {
+        let server = server_host(&self.state);
+        let line = format!(":{server} {numeric} {} {text}", self.nick);
+        self.send(&line).await
+    }
+
+    /// Reads the client's password, nickname, and user, returning the player
+    /// that it logs in as.
+    async fn register(
+        &mut self,
+        lines: &mut Lines<impl AsyncRead + Unpin>,
+        addr: SocketAddr,
+    ) -> Option<usize> {
+        let mut pass = None;
+        let mut user = false;
+
+        while pass.is_none() || self.nick == "*" || !user {
+            let Some(message) = lines.next().await? else {
+                continue;
+            };
+
+            match message.command.as_str() {
+                // names may have spaces, which clients don't always escape
+                "PASS" => pass = Some(message.params.join(" ")),
+                "NICK" => self.nick = message.param(0)?.to_string(),
+                "USER" => user = true,
+                "CAP" => {}
+                "QUIT" => return None,
+                _ => self.reply("451", ":You have not registered").await.ok()?,
+            }
+
+            if self.nick != "*" && user && pass.is_none() {
+                let _ = self
+                    .reply("464", ":Log in with your password as PASS")
+                    .await;
+                return None;
+            }
+        }
+
+        let pass = pass?;
+        let (name, password) = match pass.split_once(':') {
+            Some((name, password)) => (name.to_string(), password.to_string()),
+            None => (self.nick.clone(), pass),
+        };
+
+        let player = self.state.find_player(&name);
+
+        // hashing is slow on purpose, so keep it off of the other connections
+        let state = self.state.clone();
+        let player = tokio::task::spawn_blocking(move || {
+            player.filter(|player| state.check_login(*player, &password, &addr.to_string()))
+        })
+        .await
+        .ok()
+        .flatten();
+
+        let Some(player) = player else {
+            let _ = self.reply("464", ":Wrong player or password").await;
+            return None;
+        };
+
+        if !self.state.claim_player(player) {
+            let _ = self.reply("433", ":That player is already connected").await;
+            return None;
+        }
+
+        Some(player)
+    }
+
+    /// Relays between the client and its user until either disconnects.
+    async fn run(mut self, mut lines: Lines<impl AsyncRead + Unpin>, addr: SocketAddr) {
+        let (user_rx, mut to_user) = tokio::io::duplex(DEFAULT_MAX_LINE_LENGTH);
+        let (user_tx, from_user) = tokio::io::duplex(DEFAULT_MAX_LINE_LENGTH);
+        let user = User::new(self.state.clone(), user_tx, addr.to_string(), self.player);
+        tokio::spawn(user.run_json(user_rx));
+
+        if self.welcome().await.is_err() {
+            return;
+        }
+
+        let mut from_user = BufReader::new(from_user).lines();
+
+        loop {
+            tokio::select! {
+                message = lines.next() => {
+                    let Some(message) = message else {
+                        break;
+                    };
+
+                    let Some(message) = message else {
+                        continue;
+                    };
+
+                    match self.on_message(message).await {
+                        Ok(Some(request)) => {
+                            let request = format!("{request}\n");
+                            if to_user.write_all(request.as_bytes()).await.is_err() {
+                                break;
+                            }
+                        }
+                        Ok(None) => {}
+                        Err(_) => break,
+                    }
+                }
+                line = from_user.next_line() => {
+                    let Ok(Some(line)) = line else {
+                        let _ = self.send("ERROR :Closing link").await;
+                        break;
+                    };
+
+                    let Ok(output) = serde_json::from_str::<Json>(&line) else {
+                        continue;
+                    };
+
+                    if self.on_output(&output).await.is_err() {
+                        break;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Greets a client that has logged in and joins it to [CHANNEL].
+    async fn welcome(&mut self) -> std::io::Result<()> {
+        let canonical = nick(&self.state, self.player);
+        if canonical != self.nick {
+            let line = format!(":{} NICK {canonical}", self.nick);
+            self.send(&line).await?;
+            self.nick = canonical;
+        }
+
+        let server = self.state.server_name();
+        self.reply("001", &format!(":Welcome to {server}, {}", self.nick))
+            .await?;
+        self.reply("376", ":End of MOTD").await?;
+
+        let join = format!(":{} JOIN {CHANNEL}", self.prefix(self.player));
+        self.send(&join).await?;
+
+        let names: Vec<String> = self
+            .state
+            .connected()
+            .into_iter()
+            .map(|player| nick(&self.state, player))
+            .collect();
+
+        self.reply("353", &format!("= {CHANNEL} :{}", names.join(" ")))
+            .await?;
+        self.reply("366", &format!("{CHANNEL} :End of NAMES list"))
+            .await
+    }
+
+    /// Gets the prefix that marks a player as the source of a message.
+    fn prefix(&self, player: usize) -> String {
+        let host = server_host(&self.state);
+        format!("{}!player{player}@{host}", nick(&self.state, player))
+    }
+
+    /// Responds to a message from the client, returning the request that it
+    /// makes of its user, if any.
+    async fn on_message(&mut self, message: Message) -> std::io::Result<Option<String>> {
+        let command = match message.command.as_str() {
+            "PING" => {
+                let token = message.param(0).unwrap_or_default();
+                let server = server_host(&self.state);
+                self.send(&format!(":{server} PONG {server} :{token}"))
+                    .await?;
+                return Ok(None);
+            }
+            "QUIT" => {
+                self.send("ERROR :Closing link").await?;
+                return Err(std::io::ErrorKind::ConnectionAborted.into());
+            }
+            "PRIVMSG" | "NOTICE" => {
+                let (Some(target), Some(text)) = (message.param(0), message.param(1)) else {
+                    self.reply("411", ":No recipient given").await?;
+                    return Ok(None);
+                };
+
+                let (id, command) = match self.privmsg(target, text) {
+                    Ok(request) => request,
+                    Err(err) => {
+                        self.reply("401", &format!("{target} :{err}")).await?;
+                        return Ok(None);
+                    }
+                };
+
+                // notices must never be answered automatically
+                if message.command == "NOTICE" && id == SERVICE_REQUEST {
+                    return Ok(None);
+                }
+
+                return Ok(Some(json!({ "id": id, "command": command }).to_string()));
+            }
+            "JOIN" if message.param(0) == Some(CHANNEL) => return Ok(None),
+            "JOIN" => {
+                let channel = message.param(0).unwrap_or_default().to_string();
+                self.reply("403", &format!("{channel} :Only {CHANNEL} exists"))
+                    .await?;
+                return Ok(None);
+            }
+            "PART" | "MODE" | "WHO" | "USERHOST" | "CAP" | "PONG" => return Ok(None),
+            command => command.to_string(),
+        };
+
+        self.reply("421", &format!("{command} :Unknown command"))
+            .await?;
+        Ok(None)
+    }
+
+    /// Translates a message to a target into a request and its ID.
+    fn privmsg(&self, target: &str, text: &str) -> Result<(&'static str, String), &'static str> {
+        if target.eq_ignore_ascii_case(SERVICE) {
+            return Ok((SERVICE_REQUEST, text.to_string()));
+        }
+
+        let action = text
+            .strip_prefix("\x01ACTION ")
+            .map(|action| action.trim_end_matches('\x01'));
+
+        if target.eq_ignore_ascii_case(CHANNEL) {
+            let command = match action {
+                Some(action) => format!("emote {action}"),
+                None => format!("say {text}"),
+            };
+
+            return Ok((CHAT_REQUEST, command));
+        }
+
+        let to = find_nick(&self.state, target).ok_or("No such nick")?;
+        let text = match action {
+            Some(action) => format!("* {} {action}", self.nick),
+            None => text.to_string(),
+        };
+
+        Ok((CHAT_REQUEST, format!("page #{to} {text}")))
+    }
+
+    /// Relays a line of output from the user to the client.
+    async fn on_output(&mut self, output: &Json) -> std::io::Result<()> {
+        let field = |name: &str| output[name].as_str().unwrap_or_default().to_string();
+        let player = |name: &str| output[name].as_u64().map(|id| id as usize);
+
+        match output["type"].as_str() {
+            Some("output") if output["id"] == json!(CHAT_REQUEST) => Ok(()),
+            Some("output") => {
+                let lines = output["lines"].as_array().cloned().unwrap_or_default();
+                let service = format!("{SERVICE}!{SERVICE}@{}", server_host(&self.state));
+
+                for line in lines.iter().filter_map(Json::as_str) {
+                    let line = if line.is_empty() { " " } else { line };
+                    self.send(&format!(":{service} NOTICE {} :{line}", self.nick))
+                        .await?;
+                }
+
+                Ok(())
+            }
+            Some("event") => match output["event"].as_str().unwrap_or_default() {
+                // clients show their own messages as they send them
+                _ if player("speaker") == Some(self.player)
+                    || player("actor") == Some(self.player) =>
+                {
+                    Ok(())
+                }
+                "say" => {
+                    let Some(speaker) = player("speaker") else {
+                        return Ok(());
+                    };
+
+                    let line = format!(
+                        ":{} PRIVMSG {CHANNEL} :{}",
+                        self.prefix(speaker),
+                        field("message")
+                    );
+
+                    self.send(&line).await
+                }
+                "emote" => {
+                    let Some(actor) = player("actor") else {
+                        return Ok(());
+                    };
+
+                    let line = format!(
+                        ":{} PRIVMSG {CHANNEL} :\x01ACTION {}\x01",
+                        self.prefix(actor),
+                        field("action")
+                    );
+
+                    self.send(&line).await
+                }
+                "page" => {
+                    let Some(from) = player("from") else {
+                        return Ok(());
+                    };
+
+                    let line = format!(
+                        ":{} PRIVMSG {} :{}",
+                        self.prefix(from),
+                        self.nick,
+                        field("message")
+                    );
+
+                    self.send(&line).await
+                }
+                "notice" => {
+                    let service = format!("{SERVICE}!{SERVICE}@{}", server_host(&self.state));
+                    let line = format!(":{service} NOTICE {} :{}", self.nick, field("text"));
+                    self.send(&line).await
+                }
+                "announce" | "emit" | "social" => {
+                    let server = server_host(&self.state);
+                    let line = format!(":{server} NOTICE {CHANNEL} :{}", field("text"));
+                    self.send(&line).await
+                }
+                "connect" => match player("player") {
+                    Some(player) if player != self.player => {
+                        let line = format!(":{} JOIN {CHANNEL}", self.prefix(player));
+                        self.send(&line).await
+                    }
+                    _ => Ok(()),
+                },
+                "disconnect" => match player("player") {
+                    Some(player) if player != self.player => {
+                        let line = format!(":{} QUIT :Disconnected", self.prefix(player));
+                        self.send(&line).await
+                    }
+                    _ => Ok(()),
+                },
+                _ => Ok(()),
+            },
+            Some("error") => {
+                let line = format!(":{SERVICE} NOTICE {} :{}", self.nick, field("message"));
+                self.send(&line).await
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Gets the name that the server goes by in messages, which has no spaces.
+fn server_host(state: &State) -> String {
+    let name = state.server_name().to_lowercase();
+    let host: String = name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '.' || c == '-' {
+                c
+            } else {
+                '-'
+            }
+        })
+        .collect();
+
+    format!("{host}.moo")
+}
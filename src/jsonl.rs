@@ -0,0 +1,133 @@
+//! A protocol of JSON lines, for bots, bridges, and test harnesses.
+//!
+//! Connections switch to it with `@json on`, or start in it on the port
+//! named by [JSON_BIND_VAR], which doesn't speak telnet. Each line sent to
+//! the server is then a request like `{"id": 1, "command": "say hi"}`,
+//! whose `id` may be any JSON value or left out.
+//!
+//! Each line sent back is an object whose `type` is one of:
+//!
+//! - `output`: the `lines` that a request printed, all at once and without
+//!   paging or wrapping, with the request's `id`. Output that no request
+//!   caused, like the welcome message, has no `id`.
+//! - `event`: an [Event] that the player saw, with its fields, its kind as
+//!   `event`, the `text` that players see for it, if there is any, and the
+//!   `source` object that caused it, if one did
+//! - `error`: a request that couldn't be read, with a `message` saying why
+
+use std::{net::SocketAddr, sync::Arc, time::Duration};
+
+use serde::Deserialize;
+use serde_json::{json, Value as Json};
+use tokio::{
+    io::AsyncWriteExt,
+    net::{TcpListener, TcpStream},
+};
+
+use crate::{event::Event, State, User};
+
+/// The environment variable naming an address to listen on for connections
+/// that speak JSON lines from the start, like `127.0.0.1:8889`.
+pub const JSON_BIND_VAR: &str = "MARCIEMOO_JSON_BIND";
+
+/// A command to run.
+#[derive(Debug, Deserialize)]
+pub struct Request {
+    #[serde(default)]
+    pub id: Option<Json>,
+    pub command: String,
+}
+
+/// Reads a request from a line.
+pub fn parse_request(line: &str) -> Result<Request, String> {
+    serde_json::from_str(line).map_err(|err| format!("invalid request: {err}"))
+}
+
+/// Encodes the lines of output that a request printed.
+pub fn output(id: Option<&Json>, lines: &[String]) -> String {
+    let mut output = json!({
+        "type": "output",
+        "lines": lines,
+    });
+
+    if let Some(id) = id {
+        output["id"] = id.clone();
+    }
+
+    output.to_string()
+}
+
+/// Encodes an event that a player saw, with its text as they see it, or as
+/// bystanders see it if no player is given.
+pub fn event(event: &Event, viewer: Option<usize>) -> String {
+    let mut encoded = serde_json::to_value(event).unwrap();
+    encoded["type"] = json!("event");
+
+    let text = match viewer {
+        Some(viewer) => event.render_for(viewer),
+        None => event.render(),
+    };
+
+    if let Some(text) = text {
+        encoded["text"] = json!(text);
+    }
+
+    if let Some(source) = event.source() {
+        encoded["source"] = json!(source);
+    }
+
+    encoded.to_string()
+}
+
+/// Encodes the error of a request that couldn't be read.
+pub fn error(message: &str) -> String {
+    json!({
+        "type": "error",
+        "message": message,
+    })
+    .to_string()
+}
+
+/// Accepts connections that speak JSON lines until the server shuts down.
+pub async fn listen(state: Arc<State>, bind: String) {
+    let listener = match TcpListener::bind(&bind).await {
+        Ok(listener) => listener,
+        Err(err) => {
+            eprintln!("could not listen for JSON lines on {bind}: {err}");
+            return;
+        }
+    };
+
+    eprintln!("Listening for JSON lines on {bind}");
+    let shutdown = state.shutdown_token();
+
+    loop {
+        tokio::select! {
+            incoming = listener.accept() => match incoming {
+                Ok((conn, addr)) => accept(state.clone(), conn, addr),
+                Err(err) => {
+                    eprintln!("could not accept a connection: {err}");
+                    tokio::time::sleep(Duration::from_millis(100)).await;
+                }
+            },
+            _ = shutdown.cancelled() => break,
+        }
+    }
+}
+
+fn accept(state: Arc<State>, mut conn: TcpStream, addr: SocketAddr) {
+    tokio::spawn(async move {
+        eprintln!("JSON lines connection from {addr}");
+
+        let Some(player) = state.new_player() else {
+            let message = error("every guest is in use, so try again later");
+            let _ = conn.write_all(format!("{message}\n").as_bytes()).await;
+            return;
+        };
+
+        let (rx, tx) = tokio::io::split(conn);
+        let user = User::new(state, tx, addr.to_string(), player);
+        user.run_json(rx).await;
+        eprintln!("{addr} disconnected");
+    });
+}
@@ -0,0 +1,65 @@
+//! The log of connections, kept in its own tree of the database.
+
+use serde::{Deserialize, Serialize};
+use sled::{Db, Tree};
+
+/// Whether a log entry records the start or end of a session.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+pub enum SessionEvent {
+    Connect,
+    Disconnect,
+}
+
+/// One connection or disconnection.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct LogEntry {
+    pub event: SessionEvent,
+    pub player: usize,
+
+    /// The address connected from, or `console` for the operator's console.
+    pub addr: String,
+
+    /// When this happened, in seconds since the UNIX epoch.
+    pub at: u64,
+}
+
+pub struct ConnectionLog {
+    db: Db,
+    tree: Tree,
+}
+
+impl ConnectionLog {
+    pub fn open(db: &Db) -> Self {
+        Self {
+            db: db.clone(),
+            tree: db.open_tree("connections").unwrap(),
+        }
+    }
+
+    /// Records that a player connected or disconnected.
+    pub fn record(&self, event: SessionEvent, player: usize, addr: &str) {
+        let entry = LogEntry {
+            event,
+            player,
+            addr: addr.to_string(),
+            at: crate::now(),
+        };
+
+        // generated IDs only increase, so keys are in chronological order
+        let key = self.db.generate_id().unwrap().to_be_bytes();
+        let entry = serde_json::to_vec(&entry).unwrap();
+        self.tree.insert(key, entry).unwrap();
+    }
+
+    /// Lists up to `limit` of the latest entries, newest first, optionally
+    /// only those of one player.
+    pub fn recent(&self, player: Option<usize>, limit: usize) -> Vec<LogEntry> {
+        self.tree
+            .iter()
+            .rev()
+            .filter_map(|entry| serde_json::from_slice::<LogEntry>(&entry.unwrap().1).ok())
+            .filter(|entry| player.is_none_or(|player| entry.player == player))
+            .take(limit)
+            .collect()
+    }
+}
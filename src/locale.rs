@@ -0,0 +1,186 @@
+//! Catalogs of the messages that the server itself sends, so that worlds can
+//! translate them in-game.
+//!
+//! Every message has a key and an English text in [MESSAGES]. Wizards add
+//! translations with `@translate`, which are kept in their own tree of the
+//! database by locale and key. Each player reads messages in the locale that
+//! their `locale` field names, falling back to the system object's `locale`
+//! and then to English for the messages that aren't translated.
+//!
+//! Messages may have placeholders like `{name}`, which are filled in when
+//! they are sent. A placeholder that isn't given is left as it is.
+
+use sled::{Db, Tree};
+
+use crate::{State, Value, SYSTEM_OBJECT};
+
+/// The field holding the locale that a player reads messages in, which is
+/// also the server's default on the system object.
+pub const LOCALE_FIELD: &str = "locale";
+
+/// The longest locale codes that may be used, like `pt-BR`.
+pub const MAX_LOCALE_LEN: usize = 16;
+
+/// The key and English text of every message in the catalog.
+pub const MESSAGES: &[(&str, &str)] = &[
+    ("welcome", "Welcome to MarcieMOO!"),
+    (
+        "welcome_maintenance",
+        "The server is in maintenance mode, so changes are disabled.",
+    ),
+    ("welcome_help", "Type \"help\"."),
+    ("welcome_object", "You are object #{id}."),
+    (
+        "starting_room_failed",
+        "could not enter the starting room: {error}",
+    ),
+    ("error", "error: {error}"),
+    ("usage", "usage: {usage}"),
+    ("no_such_verb", "no such verb"),
+    ("did_you_mean", "Did you mean {command}?"),
+    ("did_you_mean_any", "Did you mean one of: {commands}?"),
+    ("missing_argument", "missing argument at index {index}"),
+    ("extra_argument", "unexpected argument at index {index}"),
+    (
+        "invalid_argument",
+        "invalid argument at index {index} (expected {expected})",
+    ),
+    ("unknown_option", "unknown option \"{name}\""),
+    (
+        "invalid_option",
+        "invalid value for option \"{name}\" (expected {expected})",
+    ),
+    ("no_match", "nothing matches \"{name}\""),
+    ("ambiguous", "which \"{name}\"? ({candidates})"),
+    ("field_error", "{error}"),
+    ("permission_denied", "permission denied"),
+    (
+        "maintenance",
+        "the server is in maintenance mode, so changes are disabled",
+    ),
+    ("booted", "You have been booted by a wizard."),
+    (
+        "taken_over",
+        "Your session was taken over by another connection.",
+    ),
+    ("friend_connected", "{name} has connected."),
+];
+
+/// Gets the English text of a message, if the key is in the catalog.
+pub fn english(key: &str) -> Option<&'static str> {
+    MESSAGES
+        .iter()
+        .find(|(known, _)| *known == key)
+        .map(|(_, text)| *text)
+}
+
+/// Tests if a string may be used as a locale code.
+pub fn is_locale(code: &str) -> bool {
+    !code.is_empty()
+        && code.len() <= MAX_LOCALE_LEN
+        && code
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+/// Gets the locale that a player reads messages in, if it isn't English.
+pub fn locale_of(state: &State, player: usize) -> Option<String> {
+    [player, SYSTEM_OBJECT]
+        .into_iter()
+        .find_map(|id| match state.get(id, LOCALE_FIELD) {
+            Some(Value::String(code)) if is_locale(&code) => Some(code),
+            _ => None,
+        })
+}
+
+/// Fills in the placeholders of a message.
+pub fn format(template: &str, args: &[(&str, String)]) -> String {
+    let mut out = String::with_capacity(template.len());
+    let mut rest = template;
+
+    while let Some(start) = rest.find('{') {
+        out.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        let arg = rest.find('}').and_then(|end| {
+            let name = &rest[1..end];
+            let (_, val) = args.iter().find(|(arg, _)| *arg == name)?;
+            Some((end, val))
+        });
+
+        match arg {
+            Some((end, val)) => {
+                out.push_str(val);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                out.push('{');
+                rest = &rest[1..];
+            }
+        }
+    }
+
+    out.push_str(rest);
+    out
+}
+
+/// Gets a message as a player reads it, with its placeholders filled in.
+pub fn text(state: &State, player: usize, key: &str, args: &[(&str, String)]) -> String {
+    let translated = locale_of(state, player).and_then(|locale| state.translation(&locale, key));
+    let template = match &translated {
+        Some(template) => template.as_str(),
+        None => english(key).unwrap_or(key),
+    };
+
+    format(template, args)
+}
+
+/// The translations of the catalog, kept in their own tree of the database.
+pub struct Translations {
+    tree: Tree,
+}
+
+/// Gets the key that stores a translation.
+fn translation_key(locale: &str, key: &str) -> Vec<u8> {
+    format!("{locale}:{key}").into_bytes()
+}
+
+impl Translations {
+    pub fn open(db: &Db) -> Self {
+        Self {
+            tree: db.open_tree("translations").unwrap(),
+        }
+    }
+
+    /// Gets the translation of a message into a locale, if it has one.
+    pub fn get(&self, locale: &str, key: &str) -> Option<String> {
+        let text = self.tree.get(translation_key(locale, key)).unwrap()?;
+        String::from_utf8(text.to_vec()).ok()
+    }
+
+    /// Adds or replaces the translation of a message.
+    pub fn set(&self, locale: &str, key: &str, text: &str) {
+        let key = translation_key(locale, key);
+        self.tree.insert(key, text.as_bytes()).unwrap();
+    }
+
+    /// Removes the translation of a message, returning false if there was
+    /// none.
+    pub fn remove(&self, locale: &str, key: &str) -> bool {
+        let key = translation_key(locale, key);
+        self.tree.remove(key).unwrap().is_some()
+    }
+
+    /// Lists the translations into a locale, sorted by key.
+    pub fn list(&self, locale: &str) -> Vec<(String, String)> {
+        self.tree
+            .scan_prefix(format!("{locale}:"))
+            .filter_map(|entry| {
+                let (key, text) = entry.unwrap();
+                let key = String::from_utf8(key.to_vec()).ok()?;
+                let (_, key) = key.split_once(':')?;
+                Some((key.to_string(), String::from_utf8(text.to_vec()).ok()?))
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,237 @@
+//! Locks, which decide who may take an exit, take an object, or open a
+//! container.
+//!
+//! A lock is an expression of objects, like `#42 || (#43 && !#44)`, that a
+//! player satisfies if they are one of the objects or are carrying it. `!`
+//! binds tighter than `&&`, which binds tighter than `||`. `@lock` parses the
+//! expression, resolving the names in it to objects, and keeps it in the
+//! object's `lock` field as JSON. Locks may nest at most [MAX_DEPTH] deep,
+//! so that they can always be read back.
+
+use std::fmt::Display;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{State, Value};
+
+/// The field holding an object's lock.
+pub const LOCK_FIELD: &str = "lock";
+
+/// How deeply a lock's operators may nest, which keeps its JSON well within
+/// what can be read back.
+pub const MAX_DEPTH: usize = 32;
+
+/// A parsed lock expression.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Lock {
+    Object(usize),
+    Not(Box<Lock>),
+    And(Box<Lock>, Box<Lock>),
+    Or(Box<Lock>, Box<Lock>),
+}
+
+impl Lock {
+    /// Tests if a player satisfies this lock.
+    pub fn passes(&self, state: &State, player: usize) -> bool {
+        match self {
+            Lock::Object(id) => *id == player || state.location(*id) == Some(player),
+            Lock::Not(lock) => !lock.passes(state, player),
+            Lock::And(a, b) => a.passes(state, player) && b.passes(state, player),
+            Lock::Or(a, b) => a.passes(state, player) || b.passes(state, player),
+        }
+    }
+
+    /// Counts how deeply this lock's operators nest.
+    pub fn depth(&self) -> usize {
+        match self {
+            Lock::Object(_) => 1,
+            Lock::Not(lock) => 1 + lock.depth(),
+            Lock::And(a, b) | Lock::Or(a, b) => 1 + a.depth().max(b.depth()),
+        }
+    }
+}
+
+impl Display for Lock {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // wraps the operands that bind more loosely than their operator
+        let operand = |lock: &Lock, binding: u8| match (lock, binding) {
+            (Lock::Or(..), 1..) | (Lock::And(..), 2..) => format!("({lock})"),
+            _ => lock.to_string(),
+        };
+
+        match self {
+            Lock::Object(id) => write!(f, "#{id}"),
+            Lock::Not(lock) => write!(f, "!{}", operand(lock, 2)),
+            Lock::And(a, b) => write!(f, "{} && {}", operand(a, 1), operand(b, 1)),
+            Lock::Or(a, b) => write!(f, "{} || {}", operand(a, 0), operand(b, 0)),
+        }
+    }
+}
+
+/// Gets an object's lock, if it has one.
+pub fn lock_of(state: &State, id: usize) -> Option<Lock> {
+    match state.get(id, LOCK_FIELD) {
+        Some(Value::String(lock)) => serde_json::from_str(&lock).ok(),
+        _ => None,
+    }
+}
+
+/// Tests if a player may pass an object's lock. Objects without locks are
+/// open to everyone, but a lock that can't be read keeps everyone out.
+pub fn allows(state: &State, id: usize, player: usize) -> bool {
+    match state.get(id, LOCK_FIELD) {
+        None => true,
+        Some(_) => lock_of(state, id).is_some_and(|lock| lock.passes(state, player)),
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Token {
+    Not,
+    And,
+    Or,
+    Open,
+    Close,
+    Name(String),
+}
+
+fn tokenize(src: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut name = String::new();
+    let mut chars = src.chars().peekable();
+
+    let flush = |name: &mut String, tokens: &mut Vec<Token>| {
+        let trimmed = name.trim();
+        if !trimmed.is_empty() {
+            tokens.push(Token::Name(trimmed.to_string()));
+        }
+
+        name.clear();
+    };
+
+    while let Some(c) = chars.next() {
+        let token = match c {
+            '!' => Token::Not,
+            '(' => Token::Open,
+            ')' => Token::Close,
+            '&' | '|' => {
+                if chars.next() != Some(c) {
+                    return Err(format!("expected {c}{c}"));
+                }
+
+                match c {
+                    '&' => Token::And,
+                    _ => Token::Or,
+                }
+            }
+            c => {
+                name.push(c);
+                continue;
+            }
+        };
+
+        flush(&mut name, &mut tokens);
+        tokens.push(token);
+    }
+
+    flush(&mut name, &mut tokens);
+    Ok(tokens)
+}
+
+struct Parser<'a> {
+    tokens: Vec<Token>,
+    next: usize,
+    depth: usize,
+    resolve: &'a dyn Fn(&str) -> Result<usize, String>,
+}
+
+impl Parser<'_> {
+    fn eat(&mut self, token: &Token) -> bool {
+        if self.tokens.get(self.next) == Some(token) {
+            self.next += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn or(&mut self) -> Result<Lock, String> {
+        let mut lock = self.and()?;
+        while self.eat(&Token::Or) {
+            lock = Lock::Or(Box::new(lock), Box::new(self.and()?));
+        }
+
+        Ok(lock)
+    }
+
+    fn and(&mut self) -> Result<Lock, String> {
+        let mut lock = self.not()?;
+        while self.eat(&Token::And) {
+            lock = Lock::And(Box::new(lock), Box::new(self.not()?));
+        }
+
+        Ok(lock)
+    }
+
+    fn not(&mut self) -> Result<Lock, String> {
+        if self.eat(&Token::Not) {
+            let lock = self.nested(Self::not)?;
+            return Ok(Lock::Not(Box::new(lock)));
+        }
+
+        if self.eat(&Token::Open) {
+            let lock = self.nested(Self::or)?;
+            if !self.eat(&Token::Close) {
+                return Err("expected )".to_string());
+            }
+
+            return Ok(lock);
+        }
+
+        match self.tokens.get(self.next) {
+            Some(Token::Name(name)) => {
+                self.next += 1;
+                (self.resolve)(name).map(Lock::Object)
+            }
+            _ => Err("expected an object".to_string()),
+        }
+    }
+
+    /// Parses a nested part of the lock, giving up if it nests too deeply.
+    fn nested(&mut self, parse: fn(&mut Self) -> Result<Lock, String>) -> Result<Lock, String> {
+        if self.depth >= MAX_DEPTH {
+            return Err(too_deep());
+        }
+
+        self.depth += 1;
+        let lock = parse(self);
+        self.depth -= 1;
+        lock
+    }
+}
+
+fn too_deep() -> String {
+    format!("the lock nests more than {MAX_DEPTH} deep")
+}
+
+/// Parses a lock expression, resolving each name in it to an object.
+pub fn parse(src: &str, resolve: &dyn Fn(&str) -> Result<usize, String>) -> Result<Lock, String> {
+    let mut parser = Parser {
+        tokens: tokenize(src)?,
+        next: 0,
+        depth: 0,
+        resolve,
+    };
+
+    let lock = parser.or()?;
+    if parser.next < parser.tokens.len() {
+        return Err("unexpected text after the lock".to_string());
+    }
+
+    if lock.depth() > MAX_DEPTH {
+        return Err(too_deep());
+    }
+
+    Ok(lock)
+}
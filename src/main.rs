@@ -1,17 +1,88 @@
-use std::{collections::HashMap, fmt::Display, net::SocketAddr, sync::Arc};
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    fmt::Display,
+    fs::Permissions,
+    io::{Read, Write},
+    net::SocketAddr,
+    os::unix::fs::{MetadataExt, PermissionsExt},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+        Arc, Mutex, RwLock,
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
 
+use dice::Dice;
+use event::Event;
+use flate2::{write::ZlibEncoder, Compression};
+use history::{ChatHistory, HistoryEntry, HISTORY_LENGTH};
+use lastlog::{ConnectionLog, LogEntry, SessionEvent};
+use locale::Translations;
 use logos::Logos;
-use script::ScriptOutput;
+use news::{Announcement, NewsLog};
+use perms::FieldPerms;
+use prefs::Prefs;
+use rand::Rng;
+use registration::Registration;
+use script::{Host, ScriptOutput};
 use serde::{Deserialize, Serialize};
-use sled::Tree;
+use sled::{
+    transaction::{
+        abort, ConflictableTransactionError, TransactionError, TransactionalTree,
+        UnabortableTransactionError,
+    },
+    Db, IVec, Tree,
+};
+use social::{Social, SocialTable};
 use tokio::{
-    io::{AsyncBufReadExt, AsyncWriteExt, BufReader, ReadHalf, WriteHalf},
-    net::{TcpListener, TcpStream},
-    sync::{broadcast, mpsc::UnboundedSender},
+    io::{
+        AsyncBufRead, AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt,
+        BufReader, BufWriter, DuplexStream,
+    },
+    net::{TcpListener, TcpStream, UnixListener, UnixStream},
+    sync::{broadcast, mpsc},
 };
 use tokio_util::sync::CancellationToken;
 
+pub mod censor;
+pub mod clock;
+pub mod config;
+pub mod dice;
+pub mod equipment;
+pub mod event;
+pub mod feed;
+pub mod filter;
+pub mod friends;
+pub mod glob;
+pub mod gmcp;
+pub mod grpc;
+pub mod history;
+pub mod home;
+pub mod http;
+pub mod irc;
+pub mod jsonl;
+pub mod lastlog;
+pub mod locale;
+pub mod lock;
+pub mod markup;
+pub mod matrix;
+pub mod mqtt;
+pub mod mxp;
+pub mod news;
+pub mod password;
+pub mod perms;
+pub mod prefs;
+pub mod proxy;
+pub mod registration;
 pub mod script;
+pub mod social;
+pub mod spoof;
+pub mod ssh;
+pub mod suggest;
+pub mod tasks;
+pub mod telnet;
+pub mod world_clock;
+pub mod wrap;
 
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub enum Value {
@@ -37,392 +108,4170 @@ impl Value {
             _ => None,
         }
     }
-}
-
-pub struct State {
-    tree: Tree,
-    shutdown: CancellationToken,
-    announcement_tx: broadcast::Sender<String>,
-}
 
-impl State {
-    pub fn new(shutdown: CancellationToken) -> Self {
-        let db = sled::open("marciemoo.db").unwrap();
-        let tree = db.open_tree("").unwrap();
-        let announcement_tx = broadcast::Sender::new(1024);
-
-        Self {
-            tree,
-            shutdown,
-            announcement_tx,
+    pub fn as_integer(&self) -> Option<i64> {
+        match self {
+            Value::Integer(val) => Some(*val),
+            _ => None,
         }
     }
 
-    /// Retrieves a child [CancellationToken] for this state.
-    pub fn shutdown_token(&self) -> CancellationToken {
-        self.shutdown.child_token()
+    pub fn as_id(&self) -> Option<usize> {
+        self.as_integer().and_then(|val| val.try_into().ok())
     }
 
-    /// Creates a new object, and returns its new ID.
-    pub fn create(&self) -> usize {
-        self.tree
-            .transaction::<_, _, ()>(|tx| {
-                let id = tx.get("object-index")?.unwrap_or("0".into());
-                let id = String::from_utf8(id.to_vec()).unwrap();
-                let id: usize = id.parse().unwrap();
+    /// Names the type of this value, as `@examine` shows it.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::String(_) => "string",
+            Value::Integer(_) => "integer",
+            Value::Bool(_) => "bool",
+        }
+    }
+}
 
-                let next = id + 1;
-                let next = format!("{}", next);
-                tx.insert("object-index", next.into_bytes())?;
+/// The maximum size of a field value that is indexed for searching.
+pub const MAX_INDEXED_LEN: usize = 256;
 
-                tx.insert(format!("object-exists-{id}").into_bytes(), "")?;
+/// Gets the key that indexes a field's value, or [None] if the value is too
+/// long to be indexed.
+///
+/// Index keys are ordered by field name and then by lowercased value so that
+/// searches on one field only need to scan that field's values.
+fn value_index_key(id: usize, key: &str, val: &Value) -> Option<Vec<u8>> {
+    let text = match val {
+        Value::String(val) => val.to_lowercase(),
+        Value::Integer(val) => val.to_string(),
+        Value::Bool(val) => val.to_string(),
+    };
 
-                Ok(id)
-            })
-            .unwrap()
+    if text.len() > MAX_INDEXED_LEN {
+        return None;
     }
 
-    /// Tests if an object exists by ID.
-    pub fn exists(&self, id: usize) -> bool {
-        self.tree
-            .contains_key(format!("object-exists-{id}"))
-            .unwrap()
-    }
+    Some(format!("index-field-{key}\0{text}\0{id}").into_bytes())
+}
 
-    /// Atomically destroys an object by ID.
-    pub fn destroy(&self, id: usize) -> bool {
-        let key = format!("object-exists-{id}");
+/// Gets the key that lists an object in ID order.
+fn id_index_key(id: usize) -> Vec<u8> {
+    format!("index-id-{id:020}").into_bytes()
+}
 
-        if self.tree.remove(key).unwrap().is_none() {
-            // either this object is already destroyed or another thread is
-            // currently destroying it, so we can exit
-            return false;
-        }
+/// Decodes the big-endian object counter.
+fn decode_counter(counter: &[u8]) -> u64 {
+    u64::from_be_bytes(counter.try_into().unwrap())
+}
 
-        let prefix = format!("object-field-{id}-");
-        for field in self.tree.scan_prefix(&prefix) {
-            self.tree.remove(field.unwrap().0).unwrap();
-        }
+/// Gets the key that holds the hash of a player's password.
+fn password_key(id: usize) -> Vec<u8> {
+    format!("password-{id}").into_bytes()
+}
 
-        true
-    }
+/// Gets the key that holds the public keys a player logs in over SSH with.
+fn ssh_keys_key(id: usize) -> Vec<u8> {
+    format!("ssh-keys-{id}").into_bytes()
+}
 
-    /// Lists all of the objects.
-    pub fn list(&self) -> Vec<usize> {
-        let prefix = "object-exists-";
-        let prefix_len = prefix.len();
-        let exists = self.tree.scan_prefix(prefix);
+/// The key that holds the server's SSH host key.
+const SSH_HOST_KEY: &[u8] = b"ssh-host-key";
 
-        let mut ids = Vec::new();
-        for exist in exists {
-            let (key, _value) = exist.unwrap();
-            let id = key[prefix_len..].to_vec();
-            let id = String::from_utf8(id).unwrap();
-            let id: usize = id.parse().unwrap();
-            ids.push(id);
-        }
+/// Gets the key that lists a purged object's ID as free for reuse.
+fn free_id_key(id: usize) -> Vec<u8> {
+    format!("free-id-{id:020}").into_bytes()
+}
 
-        ids
+/// Writes or removes a field inside of a transaction.
+///
+/// This also keeps the field value index and the contents index of
+/// `location` fields up-to-date.
+pub fn write_field(
+    tx: &TransactionalTree,
+    id: usize,
+    key: &str,
+    val: Option<&Value>,
+) -> Result<(), UnabortableTransactionError> {
+    let field_key = format!("object-field-{id}-{key}");
+
+    let old = match val {
+        Some(val) => tx.insert(field_key.into_bytes(), serde_json::to_vec(val).unwrap())?,
+        None => tx.remove(field_key.into_bytes())?,
+    };
+
+    if val.is_none() {
+        tx.remove(perms::perms_key(id, key))?;
     }
 
-    /// Shows all the fields on an object.
-    pub fn show(&self, id: usize) -> Vec<(String, Value)> {
-        let prefix = format!("object-field-{id}-");
-        let prefix_len = prefix.len();
-        let field_iter = self.tree.scan_prefix(prefix.into_bytes());
+    // keep count of how many fields an object has
+    if old.is_some() != val.is_some() {
+        let count_key = format!("object-fieldcount-{id}");
+        let count = match tx.get(&count_key)? {
+            Some(count) => decode_counter(&count),
+            None => 0,
+        };
 
-        let mut fields = Vec::new();
-        for field in field_iter {
-            let (key, value) = field.unwrap();
-            let key = key[prefix_len..].to_vec();
-            let key = String::from_utf8(key).unwrap();
-            let value = serde_json::from_slice(&value).unwrap();
-            fields.push((key, value));
-        }
+        let count = match val {
+            Some(_) => count + 1,
+            None => count.saturating_sub(1),
+        };
 
-        fields
+        if count == 0 {
+            tx.remove(count_key.into_bytes())?;
+        } else {
+            tx.insert(count_key.into_bytes(), &count.to_be_bytes())?;
+        }
     }
 
-    /// Sets the value of a field.
-    pub fn set(&self, id: usize, key: &str, val: Value) {
-        if !self.exists(id) {
-            return;
-        }
+    let old = old.and_then(|old| serde_json::from_slice::<Value>(&old).ok());
 
-        let key = format!("object-field-{id}-{key}");
-        let val = serde_json::to_vec(&val).unwrap();
-        self.tree.insert(key.into_bytes(), val).unwrap();
+    if let Some(index) = old.as_ref().and_then(|old| value_index_key(id, key, old)) {
+        tx.remove(index)?;
     }
 
-    /// Gets the value of a field.
-    pub fn get(&self, id: usize, key: &str) -> Option<Value> {
-        let val = self.tree.get(format!("object-field-{id}-{key}")).unwrap()?;
-        let val = serde_json::from_slice(&val).unwrap();
-        Some(val)
+    if let Some(index) = val.and_then(|val| value_index_key(id, key, val)) {
+        tx.insert(index, "")?;
     }
 
-    /// Makes a server announcement.
-    pub fn announce(&self, message: &str) {
-        let _ = self.announcement_tx.send(message.to_string());
+    if key == "location" {
+        if let Some(old) = old.and_then(|old| old.as_id()) {
+            tx.remove(format!("index-location-{old}-{id}").into_bytes())?;
+        }
+
+        if let Some(new) = val.and_then(Value::as_id) {
+            tx.insert(format!("index-location-{new}-{id}").into_bytes(), "")?;
+        }
     }
+
+    Ok(())
 }
 
-#[derive(Default)]
-pub struct Commands(HashMap<String, Command>);
+/// The default maximum size of a field value, in bytes.
+pub const DEFAULT_MAX_FIELD_SIZE: usize = 64 * 1024;
 
-impl Commands {
-    pub fn new() -> Self {
-        let mut cmds = Self::default();
+/// The default maximum number of fields on one object.
+pub const DEFAULT_MAX_FIELDS: usize = 256;
 
-        cmds.insert("say", say);
-        cmds.insert("help", help);
-        cmds.insert("@create", create);
-        cmds.insert("@destroy", destroy);
-        cmds.insert("@list", list);
-        cmds.insert("@show", show);
-        cmds.insert("@set", set);
-        cmds.insert("@get", get);
+/// Checks that writing a field keeps its object within the field limits,
+/// inside of a transaction.
+///
+/// The limits are the system object's `max_field_size` and `max_fields`
+/// fields, or [DEFAULT_MAX_FIELD_SIZE] and [DEFAULT_MAX_FIELDS].
+pub fn check_field(
+    tx: &TransactionalTree,
+    id: usize,
+    key: &str,
+    val: &Value,
+) -> Result<(), ConflictableTransactionError<FieldError>> {
+    let get_limit = |key: &str, default: usize| -> Result<usize, UnabortableTransactionError> {
+        let Some(val) = tx.get(format!("object-field-{SYSTEM_OBJECT}-{key}"))? else {
+            return Ok(default);
+        };
 
-        cmds
+        let val: Value = serde_json::from_slice(&val).unwrap();
+        Ok(val.as_id().unwrap_or(default))
+    };
+
+    let max = get_limit("max_field_size", DEFAULT_MAX_FIELD_SIZE)?;
+    let size = serde_json::to_vec(val).unwrap().len();
+    if size > max {
+        return Err(ConflictableTransactionError::Abort(FieldError::TooLarge {
+            size,
+            max,
+        }));
     }
 
-    pub fn insert(&mut self, name: &str, cb: Command) {
-        self.0.insert(name.to_string(), cb);
+    // only new fields count towards the maximum number of fields
+    if tx.get(format!("object-field-{id}-{key}"))?.is_none() {
+        let max = get_limit("max_fields", DEFAULT_MAX_FIELDS)?;
+        let count = match tx.get(format!("object-fieldcount-{id}"))? {
+            Some(count) => decode_counter(&count) as usize,
+            None => 0,
+        };
+
+        if count >= max {
+            return Err(ConflictableTransactionError::Abort(
+                FieldError::TooManyFields { max },
+            ));
+        }
     }
-}
 
-pub struct User {
-    pub state: Arc<State>,
-    object: usize,
-    tx: UnboundedSender<String>,
-    commands: Commands,
-    quit: bool,
+    Ok(())
 }
 
-impl User {
-    pub fn new(state: Arc<State>, mut tcp_tx: WriteHalf<TcpStream>) -> Self {
-        let commands = Commands::new();
-        let object = state.create();
+/// The default number of objects that a player may own.
+pub const DEFAULT_QUOTA: i64 = 50;
 
-        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel::<String>();
+/// The default number of seconds that recycled objects are kept for.
+pub const DEFAULT_RECYCLE_RETENTION: u64 = 7 * 24 * 60 * 60;
 
-        tokio::spawn(async move {
-            while let Some(message) = rx.recv().await {
-                if tcp_tx.write_all(message.as_bytes()).await.is_err()
-                    || tcp_tx.write_all(b"\r\n").await.is_err()
-                {
-                    break;
-                }
-            }
-        });
+/// The fields that guest characters keep when they are reset.
+///
+/// Guests stay owned by whoever made them, so that visitors can't rename or
+/// redescribe them for the next visitor.
+pub const KEPT_GUEST_FIELDS: &[&str] = &["guest", "owner", "name", "description"];
 
-        tokio::spawn({
-            let tx = tx.clone();
-            let mut rx = state.announcement_tx.subscribe();
-            async move {
-                while let Ok(message) = rx.recv().await {
-                    if tx.send(message).is_err() {
-                        break;
-                    }
-                }
-            }
-        });
+/// The default length in bytes of the longest line that clients may send.
+pub const DEFAULT_MAX_LINE_LENGTH: usize = 4096;
 
-        Self {
-            state,
-            tx,
-            commands,
-            quit: false,
-            object,
-        }
-    }
+/// Gets the current UNIX time in seconds.
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
 
-    pub async fn run(mut self, rx: ReadHalf<TcpStream>) {
-        self.message("Welcome to MarcieMOO!");
-        self.message("Type \"help\".");
-        self.message(&format!("You are object #{}.", self.object));
+/// The environment variable listing the addresses to listen on, separated by
+/// commas, like `0.0.0.0:8888,[::1]:8888`. Listening on `[::]` also accepts
+/// IPv4 connections on most systems, so it can't share a port with
+/// `0.0.0.0`.
+pub const BIND_VAR: &str = "MARCIEMOO_BIND";
 
-        let mut reader = BufReader::new(rx);
-        let mut line_buf = String::new();
-        let shutdown = self.state.shutdown_token();
+/// The address that is listened on if [BIND_VAR] isn't set.
+pub const DEFAULT_BIND: &str = "0.0.0.0:8888";
 
-        while !self.quit {
-            line_buf.clear();
+/// The environment variable naming the path of a unix socket to listen on,
+/// whose connections are wizards without logging in. Only the user running
+/// the server may connect to it.
+pub const ADMIN_SOCKET_VAR: &str = "MARCIEMOO_ADMIN_SOCKET";
 
-            tokio::select! {
-                _ = shutdown.cancelled() => {
-                    self.quit = true;
-                }
-                result = reader.read_line(&mut line_buf) => {
-                    if result.is_err() {
-                        self.quit = true;
-                    } else {
-                        self.on_line(line_buf.as_str().trim()).await;
-                    }
-                }
-            };
-        }
+/// The ID of the system object, which holds server-wide verbs and settings.
+///
+/// The system object's fields configure the server:
+/// - `motd`: the message shown to players when they connect, which may span
+///   multiple lines and contain [markup] tags
+/// - `starting_room`: the object new players are placed in
+/// - `default_parent`: the parent given to objects made with `@create` or a
+///   verb's `create()`
+/// - `player_created`: a verb run once for each new character, as `player`,
+///   after they are placed in the starting room
+/// - `default_quota`: how many objects players may own before a wizard sets
+///   their `quota` themselves
+/// - `recycle_retention`: how many seconds destroyed objects are kept in the
+///   recycle bin before they are purged
+/// - `reuse_ids`: whether the IDs of purged objects are given to new objects,
+///   which is off unless it is set to true
+/// - `max_line_length`: the most bytes in a line that clients may send, past
+///   which the rest of the line is discarded
+/// - `idle_timeout`: how many seconds players may go without sending a line
+///   before they are disconnected, which is never unless it is set
+/// - `server_name`: the name that MUD listing sites show for the server
+/// - `maintenance`: whether only wizards may run commands that change the
+///   world and verbs, which is toggled with `@maintenance`
+/// - `prompt`: the prompt for players who haven't set their own `prompt`
+/// - `help_object`: the object whose `help_<topic>` fields hold the pages
+///   shown by `help <topic>`, which is the system object itself by default
+/// - `matrix_max_speakers`: how many objects may speak for Matrix users,
+///   as described by [matrix]
+/// - `matrix_rooms`: the Matrix rooms that chat channels are bridged to, as
+///   described by [matrix]
+/// - `mqtt_topics`: the MQTT topics that events are published to, as
+///   described by [mqtt]
+/// - `censor_mask` and `censor_block`: the patterns censored for everyone,
+///   as described by [censor]
+/// - `home_delay`: how many seconds players may be disconnected before they
+///   are sent home, as described by [home]
+/// - `heartbeat_interval`: how many seconds pass between the heartbeats of
+///   NPCs, as described by [tasks]
+/// - `server_tick_interval`: how many seconds pass between runs of the system
+///   object's `server_tick` verb, as described by [tasks]
+/// - `turn_interval`: how many seconds pass between turns, for groups that
+///   don't set their own, as described by [tasks]
+/// - `max_tasks`: how many suspended verbs each player may have at once, as
+///   described by [tasks]
+/// - `day_length`, `dawn_hour`, and `dusk_hour`: how many seconds an in-game
+///   day lasts and the in-game hours that day and night start at, as
+///   described by [world_clock]
+/// - `world_tick_interval`: how many seconds pass between world ticks, as
+///   described by [world_clock]
+/// - `equipment_slots`: the slots that players wear items in, as described by
+///   [equipment]
+/// - `http_domains` and `http_rate_limit`: the domains that verbs may fetch
+///   pages from and how many pages each object may fetch a minute, as
+///   described by [http]
+/// - `locale`: the locale that players read the server's messages in if they
+///   haven't set their own, as described by [locale]
+///
+/// Any of these may also be set from a file that is reloaded without a
+/// restart, as described by [config].
+///
+/// Its `on_connect` and `on_disconnect` verbs also run for every player.
+pub const SYSTEM_OBJECT: usize = 0;
 
-        self.state.destroy(self.object);
-    }
+pub struct State {
+    db: Db,
+    tree: Tree,
+    shutdown: CancellationToken,
+    events: broadcast::Sender<Event>,
+    wizard_token: String,
 
-    pub async fn on_line(&mut self, line: &str) {
-        let (command, args) = line.split_once(' ').unwrap_or((line, ""));
+    /// The commands that run verbs, loaded from the database at startup.
+    script_commands: RwLock<HashMap<String, ScriptCommand>>,
 
-        match self.commands.0.get(command) {
-            Some(command) => {
-                if let Err(err) = self.exec_command(*command, args) {
-                    let msg = format!("error: {}", err);
-                    self.message(&msg);
-                }
-            }
-            None => {
-                self.exec(command);
-            }
-        }
-    }
+    /// The players that are connected right now.
+    connected: Mutex<HashSet<usize>>,
 
-    pub fn exec_command(&mut self, command: Command, args: &str) -> CommandResult<()> {
-        let args = Arguments::new(args)?;
-        command(self, args)?;
-        Ok(())
-    }
+    /// The wrong passwords tried for each player.
+    login_failures: password::Failures,
 
-    pub fn message(&mut self, text: &str) {
-        if self.tx.send(text.to_string()).is_err() {
-            self.quit = true;
-        }
-    }
+    /// The tokens that hand the session controlling each player over to a
+    /// new connection, by player.
+    sessions: Mutex<HashMap<usize, CancellationToken>>,
 
-    /// Executes a verb.
-    pub fn exec(&mut self, verb: &str) {
-        let output = self
-            .state
-            .tree
-            .transaction::<_, _, ()>(|tx| {
-                let key = format!("object-field-{}-{verb}", self.object);
-                let Some(val) = tx.get(key)? else {
-                    return Ok(ScriptOutput::message("no such verb"));
-                };
+    /// The characters that connections are registering, by the player that
+    /// each connection controls.
+    registrations: Mutex<HashMap<usize, Registration>>,
 
-                let val = serde_json::from_slice(&val).unwrap();
-                let Value::String(src) = val else {
-                    return Ok(ScriptOutput::message("no such verb"));
-                };
+    /// When codes were sent to each address being registered.
+    registration_sends: registration::Sends,
 
-                let runtime = script::Runtime::new(tx, self.object);
-                let output = runtime.run(&src)?;
-                Ok(output)
-            })
-            .unwrap();
+    /// Every connection and disconnection, kept for wizards to review.
+    connection_log: ConnectionLog,
 
-        for announcement in output.announcements {
-            self.state.announce(&announcement);
-        }
+    /// Every announcement, kept for the news feed.
+    news_log: NewsLog,
 
-        for message in output.messages {
-            self.message(&message);
-        }
-    }
-}
+    /// The latest things said in the world, kept for `@recap`.
+    history: ChatHistory,
 
-pub enum CommandError {
-    MissingArgument { index: usize },
-    InvalidArgument { index: usize, expected: String },
-}
+    /// The named socials, which players run by name.
+    socials: SocialTable,
 
-impl Display for CommandError {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            CommandError::MissingArgument { index } => {
-                write!(f, "missing argument at index {index}")
-            }
-            CommandError::InvalidArgument { index, expected } => {
-                write!(f, "invalid argument at index {index} (expected {expected})")
-            }
-        }
-    }
-}
+    /// The translations of the server's messages.
+    translations: Translations,
 
-pub type CommandResult<T> = Result<T, CommandError>;
+    /// The verbs running in the background after suspending.
+    tasks: tasks::Tasks,
 
-#[derive(Clone, Debug, Logos)]
-#[logos(skip r" +")]
-pub enum ArgumentKind {
-    #[regex("[0-9]+")]
-    Integer,
+    /// The compiled Rhai verbs.
+    rhai_verbs: script::VerbCache,
 
-    #[regex("\"[^\"]*\"")]
-    String,
+    /// When the server started, as a UNIX time.
+    started: u64,
 
-    #[regex("[a-zA-Z_]+")]
-    Ident,
+    /// How many commands have been run since the server started.
+    commands_run: AtomicU64,
 
-    #[token("false")]
-    False,
+    /// How many verbs have been run since the server started.
+    scripts_run: AtomicU64,
 
-    #[token("true")]
-    True,
+    /// The TCP ports that the server is listening on.
+    ports: Mutex<Vec<u16>>,
 }
 
-#[derive(Clone, Debug)]
-pub enum Argument {
-    Bool(bool),
-    Integer(i64),
-    String(String),
-    Ident(String),
+/// Figures about the server's health, shown by `@stats`.
+#[derive(Clone, Copy, Debug)]
+pub struct Stats {
+    pub objects: usize,
+
+    /// The size of the database on disk, in bytes.
+    pub db_size: u64,
+    pub connected: usize,
+
+    /// How long the server has been running, in seconds.
+    pub uptime: u64,
+    pub commands_run: u64,
+    pub scripts_run: u64,
 }
 
-pub struct Arguments(Vec<Argument>);
+/// A command that runs a verb, which is added in-game with `@addcommand`.
+///
+/// The verb sees the rest of the command line as `args`.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct ScriptCommand {
+    pub object: usize,
+    pub verb: String,
+}
 
-impl Arguments {
-    pub fn new(words: &str) -> CommandResult<Self> {
-        let mut lexer = ArgumentKind::lexer(words);
-        let mut args = Vec::new();
+/// Gets the key that stores a scripted command.
+fn command_key(name: &str) -> Vec<u8> {
+    format!("command-{name}").into_bytes()
+}
 
-        while let Some(arg) = lexer.next() {
-            let index = args.len();
+impl State {
+    pub fn new(shutdown: CancellationToken) -> Self {
+        Self::open(sled::open("marciemoo.db").unwrap(), shutdown)
+    }
 
-            let arg = if let Ok(arg) = arg {
-                arg
-            } else {
-                return Err(CommandError::InvalidArgument {
-                    index,
-                    expected: "argument".to_string(),
-                });
-            };
+    /// Opens the world kept in a database.
+    pub fn open(db: Db, shutdown: CancellationToken) -> Self {
+        let tree = db.open_tree("").unwrap();
+        let events = broadcast::Sender::new(1024);
+        let connection_log = ConnectionLog::open(&db);
+        let news_log = NewsLog::open(&db);
+        let history = ChatHistory::open(&db);
+        let socials = SocialTable::open(&db);
+        let translations = Translations::open(&db);
 
-            let slice = lexer.slice();
-            args.push(match arg {
-                ArgumentKind::Integer => Argument::Integer(slice.parse().unwrap()),
-                ArgumentKind::String => Argument::String(slice[1..slice.len() - 1].to_string()),
-                ArgumentKind::Ident => Argument::Ident(slice.to_owned()),
-                ArgumentKind::False => Argument::Bool(false),
-                ArgumentKind::True => Argument::Bool(true),
-            });
-        }
+        let state = Self {
+            db,
+            tree,
+            shutdown,
+            events,
+            wizard_token: Self::generate_token(),
+            script_commands: Default::default(),
+            connected: Default::default(),
+            login_failures: Default::default(),
+            sessions: Default::default(),
+            registrations: Default::default(),
+            registration_sends: Default::default(),
+            connection_log,
+            news_log,
+            history,
+            socials,
+            translations,
+            tasks: Default::default(),
+            rhai_verbs: Default::default(),
+            started: now(),
+            commands_run: AtomicU64::new(0),
+            scripts_run: AtomicU64::new(0),
+            ports: Default::default(),
+        };
 
-        Ok(Self(args))
+        state.init_counter();
+        state.init_system_object();
+        state.init_indices();
+        state.init_script_commands();
+        state.init_guests();
+        state
     }
 
-    pub fn get(&self, index: usize) -> CommandResult<Argument> {
-        self.0
-            .get(index)
-            .cloned()
-            .ok_or(CommandError::MissingArgument { index })
-    }
+    /// Builds the field indices for databases created before they existed.
+    fn init_indices(&self) {
+        const VERSION: &str = "3";
 
-    pub fn get_value(&self, index: usize) -> CommandResult<Value> {
-        match self.get(index)? {
-            Argument::Integer(val) => Ok(Value::Integer(val)),
-            Argument::Bool(val) => Ok(Value::Bool(val)),
-            Argument::String(val) => Ok(Value::String(val)),
-            _ => Err(CommandError::InvalidArgument {
-                index,
-                expected: "value".to_string(),
-            }),
+        if self.tree.get("index-version").unwrap().as_deref() == Some(VERSION.as_bytes()) {
+            return;
         }
-    }
+
+        let prefix = "object-exists-";
+        for exists in self.tree.scan_prefix(prefix) {
+            let (key, _val) = exists.unwrap();
+            let id = String::from_utf8(key[prefix.len()..].to_vec()).unwrap();
+            self.tree
+                .insert(id_index_key(id.parse().unwrap()), "")
+                .unwrap();
+        }
+
+        let mut field_counts = HashMap::<usize, u64>::new();
+
+        let prefix = "object-field-";
+        for field in self.tree.scan_prefix(prefix) {
+            let (key, val) = field.unwrap();
+            let key = String::from_utf8(key[prefix.len()..].to_vec()).unwrap();
+            let (id, key) = key.split_once('-').unwrap();
+            let id: usize = id.parse().unwrap();
+            let val: Value = serde_json::from_slice(&val).unwrap();
+            *field_counts.entry(id).or_default() += 1;
+
+            if let Some(index) = value_index_key(id, key, &val) {
+                self.tree.insert(index, "").unwrap();
+            }
+
+            if let Some(location) = val.as_id().filter(|_| key == "location") {
+                let index = format!("index-location-{location}-{id}");
+                self.tree.insert(index.into_bytes(), "").unwrap();
+            }
+        }
+
+        for (id, count) in field_counts {
+            let key = format!("object-fieldcount-{id}");
+            self.tree
+                .insert(key.into_bytes(), &count.to_be_bytes())
+                .unwrap();
+        }
+
+        self.tree.insert("index-version", VERSION).unwrap();
+    }
+
+    /// Converts the object counter of older databases from a decimal string.
+    fn init_counter(&self) {
+        let Some(index) = self.tree.remove("object-index").unwrap() else {
+            return;
+        };
+
+        let index = String::from_utf8(index.to_vec()).unwrap();
+        let index: u64 = index.parse().unwrap();
+        let index = index.to_be_bytes().to_vec();
+        self.tree.insert("object-counter", index).unwrap();
+    }
+
+    /// Creates the system object if it does not exist yet.
+    fn init_system_object(&self) {
+        // make sure that no other object is ever given the system object's ID
+        self.tree
+            .fetch_and_update("object-counter", |counter| {
+                let next = counter.map(decode_counter).unwrap_or(0);
+                let next = next.max(SYSTEM_OBJECT as u64 + 1);
+                Some(next.to_be_bytes().to_vec())
+            })
+            .unwrap();
+
+        self.tree
+            .transaction::<_, _, ()>(|tx| {
+                let key = format!("object-exists-{SYSTEM_OBJECT}");
+                if tx.get(&key)?.is_some() {
+                    return Ok(());
+                }
+
+                tx.insert(key.into_bytes(), "")?;
+                tx.insert(id_index_key(SYSTEM_OBJECT), "")?;
+
+                let name = serde_json::to_vec(&Value::String("System Object".into())).unwrap();
+                let key = format!("object-field-{SYSTEM_OBJECT}-name");
+                tx.insert(key.into_bytes(), name)?;
+
+                Ok(())
+            })
+            .unwrap();
+    }
+
+    /// Gathers figures about the server's health for `@stats`.
+    pub fn stats(&self) -> Stats {
+        Stats {
+            objects: self.list().len(),
+            db_size: self.db.size_on_disk().unwrap_or_default(),
+            connected: self.connected.lock().unwrap().len(),
+            uptime: now().saturating_sub(self.started),
+            commands_run: self.commands_run.load(Ordering::Relaxed),
+            scripts_run: self.scripts_run.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Records that the server is listening on a TCP port.
+    pub fn add_port(&self, port: u16) {
+        self.ports.lock().unwrap().push(port);
+    }
+
+    /// Lists the TCP ports that the server is listening on.
+    pub fn ports(&self) -> Vec<u16> {
+        self.ports.lock().unwrap().clone()
+    }
+
+    /// Gets the name of the server that is given to MUD listing sites.
+    pub fn server_name(&self) -> String {
+        self.get(SYSTEM_OBJECT, "server_name")
+            .and_then(|name| name.as_string().cloned())
+            .unwrap_or_else(|| "MarcieMOO".to_string())
+    }
+
+    /// Counts a command run by any user.
+    pub fn count_command(&self) {
+        self.commands_run.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Shuts the server down, disconnecting everyone.
+    pub fn shutdown(&self) {
+        self.shutdown.cancel();
+    }
+
+    /// Gets the verbs running in the background after suspending.
+    pub fn tasks(&self) -> &tasks::Tasks {
+        &self.tasks
+    }
+
+    /// Retrieves a child [CancellationToken] for this state.
+    pub fn shutdown_token(&self) -> CancellationToken {
+        self.shutdown.child_token()
+    }
+
+    /// Writes everything in the database to disk, returning how many bytes
+    /// were written.
+    pub async fn checkpoint(&self) -> sled::Result<usize> {
+        self.db.flush_async().await
+    }
+
+    /// Creates a new object, and returns its new ID.
+    pub fn create(&self) -> usize {
+        let id = self.allocate_id();
+
+        self.tree
+            .transaction::<_, _, ()>(|tx| {
+                tx.insert(format!("object-exists-{id}").into_bytes(), "")?;
+                tx.insert(id_index_key(id), "")?;
+                Ok(())
+            })
+            .unwrap();
+
+        self.publish(Event::Create { object: id });
+        id
+    }
+
+    /// Atomically creates an object owned by `owner`, whose parent is the
+    /// system object's `default_parent` if that names an object. Nothing is
+    /// created if its fields can't be set.
+    pub fn create_owned(&self, owner: usize) -> Result<usize, FieldError> {
+        let mut fields = vec![("owner".to_string(), Value::Integer(owner as i64))];
+
+        let parent = self.get(SYSTEM_OBJECT, "default_parent");
+        if let Some(parent) = parent.and_then(|parent| parent.as_id()) {
+            if self.exists(parent) {
+                fields.push(("parent".to_string(), Value::Integer(parent as i64)));
+            }
+        }
+
+        let id = self.allocate_id();
+        let result = self.tree.transaction(|tx| {
+            tx.insert(format!("object-exists-{id}").into_bytes(), "")?;
+            tx.insert(id_index_key(id), "")?;
+
+            for (key, val) in fields.iter() {
+                check_field(tx, id, key, val)?;
+                write_field(tx, id, key, Some(val))?;
+            }
+
+            Ok(())
+        });
+
+        match result {
+            Ok(()) => {
+                self.publish(Event::Create { object: id });
+                Ok(id)
+            }
+            Err(TransactionError::Abort(err)) => {
+                self.release_id(id);
+                Err(err)
+            }
+            Err(TransactionError::Storage(err)) => panic!("storage error: {err}"),
+        }
+    }
+
+    /// Atomically creates a copy of an object with all of its fields, owned by
+    /// `owner`, and returns the copy's ID.
+    ///
+    /// Only the fields that the new owner may read are copied, and never
+    /// [protected fields](perms::PROTECTED_FIELDS), so that cloning can't
+    /// mint coins or make wizards, guests, or consoles.
+    pub fn clone_object(&self, id: usize, owner: usize) -> Option<usize> {
+        let mut keys: Vec<String> = self.show(id).into_iter().map(|(key, _val)| key).collect();
+        keys.retain(|key| !perms::PROTECTED_FIELDS.contains(&key.as_str()));
+
+        let clone = self.allocate_id();
+
+        let result = self.tree.transaction(|tx| {
+            if tx.get(format!("object-exists-{id}"))?.is_none() {
+                return abort(());
+            }
+
+            tx.insert(format!("object-exists-{clone}").into_bytes(), "")?;
+            tx.insert(id_index_key(clone), "")?;
+
+            // fields are read again here, in case they changed since listing
+            for key in keys.iter() {
+                if !perms::can_read(tx, owner, id, key)? {
+                    continue;
+                }
+
+                let Some(val) = tx.get(format!("object-field-{id}-{key}"))? else {
+                    continue;
+                };
+
+                let val: Value = serde_json::from_slice(&val).unwrap();
+                write_field(tx, clone, key, Some(&val))?;
+            }
+
+            write_field(tx, clone, "owner", Some(&Value::Integer(owner as i64)))?;
+            write_field(tx, clone, "parent", Some(&Value::Integer(id as i64)))?;
+
+            Ok(())
+        });
+
+        if result.is_err() {
+            self.release_id(clone);
+            return None;
+        }
+
+        self.publish(Event::Create { object: clone });
+        Some(clone)
+    }
+
+    /// Gets the number of objects that a player may still create.
+    pub fn quota(&self, player: usize) -> i64 {
+        self.get(player, "quota")
+            .or_else(|| self.get(SYSTEM_OBJECT, "default_quota"))
+            .and_then(|quota| quota.as_integer())
+            .unwrap_or(DEFAULT_QUOTA)
+    }
+
+    /// Atomically adds to a player's quota, failing if it would go negative.
+    pub fn adjust_quota(&self, player: usize, delta: i64) -> bool {
+        let default = self
+            .get(SYSTEM_OBJECT, "default_quota")
+            .and_then(|quota| quota.as_integer())
+            .unwrap_or(DEFAULT_QUOTA);
+
+        self.tree
+            .transaction(|tx| {
+                let quota = match tx.get(format!("object-field-{player}-quota"))? {
+                    Some(quota) => serde_json::from_slice::<Value>(&quota).unwrap(),
+                    None => Value::Integer(default),
+                };
+
+                let quota = quota.as_integer().unwrap_or(default) + delta;
+                if quota < 0 {
+                    return abort(());
+                }
+
+                write_field(tx, player, "quota", Some(&Value::Integer(quota)))?;
+                Ok(())
+            })
+            .is_ok()
+    }
+
+    /// Tests if an object exists by ID.
+    pub fn exists(&self, id: usize) -> bool {
+        self.tree
+            .contains_key(format!("object-exists-{id}"))
+            .unwrap()
+    }
+
+    /// Atomically destroys an object by ID, moving it into the recycle bin.
+    ///
+    /// Recycled objects keep their fields until they are purged, either by
+    /// [State::purge_expired] or immediately if the recycle retention is zero.
+    pub fn destroy(&self, id: usize) -> bool {
+        if self.recycle_retention() == 0 {
+            return self.purge(id);
+        }
+
+        if !self.remove_from_world(id) {
+            return false;
+        }
+
+        self.unset(id, "location");
+
+        let key = format!("object-recycled-{id}");
+        self.tree
+            .insert(key.into_bytes(), &now().to_be_bytes())
+            .unwrap();
+        true
+    }
+
+    /// Permanently destroys an object, whether it is recycled or not.
+    pub fn purge(&self, id: usize) -> bool {
+        let recycled = self.tree.remove(format!("object-recycled-{id}")).unwrap();
+
+        if !self.remove_from_world(id) && recycled.is_none() {
+            // either this object is already destroyed or another thread is
+            // currently destroying it, so we can exit
+            return false;
+        }
+
+        if let Some(owner) = self.get(id, "owner").and_then(|owner| owner.as_id()) {
+            self.adjust_quota(owner, 1);
+        }
+
+        for (key, _val) in self.show(id) {
+            self.unset(id, &key);
+        }
+
+        self.tree.remove(password_key(id)).unwrap();
+        self.tree.remove(ssh_keys_key(id)).unwrap();
+
+        // whoever got the ID next would own everything left behind
+        if !self.owns_anything(id) {
+            self.release_id(id);
+        }
+
+        true
+    }
+
+    /// Tests if any object is still owned by an object.
+    fn owns_anything(&self, id: usize) -> bool {
+        self.owned_by(id).into_iter().any(|object| object != id)
+    }
+
+    /// Allocates the ID of a new object.
+    ///
+    /// This reuses the lowest free ID if there is one, and otherwise takes
+    /// the next ID from a big-endian counter without blocking on a
+    /// transaction.
+    fn allocate_id(&self) -> usize {
+        if let Some(key) = self.free_id() {
+            // removal is atomic, so only one caller can take each free ID
+            if self.tree.remove(&key).unwrap().is_some() {
+                let id = String::from_utf8(key["free-id-".len()..].to_vec()).unwrap();
+                return id.parse().unwrap();
+            }
+        }
+
+        let id = self
+            .tree
+            .fetch_and_update("object-counter", |counter| {
+                let next = counter.map(decode_counter).unwrap_or(0) + 1;
+                Some(next.to_be_bytes().to_vec())
+            })
+            .unwrap();
+
+        id.as_deref().map(decode_counter).unwrap_or(0) as usize
+    }
+
+    /// Makes an unused ID available to new objects, if IDs are reused.
+    fn release_id(&self, id: usize) {
+        if self.reuse_ids() {
+            self.tree.insert(free_id_key(id), "").unwrap();
+        }
+    }
+
+    /// Tests if the IDs of purged objects are reused for new objects.
+    ///
+    /// This is enabled by setting the system object's `reuse_ids` field to
+    /// true. Otherwise an ID always refers to the same object, so that
+    /// friends, gags, and other fields naming a purged object by ID can't
+    /// come to name a new one instead.
+    pub fn reuse_ids(&self) -> bool {
+        matches!(
+            self.get(SYSTEM_OBJECT, "reuse_ids"),
+            Some(Value::Bool(true))
+        )
+    }
+
+    /// Finds the free list key of the lowest purged ID, if IDs are reused.
+    fn free_id(&self) -> Option<IVec> {
+        if !self.reuse_ids() {
+            return None;
+        }
+
+        let (key, _val) = self.tree.scan_prefix("free-id-").next()?.unwrap();
+        Some(key)
+    }
+
+    /// Restores a recycled object into existence.
+    pub fn undelete(&self, id: usize) -> bool {
+        let key = format!("object-recycled-{id}");
+        if self.tree.remove(key).unwrap().is_none() {
+            return false;
+        }
+
+        self.tree
+            .insert(format!("object-exists-{id}").into_bytes(), "")
+            .unwrap();
+        self.tree.insert(id_index_key(id), "").unwrap();
+        self.publish(Event::Create { object: id });
+        true
+    }
+
+    /// Lists the recycled objects and the UNIX times they were destroyed at.
+    pub fn recycled(&self) -> Vec<(usize, u64)> {
+        let prefix = "object-recycled-";
+
+        let mut recycled = Vec::new();
+        for entry in self.tree.scan_prefix(prefix) {
+            let (key, val) = entry.unwrap();
+            let id = String::from_utf8(key[prefix.len()..].to_vec()).unwrap();
+            let at = u64::from_be_bytes(val.as_ref().try_into().unwrap());
+            recycled.push((id.parse().unwrap(), at));
+        }
+
+        recycled.sort();
+        recycled
+    }
+
+    /// Gets how many seconds recycled objects are kept before being purged.
+    pub fn recycle_retention(&self) -> u64 {
+        self.get(SYSTEM_OBJECT, "recycle_retention")
+            .and_then(|retention| retention.as_integer())
+            .and_then(|retention| retention.try_into().ok())
+            .unwrap_or(DEFAULT_RECYCLE_RETENTION)
+    }
+
+    /// Gets the most bytes that a line sent by a client may hold.
+    pub fn max_line_length(&self) -> usize {
+        self.get(SYSTEM_OBJECT, "max_line_length")
+            .and_then(|max| max.as_integer())
+            .and_then(|max| max.try_into().ok())
+            .filter(|max| *max > 0)
+            .unwrap_or(DEFAULT_MAX_LINE_LENGTH)
+    }
+
+    /// Tests if the server is in maintenance mode, when only wizards may
+    /// change anything.
+    pub fn in_maintenance(&self) -> bool {
+        matches!(
+            self.get(SYSTEM_OBJECT, "maintenance"),
+            Some(Value::Bool(true))
+        )
+    }
+
+    /// Purges the recycled objects that have outlived the recycle retention.
+    pub fn purge_expired(&self) {
+        let retention = self.recycle_retention();
+        let now = now();
+
+        for (id, at) in self.recycled() {
+            if now.saturating_sub(at) >= retention {
+                self.purge(id);
+            }
+        }
+    }
+
+    /// Atomically removes an object from the list of existing objects and
+    /// empties out its contents, returning false if it did not exist.
+    fn remove_from_world(&self, id: usize) -> bool {
+        let key = format!("object-exists-{id}");
+
+        if self.tree.remove(key).unwrap().is_none() {
+            return false;
+        }
+
+        self.tree.remove(id_index_key(id)).unwrap();
+
+        for content in self.contents(id) {
+            self.unset(content, "location");
+        }
+
+        self.publish(Event::Destroy { object: id });
+        true
+    }
+
+    /// Removes a field from an object, returning false if it was not set.
+    pub fn unset(&self, id: usize, key: &str) -> bool {
+        self.tree
+            .transaction::<_, _, ()>(|tx| {
+                let existed = tx.get(format!("object-field-{id}-{key}"))?.is_some();
+                write_field(tx, id, key, None)?;
+                Ok(existed)
+            })
+            .unwrap()
+    }
+
+    /// Finds the objects with a field matching a pattern.
+    ///
+    /// Patterns are case-insensitive and match any part of the value unless
+    /// they contain [glob] wildcards, in which case they match the whole
+    /// value. Values longer than [MAX_INDEXED_LEN] cannot be found.
+    pub fn find(&self, field: &str, pattern: &str) -> Vec<usize> {
+        let pattern = pattern.to_lowercase();
+        let is_glob = glob::is_glob(&pattern);
+
+        let mut prefix = format!("index-field-{field}\0").into_bytes();
+        let prefix_len = prefix.len();
+
+        // globs can narrow down the scan to values with their literal prefix
+        if is_glob {
+            prefix.extend_from_slice(glob::literal_prefix(&pattern).as_bytes());
+        }
+
+        let mut ids = Vec::new();
+        for entry in self.tree.scan_prefix(prefix) {
+            let (key, _value) = entry.unwrap();
+            let key = String::from_utf8(key[prefix_len..].to_vec()).unwrap();
+            let (value, id) = key.rsplit_once('\0').unwrap();
+
+            let hit = if is_glob {
+                glob::matches(&pattern, value)
+            } else {
+                value.contains(&pattern)
+            };
+
+            if hit {
+                ids.push(id.parse().unwrap());
+            }
+        }
+
+        ids.sort();
+        ids.dedup();
+        ids.retain(|id| self.exists(*id));
+        ids
+    }
+
+    /// Lists the objects whose location is the given object.
+    pub fn contents(&self, id: usize) -> Vec<usize> {
+        let prefix = format!("index-location-{id}-");
+        let prefix_len = prefix.len();
+
+        let mut ids = Vec::new();
+        for entry in self.tree.scan_prefix(prefix.into_bytes()) {
+            let (key, _value) = entry.unwrap();
+            let content = String::from_utf8(key[prefix_len..].to_vec()).unwrap();
+            ids.push(content.parse().unwrap());
+        }
+
+        ids
+    }
+
+    /// Lists all of the objects.
+    pub fn list(&self) -> Vec<usize> {
+        self.list_range(0, usize::MAX)
+    }
+
+    /// Lists the objects with IDs in an inclusive range, in order.
+    pub fn list_range(&self, from: usize, to: usize) -> Vec<usize> {
+        let prefix_len = "index-id-".len();
+        let range = self.tree.range(id_index_key(from)..=id_index_key(to));
+
+        let mut ids = Vec::new();
+        for entry in range {
+            let (key, _value) = entry.unwrap();
+            let id = String::from_utf8(key[prefix_len..].to_vec()).unwrap();
+            ids.push(id.parse().unwrap());
+        }
+
+        ids
+    }
+
+    /// Lists the objects owned by an object, in order.
+    pub fn owned_by(&self, owner: usize) -> Vec<usize> {
+        let prefix = format!("index-field-owner\0{owner}\0");
+        let prefix_len = prefix.len();
+
+        let mut ids: Vec<usize> = Vec::new();
+        for entry in self.tree.scan_prefix(prefix.into_bytes()) {
+            let (key, _value) = entry.unwrap();
+            let id = String::from_utf8(key[prefix_len..].to_vec()).unwrap();
+            ids.push(id.parse().unwrap());
+        }
+
+        ids.sort();
+        ids.retain(|id| self.exists(*id));
+        ids
+    }
+
+    /// Shows all the fields on an object.
+    pub fn show(&self, id: usize) -> Vec<(String, Value)> {
+        let prefix = format!("object-field-{id}-");
+        let prefix_len = prefix.len();
+        let field_iter = self.tree.scan_prefix(prefix.into_bytes());
+
+        let mut fields = Vec::new();
+        for field in field_iter {
+            let (key, value) = field.unwrap();
+            let key = key[prefix_len..].to_vec();
+            let key = String::from_utf8(key).unwrap();
+            let value = serde_json::from_slice(&value).unwrap();
+            fields.push((key, value));
+        }
+
+        fields
+    }
+
+    /// Sets the value of a field.
+    pub fn set(&self, id: usize, key: &str, val: Value) -> Result<(), FieldError> {
+        self.set_many(id, vec![(key.to_string(), val)])
+    }
+
+    /// Atomically sets the values of several fields at once.
+    pub fn set_many(&self, id: usize, fields: Vec<(String, Value)>) -> Result<(), FieldError> {
+        let result = self.tree.transaction(|tx| {
+            if tx.get(format!("object-exists-{id}"))?.is_none() {
+                return abort(FieldError::NoSuchObject);
+            }
+
+            for (key, val) in fields.iter() {
+                check_field(tx, id, key, val)?;
+                write_field(tx, id, key, Some(val))?;
+            }
+
+            Ok(())
+        });
+
+        match result {
+            Ok(()) => Ok(()),
+            Err(TransactionError::Abort(err)) => Err(err),
+            Err(TransactionError::Storage(err)) => panic!("storage error: {err}"),
+        }
+    }
+
+    /// Gets the value of a field.
+    pub fn get(&self, id: usize, key: &str) -> Option<Value> {
+        let val = self.tree.get(format!("object-field-{id}-{key}")).unwrap()?;
+        let val = serde_json::from_slice(&val).unwrap();
+        Some(val)
+    }
+
+    /// Gets the location of an object, if it has one.
+    pub fn location(&self, id: usize) -> Option<usize> {
+        self.get(id, "location")?.as_id()
+    }
+
+    /// Gets where an exit leads, from its `destination` field. Objects
+    /// without one aren't exits.
+    pub fn destination(&self, id: usize) -> Option<usize> {
+        self.get(id, "destination")?.as_id()
+    }
+
+    /// Tests if an object can be seen by a viewer. Objects whose `invisible`
+    /// field is true are only seen by those who control them.
+    pub fn is_visible(&self, viewer: usize, id: usize) -> bool {
+        !matches!(self.get(id, "invisible"), Some(Value::Bool(true))) || self.controls(viewer, id)
+    }
+
+    /// Gets the contents of an object that a viewer can see. Nothing in an
+    /// object whose `dark` or `closed` field is true is seen, except by those
+    /// who control it, and invisible objects are left out.
+    pub fn visible_contents(&self, viewer: usize, id: usize) -> Vec<usize> {
+        let hidden = ["dark", "closed"]
+            .into_iter()
+            .any(|flag| matches!(self.get(id, flag), Some(Value::Bool(true))));
+
+        if hidden && !self.controls(viewer, id) {
+            return Vec::new();
+        }
+
+        let mut contents = self.contents(id);
+        contents.retain(|content| self.is_visible(viewer, *content));
+        contents
+    }
+
+    /// Gets the exits out of a room, which are the objects in it that have a
+    /// destination.
+    pub fn exits(&self, room: usize) -> Vec<usize> {
+        let mut exits = self.contents(room);
+        exits.retain(|id| self.destination(*id).is_some());
+        exits
+    }
+
+    /// Atomically moves an object into a destination, returning its previous
+    /// location.
+    pub fn move_object(&self, id: usize, dest: usize) -> Result<Option<usize>, MoveError> {
+        let result = self.tree.transaction(|tx| {
+            if tx.get(format!("object-exists-{id}"))?.is_none() {
+                return abort(MoveError::NoSuchObject);
+            }
+
+            if tx.get(format!("object-exists-{dest}"))?.is_none() {
+                return abort(MoveError::NoSuchDestination);
+            }
+
+            let get_field =
+                |id: usize, key: &str| -> Result<Option<Value>, UnabortableTransactionError> {
+                    let Some(val) = tx.get(format!("object-field-{id}-{key}"))? else {
+                        return Ok(None);
+                    };
+
+                    Ok(Some(serde_json::from_slice(&val).unwrap()))
+                };
+
+            let get_location = |id: usize| -> Result<Option<usize>, UnabortableTransactionError> {
+                Ok(get_field(id, "location")?.and_then(|val| val.as_id()))
+            };
+
+            // walk up the destination's locations to refuse moving an object
+            // into itself or into something it contains
+            let mut cursor = Some(dest);
+            while let Some(location) = cursor {
+                if location == id {
+                    return abort(MoveError::Recursive);
+                }
+
+                cursor = get_location(location)?;
+            }
+
+            let from = get_location(id)?;
+
+            // worn items stay on their wearer until they are taken off
+            let slot = get_field(id, equipment::SLOT_FIELD)?;
+            let slot = slot.as_ref().and_then(Value::as_string);
+            if let (Some(from), Some(slot)) = (from, slot) {
+                let slot = equipment::slot_key(&slot.trim().to_lowercase());
+                if from != dest && get_field(from, &slot)?.and_then(|val| val.as_id()) == Some(id) {
+                    return abort(MoveError::Worn);
+                }
+            }
+
+            write_field(tx, id, "location", Some(&Value::Integer(dest as i64)))?;
+            Ok(from)
+        });
+
+        let from = match result {
+            Ok(from) => from,
+            Err(TransactionError::Abort(err)) => return Err(err),
+            Err(TransactionError::Storage(err)) => panic!("storage error: {err}"),
+        };
+
+        self.publish(Event::Move {
+            object: id,
+            from,
+            to: dest,
+        });
+
+        Ok(from)
+    }
+
+    /// Atomically moves coins from one object's `coins` to another's, so that
+    /// racing transfers can't spend the same coins twice.
+    pub fn transfer(&self, from: usize, to: usize, amount: i64) -> Result<(), TransferError> {
+        if amount <= 0 {
+            return Err(TransferError::InvalidAmount);
+        }
+
+        let result = self.tree.transaction(|tx| {
+            let get_coins =
+                |id: usize| -> Result<i64, ConflictableTransactionError<TransferError>> {
+                    if tx.get(format!("object-exists-{id}"))?.is_none() {
+                        return abort(TransferError::NoSuchObject);
+                    }
+
+                    let Some(val) = tx.get(format!("object-field-{id}-{COINS_FIELD}"))? else {
+                        return Ok(0);
+                    };
+
+                    let val: Value = serde_json::from_slice(&val).unwrap();
+                    Ok(val.as_integer().unwrap_or(0))
+                };
+
+            let balance = get_coins(from)?;
+            let received = get_coins(to)?;
+
+            if balance < amount {
+                return abort(TransferError::NotEnough { balance });
+            }
+
+            if from != to {
+                let Some(received) = received.checked_add(amount) else {
+                    return abort(TransferError::InvalidAmount);
+                };
+
+                write_field(
+                    tx,
+                    from,
+                    COINS_FIELD,
+                    Some(&Value::Integer(balance - amount)),
+                )?;
+                write_field(tx, to, COINS_FIELD, Some(&Value::Integer(received)))?;
+            }
+
+            Ok(())
+        });
+
+        match result {
+            Ok(()) => Ok(()),
+            Err(TransactionError::Abort(err)) => Err(err),
+            Err(TransactionError::Storage(err)) => panic!("storage error: {err}"),
+        }
+    }
+
+    /// Gets the name of an object, if it has one.
+    pub fn name(&self, id: usize) -> Option<String> {
+        self.get(id, "name")
+            .and_then(|name| name.as_string().cloned())
+    }
+
+    /// Formats an object's ID along with its name, if it has one.
+    pub fn display_name(&self, id: usize) -> String {
+        match self.name(id) {
+            Some(name) => format!("#{id} ({name})"),
+            None => format!("#{id}"),
+        }
+    }
+
+    /// Runs a permission check from [perms] against the database.
+    fn check<T>(
+        &self,
+        check: impl Fn(&TransactionalTree) -> Result<T, UnabortableTransactionError>,
+    ) -> T {
+        self.tree
+            .transaction::<_, _, ()>(|tx| Ok(check(tx)?))
+            .unwrap()
+    }
+
+    /// Tests if an object is a wizard.
+    pub fn is_wizard(&self, who: usize) -> bool {
+        self.check(|tx| perms::is_wizard(tx, who))
+    }
+
+    /// Gets the owner of an object, which owns itself if nobody else does.
+    pub fn owner(&self, id: usize) -> usize {
+        self.check(|tx| perms::owner(tx, id))
+    }
+
+    /// Tests if an object may act as the owner of another object.
+    pub fn controls(&self, who: usize, id: usize) -> bool {
+        self.check(|tx| perms::controls(tx, who, id))
+    }
+
+    /// Tests if an object may read a field.
+    pub fn can_read(&self, who: usize, id: usize, key: &str) -> bool {
+        self.check(|tx| perms::can_read(tx, who, id, key))
+    }
+
+    /// Tests if an object may write a field.
+    pub fn can_write(&self, who: usize, id: usize, key: &str) -> bool {
+        self.check(|tx| perms::can_write(tx, who, id, key))
+    }
+
+    /// Gets the permission flags on a field.
+    pub fn field_perms(&self, id: usize, key: &str) -> FieldPerms {
+        self.check(|tx| perms::field_perms(tx, id, key))
+    }
+
+    /// Sets the permission flags on a field.
+    pub fn chmod(&self, id: usize, key: &str, perms: FieldPerms) {
+        let flags = perms.to_string();
+        self.tree
+            .insert(perms::perms_key(id, key), flags.as_bytes())
+            .unwrap();
+    }
+
+    /// Generates a random token of letters, like the one that lets its holder
+    /// become a wizard.
+    fn generate_token() -> String {
+        let mut rng = rand::rngs::OsRng;
+        (0..16).map(|_| rng.gen_range('a'..='z')).collect()
+    }
+
+    /// Gets the token that lets its holder become a wizard with `@bootstrap`.
+    ///
+    /// The token is regenerated every time the server starts, and is printed
+    /// to the operator's terminal.
+    pub fn wizard_token(&self) -> &str {
+        &self.wizard_token
+    }
+
+    /// Finds the objects near a player that a name could refer to.
+    ///
+    /// `me` and `here` refer to the player and their location. Otherwise,
+    /// the player's inventory, their location, and the location's contents
+    /// are searched, preferring exact names over partial matches. Objects
+    /// that the player can't see aren't matched.
+    pub fn match_object(&self, player: usize, name: &str) -> Vec<usize> {
+        let name = name.trim().to_lowercase();
+        let location = self.location(player);
+
+        match name.as_str() {
+            "me" => return vec![player],
+            "here" => return location.into_iter().collect(),
+            _ => {}
+        }
+
+        let mut candidates = self.contents(player);
+        if let Some(location) = location {
+            candidates.push(location);
+            candidates.extend(self.contents(location));
+        }
+
+        candidates.sort();
+        candidates.dedup();
+        candidates.retain(|id| self.is_visible(player, *id));
+
+        let named: Vec<_> = candidates
+            .into_iter()
+            .filter_map(|id| Some((id, self.name(id)?.to_lowercase())))
+            .collect();
+
+        let exact: Vec<_> = named
+            .iter()
+            .filter(|(_, candidate)| *candidate == name)
+            .map(|(id, _)| *id)
+            .collect();
+
+        if !exact.is_empty() {
+            return exact;
+        }
+
+        named
+            .iter()
+            .filter(|(_, candidate)| {
+                candidate.starts_with(&name)
+                    || candidate
+                        .split_whitespace()
+                        .any(|word| word.starts_with(&name))
+            })
+            .map(|(id, _)| *id)
+            .collect()
+    }
+
+    /// Runs a verb on the host's object.
+    ///
+    /// Returns `None` if the object has no such verb. This blocks until the
+    /// verb finishes.
+    pub fn run_verb(&self, host: &Host, verb: &str) -> Option<ScriptOutput> {
+        let Some(Value::String(src)) = self.get(host.self_id, verb) else {
+            return None;
+        };
+
+        self.scripts_run.fetch_add(1, Ordering::Relaxed);
+        Some(script::run(host, verb, &src))
+    }
+
+    /// Gets the scripted command with a name. Names are lowercase.
+    pub fn script_command(&self, name: &str) -> Option<ScriptCommand> {
+        self.script_commands.read().unwrap().get(name).cloned()
+    }
+
+    /// Gets the named social with a name. Names are lowercase.
+    pub fn social(&self, name: &str) -> Option<Social> {
+        self.socials.get(name)
+    }
+
+    /// Adds or replaces a named social.
+    pub fn set_social(&self, name: &str, social: &Social) {
+        self.socials.set(name, social);
+    }
+
+    /// Removes a named social, returning false if there was none.
+    pub fn remove_social(&self, name: &str) -> bool {
+        self.socials.remove(name)
+    }
+
+    /// Lists every named social, sorted by name.
+    pub fn socials(&self) -> Vec<(String, Social)> {
+        self.socials.list()
+    }
+
+    /// Gets the translation of a message into a locale, if it has one.
+    pub fn translation(&self, locale: &str, key: &str) -> Option<String> {
+        self.translations.get(locale, key)
+    }
+
+    /// Adds or replaces the translation of a message.
+    pub fn set_translation(&self, locale: &str, key: &str, text: &str) {
+        self.translations.set(locale, key, text);
+    }
+
+    /// Removes the translation of a message, returning false if there was
+    /// none.
+    pub fn remove_translation(&self, locale: &str, key: &str) -> bool {
+        self.translations.remove(locale, key)
+    }
+
+    /// Lists the translations into a locale, sorted by key.
+    pub fn translations(&self, locale: &str) -> Vec<(String, String)> {
+        self.translations.list(locale)
+    }
+
+    /// Lists every scripted command, sorted by name.
+    pub fn script_commands(&self) -> Vec<(String, ScriptCommand)> {
+        let commands = self.script_commands.read().unwrap();
+        let mut commands: Vec<_> = commands
+            .iter()
+            .map(|(name, command)| (name.clone(), command.clone()))
+            .collect();
+
+        commands.sort_by(|a, b| a.0.cmp(&b.0));
+        commands
+    }
+
+    /// Adds or replaces a scripted command.
+    pub fn add_command(&self, name: &str, command: ScriptCommand) {
+        let val = serde_json::to_vec(&command).unwrap();
+        self.tree.insert(command_key(name), val).unwrap();
+
+        let mut commands = self.script_commands.write().unwrap();
+        commands.insert(name.to_string(), command);
+    }
+
+    /// Removes a scripted command, returning false if it did not exist.
+    pub fn remove_command(&self, name: &str) -> bool {
+        self.tree.remove(command_key(name)).unwrap();
+        let mut commands = self.script_commands.write().unwrap();
+        commands.remove(name).is_some()
+    }
+
+    /// Lists the guest characters, which are the objects whose `guest` field
+    /// is true.
+    pub fn guests(&self) -> Vec<usize> {
+        let mut guests = self.find("guest", "true");
+        guests.retain(|id| self.is_guest(*id));
+        guests
+    }
+
+    /// Tests if an object is a guest character.
+    pub fn is_guest(&self, id: usize) -> bool {
+        matches!(self.get(id, "guest"), Some(Value::Bool(true)))
+    }
+
+    /// Picks the player object for a new connection.
+    ///
+    /// Connections are given a free guest character if any guests exist, or
+    /// nothing if every guest is in use. Otherwise, a new object is created
+    /// for them.
+    pub fn new_player(&self) -> Option<usize> {
+        let guests = self.guests();
+        if guests.is_empty() {
+            let player = self.create();
+            self.claim_player(player);
+            return Some(player);
+        }
+
+        guests.into_iter().find(|guest| self.claim_player(*guest))
+    }
+
+    /// Tests if an object is the player of the operator's console.
+    pub fn is_console(&self, id: usize) -> bool {
+        matches!(self.get(id, "console"), Some(Value::Bool(true)))
+    }
+
+    /// Gets the wizard that the operator's console controls, creating it the
+    /// first time that the console is opened.
+    pub fn console_player(&self) -> usize {
+        let consoles = self.find("console", "true");
+        if let Some(id) = consoles.into_iter().find(|id| self.is_console(*id)) {
+            return id;
+        }
+
+        let id = self.create();
+        self.set(id, "name", Value::String("Console".to_string()))
+            .unwrap();
+        self.set(id, "console", Value::Bool(true)).unwrap();
+        self.set(id, "wizard", Value::Bool(true)).unwrap();
+        id
+    }
+
+    /// Creates a wizard for a connection to the admin socket. Like any player
+    /// without a password, it is purged once it disconnects.
+    pub fn new_admin(&self) -> usize {
+        let id = self.create();
+        self.set(id, "name", Value::String("Admin".to_string()))
+            .unwrap();
+        self.set(id, "wizard", Value::Bool(true)).unwrap();
+        self.claim_player(id);
+        id
+    }
+
+    /// Lists the players who are connected.
+    pub fn connected(&self) -> Vec<usize> {
+        let mut connected: Vec<_> = self.connected.lock().unwrap().iter().copied().collect();
+        connected.sort();
+        connected
+    }
+
+    /// Gets how long players may go without sending a line before they are
+    /// disconnected, if they ever are.
+    pub fn idle_timeout(&self) -> Option<Duration> {
+        let timeout = self.get(SYSTEM_OBJECT, "idle_timeout");
+        let timeout = timeout.and_then(|timeout| timeout.as_integer());
+        let timeout = timeout.and_then(|timeout| u64::try_from(timeout).ok());
+        timeout
+            .filter(|timeout| *timeout > 0)
+            .map(Duration::from_secs)
+    }
+
+    /// Tests if a player is connected.
+    pub fn is_connected(&self, id: usize) -> bool {
+        self.connected.lock().unwrap().contains(&id)
+    }
+
+    /// Tests if an object is someone's character: one with a password, a
+    /// guest, the console's wizard, or any player connected right now.
+    pub fn is_player(&self, id: usize) -> bool {
+        self.has_password(id) || self.is_guest(id) || self.is_console(id) || self.is_connected(id)
+    }
+
+    /// Marks a player as connected, returning false if they already are.
+    pub fn claim_player(&self, id: usize) -> bool {
+        self.connected.lock().unwrap().insert(id)
+    }
+
+    /// Cleans up after a player's connection ends.
+    ///
+    /// Guests are reset, and players without a password are purged, since
+    /// nobody could ever connect as them again. The console's player is kept
+    /// for the next time the server starts.
+    pub fn release_player(&self, id: usize) {
+        if self.is_guest(id) {
+            self.reset_guest(id);
+        } else if !self.has_password(id) && !self.is_console(id) {
+            self.purge(id);
+        }
+
+        self.connected.lock().unwrap().remove(&id);
+        self.sessions.lock().unwrap().remove(&id);
+        self.registrations.lock().unwrap().remove(&id);
+    }
+
+    /// Registers a session as controlling a player, returning the token that
+    /// is cancelled when another connection takes the session over.
+    pub fn hold_session(&self, player: usize) -> CancellationToken {
+        let token = CancellationToken::new();
+        let mut sessions = self.sessions.lock().unwrap();
+        sessions.insert(player, token.clone());
+        token
+    }
+
+    /// Takes over the session controlling a player, which closes its
+    /// connection while leaving the player connected, returning false if no
+    /// session can be taken over.
+    pub fn take_over(&self, player: usize) -> bool {
+        let Some(token) = self.sessions.lock().unwrap().remove(&player) else {
+            return false;
+        };
+
+        token.cancel();
+        true
+    }
+
+    /// Starts registering a character for the connection controlling a
+    /// player, replacing any registration it had already started, unless
+    /// another code may not be sent yet.
+    pub fn start_registration(
+        &self,
+        player: usize,
+        mut registration: Registration,
+    ) -> Result<(), registration::SendError> {
+        let mut registrations = self.registrations.lock().unwrap();
+        if let Some(old) = registrations.get(&player) {
+            registration.replace(old)?;
+        }
+
+        self.registration_sends.take(&registration.email)?;
+        registrations.insert(player, registration);
+        Ok(())
+    }
+
+    /// Takes the registration that the connection controlling a player has
+    /// started, if its code is correct. Spent registrations are kept until
+    /// the connection closes, so that registering again can't reset them.
+    pub fn finish_registration(&self, player: usize, code: i64) -> Option<Registration> {
+        let mut registrations = self.registrations.lock().unwrap();
+        let registration = registrations.get_mut(&player)?;
+
+        if registration.verify(code) {
+            return registrations.remove(&player);
+        }
+
+        None
+    }
+
+    /// Tests if a player has set a password.
+    pub fn has_password(&self, id: usize) -> bool {
+        self.tree.contains_key(password_key(id)).unwrap()
+    }
+
+    /// Sets a player's password, lifting any lockout from wrong ones.
+    pub fn set_password(&self, id: usize, password: &str) {
+        let hash = password::hash(password);
+        self.tree.insert(password_key(id), hash.as_bytes()).unwrap();
+        self.login_failures.clear_all(id);
+    }
+
+    /// Tests if a password is a player's.
+    pub fn check_password(&self, id: usize, password: &str) -> bool {
+        match self.tree.get(password_key(id)).unwrap() {
+            Some(hash) => password::verify(&String::from_utf8_lossy(&hash), password),
+            None => false,
+        }
+    }
+
+    /// Tests if a password is a player's for logging in as them from an
+    /// address. Once too many wrong passwords have been tried for the player
+    /// from the address, logins from there are refused without checking.
+    pub fn check_login(&self, id: usize, password: &str, addr: &str) -> bool {
+        if self.login_failures.is_locked(addr, id) {
+            return false;
+        }
+
+        let matches = self.check_password(id, password);
+        match matches {
+            true => self.login_failures.clear(addr, id),
+            false => self.login_failures.fail(addr, id),
+        }
+
+        matches
+    }
+
+    /// Gets the public keys that a player can log in over SSH with, in the
+    /// OpenSSH format.
+    pub fn ssh_keys(&self, id: usize) -> Vec<String> {
+        match self.tree.get(ssh_keys_key(id)).unwrap() {
+            Some(keys) => String::from_utf8_lossy(&keys)
+                .lines()
+                .map(str::to_string)
+                .collect(),
+            None => Vec::new(),
+        }
+    }
+
+    /// Lets a player log in over SSH with a public key.
+    pub fn add_ssh_key(&self, id: usize, key: &str) {
+        let mut keys = self.ssh_keys(id);
+        keys.push(key.to_string());
+        self.tree
+            .insert(ssh_keys_key(id), keys.join("\n").as_bytes())
+            .unwrap();
+    }
+
+    /// Removes one of a player's SSH public keys by its index, returning
+    /// false if they have no such key.
+    pub fn remove_ssh_key(&self, id: usize, index: usize) -> bool {
+        let mut keys = self.ssh_keys(id);
+        if index >= keys.len() {
+            return false;
+        }
+
+        keys.remove(index);
+        self.tree
+            .insert(ssh_keys_key(id), keys.join("\n").as_bytes())
+            .unwrap();
+        true
+    }
+
+    /// Gets the server's SSH host key, if it has one yet.
+    pub fn ssh_host_key(&self) -> Option<String> {
+        let key = self.tree.get(SSH_HOST_KEY).unwrap()?;
+        Some(String::from_utf8_lossy(&key).into_owned())
+    }
+
+    /// Sets the server's SSH host key.
+    pub fn set_ssh_host_key(&self, key: &str) {
+        self.tree.insert(SSH_HOST_KEY, key.as_bytes()).unwrap();
+    }
+
+    /// Finds the player with a password who has a name.
+    pub fn find_player(&self, name: &str) -> Option<usize> {
+        let name = name.to_lowercase();
+
+        self.find("name", &name).into_iter().find(|id| {
+            self.name(*id).map(|found| found.to_lowercase()) == Some(name.clone())
+                && self.has_password(*id)
+        })
+    }
+
+    /// Records a player connecting or disconnecting from an address.
+    ///
+    /// The time is also kept on the player, in `last_connected` or
+    /// `last_disconnected`, along with the `last_location` that they left
+    /// from, so that anyone can see when and where they were last seen.
+    pub fn log_connection(&self, event: SessionEvent, player: usize, addr: &str) {
+        self.connection_log.record(event, player, addr);
+
+        let now = Value::Integer(now() as i64);
+        let fields = match event {
+            SessionEvent::Connect => vec![("last_connected".to_string(), now)],
+            SessionEvent::Disconnect => {
+                let mut fields = vec![("last_disconnected".to_string(), now)];
+                if let Some(room) = self.location(player) {
+                    fields.push(("last_location".to_string(), Value::Integer(room as i64)));
+                }
+
+                fields
+            }
+        };
+
+        if let Err(err) = self.set_many(player, fields) {
+            eprintln!("could not record when #{player} was last seen: {err}");
+        }
+    }
+
+    /// Lists the latest connections and disconnections, newest first,
+    /// optionally only those of one player.
+    pub fn recent_connections(&self, player: Option<usize>, limit: usize) -> Vec<LogEntry> {
+        self.connection_log.recent(player, limit)
+    }
+
+    /// Lists up to `limit` of the latest things said in the world that a
+    /// player would see, oldest first, as they would see them.
+    pub fn recap(&self, player: usize, limit: usize) -> Vec<HistoryEntry> {
+        self.history.recent(limit, |event| {
+            if !filter::delivers(self, player, &event) {
+                return None;
+            }
+
+            censor::apply(self, player, event)
+        })
+    }
+
+    /// Lists up to `limit` of the latest announcements, newest first.
+    pub fn recent_announcements(&self, limit: usize) -> Vec<Announcement> {
+        self.news_log.recent(limit)
+    }
+
+    /// Wipes a guest character after its connection ends, so that the next
+    /// visitor starts afresh in the starting room. Only its
+    /// [KEPT_GUEST_FIELDS] are kept.
+    pub fn reset_guest(&self, id: usize) {
+        for (key, _val) in self.show(id) {
+            if !KEPT_GUEST_FIELDS.contains(&key.as_str()) {
+                self.unset(id, &key);
+            }
+        }
+
+        if let Some(room) = self.starting_room() {
+            let _ = self.move_object(id, room);
+        }
+    }
+
+    /// Gets the room that new players are placed in, if there is one.
+    pub fn starting_room(&self) -> Option<usize> {
+        self.get(SYSTEM_OBJECT, "starting_room")
+            .and_then(|room| room.as_id())
+            .filter(|room| self.exists(*room))
+    }
+
+    /// Resets every guest, in case the server stopped while they were in use.
+    fn init_guests(&self) {
+        for guest in self.guests() {
+            self.reset_guest(guest);
+        }
+    }
+
+    /// Gets the object that holds the help pages.
+    pub fn help_object(&self) -> usize {
+        self.get(SYSTEM_OBJECT, "help_object")
+            .and_then(|help| help.as_id())
+            .unwrap_or(SYSTEM_OBJECT)
+    }
+
+    /// Gets the help page for a topic, if there is one.
+    ///
+    /// Topics are matched case-insensitively, and a leading `@` is ignored so
+    /// that the page for `@set` is stored in `help_set`.
+    pub fn help_page(&self, topic: &str) -> Option<String> {
+        let topic = topic.trim_start_matches('@').to_lowercase();
+        let page = self.get(self.help_object(), &format!("help_{topic}"))?;
+        page.as_string().cloned()
+    }
+
+    /// Lists the topics that have help pages, sorted.
+    pub fn help_topics(&self) -> Vec<String> {
+        let mut topics: Vec<String> = self
+            .show(self.help_object())
+            .into_iter()
+            .filter(|(_, page)| matches!(page, Value::String(_)))
+            .filter_map(|(field, _)| field.strip_prefix("help_").map(str::to_string))
+            .collect();
+
+        topics.sort();
+        topics
+    }
+
+    /// Loads every scripted command from the database.
+    fn init_script_commands(&self) {
+        let prefix = "command-";
+        let mut commands = self.script_commands.write().unwrap();
+
+        for command in self.tree.scan_prefix(prefix) {
+            let (key, val) = command.unwrap();
+            let name = String::from_utf8_lossy(&key[prefix.len()..]).to_string();
+            if let Ok(command) = serde_json::from_slice(&val) {
+                commands.insert(name, command);
+            }
+        }
+    }
+
+    /// Publishes an [Event] to every subscriber.
+    pub fn publish(&self, event: Event) {
+        self.history.record(&event);
+        let _ = self.events.send(event);
+    }
+
+    /// Subscribes to all future [Event]s.
+    pub fn subscribe(&self) -> broadcast::Receiver<Event> {
+        self.events.subscribe()
+    }
+
+    /// Sends a message from the server to every object in a room.
+    pub fn emit(&self, room: usize, message: &str) {
+        self.publish(Event::Emit {
+            room,
+            source: None,
+            message: message.to_string(),
+        });
+    }
+
+    /// Sends a message from an object to every object in a room, marking the
+    /// lines that [spoof] someone else.
+    pub fn emit_from(&self, room: usize, source: usize, message: &str) {
+        self.publish(Event::Emit {
+            room,
+            source: Some(source),
+            message: spoof::guard(self, source, message),
+        });
+    }
+
+    /// Makes a server announcement, which is kept for the news feed.
+    pub fn announce(&self, message: &str) {
+        self.news_log.record(message);
+        self.publish(Event::Announce {
+            source: None,
+            message: message.to_string(),
+        });
+    }
+
+    /// Makes an announcement on behalf of an object, marking the lines that
+    /// [spoof] someone else. Only [State::post_announcement] keeps it for the
+    /// news feed, so that verbs can't write to the feed.
+    pub fn announce_from(&self, source: usize, message: &str) {
+        self.publish(Event::Announce {
+            source: Some(source),
+            message: spoof::guard(self, source, message),
+        });
+    }
+
+    /// Makes a wizard's announcement, which is kept for the news feed.
+    pub fn post_announcement(&self, source: usize, message: &str) {
+        let message = spoof::guard(self, source, message);
+        self.news_log.record(&message);
+        self.publish(Event::Announce {
+            source: Some(source),
+            message,
+        });
+    }
+}
+
+/// An argument in the usage of a built-in command.
+#[derive(Clone, Copy, Debug)]
+pub enum Param {
+    /// An argument that must be given, like `<object>`.
+    Required(&'static str),
+
+    /// An argument that may be left out, like `[pattern]`. Its name may span
+    /// several words, like `[owner <player>]`, which are each one argument.
+    Optional(&'static str),
+
+    /// Any number of repeats of an argument, like `[<field> <value>...]`.
+    Rest(&'static str),
+
+    /// The rest of the line exactly as it was typed, which must come last.
+    /// Its usage is written out in full, like `<message>` or `[old] <new>`,
+    /// and it may be left out if all of it is in brackets, like `[password]`.
+    Text(&'static str),
+
+    /// A named option that may be given anywhere, like `[owner=<player>]`.
+    Named(&'static str),
+}
+
+impl Display for Param {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Param::Required(name) => write!(f, "<{name}>"),
+            Param::Text(usage) => write!(f, "{usage}"),
+            Param::Optional(name) | Param::Named(name) => write!(f, "[{name}]"),
+            Param::Rest(name) => write!(f, "[{name}...]"),
+        }
+    }
+}
+
+/// A built-in command.
+#[derive(Clone)]
+pub struct Builtin {
+    pub run: Command,
+
+    /// The arguments that the command takes.
+    pub params: &'static [Param],
+
+    /// How the command is used, like `@get <object> <field>`.
+    pub usage: String,
+}
+
+impl Builtin {
+    /// Checks that a command was given as many arguments as it takes.
+    pub fn check_arity(&self, args: &Arguments) -> CommandResult<()> {
+        let mut min = 0;
+        let mut max = Some(0);
+
+        for param in self.params {
+            match param {
+                Param::Required(_) => min += 1,
+                Param::Text(usage)
+                    if args.rest().is_empty()
+                        && !(usage.starts_with('[') && usage.ends_with(']')) =>
+                {
+                    return Err(CommandError::MissingArgument { index: args.len() });
+                }
+                Param::Text(_) => {}
+                Param::Optional(name) => {
+                    max = max.map(|max| max + name.split_whitespace().count());
+                }
+                Param::Rest(_) => max = None,
+                Param::Named(_) => {}
+            }
+        }
+
+        let named = self.params.iter().filter_map(|param| match param {
+            Param::Named(usage) => usage.split('=').next(),
+            _ => None,
+        });
+
+        let named: Vec<_> = named.collect();
+        if let Some(name) = args.options().find(|name| !named.contains(name)) {
+            return Err(CommandError::UnknownOption {
+                name: name.to_string(),
+            });
+        }
+
+        let max = max.map(|max| max + min);
+        if args.len() < min {
+            return Err(CommandError::MissingArgument { index: args.len() });
+        }
+
+        match max {
+            Some(max) if args.len() > max => Err(CommandError::ExtraArgument { index: max }),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct Commands(HashMap<String, Builtin>);
+
+impl Commands {
+    pub fn new() -> Self {
+        use Param::*;
+
+        let mut cmds = Self::default();
+
+        cmds.insert("say", &[Text("<message>")], say);
+        cmds.insert("emote", &[Text("<action>")], emote);
+        cmds.insert("pose", &[Required("target"), Text("<social>")], pose);
+        cmds.insert("page", &[Required("player"), Text("<message>")], page);
+        cmds.insert(
+            "connect",
+            &[Required("player"), Text("[password]")],
+            connect,
+        );
+        cmds.insert("create", &[Required("name"), Text("<email>")], register);
+        cmds.insert("verify", &[Required("code"), Text("[password]")], verify);
+        cmds.insert("help", &[Optional("topic")], help);
+        cmds.insert("roll", &[Required("dice")], roll);
+        cmds.insert("look", &[Optional("object")], look);
+        cmds.insert("go", &[Required("exit")], go);
+        cmds.insert("home", &[], go_home);
+        cmds.insert("@sethome", &[], set_home);
+        cmds.insert("wear", &[Required("object")], wear);
+        cmds.insert("remove", &[Required("object")], remove);
+        cmds.insert("equipment", &[], show_equipment);
+        cmds.insert("time", &[], show_time);
+        cmds.insert("give", &[Text("<amount> coins to <object>")], give);
+        cmds.insert("take", &[Required("object")], take);
+        cmds.insert("drop", &[Required("object")], drop_object);
+        cmds.insert("open", &[Required("object")], open);
+        cmds.insert("close", &[Required("object")], close);
+        cmds.insert("@lock", &[Required("object"), Text("with <key>")], set_lock);
+        cmds.insert("@unlock", &[Required("object")], unlock);
+        cmds.insert("@create", &[], create);
+        cmds.insert("@clone", &[Required("object")], clone);
+        cmds.insert("@destroy", &[Required("object")], destroy);
+        cmds.insert("@undelete", &[Optional("id")], undelete);
+        cmds.insert(
+            "@list",
+            &[
+                Named("owner=<player>"),
+                Named("in=<object>"),
+                Named("from=<id>"),
+                Named("to=<id>"),
+                Named("limit=<count>"),
+            ],
+            list,
+        );
+        cmds.insert("@audit", &[Optional("player")], audit);
+        cmds.insert("@lastlog", &[Optional("player")], lastlog);
+        cmds.insert("@laston", &[Required("player")], laston);
+        cmds.insert("@whereis", &[Required("player")], whereis);
+        cmds.insert("@recap", &[Optional("count")], recap);
+        cmds.insert("@stats", &[], stats);
+        cmds.insert("@maintenance", &[Optional("on|off")], maintenance);
+        cmds.insert("@announce", &[Text("<message>")], announce);
+        cmds.insert("@boot", &[Required("player")], boot);
+        cmds.insert("@tasks", &[], list_tasks);
+        cmds.insert("@kill", &[Required("task")], kill_task);
+        cmds.insert("@shutdown", &[], shutdown);
+        cmds.insert("@quota", &[Optional("player"), Optional("amount")], quota);
+        cmds.insert("@show", &[Required("object"), Optional("pattern")], show);
+        cmds.insert("@examine", &[Required("object")], examine);
+        cmds.insert("@find", &[Required("field"), Required("value")], find);
+        cmds.insert(
+            "@set",
+            &[
+                Required("object"),
+                Required("field"),
+                Required("value"),
+                Rest("<field> <value>"),
+            ],
+            set,
+        );
+        cmds.insert("@get", &[Required("object"), Required("field")], get);
+        cmds.insert("@unset", &[Required("object"), Required("field")], unset);
+        cmds.insert(
+            "@chmod",
+            &[Required("object"), Required("field"), Optional("flags")],
+            chmod,
+        );
+        cmds.insert("@bootstrap", &[Required("token")], bootstrap);
+        cmds.insert("@rename", &[Required("object"), Required("name")], rename);
+        cmds.insert(
+            "@describe",
+            &[Required("object"), Required("text")],
+            describe,
+        );
+        cmds.insert(
+            "@move",
+            &[Required("object"), Required("destination")],
+            move_object,
+        );
+        cmds.insert("@motd", &[Optional("set|add <text>")], motd);
+        cmds.insert(
+            "@addcommand",
+            &[Required("name"), Required("#object:verb")],
+            add_command,
+        );
+        cmds.insert("@delcommand", &[Required("name")], remove_command);
+        cmds.insert("@alias", &[Required("name"), Required("expansion")], alias);
+        cmds.insert("@aliases", &[], aliases);
+        cmds.insert("@unalias", &[Required("name")], unalias);
+        cmds.insert(
+            "@social",
+            &[Required("name"), Required("alone|at"), Text("<template>")],
+            set_social,
+        );
+        cmds.insert("@socials", &[Optional("name")], socials);
+        cmds.insert("@unsocial", &[Required("name")], unsocial);
+        cmds.insert(
+            "@censor",
+            &[Text("[server] mask|block|remove <pattern>")],
+            censor,
+        );
+        cmds.insert("@censors", &[], censors);
+        cmds.insert(
+            "@translate",
+            &[Required("locale"), Required("key"), Text("<text>")],
+            translate,
+        );
+        cmds.insert(
+            "@untranslate",
+            &[Required("locale"), Required("key")],
+            untranslate,
+        );
+        cmds.insert("@messages", &[Optional("locale")], messages);
+        cmds.insert("@gag", &[Required("object")], gag);
+        cmds.insert("@gags", &[], gags);
+        cmds.insert("@ungag", &[Required("object")], ungag);
+        cmds.insert(
+            "@friend",
+            &[Required("add|remove|list"), Optional("player")],
+            friend,
+        );
+        cmds.insert("@json", &[Optional("on|off")], json);
+        cmds.insert("@origins", &[Optional("on|off")], origins);
+        cmds.insert(
+            "@prefs",
+            &[Optional("width|color|ascii|locale"), Optional("value")],
+            prefs,
+        );
+        cmds.insert("@prompt", &[Optional("\"text\"|default")], prompt);
+        cmds.insert("@password", &[Text("[[old] <new>]")], set_password);
+        cmds.insert("@sshkey", &[Text("<public key>")], ssh_key);
+        cmds.insert("@sshkeys", &[], ssh_keys);
+        cmds.insert("@unsshkey", &[Required("number")], remove_ssh_key);
+        cmds.insert(
+            "@newpassword",
+            &[Required("player"), Text("[password]")],
+            new_password,
+        );
+
+        cmds
+    }
+
+    pub fn insert(&mut self, name: &str, params: &'static [Param], cb: Command) {
+        let mut usage = name.to_string();
+        for param in params {
+            usage.push_str(&format!(" {param}"));
+        }
+
+        let builtin = Builtin {
+            run: cb,
+            params,
+            usage,
+        };
+
+        self.0.insert(name.to_string(), builtin);
+    }
+
+    /// Lists the names of the commands that start with a prefix, sorted.
+    pub fn complete(&self, prefix: &str) -> Vec<String> {
+        let mut names: Vec<String> = self
+            .0
+            .keys()
+            .filter(|name| name.starts_with(prefix))
+            .cloned()
+            .collect();
+
+        names.sort();
+        names
+    }
+}
+
+/// Reads a line of at most `max` bytes, discarding the rest of a longer line.
+///
+/// Returns how many bytes were read, including any that were discarded, and
+/// whether the line was cut short.
+async fn read_line<R: AsyncBufRead + Unpin>(
+    reader: &mut R,
+    buf: &mut Vec<u8>,
+    max: usize,
+) -> std::io::Result<(usize, bool)> {
+    let mut read = (&mut *reader)
+        .take(max as u64)
+        .read_until(b'\n', buf)
+        .await?;
+    if read < max || buf.last() == Some(&b'\n') {
+        return Ok((read, false));
+    }
+
+    loop {
+        let chunk = reader.fill_buf().await?;
+        if chunk.is_empty() {
+            break;
+        }
+
+        let (len, done) = match chunk.iter().position(|byte| *byte == b'\n') {
+            Some(end) => (end + 1, true),
+            None => (chunk.len(), false),
+        };
+
+        reader.consume(len);
+        read += len;
+
+        if done {
+            break;
+        }
+    }
+
+    // don't leave half of a character that was cut off
+    if let Err(err) = std::str::from_utf8(buf) {
+        if err.error_len().is_none() {
+            buf.truncate(err.valid_up_to());
+        }
+    }
+
+    Ok((read, true))
+}
+
+/// Decodes a line sent by a client, or returns nothing if it is not UTF-8.
+///
+/// Tabs become spaces and other control characters are removed, so that
+/// players can't send terminal escape sequences to each other.
+fn decode_line(bytes: &[u8]) -> Option<String> {
+    let line = std::str::from_utf8(bytes).ok()?;
+
+    let line = line
+        .chars()
+        .map(|c| if c == '\t' { ' ' } else { c })
+        .filter(|c| !c.is_control())
+        .collect();
+
+    Some(line)
+}
+
+/// Gets the field on a player that stores one of their aliases.
+fn alias_key(name: &str) -> String {
+    format!("alias-{name}")
+}
+
+/// The most messages that may wait to be written to a client, past which it
+/// is disconnected.
+pub const OUTPUT_BUFFER: usize = 1024;
+
+/// The most lines that may be read ahead from a client while it is busy,
+/// past which reading waits.
+pub const INPUT_BUFFER: usize = 64;
+
+/// How long writing to a client may take before it is disconnected.
+pub const WRITE_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// The default number of lines in a page of output.
+pub const DEFAULT_PAGE_SIZE: usize = 24;
+
+/// The prompt shown to users who haven't set their own.
+pub const DEFAULT_PROMPT: &str = "> ";
+
+/// Something waiting to be written to a user's client.
+#[derive(Clone, Debug)]
+pub enum Output {
+    /// A line of text, which is ended with a CRLF.
+    Line(String),
+
+    /// Bytes that are written as they are.
+    Raw(Vec<u8>),
+
+    /// Starts compressing everything written afterwards with MCCP2.
+    StartCompression,
+}
+
+/// Something read from a user's client.
+#[derive(Clone, Debug)]
+pub enum Input {
+    /// A line of text, along with the length that it was cut short to if it
+    /// was too long.
+    Line { line: String, cut: Option<usize> },
+
+    /// A line that was not valid UTF-8, so was ignored.
+    Invalid,
+}
+
+/// The lines read from a user's client, shared between their commands and
+/// the verbs that they run.
+pub type Lines = Arc<tokio::sync::Mutex<mpsc::Receiver<Input>>>;
+
+/// Reads lines from a client until it disconnects, the user is closed, or
+/// the server shuts down.
+async fn read_input(
+    state: Arc<State>,
+    rx: impl AsyncRead + Unpin,
+    tx: mpsc::Sender<Input>,
+    closed: CancellationToken,
+) {
+    let mut reader = BufReader::new(rx);
+    let mut line_buf = Vec::new();
+    let shutdown = state.shutdown_token();
+
+    loop {
+        let max_line_length = state.max_line_length();
+        let result = tokio::select! {
+            _ = shutdown.cancelled() => break,
+            _ = closed.cancelled() => break,
+            result = read_line(&mut reader, &mut line_buf, max_line_length) => result,
+        };
+
+        // reading zero bytes means that the connection was closed
+        let cut = match result {
+            Err(_) | Ok((0, _)) => break,
+            Ok((_, cut)) => cut.then_some(max_line_length),
+        };
+
+        let input = match decode_line(&line_buf) {
+            Some(line) => Input::Line { line, cut },
+            None => Input::Invalid,
+        };
+
+        line_buf.clear();
+        if tx.send(input).await.is_err() {
+            break;
+        }
+    }
+}
+/// Gets the width to wrap a user's lines to, or zero to leave them whole.
+fn window_width(window: &Mutex<Option<telnet::WindowSize>>) -> usize {
+    window
+        .lock()
+        .unwrap()
+        .map_or(0, |window| usize::from(window.width))
+}
+
+/// Writes a user's output to their client until they disconnect.
+///
+/// Every message that is already waiting is written before flushing, so
+/// that bursts of output go out in as few writes as possible.
+async fn write_output(output: impl AsyncWrite + Unpin, mut rx: mpsc::Receiver<Output>) {
+    let mut output = BufWriter::new(output);
+
+    // once compression starts, everything is written through this instead
+    let mut compressor: Option<ZlibEncoder<Vec<u8>>> = None;
+
+    while let Some(message) = rx.recv().await {
+        let batch = async {
+            let mut next = Some(message);
+
+            while let Some(message) = next {
+                next = rx.try_recv().ok();
+
+                let bytes = match message {
+                    Output::Line(line) => [line.as_bytes(), b"\r\n"].concat(),
+                    Output::Raw(bytes) => bytes,
+                    Output::StartCompression if compressor.is_none() => {
+                        let start = telnet::subnegotiate(telnet::MCCP2, &[]);
+                        output.write_all(&start).await?;
+                        compressor = Some(ZlibEncoder::new(Vec::new(), Compression::default()));
+                        continue;
+                    }
+                    Output::StartCompression => continue,
+                };
+
+                match &mut compressor {
+                    Some(compressor) => compressor.write_all(&bytes)?,
+                    None => output.write_all(&bytes).await?,
+                }
+            }
+
+            if let Some(compressor) = &mut compressor {
+                compressor.flush()?;
+                output
+                    .write_all(&std::mem::take(compressor.get_mut()))
+                    .await?;
+            }
+
+            output.flush().await
+        };
+
+        if !matches!(tokio::time::timeout(WRITE_TIMEOUT, batch).await, Ok(Ok(()))) {
+            return;
+        }
+    }
+
+    // end the compressed stream cleanly so that the client knows it is over
+    if let Some(Ok(rest)) = compressor.map(ZlibEncoder::finish) {
+        let _ = tokio::time::timeout(WRITE_TIMEOUT, async {
+            output.write_all(&rest).await?;
+            output.flush().await
+        })
+        .await;
+    }
+}
+
+/// The built-in commands that players besides wizards may still run in
+/// maintenance mode, because they don't write anything. `@json` only changes
+/// what the connection speaks, so it is allowed too.
+pub const MAINTENANCE_COMMANDS: &[&str] = &[
+    "say",
+    "emote",
+    "pose",
+    "page",
+    "connect",
+    "help",
+    "roll",
+    "look",
+    "equipment",
+    "time",
+    "@list",
+    "@audit",
+    "@quota",
+    "@show",
+    "@examine",
+    "@tasks",
+    "@find",
+    "@get",
+    "@aliases",
+    "@gags",
+    "@censors",
+    "@socials",
+    "@laston",
+    "@whereis",
+    "@recap",
+    "@sshkeys",
+    "@json",
+    "@messages",
+    "@maintenance",
+];
+
+pub struct User {
+    pub state: Arc<State>,
+    object: usize,
+
+    /// The address that this user connected from, or `console` for the
+    /// operator's console.
+    addr: String,
+
+    /// The player that this connection controls, shared with the task that
+    /// forwards events to it.
+    player: Arc<AtomicUsize>,
+
+    /// Whether the client has agreed to receive GMCP packets, shared with
+    /// the task that forwards events to it.
+    gmcp: Arc<AtomicBool>,
+
+    /// Whether the connection speaks the [jsonl] protocol instead of text,
+    /// shared with the task that forwards events to it.
+    json: Arc<AtomicBool>,
+
+    /// Whether the client has agreed to interpret MXP links.
+    mxp: bool,
+
+    /// How the end of prompts is marked, if the connection speaks telnet.
+    prompt_end: Option<u8>,
+
+    /// The size of the client's window, if it has told us with NAWS, shared
+    /// with the task that forwards events to it.
+    window: Arc<Mutex<Option<telnet::WindowSize>>>,
+    tx: mpsc::Sender<Output>,
+
+    /// The lines read from the client, once the user starts reading them.
+    lines: Option<Lines>,
+
+    /// Cancelled once this user disconnects, to stop forwarding them events.
+    closed: CancellationToken,
+
+    /// Cancelled once another connection takes over this user's player.
+    taken_over: CancellationToken,
+    commands: Commands,
+    quit: bool,
+
+    /// How many wrong passwords this connection has tried to log in with.
+    failed_logins: u32,
+
+    /// Whether messages are being buffered into the pager.
+    paging: bool,
+
+    /// Buffered lines waiting for the user to view the next page.
+    pager: VecDeque<String>,
+}
+
+impl User {
+    pub fn new(
+        state: Arc<State>,
+        output: impl AsyncWrite + Send + Unpin + 'static,
+        addr: String,
+        object: usize,
+    ) -> Self {
+        let commands = Commands::new();
+
+        let (tx, rx) = mpsc::channel::<Output>(OUTPUT_BUFFER);
+        tokio::spawn(write_output(output, rx));
+
+        let closed = CancellationToken::new();
+        let player = Arc::new(AtomicUsize::new(object));
+        let gmcp = Arc::new(AtomicBool::new(false));
+        let json = Arc::new(AtomicBool::new(false));
+        let window = Arc::new(Mutex::new(None));
+
+        tokio::spawn({
+            let player = player.clone();
+            let gmcp = gmcp.clone();
+            let json = json.clone();
+            let window = window.clone();
+            let tx = tx.clone();
+            let state = state.clone();
+            let closed = closed.clone();
+            let mut rx = state.subscribe();
+            async move {
+                loop {
+                    let event = tokio::select! {
+                        _ = closed.cancelled() => break,
+                        event = rx.recv() => event,
+                    };
+
+                    let event = match event {
+                        Ok(event) => event,
+                        Err(broadcast::error::RecvError::Lagged(_)) => continue,
+                        Err(broadcast::error::RecvError::Closed) => break,
+                    };
+
+                    let object = player.load(Ordering::Relaxed);
+                    if !filter::delivers(&state, object, &event) {
+                        continue;
+                    }
+
+                    let Some(event) = censor::apply(&state, object, event) else {
+                        continue;
+                    };
+
+                    let mut outputs = Vec::new();
+
+                    // machines are sent every event that the player sees,
+                    // even the ones that have no text
+                    if json.load(Ordering::Relaxed) {
+                        outputs.push(Output::Line(jsonl::event(&event, Some(object))));
+                    } else {
+                        if gmcp.load(Ordering::Relaxed) {
+                            if let Some(packet) = gmcp::packet(&state, object, &event) {
+                                outputs.push(Output::Raw(packet));
+                            }
+                        }
+
+                        let message = match event {
+                            Event::Boot { .. } => Some(locale::text(&state, object, "booted", &[])),
+                            _ => event.render_for(object),
+                        };
+
+                        if let Some(mut message) = message {
+                            let origins = state.get(object, spoof::SHOW_ORIGINS_FIELD);
+                            if let (Some(Value::Bool(true)), Some(source)) =
+                                (origins, event.source())
+                            {
+                                let lines = message.split('\n');
+                                let lines = lines.map(|line| format!("[#{source}] {line}"));
+                                message = lines.collect::<Vec<_>>().join("\n");
+                            }
+
+                            let prefs = Prefs::of(&state, object);
+                            let width = prefs.width(window_width(&window));
+                            let lines = wrap::wrap(&prefs.apply(&message), width).into_iter();
+                            outputs.extend(lines.map(Output::Line));
+                        }
+                    }
+
+                    // a client that can't keep up makes this fall behind, so
+                    // the events it misses are skipped as lagged
+                    for output in outputs {
+                        tokio::select! {
+                            _ = closed.cancelled() => return,
+                            sent = tx.send(output) => {
+                                if sent.is_err() {
+                                    return;
+                                }
+                            }
+                        }
+                    }
+
+                    if let Event::Boot { .. } = event {
+                        closed.cancel();
+                        break;
+                    }
+                }
+            }
+        });
+
+        Self {
+            state,
+            addr,
+            player,
+            gmcp,
+            json,
+            mxp: false,
+            prompt_end: None,
+            window,
+            tx,
+            lines: None,
+            closed,
+            taken_over: CancellationToken::new(),
+            commands,
+            quit: false,
+            failed_logins: 0,
+            object,
+            paging: false,
+            pager: VecDeque::new(),
+        }
+    }
+
+    pub async fn run(mut self, rx: impl AsyncRead + Send + Unpin + 'static) {
+        let (rx, telnet) = telnet::Reader::new(rx);
+        self.prompt_end = Some(telnet::GA);
+        self.send_raw(telnet::negotiate(telnet::WILL, telnet::TELOPT_EOR));
+        self.send_raw(telnet::negotiate(telnet::WILL, telnet::MSSP));
+        self.send_raw(telnet::negotiate(telnet::WILL, telnet::GMCP));
+        self.send_raw(telnet::negotiate(telnet::WILL, telnet::MCCP2));
+        self.send_raw(telnet::negotiate(telnet::WILL, telnet::MXP));
+        self.send_raw(telnet::negotiate(telnet::DO, telnet::NAWS));
+        self.run_session(rx, Some(telnet)).await;
+    }
+
+    /// Runs a connection over SSH, which has already logged in as a player
+    /// and doesn't speak telnet.
+    pub async fn run_ssh(mut self, rx: impl AsyncRead + Send + Unpin + 'static) {
+        self.run_session(rx, None).await;
+    }
+
+    /// Runs a connection that speaks the [jsonl] protocol from the start.
+    pub async fn run_json(mut self, rx: impl AsyncRead + Send + Unpin + 'static) {
+        self.json.store(true, Ordering::Relaxed);
+        self.run_session(rx, None).await;
+    }
+
+    async fn run_session(
+        &mut self,
+        rx: impl AsyncRead + Send + Unpin + 'static,
+        telnet: Option<mpsc::UnboundedReceiver<telnet::Command>>,
+    ) {
+        self.welcome();
+
+        self.log_connection(SessionEvent::Connect);
+        self.state.publish(Event::Connect {
+            player: self.object,
+        });
+        friends::notify_connect(&self.state, self.object);
+
+        self.taken_over = self.state.hold_session(self.object);
+        self.run_hook("on_connect");
+        self.prompt();
+        self.read_commands(rx, telnet).await;
+
+        // the player stays connected through the connection that took over
+        if self.taken_over.is_cancelled() {
+            self.message(&locale::text(&self.state, self.object, "taken_over", &[]));
+            self.log_connection(SessionEvent::Disconnect);
+            self.closed.cancel();
+            return;
+        }
+
+        self.run_hook("on_disconnect");
+
+        self.state.publish(Event::Disconnect {
+            player: self.object,
+        });
+
+        self.close();
+    }
+
+    /// Runs the operator's console, which neither enters the world nor runs
+    /// the connection hooks.
+    pub async fn run_console(mut self, rx: impl AsyncRead + Send + Unpin + 'static) {
+        self.message("Console ready. Type \"help\".");
+        self.log_connection(SessionEvent::Connect);
+        self.read_commands(rx, None).await;
+        self.close();
+    }
+
+    /// Runs each line read as a command, until the user quits, is booted,
+    /// or the server shuts down.
+    ///
+    /// Telnet commands are handled as they arrive if the connection speaks
+    /// telnet.
+    async fn read_commands(
+        &mut self,
+        rx: impl AsyncRead + Send + Unpin + 'static,
+        mut telnet: Option<mpsc::UnboundedReceiver<telnet::Command>>,
+    ) {
+        let shutdown = self.state.shutdown_token();
+        let closed = self.closed.clone();
+
+        // lines are read by a task of their own, so that verbs that `read`
+        // from the user can take them while a command is still running
+        let (tx, rx_lines) = mpsc::channel(INPUT_BUFFER);
+        tokio::spawn(read_input(self.state.clone(), rx, tx, closed.clone()));
+        let lines: Lines = Arc::new(tokio::sync::Mutex::new(rx_lines));
+        self.lines = Some(lines.clone());
+
+        let mut last_line = tokio::time::Instant::now();
+        while !self.quit {
+            let next_line = async { lines.lock().await.recv().await };
+            let taken_over = self.taken_over.clone();
+
+            // the timeout is read every time so that changes to it apply
+            let idle_timeout = self.state.idle_timeout();
+            let idle = async {
+                match idle_timeout {
+                    Some(timeout) => tokio::time::sleep_until(last_line + timeout).await,
+                    None => std::future::pending().await,
+                }
+            };
+
+            let next_command = async {
+                match &mut telnet {
+                    Some(telnet) => telnet.recv().await,
+                    None => std::future::pending().await,
+                }
+            };
+
+            tokio::select! {
+                _ = shutdown.cancelled() => {
+                    self.quit = true;
+                }
+                _ = closed.cancelled() => {
+                    self.quit = true;
+                }
+                _ = taken_over.cancelled() => {
+                    self.quit = true;
+                }
+                _ = idle => {
+                    self.message("you have been idle for too long, so you have been disconnected");
+                    self.quit = true;
+                }
+                Some(command) = next_command => {
+                    self.on_telnet(command);
+                }
+                input = next_line => match input {
+                    Some(Input::Line { line, cut }) => {
+                        last_line = tokio::time::Instant::now();
+                        if let Some(max) = cut {
+                            self.message(&format!(
+                                "line too long, so only the first {max} bytes were kept"
+                            ));
+                        }
+
+                        self.on_line(line.trim()).await;
+                        self.prompt();
+                    }
+                    Some(Input::Invalid) => {
+                        self.message("ignored a line that was not valid UTF-8");
+                    }
+                    None => self.quit = true,
+                },
+            };
+        }
+    }
+
+    /// Responds to a telnet command from the client.
+    ///
+    /// Options that the server doesn't support are refused.
+    fn on_telnet(&mut self, command: telnet::Command) {
+        use telnet::Command::*;
+
+        match command {
+            Do(telnet::MSSP) => self.send_mssp(),
+            // echo is only taken on while passwords are read
+            Do(telnet::ECHO) | Dont(telnet::ECHO) => {}
+            Do(telnet::GMCP) => {
+                self.gmcp.store(true, Ordering::Relaxed);
+
+                if let Some(room) = self.state.location(self.object) {
+                    let info = gmcp::room_info(&self.state, room);
+                    self.send_gmcp("Room.Info", &info);
+                }
+            }
+            Dont(telnet::GMCP) => self.gmcp.store(false, Ordering::Relaxed),
+            Do(telnet::MCCP2) => self.send_output(Output::StartCompression),
+            Do(telnet::TELOPT_EOR) => self.prompt_end = Some(telnet::EOR),
+            Dont(telnet::TELOPT_EOR) => self.prompt_end = Some(telnet::GA),
+            Do(telnet::MXP) => {
+                // MXP starts once the client is told to expect it
+                self.send_raw(telnet::subnegotiate(telnet::MXP, &[]));
+                self.mxp = true;
+            }
+            Dont(telnet::MXP) => self.mxp = false,
+            Will(telnet::NAWS) => {}
+            Wont(telnet::NAWS) => *self.window.lock().unwrap() = None,
+            Subnegotiation(telnet::NAWS, data) => {
+                if let Some(size) = telnet::WindowSize::parse_naws(&data) {
+                    *self.window.lock().unwrap() = Some(size);
+                }
+            }
+            Do(option) => self.send_raw(telnet::negotiate(telnet::WONT, option)),
+            Will(option) => self.send_raw(telnet::negotiate(telnet::DONT, option)),
+            _ => {}
+        }
+    }
+
+    /// Asks the user for a password, returning [None] if they don't answer
+    /// in time. Clients that speak telnet are asked not to echo it.
+    pub fn read_password(&mut self, prompt: &str) -> Option<String> {
+        let lines = self.lines.clone()?;
+        let end = self.prompt_end.filter(|_| !self.is_json());
+
+        let mut bytes = prompt.as_bytes().to_vec();
+        if let Some(end) = end {
+            self.send_raw(telnet::negotiate(telnet::WILL, telnet::ECHO));
+            bytes.extend([telnet::IAC, end]);
+        }
+
+        self.send_raw(bytes);
+
+        let runtime = tokio::runtime::Handle::current();
+        let input = tokio::task::block_in_place(|| {
+            runtime.block_on(async {
+                let mut lines = lines.lock().await;
+                tokio::time::timeout(script::READ_TIMEOUT, lines.recv()).await
+            })
+        });
+
+        // the client didn't echo the end of the line either
+        if end.is_some() {
+            self.send_raw(telnet::negotiate(telnet::WONT, telnet::ECHO));
+            self.send_raw(b"\r\n".to_vec());
+        }
+
+        match input {
+            Ok(Some(Input::Line { line, .. })) => Some(line.trim().to_string()),
+            _ => None,
+        }
+    }
+
+    /// Gets a password typed after a command, or else asks for it.
+    fn password_arg(&mut self, args: &Arguments, prompt: &str) -> Option<String> {
+        match args.rest() {
+            "" => self.read_password(prompt),
+            password => Some(password.to_string()),
+        }
+    }
+
+    /// Sends the user's prompt, ended so that their client can tell it from
+    /// other output. Only telnet connections are prompted.
+    pub fn prompt(&mut self) {
+        let Some(end) = self.prompt_end.filter(|_| !self.is_json()) else {
+            return;
+        };
+
+        let prompt = self.render_prompt();
+        if prompt.is_empty() {
+            return;
+        }
+
+        let prefs = Prefs::of(&self.state, self.object);
+        let mut bytes = prefs.apply(&markup::render(&prompt)).into_bytes();
+        bytes.extend([telnet::IAC, end]);
+        self.send_raw(bytes);
+    }
+
+    /// Fills in a prompt's `%{field}`s with the values of the user's fields.
+    /// Missing fields are left empty, and `%%` is a lone `%`.
+    ///
+    /// The prompt is the user's `prompt` field, falling back to the system
+    /// object's `prompt` or [DEFAULT_PROMPT].
+    pub fn render_prompt(&self) -> String {
+        let template = self
+            .state
+            .get(self.object, "prompt")
+            .or_else(|| self.state.get(SYSTEM_OBJECT, "prompt"))
+            .and_then(|prompt| prompt.as_string().cloned())
+            .unwrap_or_else(|| DEFAULT_PROMPT.to_string());
+
+        let mut prompt = String::new();
+        let mut rest = template.as_str();
+
+        while let Some(start) = rest.find('%') {
+            prompt.push_str(&rest[..start]);
+            rest = &rest[start..];
+
+            if let Some(after) = rest.strip_prefix("%%") {
+                prompt.push('%');
+                rest = after;
+                continue;
+            }
+
+            let field = rest
+                .strip_prefix("%{")
+                .and_then(|field| field.split_once('}'));
+
+            match field {
+                Some((field, after)) => {
+                    match self.state.get(self.object, field) {
+                        Some(Value::String(val)) => prompt.push_str(&val),
+                        Some(val) => prompt.push_str(&val.to_string()),
+                        None => {}
+                    }
+
+                    rest = after;
+                }
+                None => {
+                    prompt.push('%');
+                    rest = &rest[1..];
+                }
+            }
+        }
+
+        prompt.push_str(rest);
+        prompt
+    }
+
+    /// Sends a GMCP packet, if the client has agreed to receive them.
+    pub fn send_gmcp(&mut self, package: &str, data: &serde_json::Value) {
+        if self.gmcp.load(Ordering::Relaxed) {
+            self.send_raw(gmcp::encode(package, data));
+        }
+    }
+
+    /// Sends the server's status to a MUD listing site's crawler.
+    fn send_mssp(&mut self) {
+        let stats = self.state.stats();
+        let mut vars = vec![
+            ("NAME", self.state.server_name()),
+            ("PLAYERS", stats.connected.to_string()),
+            ("UPTIME", now().saturating_sub(stats.uptime).to_string()),
+            ("CODEBASE", "MarcieMOO".to_string()),
+        ];
+
+        for port in self.state.ports() {
+            vars.push(("PORT", port.to_string()));
+        }
+
+        let data = telnet::mssp_data(&vars);
+        self.send_raw(telnet::subnegotiate(telnet::MSSP, &data));
+    }
+
+    /// Stops forwarding events to this user and releases their player.
+    fn close(&mut self) {
+        self.closed.cancel();
+        self.log_connection(SessionEvent::Disconnect);
+        self.state.release_player(self.object);
+        home::send_home_later(&self.state, self.object);
+    }
+
+    /// Greets the user and places them in the world.
+    pub fn welcome(&mut self) {
+        match self
+            .state
+            .get(SYSTEM_OBJECT, "motd")
+            .and_then(|motd| motd.as_string().cloned())
+        {
+            Some(motd) => {
+                for line in motd.lines() {
+                    self.message(&markup::render(line));
+                }
+            }
+            None => self.message(&self.text("welcome", &[])),
+        }
+
+        if self.state.in_maintenance() {
+            self.message(&self.text("welcome_maintenance", &[]));
+        }
+
+        self.message(&self.text("welcome_help", &[]));
+        let id = self.object.to_string();
+        self.message(&self.text("welcome_object", &[("id", id)]));
+
+        self.enter_starting_room();
+    }
+
+    /// Places this user's player in the starting room, if there is one.
+    fn enter_starting_room(&mut self) {
+        let Some(room) = self.state.starting_room() else {
+            return;
+        };
+
+        if let Err(err) = self.move_object(self.object, room) {
+            let error = err.to_string();
+            self.message(&self.text("starting_room_failed", &[("error", error)]));
+        }
+    }
+
+    /// Sets up a character that this user has just created, placing it in
+    /// the starting room and running the system object's `player_created`
+    /// verb on it.
+    pub fn setup_new_player(&mut self) {
+        if self.state.location(self.object) != self.state.starting_room() {
+            self.enter_starting_room();
+        }
+
+        self.call(SYSTEM_OBJECT, "player_created");
+    }
+
+    /// Gets a message from the [locale] catalog in this user's locale.
+    pub fn text(&self, key: &str, args: &[(&str, String)]) -> String {
+        locale::text(&self.state, self.object, key, args)
+    }
+
+    /// Describes an error to this user, in their locale.
+    pub fn error_text(&self, err: &CommandError) -> String {
+        let (key, args) = err.message();
+        let error = locale::text(&self.state, self.object, key, &args);
+        self.text("error", &[("error", error)])
+    }
+
+    /// Shows a command's usage to this user, in their locale.
+    pub fn usage_text(&self, usage: &str) -> String {
+        self.text("usage", &[("usage", usage.to_string())])
+    }
+
+    /// Tests if this user is a wizard.
+    pub fn is_wizard(&self) -> bool {
+        self.state.is_wizard(self.object)
+    }
+
+    /// Fails with [CommandError::Maintenance] if the server is in maintenance
+    /// mode and this user is not a wizard.
+    pub fn check_writable(&self) -> CommandResult<()> {
+        if self.state.in_maintenance() && !self.is_wizard() {
+            Err(CommandError::Maintenance)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Tells this user that they can't run verbs if the server is in
+    /// maintenance mode, returning whether they were refused.
+    fn refuse_in_maintenance(&mut self) -> bool {
+        match self.check_writable() {
+            Ok(()) => false,
+            Err(err) => {
+                self.message(&self.error_text(&err));
+                true
+            }
+        }
+    }
+
+    /// Fails with [CommandError::PermissionDenied] if this user is not a wizard.
+    pub fn check_wizard(&self) -> CommandResult<()> {
+        if self.is_wizard() {
+            Ok(())
+        } else {
+            Err(CommandError::PermissionDenied)
+        }
+    }
+
+    /// Fails with [CommandError::PermissionDenied] if this user cannot act as
+    /// the owner of an object.
+    pub fn check_controls(&self, id: usize) -> CommandResult<()> {
+        if self.state.controls(self.object, id) {
+            Ok(())
+        } else {
+            Err(CommandError::PermissionDenied)
+        }
+    }
+
+    /// Fails with [CommandError::PermissionDenied] if this user cannot read
+    /// a field.
+    pub fn check_read(&self, id: usize, key: &str) -> CommandResult<()> {
+        if self.state.can_read(self.object, id, key) {
+            Ok(())
+        } else {
+            Err(CommandError::PermissionDenied)
+        }
+    }
+
+    /// Fails with [CommandError::PermissionDenied] if this user cannot write
+    /// a field.
+    pub fn check_write(&self, id: usize, key: &str) -> CommandResult<()> {
+        if self.state.can_write(self.object, id, key) {
+            Ok(())
+        } else {
+            Err(CommandError::PermissionDenied)
+        }
+    }
+
+    /// Resolves an argument to an object ID, matching names against the
+    /// objects near this user.
+    pub fn get_object(&self, args: &Arguments, index: usize) -> CommandResult<usize> {
+        match args.get(index)? {
+            Argument::Integer(_) | Argument::Object(_) => args.get_id(index),
+            Argument::Ident(name) | Argument::String(name) => self.match_name(name),
+            _ => Err(CommandError::InvalidArgument {
+                index,
+                expected: "object".to_string(),
+            }),
+        }
+    }
+
+    /// Resolves an argument to a player, matching names against every
+    /// registered player before the objects near this user.
+    pub fn get_player(&self, args: &Arguments, index: usize) -> CommandResult<usize> {
+        match args.get(index)? {
+            Argument::Ident(name) | Argument::String(name) => match self.state.find_player(&name) {
+                Some(player) => Ok(player),
+                None => self.match_name(name),
+            },
+            _ => self.get_object(args, index),
+        }
+    }
+
+    /// Resolves a named option to an object ID, like [User::get_object].
+    pub fn get_object_option(&self, args: &Arguments, name: &str) -> CommandResult<Option<usize>> {
+        match args.option(name) {
+            Some(Argument::Ident(object) | Argument::String(object)) => {
+                self.match_name(object.clone()).map(Some)
+            }
+            _ => args.get_option_id(name),
+        }
+    }
+
+    /// Finds the one object near this user with a name.
+    fn match_name(&self, name: String) -> CommandResult<usize> {
+        let matches = self.state.match_object(self.object, &name);
+        self.pick_match(name, matches)
+    }
+
+    /// Resolves a name to one of the exits out of this user's location.
+    pub fn match_exit(&self, name: String) -> CommandResult<usize> {
+        let exits = match self.state.location(self.object) {
+            Some(room) => self.state.exits(room),
+            None => Vec::new(),
+        };
+
+        let mut matches = self.state.match_object(self.object, &name);
+        matches.retain(|id| exits.contains(id));
+        self.pick_match(name, matches)
+    }
+
+    /// Picks the only object that matched a name, failing if there were
+    /// none or several.
+    fn pick_match(&self, name: String, mut matches: Vec<usize>) -> CommandResult<usize> {
+        match matches.len() {
+            0 => Err(CommandError::NoMatch { name }),
+            1 => Ok(matches.remove(0)),
+            _ => Err(CommandError::Ambiguous {
+                name,
+                candidates: matches
+                    .into_iter()
+                    .map(|id| self.state.display_name(id))
+                    .collect(),
+            }),
+        }
+    }
+
+    /// Uses up one object of this user's quota, returning false if none is
+    /// left. Wizards have unlimited quota.
+    pub fn take_quota(&self) -> bool {
+        self.is_wizard() || self.state.adjust_quota(self.object, -1)
+    }
+
+    /// Returns one object to this user's quota.
+    pub fn refund_quota(&self) {
+        if !self.is_wizard() {
+            self.state.adjust_quota(self.object, 1);
+        }
+    }
+
+    pub async fn on_line(&mut self, line: &str) {
+        if self.is_json() {
+            self.on_request(line);
+            return;
+        }
+
+        // a blank line continues paging, while anything else discards the
+        // rest of the pager and runs as normal
+        if !self.pager.is_empty() {
+            if line.is_empty() {
+                self.next_page();
+                return;
+            }
+
+            self.pager.clear();
+        }
+
+        if line.is_empty() {
+            return;
+        }
+
+        let line = self.expand_alias(line);
+        let (command, args) = line.split_once(' ').unwrap_or((&line, ""));
+
+        self.paging = true;
+
+        self.dispatch(command, args);
+
+        self.paging = false;
+        self.finish_command(None);
+    }
+
+    /// Runs a line of the [jsonl] protocol as a command.
+    fn on_request(&mut self, line: &str) {
+        if line.is_empty() {
+            return;
+        }
+
+        let request = match jsonl::parse_request(line) {
+            Ok(request) => request,
+            Err(err) => {
+                self.send(&jsonl::error(&err));
+                return;
+            }
+        };
+
+        let line = self.expand_alias(request.command.trim());
+        let (command, args) = line.split_once(' ').unwrap_or((&line, ""));
+
+        self.pager.clear();
+        self.paging = true;
+
+        if !command.is_empty() {
+            self.dispatch(command, args);
+        }
+
+        self.paging = false;
+        self.finish_command(request.id.as_ref());
+    }
+
+    /// Sends the output of a command that just finished, which is paged for
+    /// people and sent all at once to machines.
+    fn finish_command(&mut self, id: Option<&serde_json::Value>) {
+        if self.is_json() {
+            let lines: Vec<String> = self.pager.drain(..).collect();
+            self.send(&jsonl::output(id, &lines));
+        } else {
+            self.next_page();
+        }
+    }
+
+    /// Tests if this connection speaks the [jsonl] protocol.
+    pub fn is_json(&self) -> bool {
+        self.json.load(Ordering::Relaxed)
+    }
+
+    /// Switches this connection to or from the [jsonl] protocol.
+    pub fn set_json(&mut self, json: bool) {
+        self.json.store(json, Ordering::Relaxed);
+    }
+
+    /// Runs a command by name, falling back to the user's verbs and then to
+    /// the built-in commands that the name is an abbreviation of.
+    ///
+    /// Command names are matched case-insensitively.
+    pub fn dispatch(&mut self, command: &str, args: &str) {
+        let name = command.to_lowercase();
+        self.state.count_command();
+
+        if let Some(builtin) = self.commands.0.get(&name).cloned() {
+            self.run_builtin(&name, &builtin, args);
+            return;
+        }
+
+        if let Some(script) = self.state.script_command(&name) {
+            if self.refuse_in_maintenance() {
+                return;
+            }
+
+            if !self.call_command(&script, args) {
+                self.message("this command's verb is missing");
+            }
+
+            return;
+        }
+
+        if matches!(self.state.get(self.object, command), Some(Value::String(_)))
+            && self.refuse_in_maintenance()
+        {
+            return;
+        }
+
+        if self.call(self.object, command) {
+            return;
+        }
+
+        // exits can be taken by just typing their names
+        if args.trim().is_empty() {
+            if let Ok(exit) = self.match_exit(command.to_string()) {
+                if !self.refuse_in_maintenance() {
+                    self.go(exit);
+                }
+
+                return;
+            }
+        }
+
+        if let Some(social) = self.state.social(&name) {
+            self.run_social(&name, social, args);
+            return;
+        }
+
+        let mut matches = self.commands.complete(&name);
+        match matches.len() {
+            0 => self.suggest(command),
+            1 => {
+                let name = matches.remove(0);
+                let builtin = self.commands.0[&name].clone();
+                self.run_builtin(&name, &builtin, args);
+            }
+            _ => {
+                let matches = matches.join(", ");
+                self.message(&format!("{command} could be any of: {matches}"));
+            }
+        }
+    }
+
+    /// Acts out a named social, at the player named by its arguments if
+    /// there are any.
+    fn run_social(&mut self, name: &str, social: Social, args: &str) {
+        let result = Arguments::new(args).and_then(|args| match args.is_empty() {
+            true => Ok(None),
+            false => self.get_player(&args, 0).map(Some),
+        });
+
+        let target = match result {
+            Ok(target) => target,
+            Err(err) => {
+                self.message(&self.error_text(&err));
+                return;
+            }
+        };
+
+        let template = match target {
+            Some(_) => social.at,
+            None => social.alone,
+        };
+
+        let Some(template) = template else {
+            match target {
+                Some(_) => self.message(&format!("{name} can't be aimed at anyone")),
+                None => self.message(&format!("{name} needs someone to aim it at")),
+            }
+
+            return;
+        };
+
+        let event = social::social(&self.state, &template, self.object, target);
+        self.state.publish(event);
+    }
+
+    /// Tells the user that nothing matched a command, suggesting the
+    /// commands and verbs that it may have been a typo of.
+    fn suggest(&mut self, command: &str) {
+        let mut candidates: Vec<String> = self.commands.0.keys().cloned().collect();
+
+        let scripted = self.state.script_commands();
+        candidates.extend(scripted.into_iter().map(|(name, _)| name));
+        candidates.extend(self.state.socials().into_iter().map(|(name, _)| name));
+
+        let verbs = self.state.show(self.object).into_iter();
+        // internal fields like aliases have dashes, which verb names can't
+        let verbs =
+            verbs.filter(|(name, val)| matches!(val, Value::String(_)) && !name.contains('-'));
+        candidates.extend(verbs.map(|(name, _)| name));
+
+        let suggestions = suggest::suggest(command, candidates.iter().map(String::as_str));
+
+        match suggestions.as_slice() {
+            [] => self.message(&self.text("no_such_verb", &[])),
+            [one] => {
+                let command = one.to_string();
+                self.message(&self.text("did_you_mean", &[("command", command)]));
+            }
+            many => {
+                let commands = many.join(", ");
+                self.message(&self.text("did_you_mean_any", &[("commands", commands)]));
+            }
+        }
+    }
+
+    /// Runs a built-in command, showing its usage if it was given the wrong
+    /// arguments.
+    fn run_builtin(&mut self, name: &str, builtin: &Builtin, args: &str) {
+        let args = match builtin.params.last() {
+            Some(Param::Text(_)) => Arguments::with_rest(args, builtin.params.len() - 1),
+            _ => Arguments::new(args),
+        };
+
+        let result = args.and_then(|args| {
+            if !MAINTENANCE_COMMANDS.contains(&name) {
+                self.check_writable()?;
+            }
+
+            builtin.check_arity(&args)?;
+            (builtin.run)(self, args)
+        });
+
+        match result {
+            Ok(()) => {}
+            Err(CommandError::MissingArgument { .. } | CommandError::ExtraArgument { .. }) => {
+                self.message(&self.usage_text(&builtin.usage));
+            }
+            Err(
+                err @ (CommandError::InvalidArgument { .. }
+                | CommandError::UnknownOption { .. }
+                | CommandError::InvalidOption { .. }),
+            ) => {
+                self.message(&self.error_text(&err));
+                self.message(&self.usage_text(&builtin.usage));
+            }
+            Err(err) => self.message(&self.error_text(&err)),
+        }
+    }
+
+    /// Replaces the first word of a line with the alias that it names, if
+    /// this user has one. Aliases are not expanded recursively.
+    pub fn expand_alias(&self, line: &str) -> String {
+        let (word, rest) = line.split_once(' ').unwrap_or((line, ""));
+
+        let Some(Value::String(expansion)) = self.state.get(self.object, &alias_key(word)) else {
+            return line.to_string();
+        };
+
+        if rest.is_empty() {
+            expansion
+        } else {
+            format!("{expansion} {rest}")
+        }
+    }
+
+    /// Gets the number of lines in a page of output, or zero if paging is
+    /// disabled.
+    ///
+    /// This is the user's `page_size` field, falling back to the height of
+    /// their window, the system object's `page_size`, or [DEFAULT_PAGE_SIZE].
+    pub fn page_size(&self) -> usize {
+        // leave room in the window for the `--more--` line
+        let window = self
+            .window()
+            .map(|window| usize::from(window.height))
+            .filter(|height| *height > 1)
+            .map(|height| Value::Integer(height as i64 - 1));
+
+        self.state
+            .get(self.object, "page_size")
+            .or(window)
+            .or_else(|| self.state.get(SYSTEM_OBJECT, "page_size"))
+            .and_then(|size| size.as_id())
+            .unwrap_or(DEFAULT_PAGE_SIZE)
+    }
+
+    /// Gets the size of the user's window, if their client reported it.
+    pub fn window(&self) -> Option<telnet::WindowSize> {
+        *self.window.lock().unwrap()
+    }
+
+    /// Shares the size of this user's window, for connections that learn it
+    /// some way other than NAWS.
+    pub fn window_handle(&self) -> Arc<Mutex<Option<telnet::WindowSize>>> {
+        self.window.clone()
+    }
+
+    /// Sends the next page of buffered output.
+    fn next_page(&mut self) {
+        let len = match self.page_size() {
+            0 => self.pager.len(),
+            size => size.min(self.pager.len()),
+        };
+
+        let page: Vec<_> = self.pager.drain(..len).collect();
+        for line in page {
+            self.send(&line);
+        }
+
+        if !self.pager.is_empty() {
+            let left = self.pager.len();
+            self.send(&format!("--more-- ({left} more, press enter to continue)"));
+        }
+    }
+
+    /// Sends a message to the user, through the pager while a command runs.
+    ///
+    /// Messages are wrapped to the width of the user's window and fitted to
+    /// their [prefs], unless the user is a machine speaking the [jsonl]
+    /// protocol.
+    pub fn message(&mut self, text: &str) {
+        // machines get messages in JSON, and unwrapped
+        if self.is_json() {
+            if self.paging {
+                self.pager.push_back(text.to_string());
+            } else {
+                self.send(&jsonl::output(None, &[text.to_string()]));
+            }
+
+            return;
+        }
+
+        let prefs = Prefs::of(&self.state, self.object);
+        let width = prefs.width(window_width(&self.window));
+        for line in wrap::wrap(&prefs.apply(text), width) {
+            if self.paging {
+                self.pager.push_back(line);
+            } else {
+                self.send(&line);
+            }
+        }
+    }
+
+    /// Sends a line that may contain links, which are only clickable if the
+    /// client supports MXP.
+    pub fn message_line(&mut self, line: &mxp::Line) {
+        let line = line.render(self.mxp && !self.is_json());
+        self.message(&line);
+    }
+
+    /// Lets verbs ask this user for lines with `read`, sending them what they
+    /// print first as [User::message] does, but without the pager.
+    fn reader(&self) -> Option<script::Reader> {
+        let lines = self.lines.clone()?;
+        let state = self.state.clone();
+        let object = self.object;
+        let window = self.window.clone();
+        let json = self.is_json();
+        let tx = self.tx.clone();
+
+        let send = move |text: &str| {
+            if json {
+                let _ = tx.try_send(Output::Line(jsonl::output(None, &[text.to_string()])));
+                return;
+            }
+
+            let prefs = Prefs::of(&state, object);
+            let width = prefs.width(window_width(&window));
+            for line in wrap::wrap(&prefs.apply(text), width) {
+                let _ = tx.try_send(Output::Line(line));
+            }
+        };
+
+        Some(script::Reader {
+            lines,
+            send: Arc::new(send),
+        })
+    }
+
+    /// Sends a message to the user immediately.
+    ///
+    /// Users whose clients have stopped reading are disconnected once
+    /// [OUTPUT_BUFFER] messages are waiting for them.
+    fn send(&mut self, text: &str) {
+        self.send_output(Output::Line(text.to_string()));
+    }
+
+    /// Sends bytes to the user's client as they are, like telnet commands.
+    fn send_raw(&mut self, bytes: Vec<u8>) {
+        self.send_output(Output::Raw(bytes));
+    }
+
+    fn send_output(&mut self, output: Output) {
+        if self.tx.try_send(output).is_err() {
+            self.quit = true;
+        }
+    }
+
+    /// Executes a verb on the user's own object.
+    pub fn exec(&mut self, verb: &str) {
+        if !self.call(self.object, verb) {
+            self.message("no such verb");
+        }
+    }
+
+    /// Makes this connection control another player, releasing the one that
+    /// it controlled before.
+    ///
+    /// A player whose session is resumed from another connection never
+    /// disconnected, so they aren't connected again either.
+    pub fn switch_player(&mut self, player: usize, resumed: bool) {
+        self.run_hook("on_disconnect");
+        self.state.publish(Event::Disconnect {
+            player: self.object,
+        });
+
+        self.log_connection(SessionEvent::Disconnect);
+        self.state.release_player(self.object);
+        home::send_home_later(&self.state, self.object);
+        self.object = player;
+        self.player.store(player, Ordering::Relaxed);
+        self.taken_over = self.state.hold_session(player);
+
+        self.log_connection(SessionEvent::Connect);
+        if resumed {
+            return;
+        }
+
+        self.state.publish(Event::Connect { player });
+        friends::notify_connect(&self.state, player);
+        self.run_hook("on_connect");
+    }
+
+    /// Records this user's player connecting or disconnecting.
+    fn log_connection(&self, event: SessionEvent) {
+        self.state.log_connection(event, self.object, &self.addr);
+    }
+
+    /// Runs a hook verb on the user's object, then on the system object.
+    pub fn run_hook(&mut self, hook: &str) {
+        self.call(self.object, hook);
+
+        if self.object != SYSTEM_OBJECT {
+            self.call(SYSTEM_OBJECT, hook);
+        }
+    }
+
+    /// Moves an object, then runs the `on_exit` verb of its old location and
+    /// the `on_enter` verb of its new one with the object bound as `mover`.
+    pub fn move_object(&mut self, object: usize, dest: usize) -> Result<(), MoveError> {
+        let from = self.state.move_object(object, dest)?;
+        let bindings = [("mover", object)];
+
+        if let Some(from) = from {
+            self.call_with(from, "on_exit", &bindings);
+        }
+
+        self.call_with(dest, "on_enter", &bindings);
+        Ok(())
+    }
+
+    /// Shows an object to this user: its name, its description, its exits,
+    /// and what is in it.
+    ///
+    /// An object's `describe` verb is run with the user bound as `viewer` to
+    /// describe it, if it has one, instead of showing its `description`.
+    pub fn look_at(&mut self, id: usize) {
+        let name = self.state.name(id).unwrap_or_else(|| format!("#{id}"));
+        self.message(&name);
+
+        if !self.call_with(id, "describe", &[("viewer", self.object)]) {
+            match self.state.get(id, "description") {
+                Some(Value::String(description)) if !description.is_empty() => {
+                    for line in description.lines() {
+                        self.message(&markup::render(line));
+                    }
+                }
+                _ => self.message("You see nothing special."),
+            }
+        }
+
+        let mut exits = self.state.exits(id);
+        exits.retain(|exit| self.state.is_visible(self.object, *exit));
+        if !exits.is_empty() {
+            let mut line = mxp::Line::new();
+            line.push("Exits: ");
+            for (index, exit) in exits.iter().enumerate() {
+                if index > 0 {
+                    line.push(", ");
+                }
+
+                let name = self.state.name(*exit).unwrap_or_else(|| format!("#{exit}"));
+                line.link(&format!("go {name}"), &name);
+            }
+
+            self.message_line(&line);
+        }
+
+        let mut contents = self.state.visible_contents(self.object, id);
+        contents.retain(|content| {
+            *content != self.object && self.state.destination(*content).is_none()
+        });
+        if !contents.is_empty() {
+            let mut line = mxp::Line::new();
+            line.push("Contents: ");
+            for (index, content) in contents.iter().enumerate() {
+                if index > 0 {
+                    line.push(", ");
+                }
+
+                let name = self.state.name(*content);
+                let name = name.unwrap_or_else(|| format!("#{content}"));
+                line.link(&format!("look #{content}"), &name);
+            }
+
+            self.message_line(&line);
+        }
+    }
+
+    /// Takes an exit out of this user's location, then looks around where it
+    /// leads.
+    pub fn go(&mut self, exit: usize) {
+        let here = self.state.location(self.object);
+        let dest = self.state.destination(exit);
+
+        let Some(dest) = dest.filter(|_| here.is_some() && self.state.location(exit) == here)
+        else {
+            self.message("you can't go that way");
+            return;
+        };
+
+        if !lock::allows(&self.state, exit, self.object) {
+            self.message("that way is locked");
+            return;
+        }
+
+        match self.move_object(self.object, dest) {
+            Ok(()) => self.look_at(dest),
+            Err(err) => self.message(&format!("could not go that way: {err}")),
+        }
+    }
+
+    /// Runs a verb on an object on behalf of this user.
+    ///
+    /// Returns false if the object has no such verb.
+    pub fn call(&mut self, object: usize, verb: &str) -> bool {
+        self.call_with(object, verb, &[])
+    }
+
+    /// Runs a verb with additional objects bound by name in its scope.
+    ///
+    /// Returns false if the object has no such verb.
+    pub fn call_with(&mut self, object: usize, verb: &str, bindings: &[(&str, usize)]) -> bool {
+        let mut host = Host::new(self.state.clone(), object, self.object);
+        host.window = self.window();
+
+        for (name, id) in bindings {
+            host.bind(name, *id);
+        }
+
+        self.run_verb(&host, verb)
+    }
+
+    /// Runs a scripted command's verb with the rest of the command line.
+    ///
+    /// Returns false if the command's object has no such verb.
+    pub fn call_command(&mut self, command: &ScriptCommand, args: &str) -> bool {
+        let mut host = Host::new(self.state.clone(), command.object, self.object);
+        host.window = self.window();
+        host.args = args.to_string();
+        self.run_verb(&host, &command.verb)
+    }
+
+    /// Runs a verb on a host's object and shows the verb's output.
+    ///
+    /// In maintenance mode, verbs run by non-wizards are skipped as though
+    /// they had run.
+    fn run_verb(&mut self, host: &Host, verb: &str) -> bool {
+        if self.check_writable().is_err() {
+            return matches!(self.state.get(host.self_id, verb), Some(Value::String(_)));
+        }
+
+        let mut host = host.clone();
+        host.reader = self.reader();
+
+        // scripts may run for a long time, so hand this worker's other tasks
+        // off to the rest of the runtime while this one waits on its own
+        let runtime = tokio::runtime::Handle::current();
+        let object = host.self_id;
+        let output = tokio::task::block_in_place(|| runtime.block_on(host.spawn(verb)));
+
+        let Some(output) = output else {
+            return false;
+        };
+
+        for announcement in output.announcements {
+            self.state.announce_from(object, &announcement);
+        }
+
+        if !output.emits.is_empty() {
+            // objects that aren't anywhere are treated as rooms themselves
+            let room = self.state.location(object).unwrap_or(object);
+            for message in output.emits {
+                self.state.emit_from(room, object, &message);
+            }
+        }
+
+        for message in output.messages {
+            self.message(&message);
+        }
+
+        for (package, data) in output.gmcp {
+            self.send_gmcp(&package, &data);
+        }
+
+        true
+    }
+}
+
+/// The reasons that a field cannot be written.
+#[derive(Debug)]
+pub enum FieldError {
+    NoSuchObject,
+    TooLarge { size: usize, max: usize },
+    TooManyFields { max: usize },
+}
+
+impl Display for FieldError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FieldError::NoSuchObject => write!(f, "no such object"),
+            FieldError::TooLarge { size, max } => {
+                write!(f, "value is {size} bytes (maximum is {max} bytes)")
+            }
+            FieldError::TooManyFields { max } => {
+                write!(f, "object already has the maximum of {max} fields")
+            }
+        }
+    }
+}
+
+/// The field holding how many coins an object has.
+pub const COINS_FIELD: &str = "coins";
+
+/// The reasons that [State::transfer] can fail.
+#[derive(Debug)]
+pub enum TransferError {
+    NoSuchObject,
+    InvalidAmount,
+    NotEnough { balance: i64 },
+}
+
+impl Display for TransferError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TransferError::NoSuchObject => write!(f, "no such object"),
+            TransferError::InvalidAmount => write!(f, "invalid amount of coins"),
+            TransferError::NotEnough { balance } => {
+                write!(f, "not enough coins (only {balance})")
+            }
+        }
+    }
+}
+
+/// The reasons that [State::move_object] can fail.
+pub enum MoveError {
+    NoSuchObject,
+    NoSuchDestination,
+    Recursive,
+    Worn,
+}
+
+impl Display for MoveError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MoveError::NoSuchObject => write!(f, "no such object"),
+            MoveError::NoSuchDestination => write!(f, "no such destination"),
+            MoveError::Recursive => write!(f, "cannot move an object into itself"),
+            MoveError::Worn => write!(f, "cannot move an object that is being worn"),
+        }
+    }
+}
+
+pub enum CommandError {
+    MissingArgument {
+        index: usize,
+    },
+    ExtraArgument {
+        index: usize,
+    },
+    InvalidArgument {
+        index: usize,
+        expected: String,
+    },
+    UnknownOption {
+        name: String,
+    },
+    InvalidOption {
+        name: String,
+        expected: String,
+    },
+    NoMatch {
+        name: String,
+    },
+    Field(FieldError),
+    Ambiguous {
+        name: String,
+        candidates: Vec<String>,
+    },
+    PermissionDenied,
+    Maintenance,
+}
+
+impl CommandError {
+    /// Gets the key of this error's message in the [locale] catalog, with
+    /// the values of its placeholders.
+    pub fn message(&self) -> (&'static str, Vec<(&'static str, String)>) {
+        match self {
+            CommandError::MissingArgument { index } => {
+                ("missing_argument", vec![("index", index.to_string())])
+            }
+            CommandError::ExtraArgument { index } => {
+                ("extra_argument", vec![("index", index.to_string())])
+            }
+            CommandError::InvalidArgument { index, expected } => (
+                "invalid_argument",
+                vec![("index", index.to_string()), ("expected", expected.clone())],
+            ),
+            CommandError::UnknownOption { name } => {
+                ("unknown_option", vec![("name", name.clone())])
+            }
+            CommandError::InvalidOption { name, expected } => (
+                "invalid_option",
+                vec![("name", name.clone()), ("expected", expected.clone())],
+            ),
+            CommandError::NoMatch { name } => ("no_match", vec![("name", name.clone())]),
+            CommandError::Ambiguous { name, candidates } => (
+                "ambiguous",
+                vec![
+                    ("name", name.clone()),
+                    ("candidates", candidates.join(", ")),
+                ],
+            ),
+            CommandError::Field(err) => ("field_error", vec![("error", err.to_string())]),
+            CommandError::PermissionDenied => ("permission_denied", Vec::new()),
+            CommandError::Maintenance => ("maintenance", Vec::new()),
+        }
+    }
+}
+
+impl Display for CommandError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let (key, args) = self.message();
+        let template = locale::english(key).unwrap_or(key);
+        write!(f, "{}", locale::format(template, &args))
+    }
+}
+
+impl From<FieldError> for CommandError {
+    fn from(err: FieldError) -> Self {
+        CommandError::Field(err)
+    }
+}
+
+pub type CommandResult<T> = Result<T, CommandError>;
+
+#[derive(Clone, Debug, Logos)]
+#[logos(skip r" +")]
+pub enum ArgumentKind {
+    #[regex("[0-9]+")]
+    Integer,
+
+    #[regex("#[0-9]+")]
+    Object,
+
+    #[regex("#[0-9]+:[a-zA-Z_]+")]
+    Verb,
+
+    #[regex("@[a-zA-Z_]+")]
+    Command,
+
+    #[regex(r#""([^"\\]|\\.)*""#)]
+    String,
+
+    #[regex("[a-zA-Z_]+")]
+    Ident,
+
+    #[regex("[a-zA-Z_]+=")]
+    Key,
+
+    #[regex("[a-zA-Z_]*[*?][a-zA-Z_*?]*")]
+    Pattern,
+
+    #[regex("[0-9]*d[0-9]+([+-][0-9]+)?")]
+    Dice,
+
+    #[token("false")]
+    False,
+
+    #[token("true")]
+    True,
+}
+
+#[derive(Clone, Debug)]
+pub enum Argument {
+    Bool(bool),
+    Integer(i64),
+    Object(usize),
+    Verb(usize, String),
+    String(String),
+    Ident(String),
+    Pattern(String),
+    Dice(Dice),
+}
+
+pub struct Arguments {
+    args: Vec<Argument>,
+
+    /// Named options, like `limit=20`, which may be given anywhere.
+    options: HashMap<String, Argument>,
+
+    /// The rest of the line after the arguments that were lexed.
+    rest: String,
+}
+
+impl Arguments {
+    pub fn new(words: &str) -> CommandResult<Self> {
+        Self::with_rest(words, usize::MAX)
+    }
+
+    /// Lexes up to a number of arguments, keeping the rest of the line as it
+    /// was typed.
+    pub fn with_rest(words: &str, count: usize) -> CommandResult<Self> {
+        let mut lexer = ArgumentKind::lexer(words);
+        let mut args = Vec::new();
+        let mut options = HashMap::new();
+
+        while args.len() < count {
+            let Some(arg) = lexer.next() else {
+                break;
+            };
+
+            let index = args.len();
+
+            let arg = if let Ok(arg) = arg {
+                arg
+            } else {
+                return Err(CommandError::InvalidArgument {
+                    index,
+                    expected: "argument".to_string(),
+                });
+            };
+
+            let slice = lexer.slice();
+            let ArgumentKind::Key = arg else {
+                args.push(Self::parse(arg, slice, index)?);
+                continue;
+            };
+
+            let name = slice[..slice.len() - 1].to_string();
+            let val = match lexer.next() {
+                Some(Ok(kind)) => Self::parse(kind, lexer.slice(), index),
+                _ => Err(CommandError::MissingArgument { index }),
+            };
+
+            let val = val.map_err(|err| CommandError::InvalidOption {
+                name: name.clone(),
+                expected: match err {
+                    CommandError::InvalidArgument { expected, .. } => expected,
+                    _ => "value".to_string(),
+                },
+            })?;
+
+            options.insert(name, val);
+        }
+
+        let rest = lexer.remainder().trim().to_string();
+        Ok(Self {
+            args,
+            options,
+            rest,
+        })
+    }
+
+    /// Converts one lexed token into an argument.
+    fn parse(kind: ArgumentKind, slice: &str, index: usize) -> CommandResult<Argument> {
+        Ok(match kind {
+            ArgumentKind::Integer => match slice.parse() {
+                Ok(val) => Argument::Integer(val),
+                Err(_) => {
+                    return Err(CommandError::InvalidArgument {
+                        index,
+                        expected: "integer".to_string(),
+                    })
+                }
+            },
+            ArgumentKind::Object => match slice[1..].parse() {
+                Ok(val) => Argument::Object(val),
+                Err(_) => {
+                    return Err(CommandError::InvalidArgument {
+                        index,
+                        expected: "object ID".to_string(),
+                    })
+                }
+            },
+            ArgumentKind::Verb => {
+                let (id, verb) = slice[1..].split_once(':').unwrap();
+                match id.parse() {
+                    Ok(id) => Argument::Verb(id, verb.to_string()),
+                    Err(_) => {
+                        return Err(CommandError::InvalidArgument {
+                            index,
+                            expected: "object ID".to_string(),
+                        })
+                    }
+                }
+            }
+            ArgumentKind::String => match unescape(&slice[1..slice.len() - 1]) {
+                Some(val) => Argument::String(val),
+                None => {
+                    return Err(CommandError::InvalidArgument {
+                        index,
+                        expected: "string with \\\", \\n, or \\\\ escapes".to_string(),
+                    })
+                }
+            },
+            ArgumentKind::Ident => Argument::Ident(slice.to_owned()),
+            ArgumentKind::Command => Argument::String(slice.to_owned()),
+            ArgumentKind::Pattern => Argument::Pattern(slice.to_owned()),
+            ArgumentKind::Dice => match Dice::parse(slice) {
+                Some(dice) => Argument::Dice(dice),
+                None => {
+                    return Err(CommandError::InvalidArgument {
+                        index,
+                        expected: "dice".to_string(),
+                    })
+                }
+            },
+            ArgumentKind::False => Argument::Bool(false),
+            ArgumentKind::True => Argument::Bool(true),
+            ArgumentKind::Key => {
+                return Err(CommandError::InvalidArgument {
+                    index,
+                    expected: "value".to_string(),
+                })
+            }
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.args.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.args.is_empty()
+    }
+
+    /// Gets the untokenized rest of the line, for commands whose last
+    /// parameter is [Param::Text].
+    pub fn rest(&self) -> &str {
+        &self.rest
+    }
+
+    /// Lists the names of the options that were given.
+    pub fn options(&self) -> impl Iterator<Item = &str> {
+        self.options.keys().map(String::as_str)
+    }
+
+    /// Gets the value of a named option, like the `20` in `limit=20`.
+    pub fn option(&self, name: &str) -> Option<&Argument> {
+        self.options.get(name)
+    }
+
+    pub fn get_option_integer(&self, name: &str) -> CommandResult<Option<i64>> {
+        match self.option(name) {
+            None => Ok(None),
+            Some(Argument::Integer(val)) => Ok(Some(*val)),
+            Some(_) => Err(CommandError::InvalidOption {
+                name: name.to_string(),
+                expected: "integer".to_string(),
+            }),
+        }
+    }
+
+    pub fn get_option_id(&self, name: &str) -> CommandResult<Option<usize>> {
+        let id = match self.option(name) {
+            None => return Ok(None),
+            Some(Argument::Object(id)) => return Ok(Some(*id)),
+            Some(Argument::Integer(id)) => (*id).try_into().ok(),
+            Some(_) => None,
+        };
+
+        match id {
+            Some(id) => Ok(Some(id)),
+            None => Err(CommandError::InvalidOption {
+                name: name.to_string(),
+                expected: "object ID".to_string(),
+            }),
+        }
+    }
+
+    pub fn get(&self, index: usize) -> CommandResult<Argument> {
+        self.args
+            .get(index)
+            .cloned()
+            .ok_or(CommandError::MissingArgument { index })
+    }
+
+    pub fn get_value(&self, index: usize) -> CommandResult<Value> {
+        match self.get(index)? {
+            Argument::Integer(val) => Ok(Value::Integer(val)),
+            Argument::Bool(val) => Ok(Value::Bool(val)),
+            Argument::String(val) => Ok(Value::String(val)),
+            _ => Err(CommandError::InvalidArgument {
+                index,
+                expected: "value".to_string(),
+            }),
+        }
+    }
 
     pub fn get_integer(&self, index: usize) -> CommandResult<i64> {
         match self.get(index)? {
@@ -434,189 +4283,2610 @@ impl Arguments {
         }
     }
 
-    pub fn get_id(&self, index: usize) -> CommandResult<usize> {
-        let id = self.get_integer(index)?;
+    pub fn get_id(&self, index: usize) -> CommandResult<usize> {
+        if let Argument::Object(id) = self.get(index)? {
+            return Ok(id);
+        }
+
+        let id = self.get_integer(index)?;
+
+        match id.try_into() {
+            Ok(val) => Ok(val),
+            Err(_) => Err(CommandError::InvalidArgument {
+                index,
+                expected: "object ID".to_string(),
+            }),
+        }
+    }
+
+    pub fn get_string(&self, index: usize) -> CommandResult<String> {
+        match self.get(index)? {
+            Argument::String(val) => Ok(val),
+            _ => Err(CommandError::InvalidArgument {
+                index,
+                expected: "string".to_string(),
+            }),
+        }
+    }
+
+    /// Gets a [glob] pattern, which may also be a plain identifier or string.
+    pub fn get_pattern(&self, index: usize) -> CommandResult<String> {
+        match self.get(index)? {
+            Argument::Pattern(val) | Argument::Ident(val) | Argument::String(val) => Ok(val),
+            _ => Err(CommandError::InvalidArgument {
+                index,
+                expected: "pattern".to_string(),
+            }),
+        }
+    }
+
+    pub fn get_verb(&self, index: usize) -> CommandResult<(usize, String)> {
+        match self.get(index)? {
+            Argument::Verb(id, verb) => Ok((id, verb)),
+            _ => Err(CommandError::InvalidArgument {
+                index,
+                expected: "verb, like #0:verb".to_string(),
+            }),
+        }
+    }
+
+    pub fn get_dice(&self, index: usize) -> CommandResult<Dice> {
+        match self.get(index)? {
+            Argument::Dice(dice) => Ok(dice),
+            _ => Err(CommandError::InvalidArgument {
+                index,
+                expected: "dice".to_string(),
+            }),
+        }
+    }
+
+    pub fn get_ident(&self, index: usize) -> CommandResult<String> {
+        match self.get(index)? {
+            Argument::Ident(val) => Ok(val),
+            _ => Err(CommandError::InvalidArgument {
+                index,
+                expected: "identifier".to_string(),
+            }),
+        }
+    }
+}
+
+/// Replaces the `\"`, `\n`, and `\\` escapes in a quoted string, or returns
+/// nothing if it has any other escapes.
+fn unescape(quoted: &str) -> Option<String> {
+    let mut unescaped = String::with_capacity(quoted.len());
+    let mut chars = quoted.chars();
+
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+
+        unescaped.push(match chars.next()? {
+            '"' => '"',
+            'n' => '\n',
+            '\\' => '\\',
+            _ => return None,
+        });
+    }
+
+    Some(unescaped)
+}
+
+pub type Command = fn(&mut User, Arguments) -> CommandResult<()>;
+
+pub fn say(user: &mut User, args: Arguments) -> CommandResult<()> {
+    let who = match user.state.name(user.object) {
+        Some(name) => name,
+        None => format!("#{}", user.object),
+    };
+
+    user.state.publish(Event::Say {
+        speaker: user.object,
+        name: who,
+        message: args.rest().to_string(),
+    });
+
+    Ok(())
+}
+
+pub fn emote(user: &mut User, args: Arguments) -> CommandResult<()> {
+    let who = match user.state.name(user.object) {
+        Some(name) => name,
+        None => format!("#{}", user.object),
+    };
+
+    user.state.publish(Event::Emote {
+        actor: user.object,
+        name: who,
+        action: args.rest().to_string(),
+    });
+
+    Ok(())
+}
+
+pub fn pose(user: &mut User, args: Arguments) -> CommandResult<()> {
+    let target = user.get_player(&args, 0)?;
+    let event = social::social(&user.state, args.rest(), user.object, Some(target));
+    user.state.publish(event);
+    Ok(())
+}
+
+pub fn page(user: &mut User, args: Arguments) -> CommandResult<()> {
+    let to = user.get_object(&args, 0)?;
+    if !user.state.exists(to) {
+        return Err(FieldError::NoSuchObject.into());
+    }
+
+    let message = args.rest().to_string();
+    let target = user.state.display_name(to);
+    user.message(&format!("You page {target}: {message}"));
+
+    user.state.publish(Event::Page {
+        from: user.object,
+        name: user.state.display_name(user.object),
+        to,
+        message,
+    });
+
+    Ok(())
+}
+
+pub fn roll(user: &mut User, args: Arguments) -> CommandResult<()> {
+    let dice = args.get_dice(0)?;
+    let rolls = dice.roll();
+    let total = dice.total(&rolls);
+
+    let rolls: Vec<String> = rolls.iter().map(ToString::to_string).collect();
+    let rolls = rolls.join(", ");
+
+    let who = match user.state.name(user.object) {
+        Some(name) => name,
+        None => format!("#{}", user.object),
+    };
+
+    let message = format!("{who} rolls {dice}: {rolls} (total {total})");
+    match user.state.location(user.object) {
+        Some(room) => user.state.emit_from(room, user.object, &message),
+        None => user.message(&message),
+    }
+
+    Ok(())
+}
+
+pub fn look(user: &mut User, args: Arguments) -> CommandResult<()> {
+    let id = match args.get(0) {
+        Ok(_) => user.get_object(&args, 0)?,
+        Err(_) => match user.state.location(user.object) {
+            Some(room) => room,
+            None => {
+                user.message("You are nowhere.");
+                return Ok(());
+            }
+        },
+    };
+
+    if !user.state.exists(id) {
+        user.message("no such object");
+        return Ok(());
+    }
+
+    user.look_at(id);
+    Ok(())
+}
+
+pub fn go(user: &mut User, args: Arguments) -> CommandResult<()> {
+    let exit = match args.get(0)? {
+        Argument::Ident(name) | Argument::String(name) => user.match_exit(name)?,
+        _ => user.get_object(&args, 0)?,
+    };
+
+    user.go(exit);
+    Ok(())
+}
+
+pub fn go_home(user: &mut User, _args: Arguments) -> CommandResult<()> {
+    let Some(home) = home::home_of(&user.state, user.object) else {
+        user.message("you have no home to go to");
+        return Ok(());
+    };
+
+    if user.state.location(user.object) == Some(home) {
+        user.message("you are already home");
+        return Ok(());
+    }
+
+    match user.move_object(user.object, home) {
+        Ok(()) => user.look_at(home),
+        Err(err) => user.message(&format!("could not go home: {err}")),
+    }
+
+    Ok(())
+}
+
+pub fn set_home(user: &mut User, _args: Arguments) -> CommandResult<()> {
+    let Some(here) = user.state.location(user.object) else {
+        user.message("you aren't anywhere to make your home");
+        return Ok(());
+    };
+
+    user.state
+        .set(user.object, home::HOME_FIELD, Value::Integer(here as i64))?;
+
+    let name = user.state.display_name(here);
+    user.message(&format!("your home is now {name}"));
+    Ok(())
+}
+
+pub fn wear(user: &mut User, args: Arguments) -> CommandResult<()> {
+    let item = user.get_object(&args, 0)?;
+    if user.state.location(item) != Some(user.object) {
+        user.message("you aren't carrying that");
+        return Ok(());
+    }
+
+    let slot = equipment::slot_of(&user.state, item);
+    let Some(slot) = slot.filter(|slot| equipment::slots(&user.state).contains(slot)) else {
+        user.message("that can't be worn");
+        return Ok(());
+    };
+
+    match equipment::worn_in(&user.state, user.object, &slot) {
+        Some(worn) if worn == item => user.message("you are already wearing that"),
+        Some(worn) => {
+            let worn = user.state.display_name(worn);
+            user.message(&format!("you are already wearing {worn} on your {slot}"));
+        }
+        None => {
+            let key = equipment::slot_key(&slot);
+            user.state
+                .set(user.object, &key, Value::Integer(item as i64))?;
+
+            let item = user.state.display_name(item);
+            user.message(&format!("you wear {item} on your {slot}"));
+        }
+    }
+
+    Ok(())
+}
+
+pub fn remove(user: &mut User, args: Arguments) -> CommandResult<()> {
+    let item = user.get_object(&args, 0)?;
+    let slot = equipment::slot_of(&user.state, item);
+
+    let worn = slot.filter(|slot| equipment::worn_in(&user.state, user.object, slot) == Some(item));
+    let Some(slot) = worn else {
+        user.message("you aren't wearing that");
+        return Ok(());
+    };
+
+    user.state.unset(user.object, &equipment::slot_key(&slot));
+    let item = user.state.display_name(item);
+    user.message(&format!("you take off {item}"));
+    Ok(())
+}
+
+pub fn show_equipment(user: &mut User, _args: Arguments) -> CommandResult<()> {
+    user.message("You are wearing:");
+    for (slot, item) in equipment::equipment(&user.state, user.object) {
+        let item = match item {
+            Some(item) => user.state.display_name(item),
+            None => "nothing".to_string(),
+        };
+
+        user.message(&format!("    {slot:<12}{item}"));
+    }
+
+    Ok(())
+}
+
+pub fn show_time(user: &mut User, _args: Arguments) -> CommandResult<()> {
+    let time = world_clock::world_time(&user.state);
+    let period = match world_clock::is_daytime(&user.state) {
+        true => "day",
+        false => "night",
+    };
+
+    let time = world_clock::format_world_time(time);
+    user.message(&format!("It is {time}, and it is {period}."));
+    Ok(())
+}
+
+pub fn give(user: &mut User, args: Arguments) -> CommandResult<()> {
+    let rest = args.rest().trim();
+    let (amount, rest) = rest.split_once(' ').unwrap_or((rest, ""));
+    let Ok(amount) = amount.parse::<i64>() else {
+        return Err(CommandError::InvalidArgument {
+            index: 0,
+            expected: "amount of coins".to_string(),
+        });
+    };
+
+    let rest = rest.trim_start();
+    let rest = rest
+        .strip_prefix("coins")
+        .or_else(|| rest.strip_prefix("coin"))
+        .unwrap_or(rest);
+
+    let Some(name) = rest.trim_start().strip_prefix("to ") else {
+        return Err(CommandError::MissingArgument { index: 1 });
+    };
+
+    let to = user.match_name(name.trim().to_string())?;
+    match user.state.transfer(user.object, to, amount) {
+        Ok(()) => {
+            let to = user.state.display_name(to);
+            user.message(&format!("you give {amount} coins to {to}"));
+        }
+        Err(err) => user.message(&format!("could not give coins: {err}")),
+    }
+
+    Ok(())
+}
+
+pub fn take(user: &mut User, args: Arguments) -> CommandResult<()> {
+    let id = user.get_object(&args, 0)?;
+    let here = user.state.location(user.object);
+
+    if id == user.object || user.state.location(id) == Some(user.object) {
+        user.message("you already have that");
+        return Ok(());
+    }
+
+    let takeable = here.is_some() && user.state.location(id) == here;
+    if !takeable || user.state.is_player(id) || user.state.destination(id).is_some() {
+        user.message("you can't take that");
+        return Ok(());
+    }
+
+    if !lock::allows(&user.state, id, user.object) {
+        user.message("you can't pick that up");
+        return Ok(());
+    }
+
+    match user.move_object(id, user.object) {
+        Ok(()) => user.message("taken"),
+        Err(err) => user.message(&err.to_string()),
+    }
+
+    Ok(())
+}
+
+pub fn drop_object(user: &mut User, args: Arguments) -> CommandResult<()> {
+    let id = user.get_object(&args, 0)?;
+    if user.state.location(id) != Some(user.object) {
+        user.message("you don't have that");
+        return Ok(());
+    }
+
+    let Some(here) = user.state.location(user.object) else {
+        user.message("there is nowhere to drop it");
+        return Ok(());
+    };
+
+    match user.move_object(id, here) {
+        Ok(()) => user.message("dropped"),
+        Err(err) => user.message(&err.to_string()),
+    }
+
+    Ok(())
+}
+
+pub fn open(user: &mut User, args: Arguments) -> CommandResult<()> {
+    let id = user.get_object(&args, 0)?;
+    if !matches!(user.state.get(id, "closed"), Some(Value::Bool(true))) {
+        user.message("that isn't closed");
+        return Ok(());
+    }
+
+    if !lock::allows(&user.state, id, user.object) {
+        user.message("that is locked");
+        return Ok(());
+    }
+
+    user.state.set(id, "closed", Value::Bool(false))?;
+    user.message("opened");
+    Ok(())
+}
+
+pub fn close(user: &mut User, args: Arguments) -> CommandResult<()> {
+    let id = user.get_object(&args, 0)?;
+    if !matches!(user.state.get(id, "closed"), Some(Value::Bool(false))) {
+        user.message("that isn't open");
+        return Ok(());
+    }
+
+    user.state.set(id, "closed", Value::Bool(true))?;
+    user.message("closed");
+    Ok(())
+}
+
+pub fn set_lock(user: &mut User, args: Arguments) -> CommandResult<()> {
+    let id = user.get_object(&args, 0)?;
+    user.check_write(id, lock::LOCK_FIELD)?;
+
+    let Some(key) = args.rest().trim_start().strip_prefix("with ") else {
+        return Err(CommandError::InvalidArgument {
+            index: 1,
+            expected: "with <key>".to_string(),
+        });
+    };
+
+    let resolve = |name: &str| match name.strip_prefix('#').map(str::parse) {
+        Some(Ok(id)) if user.state.exists(id) => Ok(id),
+        Some(_) => Err(format!("no such object {name}")),
+        None => user
+            .match_name(name.to_string())
+            .map_err(|err| err.to_string()),
+    };
+
+    let lock = match lock::parse(key, &resolve) {
+        Ok(lock) => lock,
+        Err(err) => {
+            return Err(CommandError::InvalidArgument {
+                index: 1,
+                expected: format!("lock expression ({err})"),
+            })
+        }
+    };
+
+    let json = serde_json::to_string(&lock).unwrap();
+    user.state.set(id, lock::LOCK_FIELD, Value::String(json))?;
+    user.message(&format!("locked #{id} with {lock}"));
+    Ok(())
+}
+
+pub fn unlock(user: &mut User, args: Arguments) -> CommandResult<()> {
+    let id = user.get_object(&args, 0)?;
+    user.check_write(id, lock::LOCK_FIELD)?;
+
+    if user.state.unset(id, lock::LOCK_FIELD) {
+        user.message(&format!("unlocked #{id}"));
+    } else {
+        user.message("that isn't locked");
+    }
+
+    Ok(())
+}
+
+pub fn help(user: &mut User, args: Arguments) -> CommandResult<()> {
+    if let Ok(topic) = args.get_pattern(0) {
+        return help_topic(user, &topic.to_lowercase());
+    }
+
+    user.message("Available commands:");
+
+    let mut commands: Vec<_> = user
+        .commands
+        .0
+        .values()
+        .map(|cmd| cmd.usage.clone())
+        .collect();
+    commands.sort();
+
+    for usage in commands {
+        user.message(&format!("    {usage}"));
+    }
+
+    let scripted = user.state.script_commands();
+    if !scripted.is_empty() {
+        user.message("Scripted commands:");
+
+        for (name, command) in scripted {
+            user.message(&format!(
+                "    {name} (#{}:{})",
+                command.object, command.verb
+            ));
+        }
+    }
+
+    let topics = user.state.help_topics();
+    if !topics.is_empty() {
+        user.message(&format!("Help topics: {}", topics.join(", ")));
+    }
+
+    user.message("Type \"help <topic>\" for more about a command or topic.");
+    Ok(())
+}
+
+/// Shows a command's usage and the help page for a topic.
+fn help_topic(user: &mut User, topic: &str) -> CommandResult<()> {
+    let usage = user.commands.0.get(topic).map(|cmd| cmd.usage.clone());
+    let page = user.state.help_page(topic);
+
+    if usage.is_none() && page.is_none() {
+        user.message(&format!("no help for {topic:?}"));
+        return Ok(());
+    }
+
+    if let Some(usage) = usage {
+        user.message(&user.usage_text(&usage));
+    }
+
+    for line in page.iter().flat_map(|page| page.lines()) {
+        user.message(&markup::render(line));
+    }
+
+    Ok(())
+}
+
+pub fn create(user: &mut User, _args: Arguments) -> CommandResult<()> {
+    if !user.take_quota() {
+        user.message("you have no quota left");
+        return Ok(());
+    }
+
+    let idx = user.state.create_owned(user.object).inspect_err(|_| {
+        user.refund_quota();
+    })?;
+
+    user.message(&format!("created object #{idx}"));
+    Ok(())
+}
+
+pub fn clone(user: &mut User, args: Arguments) -> CommandResult<()> {
+    let id = user.get_object(&args, 0)?;
+
+    if !user.take_quota() {
+        user.message("you have no quota left");
+        return Ok(());
+    }
+
+    match user.state.clone_object(id, user.object) {
+        Some(clone) => user.message(&format!("cloned object #{id} into #{clone}")),
+        None => {
+            user.refund_quota();
+            user.message("no such object");
+        }
+    }
+
+    Ok(())
+}
+
+pub fn destroy(user: &mut User, args: Arguments) -> CommandResult<()> {
+    let idx = user.get_object(&args, 0)?;
+
+    if idx == SYSTEM_OBJECT {
+        user.message("cannot destroy the system object");
+        return Ok(());
+    }
+
+    user.check_controls(idx)?;
+
+    if user.state.destroy(idx) {
+        user.message("success");
+    } else {
+        user.message("no such object");
+    }
+
+    Ok(())
+}
+
+pub fn undelete(user: &mut User, args: Arguments) -> CommandResult<()> {
+    let Ok(id) = args.get_id(0) else {
+        let now = now();
+        user.message("Recycled objects:");
+
+        // only the objects that the user could restore are listed
+        let recycled = user.state.recycled().into_iter();
+        let recycled = recycled.filter(|(id, _)| user.state.controls(user.object, *id));
+        for (id, at) in recycled.collect::<Vec<_>>() {
+            let name = user.state.display_name(id);
+            let ago = now.saturating_sub(at) / 60;
+            user.message(&format!("    {name}, destroyed {ago} minutes ago"));
+        }
+
+        return Ok(());
+    };
+
+    user.check_controls(id)?;
+
+    if user.state.undelete(id) {
+        user.message(&format!("restored object #{id}"));
+    } else {
+        user.message("no such recycled object");
+    }
+
+    Ok(())
+}
+
+pub fn list(user: &mut User, args: Arguments) -> CommandResult<()> {
+    let owner = user.get_object_option(&args, "owner")?;
+    let container = user.get_object_option(&args, "in")?;
+    let from = args.get_option_id("from")?.unwrap_or(0);
+    let to = args.get_option_id("to")?.unwrap_or(usize::MAX);
+    let limit = args.get_option_id("limit")?;
+
+    let mut ids = match (owner, container) {
+        (_, Some(container)) => {
+            let mut ids = user.state.visible_contents(user.object, container);
+            ids.retain(|id| (from..=to).contains(id));
+            if let Some(owner) = owner {
+                ids.retain(|id| {
+                    user.state.get(*id, "owner").and_then(|o| o.as_id()) == Some(owner)
+                });
+            }
+
+            ids
+        }
+        (Some(owner), None) => {
+            let mut ids = user.state.owned_by(owner);
+            ids.retain(|id| (from..=to).contains(id));
+            ids
+        }
+        (None, None) => user.state.list_range(from, to),
+    };
+
+    let total = ids.len();
+    if let Some(limit) = limit {
+        ids.truncate(limit);
+    }
+
+    user.message("Objects:");
+
+    let count = ids.len();
+    for id in ids {
+        let number = format!("#{id}");
+        let mut line = mxp::Line::new();
+        line.push("    ").link(&format!("@show {number}"), &number);
+
+        // pad the IDs into a column, like `#{:<4}` would
+        if let Some(name) = user.state.name(id) {
+            let padding = " ".repeat(5usize.saturating_sub(number.len()));
+            line.push(&format!("{padding} ({name})"));
+        }
+
+        user.message_line(&line);
+    }
+
+    match count {
+        1 => user.message("1 object"),
+        count => user.message(&format!("{count} objects")),
+    }
+
+    if total > count {
+        user.message(&format!("({} more not shown)", total - count));
+    }
+
+    Ok(())
+}
+
+pub fn audit(user: &mut User, args: Arguments) -> CommandResult<()> {
+    let player = match args.get(0) {
+        Ok(_) => user.get_object(&args, 0)?,
+        Err(_) => user.object,
+    };
+
+    let header = format!("Objects owned by {}:", user.state.display_name(player));
+    user.message(&header);
+
+    let owned = user.state.owned_by(player);
+    let lines: Vec<_> = owned
+        .iter()
+        .map(|id| {
+            let name = user.state.name(*id).unwrap_or_default();
+
+            let line = match user.state.location(*id) {
+                Some(location) => {
+                    let location = user.state.display_name(location);
+                    format!("    #{:<4} {:<24} in {}", id, name, location)
+                }
+                None => format!("    #{:<4} {}", id, name),
+            };
+
+            line.trim_end().to_string()
+        })
+        .collect();
+
+    for line in lines {
+        user.message(&line);
+    }
+
+    match owned.len() {
+        1 => user.message("1 object"),
+        count => user.message(&format!("{count} objects")),
+    }
+
+    Ok(())
+}
+
+/// How many sessions `@lastlog` lists.
+pub const LASTLOG_LENGTH: usize = 20;
+
+pub fn lastlog(user: &mut User, args: Arguments) -> CommandResult<()> {
+    user.check_wizard()?;
+
+    let player = match args.get(0) {
+        Ok(_) => Some(user.get_object(&args, 0)?),
+        Err(_) => None,
+    };
+
+    let entries = user.state.recent_connections(player, LASTLOG_LENGTH);
+    if entries.is_empty() {
+        user.message("No connections have been logged.");
+        return Ok(());
+    }
+
+    for LogEntry {
+        event,
+        player,
+        addr,
+        at,
+    } in entries
+    {
+        let at = clock::format_time(at as i64);
+        let verb = match event {
+            SessionEvent::Connect => "connected from",
+            SessionEvent::Disconnect => "disconnected from",
+        };
+
+        let player = user.state.display_name(player);
+        user.message(&format!("{at}  {player} {verb} {addr}"));
+    }
+
+    Ok(())
+}
+
+/// Describes how long ago a UNIX time was, like `2023-09-30 12:34:56 UTC
+/// (3h 4m ago)`.
+fn format_ago(at: i64) -> String {
+    let ago = (now() as i64 - at).max(0);
+    format!(
+        "{} ({} ago)",
+        clock::format_time(at),
+        clock::format_duration(ago)
+    )
+}
+
+pub fn laston(user: &mut User, args: Arguments) -> CommandResult<()> {
+    let player = user.get_player(&args, 0)?;
+    let name = user.state.display_name(player);
+    let time = |field| user.state.get(player, field).and_then(|at| at.as_integer());
+
+    match (time("last_connected"), time("last_disconnected")) {
+        (None, _) => user.message(&format!("{name} has never connected")),
+        (Some(on), _) if user.state.is_connected(player) => {
+            user.message(&format!("{name} is connected, since {}", format_ago(on)));
+        }
+        (Some(on), off) => {
+            user.message(&format!("{name} last connected {}", format_ago(on)));
+            if let Some(off) = off {
+                user.message(&format!("{name} last disconnected {}", format_ago(off)));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+pub fn whereis(user: &mut User, args: Arguments) -> CommandResult<()> {
+    let player = user.get_player(&args, 0)?;
+    let name = user.state.display_name(player);
+
+    if user.state.is_connected(player) {
+        match user.state.location(player) {
+            Some(room) => {
+                let room = user.state.display_name(room);
+                user.message(&format!("{name} is in {room}"));
+            }
+            None => user.message(&format!("{name} is nowhere")),
+        }
+
+        return Ok(());
+    }
+
+    let last = |field| {
+        user.state
+            .get(player, field)
+            .and_then(|val| val.as_integer())
+    };
+    match (last("last_location"), last("last_disconnected")) {
+        (Some(room), Some(at)) => {
+            let room = user.state.display_name(room as usize);
+            let at = format_ago(at);
+            user.message(&format!("{name} was last seen in {room}, {at}"));
+        }
+        _ => user.message(&format!("{name} hasn't been seen")),
+    }
+
+    Ok(())
+}
+
+/// How many events `@recap` lists if it isn't given a count.
+pub const RECAP_LENGTH: usize = 20;
+
+pub fn recap(user: &mut User, args: Arguments) -> CommandResult<()> {
+    let count = match args.get(0) {
+        Ok(_) => match usize::try_from(args.get_integer(0)?) {
+            Ok(count) if count > 0 => count.min(HISTORY_LENGTH),
+            _ => {
+                return Err(CommandError::InvalidArgument {
+                    index: 0,
+                    expected: "positive count".to_string(),
+                })
+            }
+        },
+        Err(_) => RECAP_LENGTH,
+    };
+
+    let entries = user.state.recap(user.object, count);
+    if entries.is_empty() {
+        user.message("Nothing has been said recently.");
+        return Ok(());
+    }
+
+    for HistoryEntry { event, at } in entries {
+        if let Some(text) = event.render_for(user.object) {
+            let at = clock::format_time(at as i64);
+            user.message(&format!("{at}  {text}"));
+        }
+    }
+
+    Ok(())
+}
+
+pub fn stats(user: &mut User, _args: Arguments) -> CommandResult<()> {
+    user.check_wizard()?;
+
+    let stats = user.state.stats();
+    let lines = [
+        format!("Objects:          {}", stats.objects),
+        format!("Database size:    {}", format_size(stats.db_size)),
+        format!("Connected users:  {}", stats.connected),
+        format!(
+            "Uptime:           {}",
+            clock::format_duration(stats.uptime as i64)
+        ),
+        format!("Commands run:     {}", stats.commands_run),
+        format!("Verbs run:        {}", stats.scripts_run),
+    ];
+
+    for line in lines {
+        user.message(&line);
+    }
+
+    Ok(())
+}
+
+/// Formats a count of bytes with the largest binary unit that fits it.
+fn format_size(bytes: u64) -> String {
+    const UNITS: &[&str] = &["KiB", "MiB", "GiB", "TiB"];
+
+    if bytes < 1024 {
+        return format!("{bytes} B");
+    }
+
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = UNITS[0];
+    for next in &UNITS[1..] {
+        if size < 1024.0 {
+            break;
+        }
+
+        size /= 1024.0;
+        unit = next;
+    }
+
+    format!("{size:.1} {unit}")
+}
+
+pub fn announce(user: &mut User, args: Arguments) -> CommandResult<()> {
+    user.check_wizard()?;
+    user.state.post_announcement(user.object, args.rest());
+    Ok(())
+}
+
+pub fn boot(user: &mut User, args: Arguments) -> CommandResult<()> {
+    user.check_wizard()?;
+
+    let player = user.get_object(&args, 0)?;
+    let name = user.state.display_name(player);
+    if !user.state.is_connected(player) {
+        user.message(&format!("{name} is not connected"));
+        return Ok(());
+    }
+
+    user.state.publish(Event::Boot { player });
+    user.message(&format!("booted {name}"));
+    Ok(())
+}
+
+pub fn list_tasks(user: &mut User, _args: Arguments) -> CommandResult<()> {
+    let mut tasks = user.state.tasks().list();
+    if !user.is_wizard() {
+        tasks.retain(|(_, task)| task.player == user.object);
+    }
+
+    if tasks.is_empty() {
+        user.message("no tasks are running");
+        return Ok(());
+    }
+
+    user.message("Tasks:");
+    for (id, task) in tasks {
+        let player = user.state.display_name(task.player);
+        let waiting = match task.waiting {
+            tasks::Waiting::Until(at) => format!("wakes at {}", clock::format_time(at as i64)),
+            tasks::Waiting::Input => "waiting for input".to_string(),
+        };
+
+        let verb = format!("#{}:{}", task.object, task.verb);
+        user.message(&format!("    {id:<6}{verb:<24}{player}, {waiting}"));
+    }
+
+    Ok(())
+}
+
+pub fn kill_task(user: &mut User, args: Arguments) -> CommandResult<()> {
+    let id = args.get_id(0)? as u64;
+    let Some(task) = user.state.tasks().get(id) else {
+        user.message("no such task");
+        return Ok(());
+    };
+
+    if task.player != user.object && !user.is_wizard() {
+        return Err(CommandError::PermissionDenied);
+    }
+
+    user.state.tasks().kill(id);
+    user.message(&format!("killed task {id}"));
+    Ok(())
+}
+
+pub fn shutdown(user: &mut User, _args: Arguments) -> CommandResult<()> {
+    user.check_wizard()?;
+    user.state.announce("The server is shutting down.");
+    user.state.shutdown();
+    Ok(())
+}
+
+pub fn json(user: &mut User, args: Arguments) -> CommandResult<()> {
+    let Ok(mode) = args.get_ident(0) else {
+        if user.is_json() {
+            user.message("this connection speaks JSON lines");
+        } else {
+            user.message("this connection speaks text");
+        }
+
+        return Ok(());
+    };
+
+    match mode.as_str() {
+        "on" => user.set_json(true),
+        "off" => user.set_json(false),
+        _ => {
+            return Err(CommandError::InvalidArgument {
+                index: 0,
+                expected: "on or off".to_string(),
+            })
+        }
+    }
+
+    Ok(())
+}
+
+pub fn origins(user: &mut User, args: Arguments) -> CommandResult<()> {
+    let field = spoof::SHOW_ORIGINS_FIELD;
+    let Ok(mode) = args.get_ident(0) else {
+        if matches!(user.state.get(user.object, field), Some(Value::Bool(true))) {
+            user.message("messages show the objects that they came from");
+        } else {
+            user.message("messages don't show where they came from");
+        }
+
+        return Ok(());
+    };
+
+    match mode.as_str() {
+        "on" => {
+            user.state.set(user.object, field, Value::Bool(true))?;
+            user.message("messages now show the objects that they came from");
+        }
+        "off" => {
+            user.state.unset(user.object, field);
+            user.message("messages no longer show where they came from");
+        }
+        _ => {
+            return Err(CommandError::InvalidArgument {
+                index: 0,
+                expected: "on or off".to_string(),
+            })
+        }
+    }
+
+    Ok(())
+}
+
+pub fn prefs(user: &mut User, args: Arguments) -> CommandResult<()> {
+    let Ok(name) = args.get_ident(0) else {
+        let prefs = Prefs::of(&user.state, user.object);
+        let on = |flag| if flag { "on" } else { "off" };
+        let width = match prefs.width {
+            Some(0) => "never wrap".to_string(),
+            Some(width) => width.to_string(),
+            None => "auto".to_string(),
+        };
+
+        user.message(&format!("width: {width}"));
+        user.message(&format!("color: {}", on(prefs.color)));
+        user.message(&format!("ascii: {}", on(prefs.ascii)));
+
+        let locale = user.state.get(user.object, locale::LOCALE_FIELD);
+        match locale.as_ref().and_then(Value::as_string) {
+            Some(locale) => user.message(&format!("locale: {locale}")),
+            None => user.message("locale: default"),
+        }
+
+        return Ok(());
+    };
+
+    if name == "locale" {
+        let locale = args.get_pattern(1)?;
+        if locale == "default" {
+            user.state.unset(user.object, locale::LOCALE_FIELD);
+        } else if locale::is_locale(&locale) {
+            user.state
+                .set(user.object, locale::LOCALE_FIELD, Value::String(locale))?;
+        } else {
+            return Err(CommandError::InvalidArgument {
+                index: 1,
+                expected: "locale code or default".to_string(),
+            });
+        }
+
+        user.message("set your locale preference");
+        return Ok(());
+    }
+
+    let field = match name.as_str() {
+        "width" => prefs::WIDTH_FIELD,
+        "color" => prefs::COLOR_FIELD,
+        "ascii" => prefs::ASCII_FIELD,
+        _ => {
+            return Err(CommandError::InvalidArgument {
+                index: 0,
+                expected: "width, color, ascii, or locale".to_string(),
+            })
+        }
+    };
+
+    let value = match (name.as_str(), args.get(1)?) {
+        ("width", Argument::Ident(auto)) if auto == "auto" => None,
+        ("width", Argument::Integer(width)) if (0..=prefs::MAX_WIDTH).contains(&width) => {
+            Some(Value::Integer(width))
+        }
+        ("width", _) => {
+            return Err(CommandError::InvalidArgument {
+                index: 1,
+                expected: format!("width up to {} or auto", prefs::MAX_WIDTH),
+            })
+        }
+        (_, Argument::Ident(flag)) if flag == "on" => Some(Value::Bool(true)),
+        (_, Argument::Ident(flag)) if flag == "off" => Some(Value::Bool(false)),
+        _ => {
+            return Err(CommandError::InvalidArgument {
+                index: 1,
+                expected: "on or off".to_string(),
+            })
+        }
+    };
+
+    match value {
+        Some(value) => user.state.set(user.object, field, value)?,
+        None => {
+            user.state.unset(user.object, field);
+        }
+    }
+
+    user.message(&format!("set your {name} preference"));
+    Ok(())
+}
+
+pub fn prompt(user: &mut User, args: Arguments) -> CommandResult<()> {
+    if args.is_empty() {
+        let prompt = user.render_prompt();
+        user.message(&format!("Your prompt is {prompt:?}."));
+        return Ok(());
+    }
+
+    user.check_write(user.object, "prompt")?;
+
+    if let Ok(ident) = args.get_ident(0) {
+        if ident != "default" {
+            return Err(CommandError::InvalidArgument {
+                index: 0,
+                expected: "a string or default".to_string(),
+            });
+        }
+
+        user.state.unset(user.object, "prompt");
+        user.message("Your prompt is the default again.");
+        return Ok(());
+    }
+
+    let prompt = args.get_string(0)?;
+    user.state
+        .set(user.object, "prompt", Value::String(prompt))?;
+    user.message("Set your prompt.");
+    Ok(())
+}
+
+pub fn maintenance(user: &mut User, args: Arguments) -> CommandResult<()> {
+    let Ok(mode) = args.get_ident(0) else {
+        if user.state.in_maintenance() {
+            user.message("The server is in maintenance mode.");
+        } else {
+            user.message("The server is not in maintenance mode.");
+        }
+
+        return Ok(());
+    };
+
+    user.check_wizard()?;
+
+    let enabled = match mode.as_str() {
+        "on" => true,
+        "off" => false,
+        _ => {
+            return Err(CommandError::InvalidArgument {
+                index: 0,
+                expected: "on or off".to_string(),
+            })
+        }
+    };
+
+    if enabled == user.state.in_maintenance() {
+        user.message("nothing changed");
+        return Ok(());
+    }
+
+    user.state
+        .set(SYSTEM_OBJECT, "maintenance", Value::Bool(enabled))?;
+
+    let announcement = if enabled {
+        "The server is now in maintenance mode, so changes are disabled."
+    } else {
+        "Maintenance is over, so changes are enabled again."
+    };
+
+    user.state.announce(announcement);
+
+    Ok(())
+}
+
+pub fn quota(user: &mut User, args: Arguments) -> CommandResult<()> {
+    let player = match args.get(0) {
+        Ok(_) => user.get_object(&args, 0)?,
+        Err(_) => user.object,
+    };
+
+    if let Ok(quota) = args.get_integer(1) {
+        user.check_wizard()?;
+        user.state.set(player, "quota", Value::Integer(quota))?;
+    }
+
+    let quota = user.state.quota(player);
+    let player = user.state.display_name(player);
+    user.message(&format!("{player} may create {quota} more objects"));
+    Ok(())
+}
+
+pub fn find(user: &mut User, args: Arguments) -> CommandResult<()> {
+    let field = args.get_ident(0)?;
+    let (pattern, exact) = match args.get(1)? {
+        Argument::String(val) | Argument::Ident(val) | Argument::Pattern(val) => (val, None),
+        Argument::Integer(val) => (val.to_string(), Some(val)),
+        Argument::Object(id) => (id.to_string(), Some(id as i64)),
+        Argument::Bool(val) => (val.to_string(), None),
+        Argument::Dice(dice) => (dice.to_string(), None),
+        Argument::Verb(id, verb) => (format!("#{id}:{verb}"), None),
+    };
+
+    let mut found = user.state.find(&field, &pattern);
+    found.retain(|id| user.state.can_read(user.object, *id, &field));
+
+    // numbers are searched for exactly rather than by their digits
+    if let Some(exact) = exact {
+        found.retain(|id| {
+            let val = user.state.get(*id, &field);
+            val.and_then(|val| val.as_integer()) == Some(exact)
+        });
+    }
+
+    if found.is_empty() {
+        user.message("no objects found");
+        return Ok(());
+    }
+
+    user.message(&format!("Objects with {field} matching {pattern:?}:"));
+
+    for id in found {
+        let val = user.state.get(id, &field);
+
+        let msg = match val {
+            Some(val) => format!("    #{:<4} {}", id, val),
+            None => format!("    #{}", id),
+        };
+
+        user.message(&msg);
+    }
+
+    Ok(())
+}
+
+/// The number of characters of a value that [show] displays before
+/// truncating it.
+pub const MAX_SHOW_LEN: usize = 60;
+
+pub fn show(user: &mut User, args: Arguments) -> CommandResult<()> {
+    let id = user.get_object(&args, 0)?;
+
+    if !user.state.exists(id) {
+        user.message("no such object");
+        return Ok(());
+    }
+
+    let pattern = match args.get(1) {
+        Ok(_) => Some(args.get_pattern(1)?),
+        Err(_) => None,
+    };
+
+    let mut fields = user.state.show(id);
+    fields.retain(|(key, _val)| user.state.can_read(user.object, id, key));
+    fields.sort_by(|a, b| a.0.cmp(&b.0));
+
+    if let Some(pattern) = pattern.as_ref() {
+        fields.retain(|(key, _val)| glob::matches(pattern, key));
+    }
+
+    match pattern {
+        Some(pattern) => user.message(&format!("Fields on object #{id} matching {pattern}")),
+        None => user.message(&format!("Fields on object #{id}")),
+    }
+
+    for (key, val) in fields.iter() {
+        let val = val.to_string();
+        let len = val.chars().count();
+
+        let val = if len > MAX_SHOW_LEN {
+            let shown: String = val.chars().take(MAX_SHOW_LEN).collect();
+            format!("{shown}... ({len} characters)")
+        } else {
+            val
+        };
+
+        user.message(&format!("    {:<20}{}", key, val));
+    }
+
+    match fields.len() {
+        1 => user.message("1 field"),
+        count => user.message(&format!("{count} fields")),
+    }
+
+    Ok(())
+}
+
+/// The verbs that the server runs by itself, with the objects that it binds
+/// in their scope.
+pub const HOOK_VERBS: &[(&str, &[&str])] = &[
+    ("on_connect", &[]),
+    ("on_disconnect", &[]),
+    ("on_enter", &["mover"]),
+    ("on_exit", &["mover"]),
+    ("describe", &["viewer"]),
+    ("heartbeat", &[]),
+    ("server_tick", &[]),
+    ("take_turn", &["group"]),
+    ("world_tick", &[]),
+];
+
+pub fn examine(user: &mut User, args: Arguments) -> CommandResult<()> {
+    let id = user.get_object(&args, 0)?;
+
+    if !user.state.exists(id) {
+        user.message("no such object");
+        return Ok(());
+    }
+
+    let state = user.state.clone();
+    let describe = |field| match state.get(id, field).and_then(|val| val.as_id()) {
+        Some(other) => state.display_name(other),
+        None => "none".to_string(),
+    };
+
+    user.message(&state.display_name(id));
+    user.message(&format!("Owner: {}", describe("owner")));
+    user.message(&format!("Parent: {}", describe("parent")));
+    user.message(&format!("Location: {}", describe("location")));
+
+    let mut flags = Vec::new();
+    for flag in [
+        "wizard",
+        "guest",
+        "console",
+        "npc",
+        "dark",
+        "invisible",
+        "closed",
+    ] {
+        if matches!(state.get(id, flag), Some(Value::Bool(true))) {
+            flags.push(flag);
+        }
+    }
+
+    if state.has_password(id) {
+        flags.push("player");
+    }
+
+    if state.is_connected(id) {
+        flags.push("connected");
+    }
+
+    if state.destination(id).is_some() {
+        flags.push("exit");
+    }
+
+    if equipment::is_worn(&state, id) {
+        flags.push("worn");
+    }
+
+    match flags.is_empty() {
+        true => user.message("Flags: none"),
+        false => user.message(&format!("Flags: {}", flags.join(", "))),
+    }
+
+    if let Some(lock) = lock::lock_of(&state, id) {
+        user.message(&format!("Lock: {lock}"));
+    }
+
+    let mut fields = state.show(id);
+    fields.retain(|(key, _val)| state.can_read(user.object, id, key));
+    fields.sort_by(|a, b| a.0.cmp(&b.0));
+
+    // verbs are fields holding scripts, so only the ones that something
+    // runs, or that name their language, can be told apart from text
+    let commands = state.script_commands().into_iter();
+    let commands: Vec<_> = commands
+        .filter(|(_, command)| command.object == id)
+        .collect();
+    let (verbs, fields): (Vec<_>, Vec<_>) = fields.into_iter().partition(|(key, val)| {
+        let Value::String(src) = val else {
+            return false;
+        };
+
+        src.starts_with("#!")
+            || HOOK_VERBS.iter().any(|(hook, _)| hook == key)
+            || commands.iter().any(|(_, command)| command.verb == *key)
+    });
+
+    user.message("Fields:");
+    for (key, val) in fields.iter() {
+        user.message(&format!("    {:<20}{}", key, val.type_name()));
+    }
+
+    user.message("Verbs:");
+    for (key, val) in verbs.iter() {
+        let src = val.as_string().map(String::as_str).unwrap_or_default();
+        let (lang, _) = script::language(src);
+
+        let mut specs = Vec::new();
+        if let Some((_, bindings)) = HOOK_VERBS.iter().find(|(hook, _)| hook == key) {
+            specs.extend(bindings.iter().map(|binding| binding.to_string()));
+        }
+
+        for (name, command) in commands.iter() {
+            if command.verb == *key {
+                specs.push(format!("{name} <args>"));
+            }
+        }
+
+        match specs.is_empty() {
+            true => user.message(&format!("    {key:<20}{lang}")),
+            false => user.message(&format!("    {key:<20}{lang} ({})", specs.join("; "))),
+        }
+    }
+
+    Ok(())
+}
+
+pub fn set(user: &mut User, args: Arguments) -> CommandResult<()> {
+    let id = user.get_object(&args, 0)?;
+
+    // parse every key/value pair up-front so that nothing is set if any of
+    // them are invalid
+    let mut fields = vec![(args.get_ident(1)?, args.get_value(2)?)];
+    let mut index = 3;
+    while args.get(index).is_ok() {
+        fields.push((args.get_ident(index)?, args.get_value(index + 1)?));
+        index += 2;
+    }
+
+    for (key, _val) in fields.iter() {
+        user.check_write(id, key)?;
+    }
+
+    user.state.set_many(id, fields)?;
+
+    Ok(())
+}
+
+pub fn unset(user: &mut User, args: Arguments) -> CommandResult<()> {
+    let id = user.get_object(&args, 0)?;
+    let key = args.get_ident(1)?;
+
+    if !user.state.exists(id) {
+        user.message("No such object");
+        return Ok(());
+    }
+
+    user.check_write(id, &key)?;
+
+    if user.state.unset(id, &key) {
+        user.message(&format!("removed {key} from object #{id}"));
+    } else {
+        user.message(&format!("object #{id} has no field {key}"));
+    }
+
+    Ok(())
+}
+
+pub fn get(user: &mut User, args: Arguments) -> CommandResult<()> {
+    let id = user.get_object(&args, 0)?;
+    let key = args.get_ident(1)?;
+
+    user.check_read(id, &key)?;
+
+    match user.state.get(id, &key) {
+        Some(val) => user.message(&format!("value: {:?}", val)),
+        None => user.message("value: <none>"),
+    }
+
+    Ok(())
+}
+
+pub fn chmod(user: &mut User, args: Arguments) -> CommandResult<()> {
+    let id = user.get_object(&args, 0)?;
+    let key = args.get_ident(1)?;
+
+    let Ok(flags) = args.get_pattern(2) else {
+        let perms = user.state.field_perms(id, &key);
+        user.message(&format!("{key} on object #{id} is {perms}"));
+        return Ok(());
+    };
+
+    let Some(perms) = FieldPerms::parse(&flags) else {
+        return Err(CommandError::InvalidArgument {
+            index: 2,
+            expected: "permission flags (r, w, rw, or -)".to_string(),
+        });
+    };
+
+    user.check_controls(id)?;
+    user.state.chmod(id, &key, perms);
+    user.message(&format!("{key} on object #{id} is now {perms}"));
+    Ok(())
+}
+
+pub fn bootstrap(user: &mut User, args: Arguments) -> CommandResult<()> {
+    let token = args.get_pattern(0)?;
+
+    if !grpc::tokens_match(&token, user.state.wizard_token()) {
+        return Err(CommandError::PermissionDenied);
+    }
+
+    user.state.set(user.object, "wizard", Value::Bool(true))?;
+    user.message("you are now a wizard");
+    Ok(())
+}
+
+pub fn add_command(user: &mut User, args: Arguments) -> CommandResult<()> {
+    user.check_wizard()?;
+
+    let name = args.get_pattern(0)?.to_lowercase();
+    let (object, verb) = args.get_verb(1)?;
+
+    if user.commands.0.contains_key(&name) {
+        return Err(CommandError::InvalidArgument {
+            index: 0,
+            expected: "name that isn't a built-in command".to_string(),
+        });
+    }
+
+    if !user.state.exists(object) {
+        return Err(FieldError::NoSuchObject.into());
+    }
+
+    user.state
+        .add_command(&name, ScriptCommand { object, verb });
+    user.message(&format!("added command {name}"));
+    Ok(())
+}
+
+pub fn remove_command(user: &mut User, args: Arguments) -> CommandResult<()> {
+    user.check_wizard()?;
+
+    let name = args.get_pattern(0)?.to_lowercase();
+
+    if user.state.remove_command(&name) {
+        user.message(&format!("removed command {name}"));
+    } else {
+        user.message("no such command");
+    }
+
+    Ok(())
+}
+
+pub fn alias(user: &mut User, args: Arguments) -> CommandResult<()> {
+    let name = args.get_pattern(0)?;
+    let expansion = args.get_pattern(1)?;
+
+    if name.is_empty() || name.contains(char::is_whitespace) {
+        return Err(CommandError::InvalidArgument {
+            index: 0,
+            expected: "single word".to_string(),
+        });
+    }
+
+    user.state
+        .set(user.object, &alias_key(&name), Value::String(expansion))?;
+
+    user.message(&format!("{name} is now an alias"));
+    Ok(())
+}
+
+pub fn aliases(user: &mut User, _args: Arguments) -> CommandResult<()> {
+    let mut aliases: Vec<_> = user
+        .state
+        .show(user.object)
+        .into_iter()
+        .filter_map(|(key, val)| Some((key.strip_prefix("alias-")?.to_string(), val)))
+        .collect();
+
+    if aliases.is_empty() {
+        user.message("you have no aliases");
+        return Ok(());
+    }
+
+    aliases.sort_by(|a, b| a.0.cmp(&b.0));
+
+    user.message("Aliases:");
+    for (name, expansion) in aliases {
+        user.message(&format!("    {name} => {expansion}"));
+    }
+
+    Ok(())
+}
+
+pub fn unalias(user: &mut User, args: Arguments) -> CommandResult<()> {
+    let name = args.get_pattern(0)?;
+
+    if user.state.unset(user.object, &alias_key(&name)) {
+        user.message(&format!("removed alias {name}"));
+    } else {
+        user.message("no such alias");
+    }
+
+    Ok(())
+}
+
+pub fn set_social(user: &mut User, args: Arguments) -> CommandResult<()> {
+    user.check_wizard()?;
+
+    let name = args.get_pattern(0)?.to_lowercase();
+    if name.is_empty() || name.contains(char::is_whitespace) || name.starts_with('@') {
+        return Err(CommandError::InvalidArgument {
+            index: 0,
+            expected: "single word".to_string(),
+        });
+    }
+
+    let mut social = user.state.social(&name).unwrap_or_default();
+    let template = Some(args.rest().to_string());
+    match args.get_ident(1)?.as_str() {
+        "alone" => social.alone = template,
+        "at" => social.at = template,
+        _ => {
+            return Err(CommandError::InvalidArgument {
+                index: 1,
+                expected: "alone or at".to_string(),
+            })
+        }
+    }
+
+    user.state.set_social(&name, &social);
+    user.message(&format!("set the social {name}"));
+    Ok(())
+}
+
+pub fn socials(user: &mut User, args: Arguments) -> CommandResult<()> {
+    if let Ok(name) = args.get_pattern(0) {
+        let name = name.to_lowercase();
+        let Some(social) = user.state.social(&name) else {
+            user.message("no such social");
+            return Ok(());
+        };
+
+        let none = || "(none)".to_string();
+        user.message(&format!("{name}:"));
+        user.message(&format!("    alone: {}", social.alone.unwrap_or_else(none)));
+        user.message(&format!("    at:    {}", social.at.unwrap_or_else(none)));
+        return Ok(());
+    }
+
+    let socials = user.state.socials();
+    if socials.is_empty() {
+        user.message("there are no socials");
+        return Ok(());
+    }
+
+    let names: Vec<_> = socials.into_iter().map(|(name, _)| name).collect();
+    user.message(&format!("Socials: {}", names.join(", ")));
+
+    Ok(())
+}
+
+pub fn unsocial(user: &mut User, args: Arguments) -> CommandResult<()> {
+    user.check_wizard()?;
+
+    let name = args.get_pattern(0)?.to_lowercase();
+    if user.state.remove_social(&name) {
+        user.message(&format!("removed the social {name}"));
+    } else {
+        user.message("no such social");
+    }
+
+    Ok(())
+}
+
+/// Gets a locale code and message key from a command's first arguments.
+fn translation_args(args: &Arguments) -> CommandResult<(String, String)> {
+    let locale = args.get_pattern(0)?;
+    if !locale::is_locale(&locale) {
+        return Err(CommandError::InvalidArgument {
+            index: 0,
+            expected: "locale code".to_string(),
+        });
+    }
+
+    let key = args.get_pattern(1)?;
+    if locale::english(&key).is_none() {
+        return Err(CommandError::InvalidArgument {
+            index: 1,
+            expected: "message key from @messages".to_string(),
+        });
+    }
+
+    Ok((locale, key))
+}
+
+pub fn translate(user: &mut User, args: Arguments) -> CommandResult<()> {
+    user.check_wizard()?;
+
+    let (locale, key) = translation_args(&args)?;
+    user.state.set_translation(&locale, &key, args.rest());
+    user.message(&format!("translated {key} into {locale}"));
+    Ok(())
+}
+
+pub fn untranslate(user: &mut User, args: Arguments) -> CommandResult<()> {
+    user.check_wizard()?;
+
+    let (locale, key) = translation_args(&args)?;
+    if user.state.remove_translation(&locale, &key) {
+        user.message(&format!("removed the {locale} translation of {key}"));
+    } else {
+        user.message("no such translation");
+    }
+
+    Ok(())
+}
+
+pub fn messages(user: &mut User, args: Arguments) -> CommandResult<()> {
+    let Ok(locale) = args.get_pattern(0) else {
+        for (key, text) in locale::MESSAGES {
+            user.message(&format!("{key}: {text}"));
+        }
+
+        return Ok(());
+    };
+
+    let translations = user.state.translations(&locale);
+    let untranslated = locale::MESSAGES.len() - translations.len();
+    for (key, text) in translations {
+        user.message(&format!("{key}: {text}"));
+    }
+
+    user.message(&format!(
+        "{untranslated} messages are not translated into {locale}"
+    ));
+    Ok(())
+}
+
+pub fn censor(user: &mut User, args: Arguments) -> CommandResult<()> {
+    let mut rest = args.rest().trim_start();
+    let (id, whose) = match rest.split_once(' ') {
+        Some(("server", after)) => {
+            user.check_wizard()?;
+            rest = after.trim_start();
+            (SYSTEM_OBJECT, "the server's")
+        }
+        _ => (user.object, "your"),
+    };
+
+    let Some((action, pattern)) = rest.split_once(' ') else {
+        return Err(CommandError::MissingArgument { index: 1 });
+    };
+
+    let pattern = pattern.trim();
+    let field = match action {
+        "mask" => censor::MASK_FIELD,
+        "block" => censor::BLOCK_FIELD,
+        "remove" => {
+            let mut removed = false;
+            for field in [censor::MASK_FIELD, censor::BLOCK_FIELD] {
+                let mut patterns = censor::patterns(&user.state, id, field);
+                let before = patterns.len();
+                patterns.retain(|kept| kept != pattern);
+                if patterns.len() == before {
+                    continue;
+                }
+
+                removed = true;
+                if patterns.is_empty() {
+                    user.state.unset(id, field);
+                } else {
+                    user.state
+                        .set(id, field, Value::String(patterns.join("\n")))?;
+                }
+            }
+
+            match removed {
+                true => user.message(&format!("removed {pattern} from {whose} censors")),
+                false => user.message("that isn't censored"),
+            }
+
+            return Ok(());
+        }
+        _ => {
+            return Err(CommandError::InvalidArgument {
+                index: 0,
+                expected: "mask, block, or remove".to_string(),
+            })
+        }
+    };
+
+    if let Err(err) = censor::compile(pattern) {
+        user.message(&err);
+        return Ok(());
+    }
+
+    let mut patterns = censor::patterns(&user.state, id, field);
+    if !patterns.iter().any(|kept| kept == pattern) {
+        patterns.push(pattern.to_string());
+    }
+
+    user.state
+        .set(id, field, Value::String(patterns.join("\n")))?;
+
+    user.message(&format!("{whose} censors now {action} {pattern}"));
+    Ok(())
+}
+
+pub fn censors(user: &mut User, _args: Arguments) -> CommandResult<()> {
+    let lists = [
+        ("Masked", user.object, censor::MASK_FIELD),
+        ("Blocked", user.object, censor::BLOCK_FIELD),
+        ("Masked server-wide", SYSTEM_OBJECT, censor::MASK_FIELD),
+        ("Blocked server-wide", SYSTEM_OBJECT, censor::BLOCK_FIELD),
+    ];
+
+    let mut any = false;
+    for (title, id, field) in lists {
+        let patterns = censor::patterns(&user.state, id, field);
+        if patterns.is_empty() {
+            continue;
+        }
+
+        any = true;
+        user.message(&format!("{title}:"));
+        for pattern in patterns {
+            user.message(&format!("    {pattern}"));
+        }
+    }
+
+    if !any {
+        user.message("nothing is censored");
+    }
+
+    Ok(())
+}
+
+pub fn gag(user: &mut User, args: Arguments) -> CommandResult<()> {
+    let id = user.get_object(&args, 0)?;
+    if id == user.object {
+        user.message("you can't gag yourself");
+        return Ok(());
+    }
+
+    user.state
+        .set(user.object, &filter::gag_key(id), Value::Bool(true))?;
+
+    user.message(&format!("gagged {}", user.state.display_name(id)));
+    Ok(())
+}
+
+pub fn gags(user: &mut User, _args: Arguments) -> CommandResult<()> {
+    let mut gagged: Vec<usize> = user
+        .state
+        .show(user.object)
+        .into_iter()
+        .filter_map(|(key, _)| key.strip_prefix("gag-")?.parse().ok())
+        .collect();
+
+    if gagged.is_empty() {
+        user.message("you have gagged no one");
+        return Ok(());
+    }
+
+    gagged.sort();
+
+    user.message("Gagged:");
+    for id in gagged {
+        user.message(&format!("    {}", user.state.display_name(id)));
+    }
+
+    Ok(())
+}
+
+pub fn friend(user: &mut User, args: Arguments) -> CommandResult<()> {
+    let action = args.get_ident(0)?;
+    if action == "list" {
+        let friends = friends::friends(&user.state, user.object);
+        if friends.is_empty() {
+            user.message("you have no friends listed");
+            return Ok(());
+        }
+
+        user.message("Friends:");
+        for id in friends {
+            let status = match user.state.is_connected(id) {
+                true => " (connected)",
+                false => "",
+            };
+
+            let name = user.state.display_name(id);
+            user.message(&format!("    {name}{status}"));
+        }
+
+        return Ok(());
+    }
+
+    let id = user.get_player(&args, 1)?;
+    let name = user.state.display_name(id);
+    match action.as_str() {
+        "add" if id == user.object => user.message("you can't befriend yourself"),
+        "add" => {
+            let key = friends::friend_key(id);
+            user.state.set(user.object, &key, Value::Bool(true))?;
+            user.message(&format!("added {name} as a friend"));
+        }
+        "remove" => {
+            if user.state.unset(user.object, &friends::friend_key(id)) {
+                user.message(&format!("removed {name} as a friend"));
+            } else {
+                user.message("that isn't a friend");
+            }
+        }
+        _ => {
+            return Err(CommandError::InvalidArgument {
+                index: 0,
+                expected: "add, remove, or list".to_string(),
+            })
+        }
+    }
+
+    Ok(())
+}
+
+pub fn ungag(user: &mut User, args: Arguments) -> CommandResult<()> {
+    let id = user.get_object(&args, 0)?;
+
+    if user.state.unset(user.object, &filter::gag_key(id)) {
+        user.message(&format!("ungagged {}", user.state.display_name(id)));
+    } else {
+        user.message("that isn't gagged");
+    }
+
+    Ok(())
+}
+
+pub fn connect(user: &mut User, args: Arguments) -> CommandResult<()> {
+    let player = match args.get(0)? {
+        Argument::Integer(_) | Argument::Object(_) => Some(args.get_id(0)?),
+        _ => user.state.find_player(&args.get_pattern(0)?),
+    };
+
+    let Some(password) = user.password_arg(&args, "Password: ") else {
+        return Ok(());
+    };
+
+    // hashing is slow on purpose, so keep it off of the other connections
+    let player = player.filter(|player| {
+        tokio::task::block_in_place(|| user.state.check_login(*player, &password, &user.addr))
+    });
+
+    let Some(player) = player else {
+        user.message("wrong player or password");
+
+        user.failed_logins += 1;
+        if user.failed_logins >= password::MAX_FAILED_LOGINS {
+            user.message("too many wrong passwords, so you have been disconnected");
+            user.quit = true;
+        }
+
+        return Ok(());
+    };
+
+    if player == user.object {
+        user.message("you are already connected as them");
+        return Ok(());
+    }
+
+    // connecting again takes over the session that is already connected
+    let resumed = !user.state.claim_player(player);
+    if resumed && !user.state.take_over(player) {
+        user.message("that player is already connected");
+        return Ok(());
+    }
+
+    user.switch_player(player, resumed);
+    match resumed {
+        true => user.message(&format!(
+            "took over the connection of {}",
+            user.state.display_name(player)
+        )),
+        false => user.message(&format!("connected as {}", user.state.display_name(player))),
+    }
+
+    Ok(())
+}
+
+pub fn register(user: &mut User, args: Arguments) -> CommandResult<()> {
+    let Some(command) = registration::verify_command() else {
+        user.message("registration is not enabled on this server");
+        return Ok(());
+    };
+
+    let name = args.get_pattern(0)?;
+    let email = args.rest().to_string();
+
+    if name.chars().count() > MAX_NAME_LEN {
+        return Err(CommandError::InvalidArgument {
+            index: 0,
+            expected: format!("name of at most {MAX_NAME_LEN} characters"),
+        });
+    }
+
+    if !registration::is_email(&email) {
+        return Err(CommandError::InvalidArgument {
+            index: 1,
+            expected: "email address".to_string(),
+        });
+    }
 
-        match id.try_into() {
-            Ok(val) => Ok(val),
-            Err(_) => Err(CommandError::InvalidArgument {
-                index,
-                expected: "object ID".to_string(),
-            }),
-        }
+    if user.state.find_player(&name).is_some() {
+        user.message("that name is taken");
+        return Ok(());
     }
 
-    pub fn get_string(&self, index: usize) -> CommandResult<String> {
-        match self.get(index)? {
-            Argument::String(val) => Ok(val),
-            _ => Err(CommandError::InvalidArgument {
-                index,
-                expected: "string".to_string(),
-            }),
-        }
+    let registration = Registration::new(name, email);
+    let sent = registration.clone();
+    if let Err(err) = user.state.start_registration(user.object, registration) {
+        user.message(&err.to_string());
+        return Ok(());
     }
 
-    pub fn get_ident(&self, index: usize) -> CommandResult<String> {
-        match self.get(index)? {
-            Argument::Ident(val) => Ok(val),
-            _ => Err(CommandError::InvalidArgument {
-                index,
-                expected: "identifier".to_string(),
-            }),
-        }
+    registration::send_code(command, &sent);
+    user.message(&format!(
+        "a code was sent to {}; type \"verify <code>\" to finish",
+        sent.email
+    ));
+
+    Ok(())
+}
+
+pub fn verify(user: &mut User, args: Arguments) -> CommandResult<()> {
+    let code = args.get_integer(0)?;
+    let Some(password) = user.password_arg(&args, "Password: ") else {
+        return Ok(());
+    };
+
+    if let Err(err) = password::check(&password) {
+        user.message(&err);
+        return Ok(());
     }
+
+    let Some(registration) = user.state.finish_registration(user.object, code) else {
+        user.message("wrong or expired code");
+        return Ok(());
+    };
+
+    if user.state.find_player(&registration.name).is_some() {
+        user.message("that name was taken in the meantime");
+        return Ok(());
+    }
+
+    let player = user.state.create();
+    user.state
+        .set(player, "name", Value::String(registration.name))?;
+
+    user.state
+        .set(player, "email", Value::String(registration.email))?;
+
+    user.state
+        .chmod(player, "email", FieldPerms::parse("-").unwrap());
+    tokio::task::block_in_place(|| user.state.set_password(player, &password));
+
+    user.state.claim_player(player);
+    user.switch_player(player, false);
+    user.message(&format!(
+        "created and connected as {}",
+        user.state.display_name(player)
+    ));
+
+    user.setup_new_player();
+
+    Ok(())
 }
 
-pub type Command = fn(&mut User, Arguments) -> CommandResult<()>;
+pub fn set_password(user: &mut User, args: Arguments) -> CommandResult<()> {
+    if user.state.is_guest(user.object) {
+        user.message("guests can't set passwords");
+        return Ok(());
+    }
 
-pub fn say(user: &mut User, args: Arguments) -> CommandResult<()> {
-    let say = args.get_string(0)?;
+    // passwords that are asked for are read whole, so that ones from before
+    // spaces were disallowed can still be changed
+    let has_password = user.state.has_password(user.object);
+    let words: Vec<String> = match args.rest() {
+        "" if has_password => {
+            let Some(old) = user.read_password("Old password: ") else {
+                return Ok(());
+            };
 
-    let who = match user
-        .state
-        .get(user.object, "name")
-        .and_then(|name| name.as_string().cloned())
-    {
-        Some(name) => name,
-        None => format!("#{}", user.object),
+            let Some(new) = user.read_password("New password: ") else {
+                return Ok(());
+            };
+
+            vec![old, new]
+        }
+        "" => match user.read_password("New password: ") {
+            Some(new) => vec![new],
+            None => return Ok(()),
+        },
+        rest => rest.split_whitespace().map(str::to_string).collect(),
+    };
+
+    let new = match words.as_slice() {
+        [old, new] if has_password => {
+            let matches =
+                tokio::task::block_in_place(|| user.state.check_password(user.object, old));
+
+            if !matches {
+                user.message("wrong password");
+                return Ok(());
+            }
+
+            new
+        }
+        [new] if !has_password => new,
+        _ => return Err(CommandError::MissingArgument { index: words.len() }),
     };
 
-    let msg = format!("{who} says: {say}");
-    user.state.announce(&msg);
+    if let Err(err) = password::check(new) {
+        user.message(&err);
+        return Ok(());
+    }
+
+    // connections become characters of their own once they have a password
+    let created = !user.state.has_password(user.object);
+    let new = new.clone();
+    tokio::task::block_in_place(|| user.state.set_password(user.object, &new));
+    user.message("password set");
+
+    if created {
+        user.setup_new_player();
+    }
+
     Ok(())
 }
 
-pub fn help(user: &mut User, _args: Arguments) -> CommandResult<()> {
-    user.message("Available commands:");
+pub fn ssh_key(user: &mut User, args: Arguments) -> CommandResult<()> {
+    let key = args.rest().trim();
 
-    let mut commands: Vec<_> = user.commands.0.keys().cloned().collect();
-    commands.sort();
+    // only players with passwords can be found to log in as
+    if !user.state.has_password(user.object) {
+        user.message("set a password with @password before adding SSH keys");
+        return Ok(());
+    }
 
-    for command in commands {
-        user.message(&format!("    {command}"));
+    if !ssh::is_public_key(key) {
+        return Err(CommandError::InvalidArgument {
+            index: 0,
+            expected: "an OpenSSH public key".to_string(),
+        });
     }
 
+    user.state.add_ssh_key(user.object, key);
+    user.message("SSH key added");
     Ok(())
 }
 
-pub fn create(user: &mut User, _args: Arguments) -> CommandResult<()> {
-    let idx = user.state.create();
-    user.message(&format!("created object #{idx}"));
+pub fn ssh_keys(user: &mut User, _args: Arguments) -> CommandResult<()> {
+    let keys = user.state.ssh_keys(user.object);
+    if keys.is_empty() {
+        user.message("you have no SSH keys");
+    }
+
+    for (index, key) in keys.iter().enumerate() {
+        user.message(&format!("{}: {key}", index + 1));
+    }
+
     Ok(())
 }
 
-pub fn destroy(user: &mut User, args: Arguments) -> CommandResult<()> {
-    let idx = args.get_id(0)?;
+pub fn remove_ssh_key(user: &mut User, args: Arguments) -> CommandResult<()> {
+    let number = args.get_integer(0)?;
+    let removed = usize::try_from(number)
+        .ok()
+        .and_then(|number| number.checked_sub(1))
+        .is_some_and(|index| user.state.remove_ssh_key(user.object, index));
 
-    if user.state.destroy(idx) {
-        user.message("success");
+    if removed {
+        user.message("SSH key removed");
     } else {
-        user.message("no such object");
+        user.message("you have no SSH key with that number");
     }
 
     Ok(())
 }
 
-pub fn list(user: &mut User, _args: Arguments) -> CommandResult<()> {
-    user.message("Objects:");
+pub fn new_password(user: &mut User, args: Arguments) -> CommandResult<()> {
+    user.check_wizard()?;
 
-    for id in user.state.list() {
-        let msg = match user
-            .state
-            .get(id, "name")
-            .and_then(|name| name.as_string().cloned())
-        {
-            Some(name) => format!("    #{:<4} ({})", id, name),
-            None => format!("    #{}", id),
-        };
+    let player = user.get_object(&args, 0)?;
+    if !user.state.exists(player) {
+        return Err(FieldError::NoSuchObject.into());
+    }
 
-        user.message(&msg);
+    let password = match args.rest().split_whitespace().collect::<Vec<_>>()[..] {
+        [] => State::generate_token(),
+        [password] => password.to_string(),
+        _ => return Err(CommandError::ExtraArgument { index: 2 }),
+    };
+
+    if let Err(err) = password::check(&password) {
+        user.message(&err);
+        return Ok(());
     }
 
+    tokio::task::block_in_place(|| user.state.set_password(player, &password));
+
+    let player = user.state.display_name(player);
+    user.message(&format!("the password of {player} is now {password}"));
     Ok(())
 }
 
-pub fn show(user: &mut User, args: Arguments) -> CommandResult<()> {
-    let id = args.get_id(0)?;
+/// The maximum length of an object's name, in characters.
+pub const MAX_NAME_LEN: usize = 64;
 
-    if !user.state.exists(id) {
-        user.message("no such object");
-        return Ok(());
+/// The maximum length of an object's description, in characters.
+pub const MAX_DESCRIPTION_LEN: usize = 4096;
+
+pub fn rename(user: &mut User, args: Arguments) -> CommandResult<()> {
+    let id = user.get_object(&args, 0)?;
+    let name = args.get_string(1)?;
+    let name = name.trim();
+
+    if name.is_empty() || name.chars().any(char::is_control) {
+        return Err(CommandError::InvalidArgument {
+            index: 1,
+            expected: "non-empty name".to_string(),
+        });
     }
 
-    user.message(&format!("Fields on object #{id}"));
+    if name.chars().count() > MAX_NAME_LEN {
+        return Err(CommandError::InvalidArgument {
+            index: 1,
+            expected: format!("name of at most {MAX_NAME_LEN} characters"),
+        });
+    }
 
-    for (key, val) in user.state.show(id) {
-        user.message(&format!("    {:<20}{}", key, val));
+    if !user.state.exists(id) {
+        user.message("no such object");
+        return Ok(());
     }
 
+    user.check_write(id, "name")?;
+    user.state
+        .set(id, "name", Value::String(name.to_string()))?;
+    user.message(&format!("renamed object #{id} to {name:?}"));
     Ok(())
 }
 
-pub fn set(user: &mut User, args: Arguments) -> CommandResult<()> {
-    let id = args.get_id(0)?;
-    let key = args.get_ident(1)?;
-    let val = args.get_value(2)?;
+pub fn describe(user: &mut User, args: Arguments) -> CommandResult<()> {
+    let id = user.get_object(&args, 0)?;
+    let description = args.get_string(1)?;
+    let description = description.trim();
+
+    if description.chars().count() > MAX_DESCRIPTION_LEN {
+        return Err(CommandError::InvalidArgument {
+            index: 1,
+            expected: format!("description of at most {MAX_DESCRIPTION_LEN} characters"),
+        });
+    }
 
     if !user.state.exists(id) {
-        user.message("No such object");
+        user.message("no such object");
         return Ok(());
     }
 
-    user.state.set(id, &key, val);
-
+    user.check_write(id, "description")?;
+    user.state
+        .set(id, "description", Value::String(description.to_string()))?;
+    user.message(&format!("described object #{id}"));
     Ok(())
 }
 
-pub fn get(user: &mut User, args: Arguments) -> CommandResult<()> {
-    let id = args.get_id(0)?;
-    let key = args.get_ident(1)?;
+pub fn move_object(user: &mut User, args: Arguments) -> CommandResult<()> {
+    let id = user.get_object(&args, 0)?;
+    let dest = user.get_object(&args, 1)?;
 
-    match user.state.get(id, &key) {
-        Some(val) => user.message(&format!("value: {:?}", val)),
-        None => user.message("value: <none>"),
+    user.check_write(id, "location")?;
+
+    match user.move_object(id, dest) {
+        Ok(()) => user.message("moved"),
+        Err(err) => user.message(&err.to_string()),
     }
 
     Ok(())
 }
 
+pub fn motd(user: &mut User, args: Arguments) -> CommandResult<()> {
+    let current = user
+        .state
+        .get(SYSTEM_OBJECT, "motd")
+        .and_then(|motd| motd.as_string().cloned());
+
+    let Ok(action) = args.get_ident(0) else {
+        match current {
+            Some(motd) => {
+                user.message("Message of the day:");
+
+                for line in motd.lines() {
+                    user.message(&format!("    {line}"));
+                }
+            }
+            None => user.message("no message of the day is set"),
+        }
+
+        return Ok(());
+    };
+
+    user.check_wizard()?;
+
+    let motd = match action.as_str() {
+        "set" => args.get_string(1)?,
+        "add" => {
+            let line = args.get_string(1)?;
+            match current {
+                Some(current) => format!("{current}\n{line}"),
+                None => line,
+            }
+        }
+        _ => {
+            return Err(CommandError::InvalidArgument {
+                index: 0,
+                expected: "set or add".to_string(),
+            })
+        }
+    };
+
+    user.state.set(SYSTEM_OBJECT, "motd", Value::String(motd))?;
+
+    user.message("message of the day updated");
+    Ok(())
+}
+
 #[tokio::main]
 async fn main() {
-    let bind = "0.0.0.0:8888";
-    let listener = TcpListener::bind(bind).await.unwrap();
-    eprintln!("Listening on {bind}");
+    let binds = std::env::var(BIND_VAR)
+        .ok()
+        .filter(|binds| !binds.trim().is_empty())
+        .unwrap_or_else(|| DEFAULT_BIND.to_string());
+
+    let mut listeners = Vec::new();
+    for bind in binds
+        .split(',')
+        .map(str::trim)
+        .filter(|bind| !bind.is_empty())
+    {
+        match TcpListener::bind(bind).await {
+            Ok(listener) => {
+                eprintln!("Listening on {bind}");
+                listeners.push(listener);
+            }
+            Err(err) => eprintln!("could not listen on {bind}: {err}"),
+        }
+    }
+
+    if listeners.is_empty() {
+        eprintln!("no addresses to listen on");
+        std::process::exit(1);
+    }
 
     let token = CancellationToken::new();
-    let state = State::new(token.child_token());
+    let state = State::new(token.clone());
     let state = Arc::new(state);
+    eprintln!("Wizard token: {}", state.wizard_token());
+    config::load(&state);
 
     let shutdown = token.child_token();
     tokio::spawn(wait_for_interrupt(token));
+    tokio::spawn(config::reload_on_hangup(state.clone()));
+    tokio::spawn(purge_recycled(state.clone()));
+    tokio::spawn(tasks::run(state.clone()));
+    tokio::spawn(run_console(state.clone()));
+
+    if let Some(path) = std::env::var(ADMIN_SOCKET_VAR)
+        .ok()
+        .filter(|path| !path.is_empty())
+    {
+        tokio::spawn(listen_admin(state.clone(), path));
+    }
+
+    if let Some(bind) = std::env::var(jsonl::JSON_BIND_VAR)
+        .ok()
+        .filter(|bind| !bind.is_empty())
+    {
+        tokio::spawn(jsonl::listen(state.clone(), bind));
+    }
+
+    if let Some(bind) = std::env::var(grpc::GRPC_BIND_VAR)
+        .ok()
+        .filter(|bind| !bind.is_empty())
+    {
+        tokio::spawn(grpc::serve(state.clone(), bind));
+    }
+
+    if let Some(bind) = std::env::var(irc::IRC_BIND_VAR)
+        .ok()
+        .filter(|bind| !bind.is_empty())
+    {
+        tokio::spawn(irc::listen(state.clone(), bind));
+    }
+
+    if let Some(bind) = std::env::var(ssh::SSH_BIND_VAR)
+        .ok()
+        .filter(|bind| !bind.is_empty())
+    {
+        tokio::spawn(ssh::listen(state.clone(), bind));
+    }
+
+    if let Some(bind) = std::env::var(matrix::MATRIX_BIND_VAR)
+        .ok()
+        .filter(|bind| !bind.is_empty())
+    {
+        tokio::spawn(matrix::listen(state.clone(), bind));
+    }
+
+    if let Some(broker) = std::env::var(mqtt::MQTT_BROKER_VAR)
+        .ok()
+        .filter(|broker| !broker.is_empty())
+    {
+        tokio::spawn(mqtt::publish(state.clone(), broker));
+    }
+
+    if let Some(bind) = std::env::var(feed::FEED_BIND_VAR)
+        .ok()
+        .filter(|bind| !bind.is_empty())
+    {
+        tokio::spawn(feed::serve(state.clone(), bind));
+    }
+
+    let proxied = proxy::enabled();
+    if proxied {
+        eprintln!("Expecting PROXY protocol headers on every connection");
+    }
+
+    for listener in listeners {
+        if let Ok(addr) = listener.local_addr() {
+            state.add_port(addr.port());
+        }
+
+        tokio::spawn(listen(state.clone(), listener, proxied));
+    }
+
+    shutdown.cancelled().await;
+}
+
+/// How long a proxy has to send the PROXY protocol header.
+const PROXY_HEADER_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Accepts connections on a listener until the server shuts down.
+///
+/// If `proxied` is set, connections come from a proxy that starts each one
+/// with the real client's address using the [proxy] protocol.
+async fn listen(state: Arc<State>, listener: TcpListener, proxied: bool) {
+    let shutdown = state.shutdown_token();
 
     loop {
         tokio::select! {
-            incoming = listener.accept() => {
-                let (conn, addr) = incoming.unwrap();
-                accept(state.clone(), conn, addr);
-            }
-            _ = shutdown.cancelled() => {
-                break;
-            }
+            incoming = listener.accept() => match incoming {
+                Ok((conn, addr)) => accept(state.clone(), conn, addr, proxied),
+                Err(err) => {
+                    // errors like running out of file descriptors would
+                    // otherwise happen again right away
+                    eprintln!("could not accept a connection: {err}");
+                    tokio::time::sleep(Duration::from_millis(100)).await;
+                }
+            },
+            _ = shutdown.cancelled() => break,
         }
     }
 }
 
-fn accept(state: Arc<State>, conn: TcpStream, addr: SocketAddr) {
-    eprintln!("Connection from {addr}");
-    let (rx, tx) = tokio::io::split(conn);
-    let user = User::new(state, tx);
-
+fn accept(state: Arc<State>, mut conn: TcpStream, addr: SocketAddr, proxied: bool) {
     tokio::spawn(async move {
+        let addr = if proxied {
+            let header = tokio::time::timeout(PROXY_HEADER_TIMEOUT, proxy::read_header(&mut conn));
+            match header.await {
+                Ok(Ok(client)) => client.unwrap_or(addr),
+                Ok(Err(err)) => {
+                    eprintln!("refused a connection from {addr}: {err}");
+                    return;
+                }
+                Err(_) => {
+                    eprintln!("refused a connection from {addr}: no PROXY protocol header");
+                    return;
+                }
+            }
+        } else {
+            addr
+        };
+
+        eprintln!("Connection from {addr}");
+
+        let Some(player) = state.new_player() else {
+            eprintln!("{addr} turned away because every guest is in use");
+            let _ = conn
+                .write_all(b"All guests are in use. Try again later.\r\n")
+                .await;
+            return;
+        };
+
+        let (rx, tx) = tokio::io::split(conn);
+        let user = User::new(state, tx, addr.to_string(), player);
         user.run(rx).await;
         eprintln!("{addr} disconnected");
     });
 }
 
+/// Accepts connections to the admin socket until the server shuts down.
+async fn listen_admin(state: Arc<State>, path: String) {
+    // a socket left behind by a server that crashed keeps a new one from
+    // being bound at the same path
+    let _ = std::fs::remove_file(&path);
+
+    let listener = match UnixListener::bind(&path) {
+        Ok(listener) => listener,
+        Err(err) => {
+            eprintln!("could not listen on {path}: {err}");
+            return;
+        }
+    };
+
+    if let Err(err) = std::fs::set_permissions(&path, Permissions::from_mode(0o600)) {
+        eprintln!("could not restrict access to {path}: {err}");
+        return;
+    }
+
+    // the socket belongs to the user running the server
+    let uid = match std::fs::metadata(&path) {
+        Ok(metadata) => metadata.uid(),
+        Err(err) => {
+            eprintln!("could not read the owner of {path}: {err}");
+            return;
+        }
+    };
+
+    eprintln!("Listening for admins on {path}");
+    let shutdown = state.shutdown_token();
+
+    loop {
+        tokio::select! {
+            incoming = listener.accept() => match incoming {
+                Ok((conn, _addr)) => accept_admin(state.clone(), conn, uid),
+                Err(err) => eprintln!("admin connection failed: {err}"),
+            },
+            _ = shutdown.cancelled() => break,
+        }
+    }
+
+    let _ = std::fs::remove_file(&path);
+}
+
+fn accept_admin(state: Arc<State>, conn: UnixStream, uid: u32) {
+    // the socket is only accessible to its owner, but it could have been
+    // connected to in the moment before its permissions were set
+    match conn.peer_cred() {
+        Ok(cred) if cred.uid() == uid => {}
+        _ => {
+            eprintln!("refused an admin connection from another user");
+            return;
+        }
+    }
+
+    eprintln!("Admin connected");
+
+    tokio::spawn(async move {
+        let player = state.new_admin();
+        let (rx, tx) = tokio::io::split(conn);
+        let user = User::new(state, tx, "admin socket".to_string(), player);
+        user.run_console(rx).await;
+        eprintln!("Admin disconnected");
+    });
+}
+
+/// How often the recycle bin is checked for expired objects.
+const PURGE_INTERVAL: Duration = Duration::from_secs(60);
+
+async fn purge_recycled(state: Arc<State>) {
+    let shutdown = state.shutdown_token();
+    let mut interval = tokio::time::interval(PURGE_INTERVAL);
+
+    loop {
+        tokio::select! {
+            _ = interval.tick() => state.purge_expired(),
+            _ = shutdown.cancelled() => break,
+        }
+    }
+}
+
+/// Lets the operator run commands as a wizard from the server's terminal.
+async fn run_console(state: Arc<State>) {
+    let player = state.console_player();
+    if !state.claim_player(player) {
+        return;
+    }
+
+    let user = User::new(state, tokio::io::stdout(), "console".to_string(), player);
+    user.run_console(stdin_reader()).await;
+}
+
+/// Reads stdin on a thread of its own.
+///
+/// Tokio reads stdin on a blocking task, which would keep the runtime from
+/// shutting down until another line is entered.
+fn stdin_reader() -> DuplexStream {
+    let (reader, mut writer) = tokio::io::duplex(DEFAULT_MAX_LINE_LENGTH);
+    let runtime = tokio::runtime::Handle::current();
+
+    std::thread::spawn(move || {
+        let mut stdin = std::io::stdin().lock();
+        let mut buf = [0; 1024];
+
+        while let Ok(read @ 1..) = stdin.read(&mut buf) {
+            if runtime.block_on(writer.write_all(&buf[..read])).is_err() {
+                break;
+            }
+        }
+    });
+
+    reader
+}
+
 async fn wait_for_interrupt(shutdown: CancellationToken) {
     tokio::signal::ctrl_c().await.unwrap();
     shutdown.cancel();
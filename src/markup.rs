@@ -0,0 +1,50 @@
+//! Color markup for server text.
+//!
+//! Markup tags are color names in braces, like `{red}` or `{bold}`, and are
+//! rendered into ANSI escape sequences. `{reset}` returns to plain text.
+//! Unrecognized tags are left as-is.
+
+/// The markup tags and the ANSI SGR codes they render to.
+const TAGS: &[(&str, &str)] = &[
+    ("reset", "0"),
+    ("bold", "1"),
+    ("underline", "4"),
+    ("black", "30"),
+    ("red", "31"),
+    ("green", "32"),
+    ("yellow", "33"),
+    ("blue", "34"),
+    ("magenta", "35"),
+    ("cyan", "36"),
+    ("white", "37"),
+];
+
+/// Renders the markup tags in a string into ANSI escape sequences.
+pub fn render(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(start) = rest.find('{') {
+        out.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        let Some(end) = rest.find('}') else {
+            break;
+        };
+
+        let tag = &rest[1..end];
+        match TAGS.iter().find(|(name, _)| *name == tag) {
+            Some((_, code)) => {
+                out.push_str("\x1b[");
+                out.push_str(code);
+                out.push('m');
+            }
+            None => out.push_str(&rest[..=end]),
+        }
+
+        rest = &rest[end + 1..];
+    }
+
+    out.push_str(rest);
+    out
+}
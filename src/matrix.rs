@@ -0,0 +1,406 @@
+//! A bridge to Matrix, run as an application service.
+//!
+//! The system object's `matrix_rooms` field selects the channels that are
+//! bridged, as `channel=room` pairs separated by commas, like
+//! `say=!chat:example.org, announce=!news:example.org`. The channels are the
+//! ones that GMCP names: `say` carries what is said and emoted, and
+//! `announce` carries announcements.
+//!
+//! What players say is mirrored into the rooms by the bridge's user.
+//! Messages sent in a room bridged to `say` are relayed back as said or
+//! emoted by a speaker object standing in for their Matrix sender, which is
+//! created the first time that they speak and marked with `matrix_user`.
+//! There may be at most `matrix_max_speakers` speakers, as set on the system
+//! object, or [DEFAULT_MAX_SPEAKERS] if it doesn't say, and messages from
+//! new senders are dropped once there are.
+//!
+//! The bridge runs when [MATRIX_BIND_VAR] is set, and the other variables must then
+//! match the application service's registration with the homeserver.
+
+use std::{
+    collections::VecDeque,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use axum::{
+    extract::{Path, Query, State as Extract},
+    http::{HeaderMap, StatusCode},
+    routing::put,
+    Json, Router,
+};
+use serde::Deserialize;
+use serde_json::{json, Value as JsonValue};
+use tokio::{net::TcpListener, sync::broadcast};
+
+use crate::{event::Event, grpc::tokens_match, now, State, Value, SYSTEM_OBJECT};
+
+/// The environment variable naming the address that the homeserver pushes
+/// events to, like `127.0.0.1:9000`.
+pub const MATRIX_BIND_VAR: &str = "MARCIEMOO_MATRIX_BIND";
+
+/// The URL of the homeserver, like `https://matrix.example.org`.
+pub const MATRIX_HOMESERVER_VAR: &str = "MARCIEMOO_MATRIX_HOMESERVER";
+
+/// The registration's `as_token`, which the bridge calls the homeserver with.
+pub const MATRIX_AS_TOKEN_VAR: &str = "MARCIEMOO_MATRIX_AS_TOKEN";
+
+/// The registration's `hs_token`, which the homeserver pushes events with.
+pub const MATRIX_HS_TOKEN_VAR: &str = "MARCIEMOO_MATRIX_HS_TOKEN";
+
+/// The Matrix ID of the bridge's own user, like `@moo:example.org`.
+pub const MATRIX_USER_VAR: &str = "MARCIEMOO_MATRIX_USER";
+
+/// The field that marks an object as speaking for a Matrix user.
+pub const MATRIX_USER_FIELD: &str = "matrix_user";
+
+/// How many speakers there may be if the system object doesn't say.
+pub const DEFAULT_MAX_SPEAKERS: usize = 1000;
+
+/// How many transactions are remembered, so that the ones the homeserver
+/// retries aren't relayed twice.
+const SEEN_TRANSACTIONS: usize = 256;
+
+/// The bridge's registration with the homeserver.
+struct Config {
+    homeserver: String,
+    as_token: String,
+    hs_token: String,
+    user: String,
+}
+
+impl Config {
+    /// Reads the registration from the environment, naming the first
+    /// variable that isn't set if it can't.
+    fn from_env() -> Result<Self, &'static str> {
+        let var = |name| {
+            std::env::var(name)
+                .ok()
+                .filter(|val| !val.is_empty())
+                .ok_or(name)
+        };
+
+        Ok(Self {
+            homeserver: var(MATRIX_HOMESERVER_VAR)?
+                .trim_end_matches('/')
+                .to_string(),
+            as_token: var(MATRIX_AS_TOKEN_VAR)?,
+            hs_token: var(MATRIX_HS_TOKEN_VAR)?,
+            user: var(MATRIX_USER_VAR)?,
+        })
+    }
+}
+
+/// Gets the rooms that channels are bridged to, from `matrix_rooms`.
+pub fn rooms(state: &State) -> Vec<(String, String)> {
+    let Some(Value::String(rooms)) = state.get(SYSTEM_OBJECT, "matrix_rooms") else {
+        return Vec::new();
+    };
+
+    rooms
+        .split(',')
+        .filter_map(|pair| pair.split_once('='))
+        .map(|(channel, room)| (channel.trim().to_string(), room.trim().to_string()))
+        .filter(|(channel, room)| !channel.is_empty() && !room.is_empty())
+        .collect()
+}
+
+/// Gets the room that a channel is bridged to, if it is.
+fn room_for(state: &State, channel: &str) -> Option<String> {
+    rooms(state)
+        .into_iter()
+        .find(|(bridged, _)| bridged == channel)
+        .map(|(_, room)| room)
+}
+
+/// Gets the channel that a room is bridged to, if it is.
+fn channel_for(state: &State, room: &str) -> Option<String> {
+    rooms(state)
+        .into_iter()
+        .find(|(_, bridged)| bridged == room)
+        .map(|(channel, _)| channel)
+}
+
+/// Tests if an object speaks for a Matrix user, so that what it says isn't
+/// sent back to Matrix.
+pub fn is_bridged(state: &State, id: usize) -> bool {
+    matches!(state.get(id, MATRIX_USER_FIELD), Some(Value::String(_)))
+}
+
+struct Bridge {
+    state: Arc<State>,
+    config: Config,
+    client: reqwest::Client,
+
+    /// The IDs of the last transactions from the homeserver.
+    seen: Mutex<VecDeque<String>>,
+
+    /// Counts the messages sent, to give each a transaction ID of its own.
+    sent: AtomicU64,
+}
+
+/// Runs the bridge until the server shuts down.
+pub async fn listen(state: Arc<State>, bind: String) {
+    let config = match Config::from_env() {
+        Ok(config) => config,
+        Err(var) => {
+            eprintln!("not bridging to Matrix, since {var} is not set");
+            return;
+        }
+    };
+
+    match reqwest::Url::parse(&config.homeserver) {
+        Ok(url) if !url.cannot_be_a_base() => {}
+        _ => {
+            eprintln!("not bridging to Matrix, since {MATRIX_HOMESERVER_VAR} is not a URL");
+            return;
+        }
+    }
+
+    let listener = match TcpListener::bind(&bind).await {
+        Ok(listener) => listener,
+        Err(err) => {
+            eprintln!("could not listen for Matrix on {bind}: {err}");
+            return;
+        }
+    };
+
+    eprintln!("Bridging to Matrix, listening on {bind}");
+
+    let bridge = Arc::new(Bridge {
+        state: state.clone(),
+        config,
+        client: reqwest::Client::new(),
+        seen: Mutex::new(VecDeque::new()),
+        sent: AtomicU64::new(0),
+    });
+
+    tokio::spawn(mirror(bridge.clone()));
+
+    let app = Router::new()
+        .route("/_matrix/app/v1/transactions/{txn}", put(transaction))
+        .route("/transactions/{txn}", put(transaction))
+        .with_state(bridge);
+
+    let shutdown = state.shutdown_token();
+    let served = axum::serve(listener, app)
+        .with_graceful_shutdown(shutdown.cancelled_owned())
+        .await;
+
+    if let Err(err) = served {
+        eprintln!("the Matrix bridge failed: {err}");
+    }
+}
+
+/// Sends what is said in the world into the rooms that it is bridged to.
+async fn mirror(bridge: Arc<Bridge>) {
+    let shutdown = bridge.state.shutdown_token();
+    let mut events = bridge.state.subscribe();
+
+    loop {
+        let event = tokio::select! {
+            event = events.recv() => event,
+            _ = shutdown.cancelled() => break,
+        };
+
+        let event = match event {
+            Ok(event) => event,
+            Err(broadcast::error::RecvError::Lagged(_)) => continue,
+            Err(broadcast::error::RecvError::Closed) => break,
+        };
+
+        let (channel, kind) = match &event {
+            Event::Say { speaker, .. }
+            | Event::Emote { actor: speaker, .. }
+            | Event::Social { actor: speaker, .. }
+                if !is_bridged(&bridge.state, *speaker) =>
+            {
+                ("say", "m.text")
+            }
+            Event::Announce { .. } => ("announce", "m.notice"),
+            _ => continue,
+        };
+
+        let (Some(room), Some(text)) = (room_for(&bridge.state, channel), event.render()) else {
+            continue;
+        };
+
+        if let Err(err) = bridge.send(&room, kind, &text).await {
+            eprintln!("could not send to Matrix room {room}: {err}");
+        }
+    }
+}
+
+impl Bridge {
+    /// Sends a message to a room as the bridge's user.
+    async fn send(&self, room: &str, kind: &str, text: &str) -> Result<(), reqwest::Error> {
+        let txn = format!("{}-{}", now(), self.sent.fetch_add(1, Ordering::Relaxed));
+        // the URL was checked at startup, and room IDs are escaped as segments
+        let mut url = reqwest::Url::parse(&self.config.homeserver).unwrap();
+        url.path_segments_mut()
+            .unwrap()
+            .extend(["_matrix", "client", "v3", "rooms", room, "send"])
+            .extend(["m.room.message", &txn]);
+
+        self.client
+            .put(url)
+            .bearer_auth(&self.config.as_token)
+            .query(&[("user_id", &self.config.user)])
+            .json(&json!({ "msgtype": kind, "body": text }))
+            .send()
+            .await?
+            .error_for_status()?;
+
+        Ok(())
+    }
+
+    /// Remembers a transaction, returning false if it was already handled.
+    fn first_time(&self, txn: &str) -> bool {
+        let mut seen = self.seen.lock().unwrap();
+        if seen.iter().any(|seen| seen == txn) {
+            return false;
+        }
+
+        if seen.len() >= SEEN_TRANSACTIONS {
+            seen.pop_front();
+        }
+
+        seen.push_back(txn.to_string());
+        true
+    }
+
+    /// Relays a message sent in a room back into the world.
+    fn relay(&self, event: &JsonValue) {
+        let field = |name: &str| event[name].as_str().unwrap_or_default();
+        let content = |name: &str| event["content"][name].as_str().unwrap_or_default();
+
+        if field("type") != "m.room.message" || field("sender") == self.config.user {
+            return;
+        }
+
+        if channel_for(&self.state, field("room_id")).as_deref() != Some("say") {
+            return;
+        }
+
+        let body = content("body").trim();
+        if body.is_empty() {
+            return;
+        }
+
+        let Some(speaker) = self.speaker(field("sender")) else {
+            return;
+        };
+
+        let name = self.state.display_name(speaker);
+
+        let event = match content("msgtype") {
+            "m.text" | "m.notice" => Event::Say {
+                speaker,
+                name,
+                message: body.to_string(),
+            },
+            "m.emote" => Event::Emote {
+                actor: speaker,
+                name,
+                action: body.to_string(),
+            },
+            _ => return,
+        };
+
+        self.state.publish(event);
+    }
+
+    /// Gets the object that speaks for a Matrix user, creating it the first
+    /// time that they speak, or `None` if there are already as many speakers
+    /// as there may be.
+    fn speaker(&self, user: &str) -> Option<usize> {
+        let existing = self.state.find(MATRIX_USER_FIELD, user).into_iter();
+        let mut existing = existing.filter(|id| {
+            matches!(self.state.get(*id, MATRIX_USER_FIELD), Some(Value::String(found)) if found == user)
+        });
+
+        if let Some(id) = existing.next() {
+            return Some(id);
+        }
+
+        if self.state.find(MATRIX_USER_FIELD, "*").len() >= self.max_speakers() {
+            eprintln!("not relaying from {user}: there are too many Matrix speakers");
+            return None;
+        }
+
+        // a user ID looks like `@alice:example.org`
+        let localpart = user.trim_start_matches('@');
+        let localpart = localpart.split(':').next().unwrap_or(localpart);
+
+        let id = self.state.create();
+        let fields = vec![
+            ("name".to_string(), Value::String(localpart.to_string())),
+            (
+                MATRIX_USER_FIELD.to_string(),
+                Value::String(user.to_string()),
+            ),
+        ];
+
+        if let Err(err) = self.state.set_many(id, fields) {
+            eprintln!("could not set up the speaker for {user}: {err}");
+        }
+
+        Some(id)
+    }
+
+    /// Gets how many speakers there may be.
+    fn max_speakers(&self) -> usize {
+        let max = self.state.get(SYSTEM_OBJECT, "matrix_max_speakers");
+        let max = max.and_then(|max| max.as_integer());
+        let max = max.and_then(|max| usize::try_from(max).ok());
+        max.unwrap_or(DEFAULT_MAX_SPEAKERS)
+    }
+}
+
+#[derive(Deserialize)]
+struct Auth {
+    access_token: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Transaction {
+    #[serde(default)]
+    events: Vec<JsonValue>,
+}
+
+/// Checks that a request came from the homeserver, which sends the
+/// `hs_token` as a bearer token or, in older versions, as a query.
+fn is_homeserver(bridge: &Bridge, headers: &HeaderMap, query: &Auth) -> bool {
+    let header = headers
+        .get("authorization")
+        .and_then(|header| header.to_str().ok())
+        .and_then(|header| header.strip_prefix("Bearer "));
+
+    header
+        .or(query.access_token.as_deref())
+        .is_some_and(|given| tokens_match(given, &bridge.config.hs_token))
+}
+
+/// Receives a transaction of events from the homeserver.
+async fn transaction(
+    Extract(bridge): Extract<Arc<Bridge>>,
+    Path(txn): Path<String>,
+    Query(auth): Query<Auth>,
+    headers: HeaderMap,
+    Json(transaction): Json<Transaction>,
+) -> (StatusCode, Json<JsonValue>) {
+    if !is_homeserver(&bridge, &headers, &auth) {
+        let error = json!({ "errcode": "M_FORBIDDEN", "error": "wrong hs_token" });
+        return (StatusCode::FORBIDDEN, Json(error));
+    }
+
+    if bridge.first_time(&txn) {
+        for event in &transaction.events {
+            bridge.relay(event);
+        }
+    }
+
+    (StatusCode::OK, Json(json!({})))
+}
@@ -0,0 +1,125 @@
+//! Publishes world events to an MQTT broker, for integrations that would
+//! rather subscribe than poll.
+//!
+//! Events are published to the broker named by [MQTT_BROKER_VAR] as the JSON
+//! objects that the [jsonl](crate::jsonl) protocol sends for them. The system
+//! object's `mqtt_topics` field selects the events that are published, as
+//! `event=topic` pairs separated by commas, like
+//! `connect=moo/players, disconnect=moo/players, say=moo/chat`. Without it,
+//! every event that isn't addressed to one player is published to
+//! `marciemoo/<event>`, like `marciemoo/create`.
+
+use std::{sync::Arc, time::Duration};
+
+use rumqttc::{AsyncClient, MqttOptions, QoS};
+use tokio::sync::broadcast;
+
+use crate::{event::Event, jsonl, State, Value, SYSTEM_OBJECT};
+
+/// The environment variable naming the broker to publish to, like
+/// `localhost:1883`. The port is 1883 if it is left out.
+pub const MQTT_BROKER_VAR: &str = "MARCIEMOO_MQTT_BROKER";
+
+/// The environment variable holding the username to connect to the broker
+/// with, if it needs one.
+pub const MQTT_USERNAME_VAR: &str = "MARCIEMOO_MQTT_USERNAME";
+
+/// The environment variable holding the password for [MQTT_USERNAME_VAR].
+pub const MQTT_PASSWORD_VAR: &str = "MARCIEMOO_MQTT_PASSWORD";
+
+/// The prefix of the topics that events are published to by default.
+pub const DEFAULT_PREFIX: &str = "marciemoo";
+
+/// How many publishes may wait for the broker before events are dropped.
+const QUEUE: usize = 64;
+
+/// Gets the topic that an event is published to, if it is published.
+pub fn topic(state: &State, event: &Event, kind: &str) -> Option<String> {
+    let Some(Value::String(topics)) = state.get(SYSTEM_OBJECT, "mqtt_topics") else {
+        // pages and boots are private, so they are only published on request
+        return match event.recipient() {
+            Some(_) => None,
+            None => Some(format!("{DEFAULT_PREFIX}/{kind}")),
+        };
+    };
+
+    topics
+        .split(',')
+        .filter_map(|pair| pair.split_once('='))
+        .find(|(event, _)| event.trim() == kind)
+        .map(|(_, topic)| topic.trim().to_string())
+        .filter(|topic| !topic.is_empty())
+}
+
+/// Publishes events until the server shuts down.
+pub async fn publish(state: Arc<State>, broker: String) {
+    let (host, port) = match broker.rsplit_once(':') {
+        Some((host, port)) => match port.parse() {
+            Ok(port) => (host.to_string(), port),
+            Err(err) => {
+                eprintln!("could not publish to MQTT broker {broker}: {err}");
+                return;
+            }
+        },
+        None => (broker.clone(), 1883),
+    };
+
+    let mut options = MqttOptions::new(format!("marciemoo-{}", std::process::id()), host, port);
+    options.set_keep_alive(Duration::from_secs(30));
+
+    let var = |name| std::env::var(name).ok().filter(|val| !val.is_empty());
+    if let Some(username) = var(MQTT_USERNAME_VAR) {
+        options.set_credentials(username, var(MQTT_PASSWORD_VAR).unwrap_or_default());
+    }
+
+    eprintln!("Publishing events to MQTT broker {broker}");
+    let (client, mut eventloop) = AsyncClient::new(options, QUEUE);
+    let shutdown = state.shutdown_token();
+
+    // the event loop does the talking to the broker, and reconnects when it
+    // is polled again after an error
+    let connection = tokio::spawn(async move {
+        let mut failing = false;
+        loop {
+            match eventloop.poll().await {
+                Ok(_) => failing = false,
+                Err(err) => {
+                    if !failing {
+                        eprintln!("lost the MQTT broker {broker}: {err}");
+                        failing = true;
+                    }
+
+                    tokio::time::sleep(Duration::from_secs(5)).await;
+                }
+            }
+        }
+    });
+
+    let mut events = state.subscribe();
+    loop {
+        let event = tokio::select! {
+            event = events.recv() => event,
+            _ = shutdown.cancelled() => break,
+        };
+
+        let event = match event {
+            Ok(event) => event,
+            Err(broadcast::error::RecvError::Lagged(_)) => continue,
+            Err(broadcast::error::RecvError::Closed) => break,
+        };
+
+        let encoded = serde_json::to_value(&event).unwrap();
+        let kind = encoded["event"].as_str().unwrap_or_default();
+        let Some(topic) = topic(&state, &event, kind) else {
+            continue;
+        };
+
+        // events are dropped rather than stalling for a broker that is away
+        let payload = jsonl::event(&event, None);
+        let _ = client.try_publish(topic, QoS::AtMostOnce, false, payload);
+    }
+
+    let _ = client.try_disconnect();
+    tokio::time::sleep(Duration::from_millis(100)).await;
+    connection.abort();
+}
@@ -0,0 +1,66 @@
+//! MXP, the MUD eXtension Protocol.
+//!
+//! Clients that negotiate MXP are sent lines with links in them, which run a
+//! command when clicked. Lines with links are sent in MXP's secure mode, so
+//! the rest of their text is escaped.
+
+/// Starts a line whose MXP tags are interpreted, until the line ends.
+pub const SECURE_LINE: &str = "\x1b[1z";
+
+/// Escapes text so that MXP shows it as it is.
+pub fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+
+    for c in text.chars() {
+        match c {
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '&' => escaped.push_str("&amp;"),
+            '"' => escaped.push_str("&quot;"),
+            c => escaped.push(c),
+        }
+    }
+
+    escaped
+}
+
+/// A line of text that may contain links, rendered both with and without
+/// MXP so that it can be sent to any client.
+#[derive(Clone, Debug, Default)]
+pub struct Line {
+    plain: String,
+    mxp: String,
+}
+
+impl Line {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds text to the line.
+    pub fn push(&mut self, text: &str) -> &mut Self {
+        self.plain.push_str(text);
+        self.mxp.push_str(&escape(text));
+        self
+    }
+
+    /// Adds text that runs a command when it is clicked.
+    pub fn link(&mut self, command: &str, text: &str) -> &mut Self {
+        self.plain.push_str(text);
+        self.mxp.push_str(&format!(
+            "<send href=\"{}\">{}</send>",
+            escape(command),
+            escape(text)
+        ));
+        self
+    }
+
+    /// Renders the line for a client, using MXP if it supports it.
+    pub fn render(&self, mxp: bool) -> String {
+        if mxp {
+            format!("{SECURE_LINE}{}", self.mxp)
+        } else {
+            self.plain.clone()
+        }
+    }
+}
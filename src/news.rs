@@ -0,0 +1,61 @@
+//! The log of server announcements, kept in its own tree of the database so
+//! that they outlive the broadcast that made them.
+
+use serde::{Deserialize, Serialize};
+use sled::{Db, Tree};
+
+/// One server announcement.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Announcement {
+    pub message: String,
+
+    /// When it was made, in seconds since the UNIX epoch.
+    pub at: u64,
+
+    /// The key of its entry in the log, which is unique and orders entries.
+    #[serde(skip)]
+    pub id: u64,
+}
+
+pub struct NewsLog {
+    db: Db,
+    tree: Tree,
+}
+
+impl NewsLog {
+    pub fn open(db: &Db) -> Self {
+        Self {
+            db: db.clone(),
+            tree: db.open_tree("announcements").unwrap(),
+        }
+    }
+
+    /// Records an announcement.
+    pub fn record(&self, message: &str) {
+        let entry = Announcement {
+            message: message.to_string(),
+            at: crate::now(),
+            id: 0,
+        };
+
+        // generated IDs only increase, so keys are in chronological order
+        let key = self.db.generate_id().unwrap().to_be_bytes();
+        let entry = serde_json::to_vec(&entry).unwrap();
+        self.tree.insert(key, entry).unwrap();
+    }
+
+    /// Lists up to `limit` of the latest announcements, newest first.
+    pub fn recent(&self, limit: usize) -> Vec<Announcement> {
+        self.tree
+            .iter()
+            .rev()
+            .filter_map(|entry| {
+                let (key, entry) = entry.unwrap();
+                let mut entry = serde_json::from_slice::<Announcement>(&entry).ok()?;
+                entry.id = u64::from_be_bytes(key.as_ref().try_into().ok()?);
+                Some(entry)
+            })
+            .take(limit)
+            .collect()
+    }
+}
@@ -0,0 +1,122 @@
+//! Player passwords.
+//!
+//! Passwords are never stored, only their [argon2] hashes in PHC string
+//! format, which record the salt and parameters they were hashed with.
+//!
+//! Once [MAX_FAILED_LOGINS] wrong passwords have been tried for a player
+//! from one IP address, logins as them from that address are refused
+//! without checking until [LOCKOUT] has passed, so that their password can't
+//! be guessed quickly and guesses can't tie up the server with hashing.
+//! Logins from other addresses go on as usual, so nobody can lock a player
+//! out of their own account by guessing wrong on purpose.
+
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use argon2::{
+    password_hash::{PasswordHash, PasswordHasher, PasswordVerifier, SaltString},
+    Argon2,
+};
+
+/// The fewest characters that a password may have.
+pub const MIN_PASSWORD_LEN: usize = 8;
+
+/// How many wrong passwords may be tried for a player from one address
+/// before logins as them from there are refused.
+pub const MAX_FAILED_LOGINS: u32 = 5;
+
+/// How long logins are refused for after too many wrong passwords.
+pub const LOCKOUT: Duration = Duration::from_secs(15 * 60);
+
+/// Checks that a password may be set, returning why not if it can't be.
+///
+/// Passwords can't contain whitespace, so that every command can tell them
+/// apart from the words around them.
+pub fn check(password: &str) -> Result<(), String> {
+    if password.chars().count() < MIN_PASSWORD_LEN {
+        return Err(format!(
+            "passwords must be at least {MIN_PASSWORD_LEN} characters long"
+        ));
+    }
+
+    if password.contains(char::is_whitespace) {
+        return Err("passwords can't contain spaces".to_string());
+    }
+
+    Ok(())
+}
+
+/// Hashes a password with a new random salt.
+pub fn hash(password: &str) -> String {
+    let salt = SaltString::generate(&mut rand::rngs::OsRng);
+
+    Argon2::default()
+        .hash_password(password.as_bytes(), &salt)
+        .expect("failed to hash password")
+        .to_string()
+}
+
+/// Gets the IP address that a connection's address comes from, so that
+/// reconnecting from another port doesn't reset its failures.
+fn source(addr: &str) -> String {
+    match addr.parse::<SocketAddr>() {
+        Ok(addr) => addr.ip().to_string(),
+        Err(_) => addr.to_string(),
+    }
+}
+
+/// Keeps count of the wrong passwords tried for each player from each
+/// address.
+#[derive(Default)]
+pub struct Failures {
+    failed: Mutex<HashMap<(String, usize), (u32, Instant)>>,
+}
+
+impl Failures {
+    /// Tests if logins as a player from an address are being refused.
+    pub fn is_locked(&self, addr: &str, player: usize) -> bool {
+        let mut failed = self.failed.lock().unwrap();
+
+        // forgetting old failures keeps the map small
+        failed.retain(|_, (_, last)| last.elapsed() < LOCKOUT);
+        failed
+            .get(&(source(addr), player))
+            .is_some_and(|(count, _)| *count >= MAX_FAILED_LOGINS)
+    }
+
+    /// Counts a wrong password tried for a player from an address.
+    pub fn fail(&self, addr: &str, player: usize) {
+        let mut failed = self.failed.lock().unwrap();
+        let key = (source(addr), player);
+        let (count, last) = failed.entry(key).or_insert((0, Instant::now()));
+        *count += 1;
+        *last = Instant::now();
+    }
+
+    /// Forgets the wrong passwords tried for a player from an address, once
+    /// they log in from there.
+    pub fn clear(&self, addr: &str, player: usize) {
+        self.failed.lock().unwrap().remove(&(source(addr), player));
+    }
+
+    /// Forgets the wrong passwords tried for a player from anywhere.
+    pub fn clear_all(&self, player: usize) {
+        let mut failed = self.failed.lock().unwrap();
+        failed.retain(|(_, failed), _| *failed != player);
+    }
+}
+
+/// Tests if a password matches a hash made by [hash].
+pub fn verify(hash: &str, password: &str) -> bool {
+    let Ok(hash) = PasswordHash::new(hash) else {
+        return false;
+    };
+
+    Argon2::default()
+        .verify_password(password.as_bytes(), &hash)
+        .is_ok()
+}
@@ -0,0 +1,133 @@
+//! Ownership and field permissions.
+//!
+//! Every object is owned by the object in its `owner` field, or by itself if
+//! it has none. Owners and wizards may always read and write the fields on
+//! their objects, while everyone else is limited by each field's
+//! [FieldPerms]. Only wizards may write [PROTECTED_FIELDS].
+
+use std::fmt::Display;
+
+use sled::transaction::{TransactionalTree, UnabortableTransactionError};
+
+use crate::Value;
+
+type Result<T> = std::result::Result<T, UnabortableTransactionError>;
+
+/// The fields that only wizards may write.
+pub const PROTECTED_FIELDS: &[&str] = &[
+    "owner",
+    "wizard",
+    "quota",
+    "coins",
+    "guest",
+    "console",
+    "last_connected",
+    "last_disconnected",
+    "last_location",
+    "http_domains",
+];
+
+/// The permission flags on a field, for objects other than its owner.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FieldPerms {
+    /// Whether anyone may read the field.
+    pub readable: bool,
+
+    /// Whether anyone may write the field.
+    pub writable: bool,
+}
+
+impl Default for FieldPerms {
+    fn default() -> Self {
+        Self {
+            readable: true,
+            writable: false,
+        }
+    }
+}
+
+impl Display for FieldPerms {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match (self.readable, self.writable) {
+            (true, true) => write!(f, "rw"),
+            (true, false) => write!(f, "r"),
+            (false, true) => write!(f, "w"),
+            (false, false) => write!(f, "-"),
+        }
+    }
+}
+
+impl FieldPerms {
+    /// Parses permission flags made of `r` and `w`, or `-` for owner-only.
+    pub fn parse(flags: &str) -> Option<Self> {
+        let mut perms = Self {
+            readable: false,
+            writable: false,
+        };
+
+        for flag in flags.chars() {
+            match flag {
+                'r' => perms.readable = true,
+                'w' => perms.writable = true,
+                '-' => {}
+                _ => return None,
+            }
+        }
+
+        Some(perms)
+    }
+}
+
+/// Gets the key that stores a field's permission flags.
+pub fn perms_key(id: usize, key: &str) -> Vec<u8> {
+    format!("object-perms-{id}-{key}").into_bytes()
+}
+
+/// Reads a field's value inside of a transaction.
+fn read(tx: &TransactionalTree, id: usize, key: &str) -> Result<Option<Value>> {
+    let Some(val) = tx.get(format!("object-field-{id}-{key}"))? else {
+        return Ok(None);
+    };
+
+    Ok(serde_json::from_slice(&val).ok())
+}
+
+/// Tests if an object is a wizard.
+pub fn is_wizard(tx: &TransactionalTree, who: usize) -> Result<bool> {
+    Ok(matches!(read(tx, who, "wizard")?, Some(Value::Bool(true))))
+}
+
+/// Gets the owner of an object.
+pub fn owner(tx: &TransactionalTree, id: usize) -> Result<usize> {
+    let owner = read(tx, id, "owner")?.and_then(|owner| owner.as_id());
+    Ok(owner.unwrap_or(id))
+}
+
+/// Tests if an object may act as the owner of another object.
+pub fn controls(tx: &TransactionalTree, who: usize, id: usize) -> Result<bool> {
+    Ok(is_wizard(tx, who)? || owner(tx, id)? == who)
+}
+
+/// Gets the permission flags on a field.
+pub fn field_perms(tx: &TransactionalTree, id: usize, key: &str) -> Result<FieldPerms> {
+    let Some(flags) = tx.get(perms_key(id, key))? else {
+        return Ok(FieldPerms::default());
+    };
+
+    let flags = String::from_utf8_lossy(&flags);
+    Ok(FieldPerms::parse(&flags).unwrap_or_default())
+}
+
+/// Tests if an object may read a field.
+pub fn can_read(tx: &TransactionalTree, who: usize, id: usize, key: &str) -> Result<bool> {
+    Ok(controls(tx, who, id)? || field_perms(tx, id, key)?.readable)
+}
+
+/// Tests if an object may write a field.
+pub fn can_write(tx: &TransactionalTree, who: usize, id: usize, key: &str) -> Result<bool> {
+    if PROTECTED_FIELDS.contains(&key) {
+        return is_wizard(tx, who);
+    }
+
+    Ok(controls(tx, who, id)? || field_perms(tx, id, key)?.writable)
+}
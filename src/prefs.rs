@@ -0,0 +1,155 @@
+//! Players' preferences for how their output looks, which `@prefs` sets.
+//!
+//! Each preference is a field on the player:
+//!
+//! - `pref_width`: the width to wrap lines to, instead of the width of the
+//!   player's window, or zero to never wrap them
+//! - `pref_color`: whether colors and other escape sequences are kept, which
+//!   they are unless this is false
+//! - `pref_ascii`: whether characters outside of ASCII are replaced with
+//!   look-alikes, for terminals that can't show them
+
+use crate::{State, Value};
+
+/// The field holding the width to wrap lines to.
+pub const WIDTH_FIELD: &str = "pref_width";
+
+/// The field holding whether colors are shown.
+pub const COLOR_FIELD: &str = "pref_color";
+
+/// The field holding whether only ASCII is sent.
+pub const ASCII_FIELD: &str = "pref_ascii";
+
+/// The widest lines that players may ask for.
+pub const MAX_WIDTH: i64 = 1000;
+
+/// How a player's output is written.
+#[derive(Clone, Copy, Debug)]
+pub struct Prefs {
+    /// The width to wrap lines to, if it isn't the window's.
+    pub width: Option<usize>,
+    pub color: bool,
+    pub ascii: bool,
+}
+
+impl Prefs {
+    /// Reads a player's preferences from their fields.
+    pub fn of(state: &State, player: usize) -> Self {
+        let width = state
+            .get(player, WIDTH_FIELD)
+            .and_then(|width| width.as_id());
+        let flag = |field, default| match state.get(player, field) {
+            Some(Value::Bool(flag)) => flag,
+            _ => default,
+        };
+
+        Self {
+            width,
+            color: flag(COLOR_FIELD, true),
+            ascii: flag(ASCII_FIELD, false),
+        }
+    }
+
+    /// Gets the width to wrap lines to, given the width of the window.
+    pub fn width(&self, window: usize) -> usize {
+        self.width.unwrap_or(window)
+    }
+
+    /// Rewrites a line of output to suit these preferences.
+    pub fn apply(&self, line: &str) -> String {
+        let line = match self.color {
+            true => line.to_string(),
+            false => strip_escapes(line),
+        };
+
+        match self.ascii {
+            true => to_ascii(&line),
+            false => line,
+        }
+    }
+}
+
+/// Removes the escape sequences from a line, like the ANSI colors that
+/// [markup](crate::markup) renders.
+pub fn strip_escapes(line: &str) -> String {
+    let mut out = String::with_capacity(line.len());
+    let mut chars = line.chars();
+
+    while let Some(c) = chars.next() {
+        if c != '\x1b' {
+            out.push(c);
+            continue;
+        }
+
+        // control sequences run until a final byte from `@` to `~`
+        if chars.next() == Some('[') {
+            for c in chars.by_ref() {
+                if ('@'..='~').contains(&c) {
+                    break;
+                }
+            }
+        }
+    }
+
+    out
+}
+
+/// Replaces the characters of a line that aren't ASCII with look-alikes, or
+/// with `?` if they have none.
+pub fn to_ascii(line: &str) -> String {
+    let mut out = String::with_capacity(line.len());
+    for c in line.chars() {
+        if c.is_ascii() {
+            out.push(c);
+            continue;
+        }
+
+        let replacement = match c {
+            '\u{2018}' | '\u{2019}' | '\u{201a}' | '\u{201b}' | '\u{2032}' => "'",
+            '\u{201c}' | '\u{201d}' | '\u{201e}' | '\u{2033}' | '«' | '»' => "\"",
+            '\u{2010}'..='\u{2015}' | '\u{2212}' => "-",
+            '\u{2026}' => "...",
+            '\u{2022}' | '·' => "*",
+            '\u{a0}' | '\u{2002}'..='\u{200a}' => " ",
+            '©' => "(c)",
+            '®' => "(R)",
+            '\u{2122}' => "(TM)",
+            '×' => "x",
+            '÷' => "/",
+            '°' => "o",
+            '\u{2190}' => "<-",
+            '\u{2192}' => "->",
+            '─' | '━' | '═' => "-",
+            '│' | '┃' | '║' => "|",
+            '┌'..='╋' | '╔'..='╬' => "+",
+            'À'..='Å' => "A",
+            'Æ' => "AE",
+            'Ç' => "C",
+            'È'..='Ë' => "E",
+            'Ì'..='Ï' => "I",
+            'Ð' => "D",
+            'Ñ' => "N",
+            'Ò'..='Ö' | 'Ø' => "O",
+            'Ù'..='Ü' => "U",
+            'Ý' => "Y",
+            'Þ' => "Th",
+            'ß' => "ss",
+            'à'..='å' => "a",
+            'æ' => "ae",
+            'ç' => "c",
+            'è'..='ë' => "e",
+            'ì'..='ï' => "i",
+            'ð' => "d",
+            'ñ' => "n",
+            'ò'..='ö' | 'ø' => "o",
+            'ù'..='ü' => "u",
+            'ý' | 'ÿ' => "y",
+            'þ' => "th",
+            _ => "?",
+        };
+
+        out.push_str(replacement);
+    }
+
+    out
+}
@@ -0,0 +1,125 @@
+//! The PROXY protocol, which load balancers like HAProxy use to pass on the
+//! address of the client that they are forwarding.
+//!
+//! When it is enabled, every connection must start with a version 1 or 2
+//! header, and connections that don't are refused, since the address that
+//! they claim to be from couldn't be trusted.
+
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+/// The environment variable that enables the PROXY protocol when it is set
+/// to `1` or `true`.
+pub const PROXY_PROTOCOL_VAR: &str = "MARCIEMOO_PROXY_PROTOCOL";
+
+/// The signature that starts a version 2 header.
+const V2_SIGNATURE: &[u8; 12] = b"\r\n\r\n\0\r\nQUIT\n";
+
+/// The longest that a version 1 header may be, including its CRLF.
+const V1_MAX_LEN: usize = 107;
+
+/// Tests if the PROXY protocol is enabled.
+pub fn enabled() -> bool {
+    matches!(
+        std::env::var(PROXY_PROTOCOL_VAR).as_deref(),
+        Ok("1" | "true")
+    )
+}
+
+/// Reads a PROXY protocol header from the start of a connection.
+///
+/// Returns the address of the client that the proxy forwarded, or nothing
+/// if the proxy connected on its own behalf, like for a health check.
+pub async fn read_header(
+    conn: &mut (impl AsyncRead + Unpin),
+) -> std::io::Result<Option<SocketAddr>> {
+    // both versions' headers are at least this long, so this never reads
+    // past the end of one
+    let mut start = [0; 12];
+    conn.read_exact(&mut start).await?;
+
+    if &start == V2_SIGNATURE {
+        read_v2(conn).await
+    } else if start.starts_with(b"PROXY ") {
+        read_v1(conn, &start).await
+    } else {
+        Err(invalid("missing PROXY protocol header"))
+    }
+}
+
+/// Reads the rest of a version 1 header, which is a line of text like
+/// `PROXY TCP4 192.0.2.1 198.51.100.1 56324 8888`.
+async fn read_v1(
+    conn: &mut (impl AsyncRead + Unpin),
+    start: &[u8],
+) -> std::io::Result<Option<SocketAddr>> {
+    let mut line = start.to_vec();
+
+    // read a byte at a time so that none of the client's text is taken
+    while !line.ends_with(b"\r\n") {
+        if line.len() >= V1_MAX_LEN {
+            return Err(invalid("PROXY protocol header is too long"));
+        }
+
+        line.push(conn.read_u8().await?);
+    }
+
+    let line = std::str::from_utf8(&line[..line.len() - 2])
+        .map_err(|_| invalid("PROXY protocol header is not text"))?;
+
+    let parts: Vec<&str> = line.split(' ').collect();
+    match parts.as_slice() {
+        ["PROXY", "UNKNOWN", ..] => Ok(None),
+        ["PROXY", "TCP4" | "TCP6", src, _dst, src_port, _dst_port] => {
+            let ip: IpAddr = src.parse().map_err(|_| invalid("invalid source address"))?;
+            let port: u16 = src_port
+                .parse()
+                .map_err(|_| invalid("invalid source port"))?;
+            Ok(Some(SocketAddr::new(ip, port)))
+        }
+        _ => Err(invalid("invalid PROXY protocol header")),
+    }
+}
+
+/// Reads the rest of a version 2 header, which is binary.
+async fn read_v2(conn: &mut (impl AsyncRead + Unpin)) -> std::io::Result<Option<SocketAddr>> {
+    let version_command = conn.read_u8().await?;
+    let family = conn.read_u8().await?;
+    let len = conn.read_u16().await?;
+
+    let mut data = vec![0; len as usize];
+    conn.read_exact(&mut data).await?;
+
+    if version_command >> 4 != 2 {
+        return Err(invalid("unsupported PROXY protocol version"));
+    }
+
+    // the LOCAL command is for connections that the proxy makes itself
+    if version_command & 0xf == 0 {
+        return Ok(None);
+    }
+
+    let addr = match family {
+        // TCP over IPv4
+        0x11 if data.len() >= 12 => {
+            let ip: [u8; 4] = data[0..4].try_into().unwrap();
+            let port = u16::from_be_bytes([data[8], data[9]]);
+            SocketAddr::new(Ipv4Addr::from(ip).into(), port)
+        }
+        // TCP over IPv6
+        0x21 if data.len() >= 36 => {
+            let ip: [u8; 16] = data[0..16].try_into().unwrap();
+            let port = u16::from_be_bytes([data[32], data[33]]);
+            SocketAddr::new(Ipv6Addr::from(ip).into(), port)
+        }
+        // other protocols, like unix sockets, have no address to use
+        _ => return Ok(None),
+    };
+
+    Ok(Some(addr))
+}
+
+fn invalid(message: &str) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, message)
+}
@@ -0,0 +1,197 @@
+//! Registering new characters.
+//!
+//! Registration is enabled by setting the `MARCIEMOO_VERIFY_COMMAND`
+//! environment variable to a program that delivers verification codes, such
+//! as a script that sends an email or calls a webhook. It is run with the
+//! email address and the code as its two arguments. A character is only
+//! created once its code is entered, so throwaway addresses can't be used.
+//!
+//! So that the server can't be made to flood an inbox, each connection may
+//! only have [MAX_CODES_PER_CONNECTION] codes sent, and each address may only
+//! be sent [MAX_CODES_PER_EMAIL] codes in [SEND_WINDOW]. Registering again
+//! sends a new code, but wrong codes entered for the old one still count
+//! against [MAX_ATTEMPTS].
+
+use std::{
+    collections::{HashMap, VecDeque},
+    fmt::Display,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use rand::Rng;
+
+/// The environment variable naming the program that sends codes.
+pub const VERIFY_COMMAND_VAR: &str = "MARCIEMOO_VERIFY_COMMAND";
+
+/// How long a verification code may be used for.
+pub const CODE_LIFETIME: Duration = Duration::from_secs(15 * 60);
+
+/// How many wrong codes may be entered before a registration is cancelled.
+pub const MAX_ATTEMPTS: u32 = 5;
+
+/// How many codes each connection may have sent.
+pub const MAX_CODES_PER_CONNECTION: u32 = 3;
+
+/// How many codes may be sent to each address in [SEND_WINDOW].
+pub const MAX_CODES_PER_EMAIL: usize = 3;
+
+/// How long codes sent to an address count against its limit.
+pub const SEND_WINDOW: Duration = Duration::from_secs(60 * 60);
+
+/// The longest email address that may be registered, in bytes.
+pub const MAX_EMAIL_LEN: usize = 254;
+
+/// A character waiting for its email address to be verified.
+#[derive(Clone, Debug)]
+pub struct Registration {
+    pub name: String,
+    pub email: String,
+    code: u32,
+    expires: Instant,
+    attempts: u32,
+
+    /// How many codes the connection has had sent, counting this one.
+    sent: u32,
+}
+
+/// The reasons that a code can't be sent.
+#[derive(Debug)]
+pub enum SendError {
+    TooManyCodes,
+    TooManyForEmail,
+}
+
+impl Display for SendError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SendError::TooManyCodes => write!(f, "too many codes have been sent already"),
+            SendError::TooManyForEmail => {
+                write!(f, "too many codes have been sent to that address lately")
+            }
+        }
+    }
+}
+
+impl Registration {
+    /// Starts registering a character with a new random code.
+    pub fn new(name: String, email: String) -> Self {
+        Self {
+            name,
+            email,
+            code: rand::thread_rng().gen_range(0..1_000_000),
+            expires: Instant::now() + CODE_LIFETIME,
+            attempts: 0,
+            sent: 1,
+        }
+    }
+
+    /// Takes over the counts of codes sent and entered from the
+    /// registration that this one replaces, failing if the connection may
+    /// not have another code sent.
+    pub fn replace(&mut self, old: &Self) -> Result<(), SendError> {
+        if old.sent >= MAX_CODES_PER_CONNECTION {
+            return Err(SendError::TooManyCodes);
+        }
+
+        self.attempts = old.attempts;
+        self.sent = old.sent + 1;
+        Ok(())
+    }
+
+    /// Formats the code to send.
+    pub fn code(&self) -> String {
+        format!("{:06}", self.code)
+    }
+
+    /// Tests if a code entered by the player is this registration's.
+    pub fn verify(&mut self, code: i64) -> bool {
+        self.attempts += 1;
+        !self.is_spent() && i64::from(self.code) == code
+    }
+
+    /// Tests if this registration has expired or had too many wrong codes
+    /// entered, so that it can no longer be verified.
+    pub fn is_spent(&self) -> bool {
+        Instant::now() >= self.expires || self.attempts > MAX_ATTEMPTS
+    }
+}
+
+/// Keeps track of when codes were sent to each address.
+#[derive(Default)]
+pub struct Sends {
+    sent: Mutex<HashMap<String, VecDeque<Instant>>>,
+}
+
+impl Sends {
+    /// Counts a code sent to an address, failing if the address has been
+    /// sent as many codes as it may lately.
+    pub fn take(&self, email: &str) -> Result<(), SendError> {
+        let mut sent = self.sent.lock().unwrap();
+
+        // forgetting addresses that haven't been sent codes lately keeps the
+        // map small
+        sent.retain(|_, times| {
+            while times
+                .front()
+                .is_some_and(|time| time.elapsed() >= SEND_WINDOW)
+            {
+                times.pop_front();
+            }
+
+            !times.is_empty()
+        });
+
+        let times = sent.entry(email.to_lowercase()).or_default();
+        if times.len() >= MAX_CODES_PER_EMAIL {
+            return Err(SendError::TooManyForEmail);
+        }
+
+        times.push_back(Instant::now());
+        Ok(())
+    }
+}
+
+/// Gets the program that sends codes, if registration is enabled.
+pub fn verify_command() -> Option<String> {
+    std::env::var(VERIFY_COMMAND_VAR)
+        .ok()
+        .filter(|command| !command.is_empty())
+}
+
+/// Tests if a string looks enough like an email address to send a code to.
+pub fn is_email(email: &str) -> bool {
+    let Some((user, domain)) = email.split_once('@') else {
+        return false;
+    };
+
+    // addresses are passed as arguments, so they mustn't look like options
+    email.len() <= MAX_EMAIL_LEN
+        && !user.is_empty()
+        && !user.starts_with('-')
+        && domain.contains('.')
+        && !domain.starts_with('.')
+        && !domain.ends_with('.')
+        && !email.contains(char::is_whitespace)
+        && !domain.contains('@')
+}
+
+/// Runs the program that sends a registration's code, in the background.
+pub fn send_code(command: String, registration: &Registration) {
+    let email = registration.email.clone();
+    let code = registration.code();
+
+    tokio::spawn(async move {
+        let status = tokio::process::Command::new(&command)
+            .arg(&email)
+            .arg(&code)
+            .status()
+            .await;
+
+        match status {
+            Ok(status) if status.success() => {}
+            Ok(status) => eprintln!("{command} failed to send a code to {email}: {status}"),
+            Err(err) => eprintln!("could not run {command}: {err}"),
+        }
+    });
+}
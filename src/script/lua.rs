@@ -0,0 +1,361 @@
+//! Verbs written in Lua.
+//!
+//! Lua verbs start with `#!lua`. They see the same globals as Rhai verbs:
+//! `self`, `player`, and any bound objects like `mover` are objects whose
+//! fields are read and written by indexing them, `object(id)` gets any other
+//! object, `args` holds the rest of a scripted command's line, `width` and
+//! `height` hold the size of the player's window, and `print`,
+//! `emit`, and `announce` output text. Only Lua's
+//! string, table, math, and utf8 libraries are loaded.
+//!
+//! `gmcp_send(package, json)` sends a GMCP packet to the player,
+//! `transfer(from, to, amount)` moves coins between objects, and
+//! `world_time()` and `is_daytime()` read the world clock. `create()`,
+//! `clone(object)`, and `destroy(object)` make and unmake objects, as the
+//! `@create`, `@clone`, and `@destroy` commands do, and `move(object, dest)`
+//! moves an object and runs the hooks of the rooms it leaves and enters.
+//! `objects()`, `children(object)`, and `contents(object)` return sequences
+//! of objects, and `fields(object)` the names of an object's fields.
+//! `match_object(name)` and `match_player(name)` find objects by name as
+//! commands do, returning nil if nothing or more than one thing matches.
+//! `read(prompt)` asks the player a question and returns their answer, and
+//! `suspend(seconds)` pauses the verb. `http_get(url)` fetches a page from
+//! one of the domains that wizards allow, as described by
+//! [http](crate::http).
+
+use std::{cell::Cell, sync::Arc};
+
+use mlua::{
+    HookTriggers, Lua, LuaOptions, MetaMethod, StdLib, UserData, UserDataMethods, UserDataRef,
+    VmState,
+};
+
+use super::{Host, ScriptEngine};
+use crate::Value;
+
+/// The most Lua VM instructions that each verb may run.
+pub const LUA_INSTRUCTIONS: u64 = 100_000_000;
+
+/// The most bytes of memory that each verb's Lua state may use.
+pub const LUA_MEMORY: usize = 64 << 20;
+
+/// How many instructions run between checks of the instruction limit.
+const HOOK_INTERVAL: u32 = 10_000;
+
+/// An object as seen by a Lua script.
+struct Object {
+    id: usize,
+    host: Arc<Host>,
+}
+
+impl UserData for Object {
+    fn add_methods<M: UserDataMethods<Self>>(methods: &mut M) {
+        methods.add_meta_method(MetaMethod::Index, |lua, this, field: String| {
+            let val = this
+                .host
+                .get(this.id, &field)
+                .map_err(mlua::Error::runtime)?;
+
+            Ok(match val {
+                None => mlua::Value::Nil,
+                Some(Value::Integer(val)) => mlua::Value::Integer(val),
+                Some(Value::Bool(val)) => mlua::Value::Boolean(val),
+                Some(Value::String(val)) => mlua::Value::String(lua.create_string(val)?),
+            })
+        });
+
+        methods.add_meta_method(
+            MetaMethod::NewIndex,
+            |_, this, (field, val): (String, mlua::Value)| {
+                let val = match val {
+                    mlua::Value::Nil => None,
+                    mlua::Value::Integer(val) => Some(Value::Integer(val)),
+                    mlua::Value::Boolean(val) => Some(Value::Bool(val)),
+                    mlua::Value::String(val) => Some(Value::String(val.to_str()?.to_string())),
+                    _ => return Err(mlua::Error::runtime("invalid value type")),
+                };
+
+                this.host
+                    .set(this.id, &field, val)
+                    .map_err(mlua::Error::runtime)
+            },
+        );
+    }
+}
+
+/// Runs verbs written in Lua.
+pub struct LuaEngine;
+
+impl LuaEngine {
+    /// Sets up a Lua state and runs a verb in it.
+    fn try_run(&self, host: &Host, verb: &str, src: &str) -> mlua::Result<()> {
+        let host = Arc::new(host.clone());
+        let libs = StdLib::STRING | StdLib::TABLE | StdLib::MATH | StdLib::UTF8;
+        let lua = Lua::new_with(libs, LuaOptions::default())?;
+        lua.set_memory_limit(LUA_MEMORY)?;
+
+        let ran = Cell::new(0u64);
+        lua.set_hook(
+            HookTriggers::new().every_nth_instruction(HOOK_INTERVAL),
+            move |_, _| {
+                ran.set(ran.get() + HOOK_INTERVAL as u64);
+                if ran.get() > LUA_INSTRUCTIONS {
+                    return Err(mlua::Error::runtime("ran out of instructions"));
+                }
+
+                Ok(VmState::Continue)
+            },
+        )?;
+
+        let globals = lua.globals();
+        let object = |id| Object {
+            id,
+            host: host.clone(),
+        };
+
+        globals.set("self", object(host.self_id))?;
+        globals.set("player", object(host.player))?;
+        globals.set("args", host.args.as_str())?;
+
+        let (width, height) = host.window_size();
+        globals.set("width", width)?;
+        globals.set("height", height)?;
+
+        for (name, id) in host.bindings.iter() {
+            globals.set(name.as_str(), object(*id))?;
+        }
+
+        globals.set(
+            "object",
+            lua.create_function({
+                let host = host.clone();
+                move |_, id: i64| {
+                    let id = id
+                        .try_into()
+                        .map_err(|_| mlua::Error::runtime("invalid object ID"))?;
+
+                    Ok(Object {
+                        id,
+                        host: host.clone(),
+                    })
+                }
+            })?,
+        )?;
+
+        for (name, output) in [
+            ("print", Host::print as fn(&Host, &str)),
+            ("emit", Host::emit),
+            ("announce", Host::announce),
+        ] {
+            let host = host.clone();
+            let output = lua.create_function(move |_, message: mlua::LuaString| {
+                output(&host, &message.to_str()?);
+                Ok(())
+            })?;
+
+            globals.set(name, output)?;
+        }
+
+        globals.set(
+            "suspend",
+            lua.create_function({
+                let host = host.clone();
+                move |_, seconds: i64| host.suspend(seconds).map_err(mlua::Error::runtime)
+            })?,
+        )?;
+
+        globals.set(
+            "read",
+            lua.create_function({
+                let host = host.clone();
+                move |_, prompt: mlua::LuaString| {
+                    host.read(&prompt.to_str()?).map_err(mlua::Error::runtime)
+                }
+            })?,
+        )?;
+
+        globals.set(
+            "http_get",
+            lua.create_function({
+                let host = host.clone();
+                move |_, url: mlua::LuaString| {
+                    host.http_get(&url.to_str()?).map_err(mlua::Error::runtime)
+                }
+            })?,
+        )?;
+
+        globals.set(
+            "gmcp_send",
+            lua.create_function({
+                let host = host.clone();
+                move |_, (package, data): (mlua::LuaString, mlua::LuaString)| {
+                    host.gmcp_send(&package.to_str()?, &data.to_str()?)
+                        .map_err(mlua::Error::runtime)
+                }
+            })?,
+        )?;
+
+        globals.set(
+            "create",
+            lua.create_function({
+                let host = host.clone();
+                move |_, ()| {
+                    let id = host.create().map_err(mlua::Error::runtime)?;
+                    Ok(Object {
+                        id,
+                        host: host.clone(),
+                    })
+                }
+            })?,
+        )?;
+
+        globals.set(
+            "clone",
+            lua.create_function({
+                let host = host.clone();
+                move |_, object: UserDataRef<Object>| {
+                    let id = host.clone_object(object.id).map_err(mlua::Error::runtime)?;
+
+                    Ok(Object {
+                        id,
+                        host: host.clone(),
+                    })
+                }
+            })?,
+        )?;
+
+        globals.set(
+            "destroy",
+            lua.create_function({
+                let host = host.clone();
+                move |_, object: UserDataRef<Object>| {
+                    host.destroy(object.id).map_err(mlua::Error::runtime)
+                }
+            })?,
+        )?;
+
+        globals.set(
+            "move",
+            lua.create_function({
+                let host = host.clone();
+                move |_, (object, dest): (UserDataRef<Object>, UserDataRef<Object>)| {
+                    host.move_object(object.id, dest.id)
+                        .map_err(mlua::Error::runtime)
+                }
+            })?,
+        )?;
+
+        for (name, matcher) in [
+            (
+                "match_object",
+                Host::match_object as fn(&Host, &str) -> Option<usize>,
+            ),
+            ("match_player", Host::match_player),
+        ] {
+            let host = host.clone();
+            let matcher = lua.create_function(move |_, name: mlua::LuaString| {
+                let found = matcher(&host, &name.to_str()?);
+                Ok(found.map(|id| Object {
+                    id,
+                    host: host.clone(),
+                }))
+            })?;
+
+            globals.set(name, matcher)?;
+        }
+
+        let objects = {
+            let host = host.clone();
+            move |lua: &Lua, ids: Vec<usize>| {
+                let objects = ids.into_iter().map(|id| Object {
+                    id,
+                    host: host.clone(),
+                });
+
+                lua.create_sequence_from(objects)
+            }
+        };
+
+        globals.set(
+            "objects",
+            lua.create_function({
+                let host = host.clone();
+                let objects = objects.clone();
+                move |lua, ()| objects(lua, host.objects())
+            })?,
+        )?;
+
+        globals.set(
+            "children",
+            lua.create_function({
+                let host = host.clone();
+                let objects = objects.clone();
+                move |lua, object: UserDataRef<Object>| objects(lua, host.children(object.id))
+            })?,
+        )?;
+
+        globals.set(
+            "contents",
+            lua.create_function({
+                let host = host.clone();
+                move |lua, object: UserDataRef<Object>| objects(lua, host.contents(object.id))
+            })?,
+        )?;
+
+        globals.set(
+            "fields",
+            lua.create_function({
+                let host = host.clone();
+                move |lua, object: UserDataRef<Object>| {
+                    let fields = host.fields(object.id).map_err(mlua::Error::runtime)?;
+                    lua.create_sequence_from(fields)
+                }
+            })?,
+        )?;
+
+        globals.set(
+            "world_time",
+            lua.create_function({
+                let host = host.clone();
+                move |_, ()| Ok(host.world_time())
+            })?,
+        )?;
+
+        globals.set(
+            "is_daytime",
+            lua.create_function({
+                let host = host.clone();
+                move |_, ()| Ok(host.is_daytime())
+            })?,
+        )?;
+
+        globals.set(
+            "transfer",
+            lua.create_function({
+                let host = host.clone();
+                move |_, (from, to, amount): (i64, i64, i64)| {
+                    let (Ok(from), Ok(to)) = (from.try_into(), to.try_into()) else {
+                        return Err(mlua::Error::runtime("invalid object ID"));
+                    };
+
+                    host.transfer(from, to, amount)
+                        .map_err(mlua::Error::runtime)
+                }
+            })?,
+        )?;
+
+        lua.load(src).set_name(host.describe_verb(verb)).exec()
+    }
+}
+
+impl ScriptEngine for LuaEngine {
+    fn run(&self, host: &Host, verb: &str, src: &str) {
+        let Err(err) = self.try_run(host, verb, src) else {
+            return;
+        };
+
+        let verb = host.describe_verb(verb);
+        let err = err.to_string();
+        host.print(&format!("script error in {verb}: {}", err.trim_end()));
+    }
+}
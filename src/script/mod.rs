@@ -0,0 +1,845 @@
+//! Verb scripting.
+//!
+//! A verb is a field holding a script's source. Verbs are written in Rhai
+//! unless their source starts with `#!` and the name of another
+//! [ScriptEngine], like `#!lua` or `#!wasm`. Every engine accesses the world through
+//! the same [Host].
+//!
+//! Verbs that `suspend` carry on in the background once they wake, with
+//! everything in their scope as they left it. Whatever ran the verb stops
+//! waiting for it when it first suspends, and what the verb outputs after
+//! that is sent out as it goes.
+//!
+//! When the host refuses a verb, it fails with a [ScriptError], whose code
+//! stays the same even if its message is reworded, so that verbs can catch
+//! the errors they expect and fall back gracefully.
+
+use std::sync::{Arc, Mutex, OnceLock};
+
+use std::{
+    fmt::Display,
+    sync::atomic::{AtomicBool, Ordering},
+    time::Duration,
+};
+
+use tokio::sync::oneshot;
+use tokio_util::sync::CancellationToken;
+
+use crate::{
+    clock,
+    http::{self, FetchError},
+    now,
+    tasks::{self, Task, Waiting},
+    telnet::WindowSize,
+    world_clock, FieldError, Input, Lines, MoveError, State, TransferError, Value, SYSTEM_OBJECT,
+};
+
+mod lua;
+mod rhai;
+mod wasm;
+
+pub use self::lua::LuaEngine;
+pub use self::rhai::{RhaiEngine, VerbCache};
+pub use self::wasm::WasmEngine;
+
+/// A language that verbs may be written in.
+pub trait ScriptEngine: Send + Sync {
+    /// Runs a verb's source, reporting any script error to the player.
+    fn run(&self, host: &Host, verb: &str, src: &str);
+}
+
+/// Finds the engine for a language by name.
+pub fn engine(lang: &str) -> Option<&'static dyn ScriptEngine> {
+    static RHAI: RhaiEngine = RhaiEngine;
+    static LUA: LuaEngine = LuaEngine;
+    static WASM: OnceLock<WasmEngine> = OnceLock::new();
+
+    match lang {
+        "rhai" => Some(&RHAI),
+        "lua" => Some(&LUA),
+        "wasm" => Some(WASM.get_or_init(WasmEngine::new)),
+        _ => None,
+    }
+}
+
+/// Splits the language named by a verb's `#!` line from the rest of its
+/// source.
+pub fn language(src: &str) -> (&str, &str) {
+    let Some(header) = src.strip_prefix("#!") else {
+        return ("rhai", src);
+    };
+
+    let (lang, body) = header
+        .split_once(char::is_whitespace)
+        .unwrap_or((header, ""));
+
+    (lang, body)
+}
+
+/// Runs a verb in the language it is written in.
+pub fn run(host: &Host, verb: &str, src: &str) -> ScriptOutput {
+    let (lang, body) = language(src);
+
+    match engine(lang) {
+        Some(engine) => engine.run(host, verb, body),
+        None => host.print(&format!(
+            "script error in {}: unknown language {lang:?}",
+            host.describe_verb(verb)
+        )),
+    }
+
+    host.take_output()
+}
+
+/// The longest that verbs may `suspend` for at once.
+pub const MAX_SUSPEND: i64 = clock::DAY;
+
+/// How long `read` waits for the player to answer before giving up.
+pub const READ_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+
+/// How a verb asks the invoking player for lines with `read`.
+#[derive(Clone)]
+pub struct Reader {
+    /// The lines that the player sends, which are shared with their
+    /// commands.
+    pub lines: Lines,
+
+    /// Sends a message to the player right away.
+    pub send: Arc<dyn Fn(&str) + Send + Sync>,
+}
+
+/// The most verbs that may be nested inside each other by verbs that run
+/// other verbs' hooks.
+pub const MAX_CALL_DEPTH: usize = 16;
+
+/// The world as seen by a running verb.
+///
+/// Every object a verb accesses is read and written with the permissions of
+/// the invoking player, just like the `@get` and `@set` commands. Each access
+/// is atomic on its own, but a verb as a whole is not, so that long-running
+/// verbs don't hold up the database.
+#[derive(Clone)]
+pub struct Host {
+    state: Arc<State>,
+
+    /// The object that the verb is running on.
+    pub self_id: usize,
+
+    /// The player whose permissions the verb runs with.
+    pub player: usize,
+
+    /// Extra objects bound by name for the verb.
+    pub bindings: Vec<(String, usize)>,
+
+    /// The rest of the command line that ran the verb, if a command did.
+    pub args: String,
+
+    /// The size of the invoking player's window, if their client reported
+    /// it.
+    pub window: Option<WindowSize>,
+
+    /// The invoking player's connection, if they are connected to answer
+    /// `read`.
+    pub reader: Option<Reader>,
+
+    /// How many verbs this verb was run from, like an `on_enter` verb run
+    /// by a verb that moved something.
+    depth: usize,
+
+    /// Hands the verb's output so far to whatever is waiting on it, when it
+    /// first suspends.
+    suspender: Arc<Mutex<Option<oneshot::Sender<ScriptOutput>>>>,
+
+    /// Whether nothing is waiting on the verb any more, because it has
+    /// suspended.
+    detached: Arc<AtomicBool>,
+
+    /// The verb's ID among the [tasks], once it has suspended.
+    task: Arc<Mutex<Option<u64>>>,
+
+    /// The name of the verb being run, once it has started.
+    verb: String,
+
+    output: Arc<Mutex<ScriptOutput>>,
+}
+
+impl Host {
+    pub fn new(state: Arc<State>, self_id: usize, player: usize) -> Self {
+        Self {
+            state,
+            self_id,
+            player,
+            bindings: Vec::new(),
+            args: String::new(),
+            window: None,
+            reader: None,
+            depth: 0,
+            suspender: Default::default(),
+            detached: Default::default(),
+            task: Default::default(),
+            verb: String::new(),
+            output: Default::default(),
+        }
+    }
+
+    /// Binds an object to a variable name for the verb.
+    pub fn bind(&mut self, name: &str, id: usize) {
+        self.bindings.push((name.to_string(), id));
+    }
+
+    /// Gets the width and height of the invoking player's window, which are
+    /// zero if they aren't known.
+    pub fn window_size(&self) -> (i64, i64) {
+        self.window.map_or((0, 0), |window| {
+            (i64::from(window.width), i64::from(window.height))
+        })
+    }
+
+    /// Names a verb on this host's object for error messages.
+    pub fn describe_verb(&self, verb: &str) -> String {
+        format!("#{}:{verb}", self.self_id)
+    }
+
+    /// Reads a field on an object.
+    pub fn get(&self, id: usize, field: &str) -> Result<Option<Value>, ScriptError> {
+        if !self.state.can_read(self.player, id, field) {
+            return Err(ScriptError::permission_denied());
+        }
+
+        Ok(self.state.get(id, field))
+    }
+
+    /// Writes a field on an object, or removes it if there is no value.
+    pub fn set(&self, id: usize, field: &str, val: Option<Value>) -> Result<(), ScriptError> {
+        if !self.state.exists(id) {
+            return Err(ScriptError::no_such_object());
+        }
+
+        if !self.state.can_write(self.player, id, field) {
+            return Err(ScriptError::permission_denied());
+        }
+
+        match val {
+            Some(val) => self.state.set(id, field, val).map_err(ScriptError::from),
+            None => {
+                self.state.unset(id, field);
+                Ok(())
+            }
+        }
+    }
+
+    /// Takes an object from the invoking player's quota, which wizards don't
+    /// have to.
+    fn take_quota(&self) -> Result<(), ScriptError> {
+        if self.state.is_wizard(self.player) || self.state.adjust_quota(self.player, -1) {
+            Ok(())
+        } else {
+            Err(ScriptError::new("quota_exceeded", "no quota left"))
+        }
+    }
+
+    /// Returns an object to the invoking player's quota.
+    fn refund_quota(&self) {
+        if !self.state.is_wizard(self.player) {
+            self.state.adjust_quota(self.player, 1);
+        }
+    }
+
+    /// Creates an object owned by the invoking player, out of their quota.
+    pub fn create(&self) -> Result<usize, ScriptError> {
+        self.take_quota()?;
+        self.state.create_owned(self.player).map_err(|err| {
+            self.refund_quota();
+            err.into()
+        })
+    }
+
+    /// Copies an object for the invoking player, out of their quota, like
+    /// `@clone`.
+    pub fn clone_object(&self, id: usize) -> Result<usize, ScriptError> {
+        if !self.state.exists(id) {
+            return Err(ScriptError::no_such_object());
+        }
+
+        self.take_quota()?;
+        self.state.clone_object(id, self.player).ok_or_else(|| {
+            self.refund_quota();
+            ScriptError::no_such_object()
+        })
+    }
+
+    /// Destroys an object that the invoking player controls, like
+    /// `@destroy`.
+    pub fn destroy(&self, id: usize) -> Result<(), ScriptError> {
+        if id == SYSTEM_OBJECT {
+            return Err(ScriptError::invalid_argument(
+                "cannot destroy the system object",
+            ));
+        }
+
+        if !self.state.exists(id) {
+            return Err(ScriptError::no_such_object());
+        }
+
+        if !self.state.controls(self.player, id) {
+            return Err(ScriptError::permission_denied());
+        }
+
+        match self.state.destroy(id) {
+            true => Ok(()),
+            false => Err(ScriptError::no_such_object()),
+        }
+    }
+
+    /// Finds the one object near the invoking player with a name, or with an
+    /// ID like `#42`, as commands do. Names that match nothing, or more than
+    /// one object, find nothing.
+    pub fn match_object(&self, name: &str) -> Option<usize> {
+        if let Some(id) = name.trim().strip_prefix('#') {
+            let id = id.parse().ok();
+            return id.filter(|id| self.state.exists(*id));
+        }
+
+        let mut matches = self.state.match_object(self.player, name);
+        (matches.len() == 1).then(|| matches.remove(0))
+    }
+
+    /// Finds a registered player with a name, or else the one object near
+    /// the invoking player with it, as commands do.
+    pub fn match_player(&self, name: &str) -> Option<usize> {
+        let player = self.state.find_player(name.trim());
+        player.or_else(|| self.match_object(name))
+    }
+
+    /// Lists every object in the world.
+    pub fn objects(&self) -> Vec<usize> {
+        self.state.list()
+    }
+
+    /// Lists the objects cloned from an object.
+    pub fn children(&self, id: usize) -> Vec<usize> {
+        let mut children = self.state.find("parent", &id.to_string());
+        children.retain(|child| {
+            let parent = self.state.get(*child, "parent");
+            parent.and_then(|parent| parent.as_id()) == Some(id)
+        });
+
+        children
+    }
+
+    /// Lists the objects inside an object.
+    pub fn contents(&self, id: usize) -> Vec<usize> {
+        self.state.contents(id)
+    }
+
+    /// Lists the fields of an object that the invoking player may read.
+    pub fn fields(&self, id: usize) -> Result<Vec<String>, ScriptError> {
+        if !self.state.exists(id) {
+            return Err(ScriptError::no_such_object());
+        }
+
+        let fields = self.state.show(id).into_iter().map(|(key, _val)| key);
+        let fields = fields.filter(|key| self.state.can_read(self.player, id, key));
+        Ok(fields.collect())
+    }
+
+    /// Runs a verb on another object on behalf of this one, as the same
+    /// player. What it prints goes to the player along with this verb's
+    /// output, while what it emits and announces goes out right away.
+    fn call(&self, object: usize, verb: &str, bindings: &[(&str, usize)]) {
+        let mut host = Host::new(self.state.clone(), object, self.player);
+        host.window = self.window;
+        host.reader = self.reader.clone();
+        host.depth = self.depth + 1;
+
+        // suspending hooks take the verbs that ran them along with them
+        host.suspender = self.suspender.clone();
+        host.detached = self.detached.clone();
+        host.task = self.task.clone();
+        host.verb = verb.to_string();
+        for (name, id) in bindings {
+            host.bind(name, *id);
+        }
+
+        let Some(output) = self.state.run_verb(&host, verb) else {
+            return;
+        };
+
+        for announcement in &output.announcements {
+            self.state.announce_from(object, announcement);
+        }
+
+        let room = self.state.location(object).unwrap_or(object);
+        for message in &output.emits {
+            self.state.emit_from(room, object, message);
+        }
+
+        let mut own = self.output.lock().unwrap();
+        own.messages.extend(output.messages);
+        own.gmcp.extend(output.gmcp);
+    }
+
+    /// Moves an object, like `@move`, and runs the `on_exit` verb of its old
+    /// location and the `on_enter` verb of its new one with the object bound
+    /// as `mover`.
+    pub fn move_object(&self, id: usize, dest: usize) -> Result<(), ScriptError> {
+        if self.depth >= MAX_CALL_DEPTH {
+            return Err(ScriptError::new("too_deep", "verbs are nested too deeply"));
+        }
+
+        if !self.state.exists(id) {
+            return Err(ScriptError::no_such_object());
+        }
+
+        if !self.state.can_write(self.player, id, "location") {
+            return Err(ScriptError::permission_denied());
+        }
+
+        let from = self
+            .state
+            .move_object(id, dest)
+            .map_err(ScriptError::from)?;
+
+        let bindings = [("mover", id)];
+        if let Some(from) = from {
+            self.call(from, "on_exit", &bindings);
+        }
+
+        self.call(dest, "on_enter", &bindings);
+        Ok(())
+    }
+
+    /// Moves coins between objects, from the verb's object or from one that
+    /// the verb's owner controls. Hooks run for whoever looks at or moves
+    /// the object, so the invoking player's permissions can't be trusted to
+    /// spend their coins.
+    pub fn transfer(&self, from: usize, to: usize, amount: i64) -> Result<(), ScriptError> {
+        let owner = self.state.owner(self.self_id);
+        if from != self.self_id && !self.state.controls(owner, from) {
+            return Err(ScriptError::permission_denied());
+        }
+
+        self.state
+            .transfer(from, to, amount)
+            .map_err(ScriptError::from)
+    }
+
+    /// Gets the in-game time from the world clock.
+    pub fn world_time(&self) -> i64 {
+        world_clock::world_time(&self.state)
+    }
+
+    /// Tests if it is day in the world.
+    pub fn is_daytime(&self) -> bool {
+        world_clock::is_daytime(&self.state)
+    }
+
+    /// Asks the invoking player a question and waits for the line that they
+    /// answer with, which doesn't run as a command. What the verb has
+    /// printed so far is sent before the question.
+    pub fn read(&self, prompt: &str) -> Result<String, ScriptError> {
+        let Some(reader) = &self.reader else {
+            return Err(ScriptError::new("no_input", "nobody is there to answer"));
+        };
+
+        // only verbs in the background are listed as waiting for input
+        let killed = match self.detached.load(Ordering::Relaxed) {
+            true => self.enter_task(Waiting::Input)?,
+            false => CancellationToken::new(),
+        };
+
+        let printed = std::mem::take(&mut self.output.lock().unwrap().messages);
+        for message in printed.iter().map(String::as_str).chain([prompt]) {
+            (reader.send)(message);
+        }
+
+        let Ok(runtime) = tokio::runtime::Handle::try_current() else {
+            return Err(ScriptError::new("no_input", "nobody is there to answer"));
+        };
+
+        runtime.block_on(async {
+            let mut lines = reader.lines.lock().await;
+            loop {
+                let input = tokio::select! {
+                    _ = killed.cancelled() => return Err(ScriptError::killed()),
+                    input = tokio::time::timeout(READ_TIMEOUT, lines.recv()) => input,
+                };
+
+                return match input {
+                    Ok(Some(Input::Line { line, .. })) => Ok(line.trim().to_string()),
+                    Ok(Some(Input::Invalid)) => continue,
+                    Ok(None) => Err(ScriptError::new("disconnected", "the player disconnected")),
+                    Err(_) => Err(ScriptError::new("timed_out", "nobody answered in time")),
+                };
+            }
+        })
+    }
+
+    /// Fetches a page from one of the allowed domains, returning its body.
+    /// Fetches count against the rate limit of the object that the verb
+    /// runs on.
+    pub fn http_get(&self, url: &str) -> Result<String, ScriptError> {
+        let Ok(runtime) = tokio::runtime::Handle::try_current() else {
+            return Err(ScriptError::new(
+                "http_failed",
+                "the page could not be fetched",
+            ));
+        };
+
+        let shutdown = self.state.shutdown_token();
+        runtime.block_on(async {
+            tokio::select! {
+                body = http::get(&self.state, self.self_id, url) => Ok(body?),
+                _ = shutdown.cancelled() => Err(ScriptError::new(
+                    "shutting_down",
+                    "the server is shutting down",
+                )),
+            }
+        })
+    }
+
+    /// Runs a verb on a thread of its own, waiting until it finishes or
+    /// first suspends. Returns what it output until then, or nothing if the
+    /// object has no such verb.
+    pub async fn spawn(mut self, verb: &str) -> Option<ScriptOutput> {
+        let (tx, suspended) = oneshot::channel();
+        self.suspender = Arc::new(Mutex::new(Some(tx)));
+        self.detached = Default::default();
+        self.task = Default::default();
+        self.verb = verb.to_string();
+
+        let finished = tokio::task::spawn_blocking(move || {
+            let output = self.state.run_verb(&self, &self.verb.clone());
+            if let Some(task) = *self.task.lock().unwrap() {
+                self.state.tasks().remove(task);
+            }
+
+            let output = output?;
+            if !self.detached.load(Ordering::Relaxed) {
+                return Some(output);
+            }
+
+            self.deliver(output);
+            None
+        });
+
+        // a verb that suspended has handed over its output before finishing
+        tokio::select! {
+            biased;
+            Ok(output) = suspended => Some(output),
+            output = finished => output.ok().flatten(),
+        }
+    }
+
+    /// Sends out a verb's output once nothing is waiting on the verb. What
+    /// it prints goes to the invoking player if they are connected.
+    fn deliver(&self, output: ScriptOutput) {
+        for announcement in &output.announcements {
+            self.state.announce_from(self.self_id, announcement);
+        }
+
+        let room = self.state.location(self.self_id).unwrap_or(self.self_id);
+        for message in &output.emits {
+            self.state.emit_from(room, self.self_id, message);
+        }
+
+        if let Some(reader) = &self.reader {
+            for message in &output.messages {
+                (reader.send)(message);
+            }
+        }
+    }
+
+    /// Pauses the verb for a number of seconds, letting whatever ran it
+    /// carry on without it.
+    pub fn suspend(&self, seconds: i64) -> Result<(), ScriptError> {
+        if !(0..=MAX_SUSPEND).contains(&seconds) {
+            return Err(ScriptError::invalid_argument(format!(
+                "verbs may only suspend for up to {MAX_SUSPEND} seconds"
+            )));
+        }
+
+        let wakes = now() + seconds as u64;
+        let killed = self.enter_task(Waiting::Until(wakes))?;
+
+        let output = self.take_output();
+        self.detached.store(true, Ordering::Relaxed);
+        let undelivered = match self.suspender.lock().unwrap().take() {
+            Some(waiting) => waiting.send(output).err(),
+            None => Some(output),
+        };
+
+        if let Some(output) = undelivered {
+            self.deliver(output);
+        }
+
+        let Ok(runtime) = tokio::runtime::Handle::try_current() else {
+            std::thread::sleep(Duration::from_secs(seconds as u64));
+            return Ok(());
+        };
+
+        let shutdown = self.state.shutdown_token();
+        runtime.block_on(async {
+            tokio::select! {
+                _ = tokio::time::sleep(Duration::from_secs(seconds as u64)) => Ok(()),
+                _ = shutdown.cancelled() => Err(ScriptError::new(
+                    "shutting_down",
+                    "the server is shutting down",
+                )),
+                _ = killed.cancelled() => Err(ScriptError::killed()),
+            }
+        })
+    }
+
+    /// Lists the verb among the [tasks] as waiting for something, or
+    /// updates what it waits for if it already is. Returns the token that
+    /// is cancelled if the task is killed.
+    fn enter_task(&self, waiting: Waiting) -> Result<CancellationToken, ScriptError> {
+        let mut task = self.task.lock().unwrap();
+        if let Some(id) = *task {
+            let Some(known) = self.state.tasks().get(id) else {
+                return Err(ScriptError::killed());
+            };
+
+            self.state.tasks().wait(id, waiting);
+            return Ok(known.killed);
+        }
+
+        let killed = CancellationToken::new();
+        let max = match self.state.is_wizard(self.player) {
+            true => None,
+            false => Some(tasks::max_tasks(&self.state)),
+        };
+
+        let new = Task {
+            player: self.player,
+            object: self.self_id,
+            verb: self.verb.clone(),
+            waiting,
+            killed: killed.clone(),
+        };
+
+        let Some(id) = self.state.tasks().add(new, max) else {
+            return Err(ScriptError::new(
+                "too_many_tasks",
+                "too many tasks are already running",
+            ));
+        };
+
+        *task = Some(id);
+        Ok(killed)
+    }
+
+    /// Sends a message to the invoking player.
+    pub fn print(&self, message: &str) {
+        self.output
+            .lock()
+            .unwrap()
+            .messages
+            .push(message.to_string());
+    }
+
+    /// Sends a message to everyone in the same room as the verb's object.
+    pub fn emit(&self, message: &str) {
+        self.output.lock().unwrap().emits.push(message.to_string());
+    }
+
+    /// Makes a server announcement.
+    pub fn announce(&self, message: &str) {
+        let mut output = self.output.lock().unwrap();
+        output.announcements.push(message.to_string());
+    }
+
+    /// Sends a GMCP packet to the invoking player, if their client supports
+    /// GMCP. The data must be JSON, or empty to send the package alone.
+    pub fn gmcp_send(&self, package: &str, data: &str) -> Result<(), ScriptError> {
+        if !crate::gmcp::is_package(package) {
+            return Err(ScriptError::invalid_argument(format!(
+                "invalid GMCP package {package:?}"
+            )));
+        }
+
+        let data = match data.trim() {
+            "" => serde_json::Value::Null,
+            data => serde_json::from_str(data)
+                .map_err(|err| ScriptError::invalid_argument(format!("invalid JSON: {err}")))?,
+        };
+
+        let mut output = self.output.lock().unwrap();
+        output.gmcp.push((package.to_string(), data));
+        Ok(())
+    }
+
+    /// Takes everything that the verb has output so far.
+    fn take_output(&self) -> ScriptOutput {
+        std::mem::take(&mut self.output.lock().unwrap())
+    }
+}
+
+/// A failure that verbs may catch, like being denied permission.
+///
+/// The host's errors have these codes:
+/// - `permission_denied`: the player may not do that to the object
+/// - `no_such_object`: the object doesn't exist
+/// - `invalid_argument`: a builtin was given something that it can't use
+/// - `quota_exceeded`: the player may not own any more objects
+/// - `too_large` and `too_many_fields`: a field can't be written
+/// - `invalid_move`: an object can't be moved there
+/// - `not_enough`: an object doesn't have enough coins
+/// - `too_deep`: verbs have run other verbs too many times over
+/// - `no_input`, `disconnected`, and `timed_out`: `read` got no answer
+/// - `shutting_down`: the server stopped while the verb was suspended
+/// - `too_many_tasks`: the player has as many suspended verbs as they may
+/// - `killed`: the verb was killed with `@kill` while it was suspended
+/// - `not_allowed`: `http_get` was given a domain that isn't allowed
+/// - `rate_limited`: the object has fetched too many pages lately
+/// - `http_failed`: the page couldn't be fetched, or wasn't found
+///
+/// Verbs may raise errors with codes of their own.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ScriptError {
+    pub code: String,
+    pub message: String,
+}
+
+impl ScriptError {
+    pub fn new(code: impl ToString, message: impl ToString) -> Self {
+        Self {
+            code: code.to_string(),
+            message: message.to_string(),
+        }
+    }
+
+    pub fn permission_denied() -> Self {
+        Self::new("permission_denied", "permission denied")
+    }
+
+    pub fn no_such_object() -> Self {
+        Self::new("no_such_object", FieldError::NoSuchObject)
+    }
+
+    pub fn invalid_argument(message: impl ToString) -> Self {
+        Self::new("invalid_argument", message)
+    }
+
+    pub fn killed() -> Self {
+        Self::new("killed", "the task was killed")
+    }
+}
+
+impl Display for ScriptError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl From<FieldError> for ScriptError {
+    fn from(err: FieldError) -> Self {
+        let code = match err {
+            FieldError::NoSuchObject => "no_such_object",
+            FieldError::TooLarge { .. } => "too_large",
+            FieldError::TooManyFields { .. } => "too_many_fields",
+        };
+
+        Self::new(code, err)
+    }
+}
+
+impl From<MoveError> for ScriptError {
+    fn from(err: MoveError) -> Self {
+        let code = match err {
+            MoveError::NoSuchObject | MoveError::NoSuchDestination => "no_such_object",
+            MoveError::Recursive | MoveError::Worn => "invalid_move",
+        };
+
+        Self::new(code, err)
+    }
+}
+
+impl From<FetchError> for ScriptError {
+    fn from(err: FetchError) -> Self {
+        let code = match err {
+            FetchError::InvalidUrl => "invalid_argument",
+            FetchError::NotAllowed { .. } => "not_allowed",
+            FetchError::RateLimited => "rate_limited",
+            FetchError::TimedOut => "timed_out",
+            FetchError::TooLarge => "too_large",
+            FetchError::Status(_) | FetchError::Failed(_) => "http_failed",
+        };
+
+        Self::new(code, err)
+    }
+}
+
+impl From<TransferError> for ScriptError {
+    fn from(err: TransferError) -> Self {
+        let code = match err {
+            TransferError::NoSuchObject => "no_such_object",
+            TransferError::InvalidAmount => "invalid_argument",
+            TransferError::NotEnough { .. } => "not_enough",
+        };
+
+        Self::new(code, err)
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct ScriptOutput {
+    /// Messages addressed to the subject.
+    pub messages: Vec<String>,
+
+    /// Messages to everyone in the same room as the executing object.
+    pub emits: Vec<String>,
+
+    /// Server-wide announcements.
+    pub announcements: Vec<String>,
+
+    /// GMCP packages and their data for the subject.
+    pub gmcp: Vec<(String, serde_json::Value)>,
+}
+
+impl ScriptOutput {
+    pub fn message(message: impl ToString) -> Self {
+        Self {
+            messages: vec![message.to_string()],
+            ..Default::default()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::COINS_FIELD;
+
+    fn temporary_state() -> Arc<State> {
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        Arc::new(State::open(db, CancellationToken::new()))
+    }
+
+    #[test]
+    fn describe_cannot_take_the_viewers_coins() {
+        let state = temporary_state();
+        let builder = state.create();
+        let viewer = state.create();
+        state.set(viewer, COINS_FIELD, Value::Integer(10)).unwrap();
+
+        let object = state.create_owned(builder).unwrap();
+        let describe = format!("transfer({viewer}, {builder}, 10);");
+        state
+            .set(object, "describe", Value::String(describe))
+            .unwrap();
+
+        let host = Host::new(state.clone(), object, viewer);
+        let output = state.run_verb(&host, "describe").unwrap();
+
+        assert!(output.messages.concat().contains("permission denied"));
+        let coins = |id| {
+            state
+                .get(id, COINS_FIELD)
+                .and_then(|coins| coins.as_integer())
+        };
+        assert_eq!(coins(viewer), Some(10));
+        assert_eq!(coins(builder), None);
+    }
+}
@@ -0,0 +1,545 @@
+use std::{
+    collections::HashMap,
+    str::FromStr,
+    sync::{Arc, Mutex, OnceLock},
+};
+
+use regex::{Regex, RegexBuilder};
+
+use rhai::{
+    packages::{
+        BasicArrayPackage, BasicMapPackage, BasicMathPackage, CorePackage, MoreStringPackage,
+        Package,
+    },
+    Array, Dynamic, Engine, EvalAltResult, ImmutableString, Map, Position, Scope, AST, FLOAT, INT,
+};
+
+use super::{Host, ScriptEngine, ScriptError};
+use crate::{clock, dice, now, Value};
+
+/// The most operations that each verb may run.
+pub const RHAI_OPERATIONS: u64 = 100_000_000;
+
+/// The most functions that may be nested inside each other in a verb.
+const MAX_CALL_LEVELS: usize = 64;
+
+/// The longest string, in characters, that a verb may build.
+const MAX_STRING_SIZE: usize = 1 << 20;
+
+/// The most items that a verb may put in an array or map.
+const MAX_COLLECTION_SIZE: usize = 1 << 16;
+
+/// A compiled verb and the source it was compiled from.
+type CachedVerb = (String, Arc<AST>);
+
+/// The compiled verbs, shared by every thread that runs them. The source is
+/// kept alongside each AST as its version, so setting a verb to new source
+/// invalidates its cached AST.
+#[derive(Default)]
+pub struct VerbCache {
+    verbs: Mutex<HashMap<(usize, String), CachedVerb>>,
+}
+
+impl VerbCache {
+    /// Compiles a verb's source, reusing its last compilation if its source
+    /// has not changed since.
+    fn compile(
+        &self,
+        engine: &Engine,
+        id: usize,
+        verb: &str,
+        src: &str,
+    ) -> Result<Arc<AST>, Box<EvalAltResult>> {
+        let key = (id, verb.to_string());
+
+        if let Some((version, ast)) = self.verbs.lock().unwrap().get(&key) {
+            if version == src {
+                return Ok(ast.clone());
+            }
+        }
+
+        // verbs are compiled without the lock held, so that a slow compile
+        // doesn't hold up other verbs
+        let ast = Arc::new(engine.compile(src)?);
+        let mut verbs = self.verbs.lock().unwrap();
+
+        if verbs.len() >= MAX_CACHED_VERBS {
+            verbs.clear();
+        }
+
+        verbs.insert(key, (src.to_string(), ast.clone()));
+        Ok(ast)
+    }
+}
+
+/// An object as seen by a Rhai script.
+#[derive(Clone)]
+pub struct Object {
+    id: usize,
+    host: Arc<Host>,
+}
+
+impl Object {
+    fn get(&mut self, field: &str) -> Result<Dynamic, Box<EvalAltResult>> {
+        let Some(val) = self.host.get(self.id, field)? else {
+            return Ok(Dynamic::UNIT);
+        };
+
+        let val = match val {
+            Value::Integer(val) => Dynamic::from_int(val),
+            Value::String(val) => Dynamic::from_str(&val).unwrap(),
+            Value::Bool(val) => Dynamic::from_bool(val),
+        };
+
+        Ok(val)
+    }
+
+    fn set(&mut self, field: &str, val: Dynamic) -> Result<(), Box<EvalAltResult>> {
+        let val = if val.is_unit() {
+            None
+        } else if val.is_string() {
+            Some(Value::String(val.into_string().unwrap()))
+        } else if val.is_int() {
+            Some(Value::Integer(val.as_int().unwrap()))
+        } else if val.is_bool() {
+            Some(Value::Bool(val.as_bool().unwrap()))
+        } else {
+            return Err(ScriptError::invalid_argument("invalid value type").into());
+        };
+
+        Ok(self.host.set(self.id, field, val)?)
+    }
+}
+
+/// Host errors are thrown as maps with a `code` and a `message`, so that
+/// verbs can catch them and tell them apart.
+impl From<ScriptError> for Box<EvalAltResult> {
+    fn from(err: ScriptError) -> Self {
+        let mut map = Map::new();
+        map.insert("code".into(), err.code.into());
+        map.insert("message".into(), err.message.into());
+        Box::new(EvalAltResult::ErrorRuntime(map.into(), Position::NONE))
+    }
+}
+
+/// Runs verbs written in Rhai.
+pub struct RhaiEngine;
+
+impl ScriptEngine for RhaiEngine {
+    fn run(&self, host: &Host, verb: &str, src: &str) {
+        let host = Arc::new(host.clone());
+        let mut engine = Engine::new_raw();
+        engine
+            .set_max_operations(RHAI_OPERATIONS)
+            .set_max_call_levels(MAX_CALL_LEVELS)
+            .set_max_string_size(MAX_STRING_SIZE)
+            .set_max_array_size(MAX_COLLECTION_SIZE)
+            .set_max_map_size(MAX_COLLECTION_SIZE);
+
+        engine
+            .register_type::<Object>()
+            .register_indexer_get(Object::get)
+            .register_indexer_set(Object::set);
+
+        engine.register_fn("object", {
+            let host = host.clone();
+            move |id: INT| -> Result<Dynamic, Box<EvalAltResult>> {
+                let Ok(id) = id.try_into() else {
+                    return Err(ScriptError::invalid_argument("invalid object ID").into());
+                };
+
+                Ok(Dynamic::from(Object {
+                    id,
+                    host: host.clone(),
+                }))
+            }
+        });
+
+        register_stdlib(&mut engine);
+
+        engine.register_fn(
+            "error",
+            |code: &str, message: &str| -> Result<(), Box<EvalAltResult>> {
+                Err(ScriptError::new(code, message).into())
+            },
+        );
+
+        engine.on_print({
+            let host = host.clone();
+            move |message| host.print(message)
+        });
+
+        engine.register_fn("suspend", {
+            let host = host.clone();
+            move |seconds: INT| -> Result<(), Box<EvalAltResult>> { Ok(host.suspend(seconds)?) }
+        });
+
+        engine.register_fn("read", {
+            let host = host.clone();
+            move |prompt: &str| -> Result<String, Box<EvalAltResult>> { Ok(host.read(prompt)?) }
+        });
+
+        engine.register_fn("http_get", {
+            let host = host.clone();
+            move |url: &str| -> Result<String, Box<EvalAltResult>> { Ok(host.http_get(url)?) }
+        });
+
+        engine.register_fn("emit", {
+            let host = host.clone();
+            move |message: &str| host.emit(message)
+        });
+
+        engine.register_fn("announce", {
+            let host = host.clone();
+            move |message: &str| host.announce(message)
+        });
+
+        engine.register_fn("create", {
+            let host = host.clone();
+            move || -> Result<Object, Box<EvalAltResult>> {
+                Ok(Object {
+                    id: host.create()?,
+                    host: host.clone(),
+                })
+            }
+        });
+
+        engine.register_fn("clone", {
+            let host = host.clone();
+            move |object: Object| -> Result<Object, Box<EvalAltResult>> {
+                Ok(Object {
+                    id: host.clone_object(object.id)?,
+                    host: host.clone(),
+                })
+            }
+        });
+
+        engine.register_fn("destroy", {
+            let host = host.clone();
+            move |object: Object| -> Result<(), Box<EvalAltResult>> { Ok(host.destroy(object.id)?) }
+        });
+
+        engine.register_fn("move", {
+            let host = host.clone();
+            move |object: Object, dest: Object| -> Result<(), Box<EvalAltResult>> {
+                Ok(host.move_object(object.id, dest.id)?)
+            }
+        });
+
+        // names that don't match exactly one object match nothing
+        for (name, matcher) in [
+            (
+                "match_object",
+                Host::match_object as fn(&Host, &str) -> Option<usize>,
+            ),
+            ("match_player", Host::match_player),
+        ] {
+            let host = host.clone();
+            engine.register_fn(name, move |name: &str| match matcher(&host, name) {
+                Some(id) => Dynamic::from(Object {
+                    id,
+                    host: host.clone(),
+                }),
+                None => Dynamic::UNIT,
+            });
+        }
+
+        // lists of objects are arrays of objects, which can't outlive the verb
+        let objects = {
+            let host = host.clone();
+            move |ids: Vec<usize>| -> Array {
+                let objects = ids.into_iter().map(|id| Object {
+                    id,
+                    host: host.clone(),
+                });
+
+                objects.map(Dynamic::from).collect()
+            }
+        };
+
+        engine.register_fn("objects", {
+            let host = host.clone();
+            let objects = objects.clone();
+            move || objects(host.objects())
+        });
+
+        engine.register_fn("children", {
+            let host = host.clone();
+            let objects = objects.clone();
+            move |object: Object| objects(host.children(object.id))
+        });
+
+        engine.register_fn("contents", {
+            let host = host.clone();
+            move |object: Object| objects(host.contents(object.id))
+        });
+
+        engine.register_fn("fields", {
+            let host = host.clone();
+            move |object: Object| -> Result<Array, Box<EvalAltResult>> {
+                let fields = host.fields(object.id)?;
+                Ok(fields.into_iter().map(Dynamic::from).collect())
+            }
+        });
+
+        engine.register_fn("world_time", {
+            let host = host.clone();
+            move || host.world_time()
+        });
+
+        engine.register_fn("is_daytime", {
+            let host = host.clone();
+            move || host.is_daytime()
+        });
+
+        engine.register_fn("transfer", {
+            let host = host.clone();
+            move |from: INT, to: INT, amount: INT| -> Result<(), Box<EvalAltResult>> {
+                let (Ok(from), Ok(to)) = (from.try_into(), to.try_into()) else {
+                    return Err(ScriptError::invalid_argument("invalid object ID").into());
+                };
+
+                host.transfer(from, to, amount).map_err(|err| err.into())
+            }
+        });
+
+        engine.register_fn("gmcp_send", {
+            let host = host.clone();
+            move |package: &str, data: &str| -> Result<(), Box<EvalAltResult>> {
+                host.gmcp_send(package, data).map_err(|err| err.into())
+            }
+        });
+
+        let object = |id| Object {
+            id,
+            host: host.clone(),
+        };
+
+        let mut scope = Scope::new();
+        scope.set_value("self", object(host.self_id));
+        scope.set_value("player", object(host.player));
+        scope.set_value("args", host.args.clone());
+
+        let (width, height) = host.window_size();
+        scope.set_value("width", width as INT);
+        scope.set_value("height", height as INT);
+
+        for (name, id) in host.bindings.iter() {
+            scope.set_value(name.clone(), object(*id));
+        }
+
+        let verbs = &host.state.rhai_verbs;
+        let result = verbs
+            .compile(&engine, host.self_id, verb, src)
+            .and_then(|ast| engine.eval_ast_with_scope::<()>(&mut scope, &ast));
+
+        if let Err(err) = result {
+            host.print(&describe_error(&host.describe_verb(verb), *err));
+        }
+    }
+}
+
+/// Describes a script error, where it happened, and the chain of script
+/// function calls that led to it.
+fn describe_error(verb: &str, mut err: EvalAltResult) -> String {
+    let mut frames = Vec::new();
+
+    while let EvalAltResult::ErrorInFunctionCall(name, _, inner, pos) = err {
+        frames.push(format!("    in {name}() called {}", describe_position(pos)));
+        err = *inner;
+    }
+
+    let pos = err.take_position();
+    let message = match err {
+        EvalAltResult::ErrorRuntime(val, _) => match val.read_lock::<Map>() {
+            Some(map) => describe_thrown(&map),
+            None => val.to_string(),
+        },
+        err => err.to_string(),
+    };
+
+    let mut out = format!(
+        "script error in {verb} {}: {message}",
+        describe_position(pos)
+    );
+
+    // innermost calls first, like a stack
+    for frame in frames.iter().rev() {
+        out.push('\n');
+        out.push_str(frame);
+    }
+
+    out
+}
+
+/// Describes an error thrown with a code, like those from the host or from
+/// `error(code, message)`, or any other map that a verb threw.
+fn describe_thrown(map: &Map) -> String {
+    let field = |name: &str| map.get(name).and_then(|val| val.clone().into_string().ok());
+    match (field("code"), field("message")) {
+        (Some(code), Some(message)) => format!("{message} ({code})"),
+        _ => Dynamic::from_map(map.clone()).to_string(),
+    }
+}
+
+/// Describes where in a script's source something happened.
+fn describe_position(pos: Position) -> String {
+    match (pos.line(), pos.position()) {
+        (Some(line), Some(column)) => format!("at line {line}, column {column}"),
+        (Some(line), None) => format!("at line {line}"),
+        _ => "at unknown position".to_string(),
+    }
+}
+
+/// Registers the string, list, number, randomness, time, and regex helpers
+/// available to every script.
+fn register_stdlib(engine: &mut Engine) {
+    // string split/trim/etc., list push/len/contains, maps, and math
+    engine.register_global_module(CorePackage::new().as_shared_module());
+    engine.register_global_module(MoreStringPackage::new().as_shared_module());
+    engine.register_global_module(BasicArrayPackage::new().as_shared_module());
+    engine.register_global_module(BasicMapPackage::new().as_shared_module());
+    engine.register_global_module(BasicMathPackage::new().as_shared_module());
+
+    engine.register_fn("join", |list: Array, separator: &str| -> ImmutableString {
+        let items: Vec<String> = list.iter().map(|item| item.to_string()).collect();
+        items.join(separator).into()
+    });
+
+    engine.register_fn(
+        "rand",
+        |min: INT, max: INT| -> Result<INT, Box<EvalAltResult>> {
+            dice::random(min, max).ok_or_else(|| "minimum is greater than maximum".into())
+        },
+    );
+
+    engine.register_fn(
+        "roll",
+        |notation: &str| -> Result<INT, Box<EvalAltResult>> {
+            let dice = dice::Dice::parse(notation).ok_or("invalid dice")?;
+            Ok(dice.total(&dice.roll()))
+        },
+    );
+
+    // times are integer UNIX timestamps and durations are integer seconds
+    engine.register_fn("now", || now() as INT);
+    engine.register_fn("since", |timestamp: INT| now() as INT - timestamp);
+    engine.register_fn("seconds", |count: INT| count);
+    engine.register_fn("minutes", |count: INT| count.saturating_mul(clock::MINUTE));
+    engine.register_fn("hours", |count: INT| count.saturating_mul(clock::HOUR));
+    engine.register_fn("days", |count: INT| count.saturating_mul(clock::DAY));
+    engine.register_fn("format_time", clock::format_time);
+    engine.register_fn("format_duration", clock::format_duration);
+
+    engine.register_fn(
+        "regex_match",
+        |text: &str, pattern: &str| -> Result<bool, Box<EvalAltResult>> {
+            Ok(compile_regex(pattern)?.is_match(text))
+        },
+    );
+
+    engine.register_fn(
+        "regex_replace",
+        |text: &str, pattern: &str, replacement: &str| -> Result<String, Box<EvalAltResult>> {
+            let regex = compile_regex(pattern)?;
+            Ok(regex.replace_all(text, replacement).into_owned())
+        },
+    );
+
+    // the whole match and then each group, or an empty list for no match
+    engine.register_fn(
+        "regex_captures",
+        |text: &str, pattern: &str| -> Result<Array, Box<EvalAltResult>> {
+            let regex = compile_regex(pattern)?;
+            let Some(captures) = regex.captures(text) else {
+                return Ok(Array::new());
+            };
+
+            Ok(captures
+                .iter()
+                .map(|group| match group {
+                    Some(group) => Dynamic::from(group.as_str().to_string()),
+                    None => Dynamic::UNIT,
+                })
+                .collect())
+        },
+    );
+
+    engine.register_fn("format_number", |num: INT| group_digits(&num.to_string()));
+
+    engine.register_fn("format_number", |num: FLOAT, places: INT| {
+        let places = places.clamp(0, 16) as usize;
+        group_digits(&format!("{num:.places$}"))
+    });
+}
+
+/// The most compiled verbs that are cached at once.
+const MAX_CACHED_VERBS: usize = 1024;
+
+/// The longest regex pattern that scripts may compile.
+const MAX_PATTERN_LEN: usize = 1024;
+
+/// The most memory that a compiled regex may use.
+const MAX_REGEX_SIZE: usize = 1 << 20;
+
+/// The most compiled regexes that are cached at once.
+const MAX_CACHED_REGEXES: usize = 256;
+
+/// Compiles a regex pattern, reusing the cached compilation if the pattern
+/// has been compiled before.
+///
+/// Regexes match in linear time, so patterns cannot backtrack
+/// catastrophically, but their length and compiled size are still limited.
+fn compile_regex(pattern: &str) -> Result<Regex, Box<EvalAltResult>> {
+    static CACHE: OnceLock<Mutex<HashMap<String, Regex>>> = OnceLock::new();
+
+    if pattern.len() > MAX_PATTERN_LEN {
+        return Err(format!("regex is longer than {MAX_PATTERN_LEN} bytes").into());
+    }
+
+    let mut cache = CACHE.get_or_init(Default::default).lock().unwrap();
+    if let Some(regex) = cache.get(pattern) {
+        return Ok(regex.clone());
+    }
+
+    let regex = RegexBuilder::new(pattern)
+        .size_limit(MAX_REGEX_SIZE)
+        .build()
+        .map_err(|err| format!("invalid regex: {err}"))?;
+
+    if cache.len() >= MAX_CACHED_REGEXES {
+        cache.clear();
+    }
+
+    cache.insert(pattern.to_string(), regex.clone());
+    Ok(regex)
+}
+
+/// Inserts thousands separators into a formatted number.
+fn group_digits(formatted: &str) -> String {
+    let (sign, formatted) = match formatted.strip_prefix('-') {
+        Some(rest) => ("-", rest),
+        None => ("", formatted),
+    };
+
+    let (whole, fraction) = match formatted.split_once('.') {
+        Some((whole, fraction)) => (whole, Some(fraction)),
+        None => (formatted, None),
+    };
+
+    let mut out = sign.to_string();
+    for (i, digit) in whole.chars().enumerate() {
+        if i > 0 && (whole.len() - i) % 3 == 0 {
+            out.push(',');
+        }
+
+        out.push(digit);
+    }
+
+    if let Some(fraction) = fraction {
+        out.push('.');
+        out.push_str(fraction);
+    }
+
+    out
+}
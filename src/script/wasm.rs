@@ -0,0 +1,516 @@
+//! Verbs written in WebAssembly.
+//!
+//! WASM verbs are stored as WebAssembly text, which any language compiling
+//! to WASM can produce. A verb's module exports its `memory` and a
+//! `run(self: i64, player: i64)` function, and imports these functions from
+//! the `moo` module, where strings are pointers and lengths into memory:
+//!
+//! - `get(id: i64, key, key_len, buf, buf_len) -> i32`: writes a field's
+//!   value into the buffer as JSON, like `{"Integer":5}`, and returns the
+//!   length of the JSON, or -1 if the field does not exist. Values longer
+//!   than the buffer are not written.
+//! - `set(id: i64, key, key_len, val, val_len)`: sets a field to a JSON
+//!   value, or removes it if the value is empty.
+//! - `args(buf, buf_len) -> i32`: writes the rest of a scripted command's
+//!   line into the buffer, and returns its length, like `get`.
+//! - `binding(name, name_len) -> i64`: gets the ID of an object bound by
+//!   name, like `mover`, or -1 if none is.
+//! - `width() -> i64` and `height() -> i64`: get the size of the player's
+//!   window, or zero if it isn't known.
+//! - `print(msg, msg_len)`, `emit(msg, msg_len)`, and
+//!   `announce(msg, msg_len)`: output text, like the Rhai functions.
+//! - `gmcp_send(package, package_len, data, data_len)`: sends a GMCP packet
+//!   to the player, with its data as JSON.
+//! - `transfer(from: i64, to: i64, amount: i64)`: moves coins between
+//!   objects.
+//! - `create() -> i64`, `clone(id: i64) -> i64`, and `destroy(id: i64)`:
+//!   make and unmake objects, as the `@create`, `@clone`, and `@destroy`
+//!   commands do.
+//! - `move(id: i64, dest: i64)`: moves an object and runs the hooks of the
+//!   rooms it leaves and enters.
+//! - `match_object(name, name_len) -> i64` and `match_player(name, name_len)
+//!   -> i64`: find an object by name as commands do, or return -1 if nothing
+//!   or more than one thing matches.
+//! - `objects(buf, buf_len) -> i32`, and `children`, `contents`, and
+//!   `fields`, which take an `id: i64` before the buffer: write a JSON array
+//!   of object IDs or field names into the buffer, like `get`.
+//! - `suspend(seconds: i64)`: pauses the verb, letting whatever ran it carry
+//!   on without it.
+//! - `read(prompt, prompt_len, buf, buf_len) -> i32` and `http_get(url,
+//!   url_len, buf, buf_len) -> i32`: ask the player a question or fetch a
+//!   page, like the Rhai functions, and write the answer or the page's body
+//!   into the buffer, like `args`. The answer is gone if it doesn't fit, so
+//!   verbs should pass a buffer as large as they might need.
+//! - `world_time() -> i64` and `is_daytime() -> i32`: read the world clock,
+//!   with `is_daytime` returning 1 for day and 0 for night.
+//!
+//! Every verb runs with a limited amount of fuel, so that verbs that run for
+//! too long are stopped, and may grow its memory only up to [WASM_MEMORY].
+
+use std::{collections::HashMap, ops::Deref, sync::Mutex};
+
+use wasmtime::{
+    Caller, Config, Engine, Extern, Linker, Module, Store, StoreLimits, StoreLimitsBuilder, Trap,
+};
+
+use super::{Host, ScriptEngine, ScriptError};
+use crate::Value;
+
+/// The fuel that each verb may use, which is roughly one unit per
+/// instruction.
+pub const WASM_FUEL: u64 = 100_000_000;
+
+/// The most bytes of memory that each verb may use.
+pub const WASM_MEMORY: usize = 64 << 20;
+
+/// The most elements that each verb's tables may hold.
+const WASM_TABLE_ELEMENTS: usize = 10_000;
+
+/// The most compiled verbs that are cached at once.
+const MAX_CACHED_MODULES: usize = 256;
+
+/// A compiled verb and the source it was compiled from.
+type CachedModule = (String, Module);
+
+/// The state of a running verb, which is its host and the limits on what it
+/// may allocate.
+struct Verb {
+    host: Host,
+    limits: StoreLimits,
+}
+
+impl Deref for Verb {
+    type Target = Host;
+
+    fn deref(&self) -> &Host {
+        &self.host
+    }
+}
+
+/// Runs verbs written in WebAssembly.
+pub struct WasmEngine {
+    engine: Engine,
+    linker: Linker<Verb>,
+    cache: Mutex<HashMap<(usize, String), CachedModule>>,
+}
+
+impl WasmEngine {
+    pub fn new() -> Self {
+        let mut config = Config::new();
+        config.consume_fuel(true);
+
+        let engine = Engine::new(&config).expect("failed to create WASM engine");
+        let mut linker = Linker::new(&engine);
+        define_host(&mut linker).expect("failed to define WASM host functions");
+
+        Self {
+            engine,
+            linker,
+            cache: Default::default(),
+        }
+    }
+
+    /// Compiles a verb, reusing its last compilation if its source has not
+    /// changed since.
+    fn compile(&self, id: usize, verb: &str, src: &str) -> wasmtime::Result<Module> {
+        let key = (id, verb.to_string());
+        let mut cache = self.cache.lock().unwrap();
+
+        if let Some((version, module)) = cache.get(&key) {
+            if version == src {
+                return Ok(module.clone());
+            }
+        }
+
+        let module = Module::new(&self.engine, src)?;
+
+        if cache.len() >= MAX_CACHED_MODULES {
+            cache.clear();
+        }
+
+        cache.insert(key, (src.to_string(), module.clone()));
+        Ok(module)
+    }
+
+    /// Compiles and runs a verb, returning any error.
+    fn try_run(&self, host: &Host, verb: &str, src: &str) -> wasmtime::Result<()> {
+        let module = self.compile(host.self_id, verb, src)?;
+
+        let limits = StoreLimitsBuilder::new()
+            .memory_size(WASM_MEMORY)
+            .table_elements(WASM_TABLE_ELEMENTS)
+            .build();
+
+        let verb = Verb {
+            host: host.clone(),
+            limits,
+        };
+
+        let mut store = Store::new(&self.engine, verb);
+        store.limiter(|verb| &mut verb.limits);
+        store.set_fuel(WASM_FUEL)?;
+
+        let instance = self.linker.instantiate(&mut store, &module)?;
+        let run = instance.get_typed_func::<(i64, i64), ()>(&mut store, "run")?;
+        run.call(&mut store, (host.self_id as i64, host.player as i64))
+    }
+}
+
+impl Default for WasmEngine {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ScriptEngine for WasmEngine {
+    fn run(&self, host: &Host, verb: &str, src: &str) {
+        let Err(err) = self.try_run(host, verb, src) else {
+            return;
+        };
+
+        let message = match err.downcast_ref::<Trap>() {
+            Some(Trap::OutOfFuel) => "ran out of fuel".to_string(),
+            _ => format!("{err:#}"),
+        };
+
+        let verb = host.describe_verb(verb);
+        host.print(&format!("script error in {verb}: {message}"));
+    }
+}
+
+/// Defines the host functions that WASM verbs import.
+fn define_host(linker: &mut Linker<Verb>) -> wasmtime::Result<()> {
+    linker.func_wrap(
+        "moo",
+        "get",
+        |mut caller: Caller<'_, Verb>,
+         id: i64,
+         key: i32,
+         key_len: i32,
+         buf: i32,
+         buf_len: i32|
+         -> wasmtime::Result<i32> {
+            let key = read_string(&mut caller, key, key_len)?;
+            let Some(val) = caller
+                .data()
+                .get(to_id(id)?, &key)
+                .map_err(wasmtime::Error::msg)?
+            else {
+                return Ok(-1);
+            };
+
+            let val = serde_json::to_vec(&val)?;
+            if val.len() <= buf_len as usize {
+                write_bytes(&mut caller, buf, &val)?;
+            }
+
+            Ok(val.len() as i32)
+        },
+    )?;
+
+    linker.func_wrap(
+        "moo",
+        "set",
+        |mut caller: Caller<'_, Verb>,
+         id: i64,
+         key: i32,
+         key_len: i32,
+         val: i32,
+         val_len: i32|
+         -> wasmtime::Result<()> {
+            let key = read_string(&mut caller, key, key_len)?;
+            let val = read_bytes(&mut caller, val, val_len)?;
+
+            let val: Option<Value> = if val.is_empty() {
+                None
+            } else {
+                Some(serde_json::from_slice(&val)?)
+            };
+
+            let host = caller.data();
+            host.set(to_id(id)?, &key, val)
+                .map_err(wasmtime::Error::msg)
+        },
+    )?;
+
+    linker.func_wrap(
+        "moo",
+        "args",
+        |mut caller: Caller<'_, Verb>, buf: i32, buf_len: i32| -> wasmtime::Result<i32> {
+            let args = caller.data().args.clone().into_bytes();
+            if args.len() <= buf_len as usize {
+                write_bytes(&mut caller, buf, &args)?;
+            }
+
+            Ok(args.len() as i32)
+        },
+    )?;
+
+    linker.func_wrap("moo", "width", |caller: Caller<'_, Verb>| -> i64 {
+        caller.data().window_size().0
+    })?;
+
+    linker.func_wrap("moo", "height", |caller: Caller<'_, Verb>| -> i64 {
+        caller.data().window_size().1
+    })?;
+
+    linker.func_wrap(
+        "moo",
+        "binding",
+        |mut caller: Caller<'_, Verb>, name: i32, name_len: i32| -> wasmtime::Result<i64> {
+            let name = read_string(&mut caller, name, name_len)?;
+            let bindings = &caller.data().bindings;
+            let id = bindings.iter().find(|(bound, _)| *bound == name);
+            Ok(id.map(|(_, id)| *id as i64).unwrap_or(-1))
+        },
+    )?;
+
+    for (name, output) in [
+        ("print", Host::print as fn(&Host, &str)),
+        ("emit", Host::emit),
+        ("announce", Host::announce),
+    ] {
+        linker.func_wrap(
+            "moo",
+            name,
+            move |mut caller: Caller<'_, Verb>, msg: i32, msg_len: i32| -> wasmtime::Result<()> {
+                let msg = read_string(&mut caller, msg, msg_len)?;
+                output(caller.data(), &msg);
+                Ok(())
+            },
+        )?;
+    }
+
+    linker.func_wrap(
+        "moo",
+        "gmcp_send",
+        |mut caller: Caller<'_, Verb>,
+         package: i32,
+         package_len: i32,
+         data: i32,
+         data_len: i32|
+         -> wasmtime::Result<()> {
+            let package = read_string(&mut caller, package, package_len)?;
+            let data = read_string(&mut caller, data, data_len)?;
+            caller
+                .data()
+                .gmcp_send(&package, &data)
+                .map_err(wasmtime::Error::msg)
+        },
+    )?;
+
+    linker.func_wrap(
+        "moo",
+        "create",
+        |caller: Caller<'_, Verb>| -> wasmtime::Result<i64> {
+            let id = caller.data().create().map_err(wasmtime::Error::msg)?;
+            Ok(id as i64)
+        },
+    )?;
+
+    linker.func_wrap(
+        "moo",
+        "clone",
+        |caller: Caller<'_, Verb>, id: i64| -> wasmtime::Result<i64> {
+            let host = caller.data();
+            let id = host
+                .clone_object(to_id(id)?)
+                .map_err(wasmtime::Error::msg)?;
+            Ok(id as i64)
+        },
+    )?;
+
+    linker.func_wrap(
+        "moo",
+        "destroy",
+        |caller: Caller<'_, Verb>, id: i64| -> wasmtime::Result<()> {
+            caller
+                .data()
+                .destroy(to_id(id)?)
+                .map_err(wasmtime::Error::msg)
+        },
+    )?;
+
+    linker.func_wrap(
+        "moo",
+        "move",
+        |caller: Caller<'_, Verb>, id: i64, dest: i64| -> wasmtime::Result<()> {
+            caller
+                .data()
+                .move_object(to_id(id)?, to_id(dest)?)
+                .map_err(wasmtime::Error::msg)
+        },
+    )?;
+
+    for (name, matcher) in [
+        (
+            "match_object",
+            Host::match_object as fn(&Host, &str) -> Option<usize>,
+        ),
+        ("match_player", Host::match_player),
+    ] {
+        linker.func_wrap(
+            "moo",
+            name,
+            move |mut caller: Caller<'_, Verb>,
+                  name: i32,
+                  name_len: i32|
+                  -> wasmtime::Result<i64> {
+                let name = read_string(&mut caller, name, name_len)?;
+                let found = matcher(caller.data(), &name);
+                Ok(found.map_or(-1, |id| id as i64))
+            },
+        )?;
+    }
+
+    linker.func_wrap(
+        "moo",
+        "objects",
+        |mut caller: Caller<'_, Verb>, buf: i32, buf_len: i32| -> wasmtime::Result<i32> {
+            let objects = caller.data().objects();
+            write_json(&mut caller, buf, buf_len, &objects)
+        },
+    )?;
+
+    linker.func_wrap(
+        "moo",
+        "children",
+        |mut caller: Caller<'_, Verb>, id: i64, buf: i32, buf_len: i32| -> wasmtime::Result<i32> {
+            let children = caller.data().children(to_id(id)?);
+            write_json(&mut caller, buf, buf_len, &children)
+        },
+    )?;
+
+    linker.func_wrap(
+        "moo",
+        "contents",
+        |mut caller: Caller<'_, Verb>, id: i64, buf: i32, buf_len: i32| -> wasmtime::Result<i32> {
+            let contents = caller.data().contents(to_id(id)?);
+            write_json(&mut caller, buf, buf_len, &contents)
+        },
+    )?;
+
+    linker.func_wrap(
+        "moo",
+        "fields",
+        |mut caller: Caller<'_, Verb>, id: i64, buf: i32, buf_len: i32| -> wasmtime::Result<i32> {
+            let fields = caller.data().fields(to_id(id)?);
+            let fields = fields.map_err(wasmtime::Error::msg)?;
+            write_json(&mut caller, buf, buf_len, &fields)
+        },
+    )?;
+
+    linker.func_wrap(
+        "moo",
+        "suspend",
+        |caller: Caller<'_, Verb>, seconds: i64| -> wasmtime::Result<()> {
+            caller.data().suspend(seconds).map_err(wasmtime::Error::msg)
+        },
+    )?;
+
+    for (name, ask) in [
+        (
+            "read",
+            Host::read as fn(&Host, &str) -> Result<String, ScriptError>,
+        ),
+        ("http_get", Host::http_get),
+    ] {
+        linker.func_wrap(
+            "moo",
+            name,
+            move |mut caller: Caller<'_, Verb>,
+                  arg: i32,
+                  arg_len: i32,
+                  buf: i32,
+                  buf_len: i32|
+                  -> wasmtime::Result<i32> {
+                let arg = read_string(&mut caller, arg, arg_len)?;
+                let answer = ask(caller.data(), &arg).map_err(wasmtime::Error::msg)?;
+                let answer = answer.into_bytes();
+                if answer.len() <= buf_len as usize {
+                    write_bytes(&mut caller, buf, &answer)?;
+                }
+
+                Ok(answer.len() as i32)
+            },
+        )?;
+    }
+
+    linker.func_wrap("moo", "world_time", |caller: Caller<'_, Verb>| -> i64 {
+        caller.data().world_time()
+    })?;
+
+    linker.func_wrap("moo", "is_daytime", |caller: Caller<'_, Verb>| -> i32 {
+        caller.data().is_daytime() as i32
+    })?;
+
+    linker.func_wrap(
+        "moo",
+        "transfer",
+        |caller: Caller<'_, Verb>, from: i64, to: i64, amount: i64| -> wasmtime::Result<()> {
+            caller
+                .data()
+                .transfer(to_id(from)?, to_id(to)?, amount)
+                .map_err(wasmtime::Error::msg)
+        },
+    )?;
+
+    Ok(())
+}
+
+/// Converts an object ID passed by a verb.
+fn to_id(id: i64) -> wasmtime::Result<usize> {
+    id.try_into()
+        .map_err(|_| wasmtime::Error::msg("invalid object ID"))
+}
+
+/// Reads bytes out of a verb's memory.
+fn read_bytes(caller: &mut Caller<'_, Verb>, ptr: i32, len: i32) -> wasmtime::Result<Vec<u8>> {
+    let memory = memory(caller)?;
+    let data = memory.data(&caller);
+    let start = ptr as u32 as usize;
+    let end = start.saturating_add(len as u32 as usize);
+
+    match data.get(start..end) {
+        Some(bytes) => Ok(bytes.to_vec()),
+        None => Err(wasmtime::Error::msg("out-of-bounds memory access")),
+    }
+}
+
+/// Reads a UTF-8 string out of a verb's memory.
+fn read_string(caller: &mut Caller<'_, Verb>, ptr: i32, len: i32) -> wasmtime::Result<String> {
+    let bytes = read_bytes(caller, ptr, len)?;
+    String::from_utf8(bytes).map_err(|_| wasmtime::Error::msg("invalid UTF-8"))
+}
+
+/// Writes bytes into a verb's memory.
+fn write_bytes(caller: &mut Caller<'_, Verb>, ptr: i32, bytes: &[u8]) -> wasmtime::Result<()> {
+    let memory = memory(caller)?;
+    memory
+        .write(caller, ptr as u32 as usize, bytes)
+        .map_err(|_| wasmtime::Error::msg("out-of-bounds memory access"))
+}
+
+/// Writes a value into a verb's buffer as JSON if it fits, returning the
+/// length of the JSON.
+fn write_json(
+    caller: &mut Caller<'_, Verb>,
+    buf: i32,
+    buf_len: i32,
+    val: &impl serde::Serialize,
+) -> wasmtime::Result<i32> {
+    let json = serde_json::to_vec(val)?;
+    if json.len() <= buf_len as usize {
+        write_bytes(caller, buf, &json)?;
+    }
+
+    Ok(json.len() as i32)
+}
+
+/// Gets the memory exported by a verb.
+fn memory(caller: &mut Caller<'_, Verb>) -> wasmtime::Result<wasmtime::Memory> {
+    match caller.get_export("memory") {
+        Some(Extern::Memory(memory)) => Ok(memory),
+        _ => Err(wasmtime::Error::msg("verb does not export its memory")),
+    }
+}
@@ -0,0 +1,290 @@
+//! Socials, which are emotes that read differently to the player acting,
+//! the player they target, and everyone else.
+//!
+//! A social's template is written from a bystander's view, with codes for
+//! the objects involved:
+//!
+//! - `%n`: the actor's name
+//! - `%s`, `%o`, `%p`, `%r`: the actor's subject, object, possessive, and
+//!   reflexive pronouns, like `she`, `her`, `her`, and `herself`
+//! - `%t`: the target's name
+//! - `%ts`, `%to`, `%tp`, `%tr`: the target's pronouns
+//! - `{smile|smiles}`: the first form for the actor, who is addressed as
+//!   `you`, and the second for everyone else
+//! - `%%`: a percent sign
+//!
+//! Codes that start with a capital letter, like `%N` or `%Tp`, are
+//! capitalized. So `%N {smile|smiles} at %t.` reads as `You smile at Alice.`
+//! to its actor, `Bob smiles at you.` to Alice, and `Bob smiles at Alice.`
+//! to everyone else.
+//!
+//! Pronouns are read from each object's `pronouns` field, which is one of
+//! `he`, `she`, `they`, or `it`, or all four forms like `xe/xem/xyr/xemself`.
+//! Objects without it are `they`.
+//!
+//! The [SocialTable] holds named socials like `wave`, which players run by
+//! typing their names when no command or verb matches, and which wizards
+//! edit with `@social`.
+
+use serde::{Deserialize, Serialize};
+use sled::{Db, Tree};
+
+use crate::{event::Event, State, Value};
+
+/// The socials that a new database starts with, as their names and their
+/// templates without and with a target.
+pub const DEFAULT_SOCIALS: &[(&str, &str, &str)] = &[
+    ("bow", "%N {bow|bows}.", "%N {bow|bows} to %t."),
+    ("grin", "%N {grin|grins}.", "%N {grin|grins} at %t."),
+    ("hug", "%N {hug|hugs} %r.", "%N {hug|hugs} %t."),
+    ("laugh", "%N {laugh|laughs}.", "%N {laugh|laughs} at %t."),
+    ("nod", "%N {nod|nods}.", "%N {nod|nods} at %t."),
+    ("shrug", "%N {shrug|shrugs}.", "%N {shrug|shrugs} at %t."),
+    ("smile", "%N {smile|smiles}.", "%N {smile|smiles} at %t."),
+    ("wave", "%N {wave|waves}.", "%N {wave|waves} at %t."),
+];
+
+/// A named social's templates.
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+pub struct Social {
+    /// The template used without a target.
+    pub alone: Option<String>,
+
+    /// The template used with a target.
+    pub at: Option<String>,
+}
+
+/// The named socials, kept in their own tree of the database.
+pub struct SocialTable {
+    tree: Tree,
+}
+
+impl SocialTable {
+    /// Opens the table, filling it with [DEFAULT_SOCIALS] if it is new.
+    pub fn open(db: &Db) -> Self {
+        let exists = db
+            .tree_names()
+            .iter()
+            .any(|name| name.as_ref() == b"socials");
+        let table = Self {
+            tree: db.open_tree("socials").unwrap(),
+        };
+
+        if !exists {
+            for (name, alone, at) in DEFAULT_SOCIALS {
+                let social = Social {
+                    alone: Some(alone.to_string()),
+                    at: Some(at.to_string()),
+                };
+
+                table.set(name, &social);
+            }
+        }
+
+        table
+    }
+
+    /// Gets a social by name. Names are lowercase.
+    pub fn get(&self, name: &str) -> Option<Social> {
+        let social = self.tree.get(name).unwrap()?;
+        serde_json::from_slice(&social).ok()
+    }
+
+    /// Adds or replaces a social.
+    pub fn set(&self, name: &str, social: &Social) {
+        let social = serde_json::to_vec(social).unwrap();
+        self.tree.insert(name, social).unwrap();
+    }
+
+    /// Removes a social, returning false if there was none.
+    pub fn remove(&self, name: &str) -> bool {
+        self.tree.remove(name).unwrap().is_some()
+    }
+
+    /// Lists every social, sorted by name.
+    pub fn list(&self) -> Vec<(String, Social)> {
+        self.tree
+            .iter()
+            .filter_map(|entry| {
+                let (name, social) = entry.unwrap();
+                let name = String::from_utf8(name.to_vec()).ok()?;
+                Some((name, serde_json::from_slice(&social).ok()?))
+            })
+            .collect()
+    }
+}
+
+/// The pronouns that an object is referred to with.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Pronouns {
+    pub subject: String,
+    pub object: String,
+    pub possessive: String,
+    pub reflexive: String,
+}
+
+impl Pronouns {
+    fn new(subject: &str, object: &str, possessive: &str, reflexive: &str) -> Self {
+        Self {
+            subject: subject.to_string(),
+            object: object.to_string(),
+            possessive: possessive.to_string(),
+            reflexive: reflexive.to_string(),
+        }
+    }
+
+    /// The pronouns that the player reading about themselves sees.
+    fn you() -> Self {
+        Self::new("you", "you", "your", "yourself")
+    }
+
+    /// Parses a `pronouns` field, which names a set like `she` or `she/her`,
+    /// or gives all four forms.
+    pub fn parse(pronouns: &str) -> Option<Self> {
+        let forms: Vec<_> = pronouns.split('/').map(str::trim).collect();
+        if let [subject, object, possessive, reflexive] = forms[..] {
+            return Some(Self::new(subject, object, possessive, reflexive));
+        }
+
+        match forms.first()?.to_lowercase().as_str() {
+            "he" => Some(Self::new("he", "him", "his", "himself")),
+            "she" => Some(Self::new("she", "her", "her", "herself")),
+            "they" => Some(Self::new("they", "them", "their", "themselves")),
+            "it" => Some(Self::new("it", "it", "its", "itself")),
+            _ => None,
+        }
+    }
+
+    /// Gets the pronouns of an object, from its `pronouns` field.
+    pub fn of(state: &State, id: usize) -> Self {
+        match state.get(id, "pronouns") {
+            Some(Value::String(pronouns)) => Self::parse(&pronouns),
+            _ => None,
+        }
+        .unwrap_or_else(|| Self::parse("they").unwrap())
+    }
+}
+
+/// Whose view a social is rendered from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum View {
+    Actor,
+    Target,
+    Others,
+}
+
+/// How one object involved in a social is referred to.
+struct Party {
+    name: String,
+    pronouns: Pronouns,
+}
+
+impl Party {
+    fn new(state: &State, id: usize) -> Self {
+        Self {
+            name: state.name(id).unwrap_or_else(|| format!("#{id}")),
+            pronouns: Pronouns::of(state, id),
+        }
+    }
+
+    /// This party as seen by themselves.
+    fn you() -> Self {
+        Self {
+            name: "you".to_string(),
+            pronouns: Pronouns::you(),
+        }
+    }
+}
+
+/// Renders a social from an actor at an optional target, as the event that
+/// delivers each view of it.
+pub fn social(state: &State, template: &str, actor: usize, target: Option<usize>) -> Event {
+    let party = |id| Party::new(state, id);
+    let render = |view: View| {
+        let actor = match view {
+            View::Actor => Party::you(),
+            _ => party(actor),
+        };
+
+        let target = match (view, target) {
+            (View::Target, Some(_)) => Some(Party::you()),
+            (_, Some(target)) => Some(party(target)),
+            (_, None) => None,
+        };
+
+        render(template, view, &actor, target.as_ref())
+    };
+
+    Event::Social {
+        actor,
+        name: party(actor).name,
+        target,
+        to_actor: render(View::Actor),
+        to_target: render(View::Target),
+        to_others: render(View::Others),
+    }
+}
+
+/// Renders a template from one view. Codes for a missing target are left
+/// as they were written.
+fn render(template: &str, view: View, actor: &Party, target: Option<&Party>) -> String {
+    let mut out = String::with_capacity(template.len());
+    let mut chars = template.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '%' => {
+                let Some(code) = chars.next() else {
+                    out.push('%');
+                    break;
+                };
+
+                let capital = code.is_uppercase();
+                let mut raw = format!("%{code}");
+                let (party, code) = match code.to_ascii_lowercase() {
+                    't' => match chars.peek().copied() {
+                        Some(form @ ('s' | 'o' | 'p' | 'r')) => {
+                            raw.push(form);
+                            chars.next();
+                            (target, form)
+                        }
+                        _ => (target, 'n'),
+                    },
+                    code => (Some(actor), code),
+                };
+
+                let word = party.and_then(|party| match code {
+                    'n' => Some(&party.name),
+                    's' => Some(&party.pronouns.subject),
+                    'o' => Some(&party.pronouns.object),
+                    'p' => Some(&party.pronouns.possessive),
+                    'r' => Some(&party.pronouns.reflexive),
+                    _ => None,
+                });
+
+                match (word, code) {
+                    (Some(word), _) if capital => out.push_str(&capitalize(word)),
+                    (Some(word), _) => out.push_str(word),
+                    (None, '%') => out.push('%'),
+                    (None, _) => out.push_str(&raw),
+                }
+            }
+            '{' => {
+                let forms: String = chars.by_ref().take_while(|c| *c != '}').collect();
+                let (mine, theirs) = forms.split_once('|').unwrap_or((&forms, &forms));
+                out.push_str(if view == View::Actor { mine } else { theirs });
+            }
+            c => out.push(c),
+        }
+    }
+
+    out
+}
+
+fn capitalize(word: &str) -> String {
+    let mut chars = word.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
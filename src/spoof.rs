@@ -0,0 +1,55 @@
+//! Protection against scripts that impersonate the server or other players.
+//!
+//! Every event that carries text knows the [source](crate::event::Event::source)
+//! that it came from, which players can see beside each message with
+//! `@origins on`. The text that scripts emit and announce is also checked
+//! line by line, and lines that read like someone else's speech or like the
+//! server's own notices are marked with the object that really sent them.
+
+use crate::State;
+
+/// Phrases that the server writes after a speaker's name, which only the
+/// server may put after someone else's name.
+const SPEECH_MARKERS: &[&str] = &[" says: ", " pages: ", " has connected."];
+
+/// The starts of the server's own notices to players.
+const SERVER_NOTICES: &[&str] = &["You page ", "You have been booted"];
+
+/// Gets the field on a player that shows where each message came from.
+pub const SHOW_ORIGINS_FIELD: &str = "show_origins";
+
+/// Tests if a line from an object would pass for speech or notices that it
+/// didn't make.
+pub fn is_spoof(state: &State, source: usize, line: &str) -> bool {
+    let own_name = state.name(source);
+    let own = own_name
+        .as_deref()
+        .is_some_and(|name| line.starts_with(name));
+
+    // objects may be named after players, so speaking as a connected player
+    // is a spoof even for objects with the same name
+    let impersonates = state.connected().into_iter().any(|player| {
+        player != source
+            && state
+                .name(player)
+                .is_some_and(|name| !name.is_empty() && line.starts_with(&name))
+    });
+
+    let notice = SERVER_NOTICES.iter().any(|notice| line.starts_with(notice));
+    let speech = SPEECH_MARKERS.iter().any(|marker| line.contains(marker));
+    impersonates || notice || (speech && !own)
+}
+
+/// Marks the lines of a message from an object that are spoofs, by starting
+/// them with the object that sent them.
+pub fn guard(state: &State, source: usize, message: &str) -> String {
+    let lines = message.split('\n').map(|line| {
+        if is_spoof(state, source, line) {
+            format!("(from {}) {line}", state.display_name(source))
+        } else {
+            line.to_string()
+        }
+    });
+
+    lines.collect::<Vec<_>>().join("\n")
+}
@@ -0,0 +1,495 @@
+//! Logging in over SSH.
+//!
+//! Players log in as themselves by name, with either their password or one
+//! of the public keys that they added with `@sshkey`. Unlike telnet, there
+//! are no guests, so a connection must log in before it is let in.
+//!
+//! Terminals that ask for a pty send each key as it is pressed, so their
+//! lines are edited and echoed here before they are run as commands.
+
+use std::{
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use russh::{
+    keys::{Algorithm, PrivateKey, PublicKey},
+    server::{Auth, Config, Handle, Handler, Msg, Server, Session},
+    Channel, ChannelId, ChannelMsg, MethodKind, MethodSet,
+};
+use tokio::{
+    io::{AsyncWriteExt, DuplexStream},
+    net::TcpListener,
+};
+
+use crate::{telnet::WindowSize, State, User, DEFAULT_MAX_LINE_LENGTH};
+
+/// The environment variable naming the address to listen for SSH on, like
+/// `0.0.0.0:2222`. SSH is disabled if it isn't set.
+pub const SSH_BIND_VAR: &str = "MARCIEMOO_SSH_BIND";
+
+/// How long a failed login waits before it is answered, to slow down
+/// guessing passwords.
+const AUTH_REJECTION_TIME: Duration = Duration::from_secs(1);
+
+/// How long a connection may sit without sending anything.
+const INACTIVITY_TIMEOUT: Duration = Duration::from_secs(60 * 60);
+
+/// Accepts SSH connections until the server shuts down.
+pub async fn listen(state: Arc<State>, bind: String) {
+    let listener = match TcpListener::bind(&bind).await {
+        Ok(listener) => listener,
+        Err(err) => {
+            eprintln!("could not listen for SSH on {bind}: {err}");
+            return;
+        }
+    };
+
+    let key = match host_key(&state) {
+        Ok(key) => key,
+        Err(err) => {
+            eprintln!("could not load the SSH host key: {err}");
+            return;
+        }
+    };
+
+    let methods = [MethodKind::PublicKey, MethodKind::Password];
+    let config = Config {
+        methods: MethodSet::from(&methods[..]),
+        auth_rejection_time: AUTH_REJECTION_TIME,
+        auth_rejection_time_initial: Some(Duration::ZERO),
+        inactivity_timeout: Some(INACTIVITY_TIMEOUT),
+        keys: vec![key],
+        ..Default::default()
+    };
+
+    eprintln!("Listening for SSH on {bind}");
+    let shutdown = state.shutdown_token();
+    let mut server = SshServer { state };
+
+    tokio::select! {
+        result = server.run_on_socket(Arc::new(config), &listener) => {
+            if let Err(err) = result {
+                eprintln!("SSH listener failed: {err}");
+            }
+        }
+        _ = shutdown.cancelled() => {}
+    }
+}
+
+/// Gets the server's host key, generating it the first time that SSH is
+/// enabled so that clients see the same key every time after.
+fn host_key(state: &State) -> Result<PrivateKey, russh::keys::ssh_key::Error> {
+    if let Some(key) = state.ssh_host_key() {
+        return PrivateKey::from_openssh(key);
+    }
+
+    let key = PrivateKey::random(&mut rand::rngs::OsRng, Algorithm::Ed25519)?;
+    let encoded = key.to_openssh(Default::default())?;
+    state.set_ssh_host_key(&encoded);
+    Ok(key)
+}
+
+/// Tests if text is a public key in the OpenSSH format, like a line of an
+/// `authorized_keys` file.
+pub fn is_public_key(key: &str) -> bool {
+    PublicKey::from_openssh(key).is_ok()
+}
+
+/// Tests if a public key is one of a player's.
+fn has_key(state: &State, player: usize, key: &PublicKey) -> bool {
+    state
+        .ssh_keys(player)
+        .iter()
+        .filter_map(|added| PublicKey::from_openssh(added).ok())
+        .any(|added| added.key_data() == key.key_data())
+}
+
+struct SshServer {
+    state: Arc<State>,
+}
+
+impl Server for SshServer {
+    type Handler = Connection;
+
+    fn new_client(&mut self, addr: Option<SocketAddr>) -> Connection {
+        let addr = addr.map_or_else(|| "unknown".to_string(), |addr| addr.to_string());
+        eprintln!("SSH connection from {addr}");
+
+        Connection {
+            state: self.state.clone(),
+            addr,
+            player: None,
+            channel: None,
+            pty: false,
+            size: None,
+            window: None,
+        }
+    }
+}
+
+/// One SSH connection, which may play once it has logged in and opened a
+/// shell.
+struct Connection {
+    state: Arc<State>,
+    addr: String,
+
+    /// The player that this connection logged in as.
+    player: Option<usize>,
+
+    /// The session channel, until a shell is started on it.
+    channel: Option<Channel<Msg>>,
+
+    /// Whether the client asked for a pty, so its lines must be edited here.
+    pty: bool,
+
+    /// The size of the pty, until a shell is started.
+    size: Option<WindowSize>,
+
+    /// The window size of the user playing on this connection, once there is
+    /// one.
+    window: Option<Arc<Mutex<Option<WindowSize>>>>,
+}
+
+impl Connection {
+    fn set_size(&mut self, width: u32, height: u32) {
+        let size = WindowSize {
+            width: width.try_into().unwrap_or(u16::MAX),
+            height: height.try_into().unwrap_or(u16::MAX),
+        };
+
+        match &self.window {
+            Some(window) => *window.lock().unwrap() = Some(size),
+            None => self.size = Some(size),
+        }
+    }
+
+    fn start_shell(&mut self, channel: Channel<Msg>, player: usize, handle: Handle) {
+        let id = channel.id();
+        let (reader, writer) = tokio::io::duplex(DEFAULT_MAX_LINE_LENGTH);
+        let (output, mut from_user) = tokio::io::duplex(DEFAULT_MAX_LINE_LENGTH);
+        let user = User::new(self.state.clone(), output, self.addr.clone(), player);
+
+        // the channel is closed once everything that the user was sent has
+        // been written to it
+        let mut to_client = channel.make_writer();
+        tokio::spawn(async move {
+            let _ = tokio::io::copy(&mut from_user, &mut to_client).await;
+            let _ = handle.exit_status_request(id, 0).await;
+            let _ = handle.close(id).await;
+        });
+
+        let window = user.window_handle();
+        *window.lock().unwrap() = self.size;
+        self.window = Some(window);
+
+        let max_line_length = self.state.max_line_length();
+        tokio::spawn(read_input(channel, writer, self.pty, max_line_length));
+
+        let addr = self.addr.clone();
+        tokio::spawn(async move {
+            user.run_ssh(reader).await;
+            eprintln!("{addr} disconnected");
+        });
+    }
+}
+
+impl Handler for Connection {
+    type Error = russh::Error;
+
+    async fn auth_password(&mut self, name: &str, password: &str) -> Result<Auth, Self::Error> {
+        let Some(player) = self.state.find_player(name) else {
+            return Ok(Auth::reject());
+        };
+
+        // hashing is slow on purpose, so keep it off of the other connections
+        let state = self.state.clone();
+        let password = password.to_string();
+        let addr = self.addr.clone();
+        let matches =
+            tokio::task::spawn_blocking(move || state.check_login(player, &password, &addr))
+                .await
+                .unwrap_or(false);
+
+        if !matches {
+            return Ok(Auth::reject());
+        }
+
+        self.player = Some(player);
+        Ok(Auth::Accept)
+    }
+
+    async fn auth_publickey_offered(
+        &mut self,
+        name: &str,
+        key: &PublicKey,
+    ) -> Result<Auth, Self::Error> {
+        match self.state.find_player(name) {
+            Some(player) if has_key(&self.state, player, key) => Ok(Auth::Accept),
+            _ => Ok(Auth::reject()),
+        }
+    }
+
+    async fn auth_publickey(&mut self, name: &str, key: &PublicKey) -> Result<Auth, Self::Error> {
+        // the client has proven that it holds the key by now
+        match self.state.find_player(name) {
+            Some(player) if has_key(&self.state, player, key) => {
+                self.player = Some(player);
+                Ok(Auth::Accept)
+            }
+            _ => Ok(Auth::reject()),
+        }
+    }
+
+    async fn channel_open_session(
+        &mut self,
+        channel: Channel<Msg>,
+        _session: &mut Session,
+    ) -> Result<bool, Self::Error> {
+        // each connection plays as one player, so it only gets one session
+        if self.channel.is_some() || self.window.is_some() {
+            return Ok(false);
+        }
+
+        self.channel = Some(channel);
+        Ok(true)
+    }
+
+    async fn pty_request(
+        &mut self,
+        channel: ChannelId,
+        _term: &str,
+        width: u32,
+        height: u32,
+        _pix_width: u32,
+        _pix_height: u32,
+        _modes: &[(russh::Pty, u32)],
+        session: &mut Session,
+    ) -> Result<(), Self::Error> {
+        self.pty = true;
+        self.set_size(width, height);
+        session.channel_success(channel)
+    }
+
+    async fn window_change_request(
+        &mut self,
+        _channel: ChannelId,
+        width: u32,
+        height: u32,
+        _pix_width: u32,
+        _pix_height: u32,
+        _session: &mut Session,
+    ) -> Result<(), Self::Error> {
+        self.set_size(width, height);
+        Ok(())
+    }
+
+    async fn shell_request(
+        &mut self,
+        id: ChannelId,
+        session: &mut Session,
+    ) -> Result<(), Self::Error> {
+        let (Some(player), Some(channel)) = (self.player, self.channel.take()) else {
+            return session.channel_failure(id);
+        };
+
+        session.channel_success(id)?;
+
+        if !self.state.claim_player(player) {
+            let _ = channel
+                .data(&b"That player is already connected.\r\n"[..])
+                .await;
+            return session.close(id);
+        }
+
+        self.start_shell(channel, player, session.handle());
+        Ok(())
+    }
+}
+
+/// Passes what a client types on to its user, until the channel closes.
+async fn read_input(
+    mut channel: Channel<Msg>,
+    mut writer: DuplexStream,
+    pty: bool,
+    max_line_length: usize,
+) {
+    let mut editor = LineEditor::new(max_line_length);
+
+    while let Some(message) = channel.wait().await {
+        let data = match message {
+            ChannelMsg::Data { data } => data,
+            ChannelMsg::Eof | ChannelMsg::Close => break,
+            _ => continue,
+        };
+
+        if !pty {
+            if writer.write_all(&data).await.is_err() {
+                break;
+            }
+
+            continue;
+        }
+
+        let mut echo = Vec::new();
+        let mut lines = Vec::new();
+        let mut ended = false;
+
+        for byte in data.iter() {
+            match editor.feed(*byte, &mut echo) {
+                Edit::Pending => {}
+                Edit::Line(line) => lines.push(line),
+                Edit::End => {
+                    ended = true;
+                    break;
+                }
+            }
+        }
+
+        if !echo.is_empty() && channel.data(&echo[..]).await.is_err() {
+            break;
+        }
+
+        for line in lines {
+            if writer.write_all(&line).await.is_err() {
+                return;
+            }
+        }
+
+        if ended {
+            break;
+        }
+    }
+}
+
+/// What happened after a key was pressed.
+enum Edit {
+    Pending,
+
+    /// A line was entered, ending in a newline.
+    Line(Vec<u8>),
+
+    /// The user ended their input with Ctrl-D.
+    End,
+}
+
+/// Whether the editor is in the middle of an escape sequence, like the one
+/// that an arrow key sends.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Escape {
+    None,
+    Started,
+    Csi,
+}
+
+/// Edits the line being typed into a terminal, which sends each key as it
+/// is pressed and leaves echoing them to the server.
+struct LineEditor {
+    line: Vec<u8>,
+    escape: Escape,
+    max_len: usize,
+
+    /// Whether the character being typed didn't fit in the line.
+    dropping: bool,
+
+    /// Whether the last byte ended a line with a carriage return, since
+    /// some terminals follow it with a line feed.
+    after_cr: bool,
+}
+
+impl LineEditor {
+    fn new(max_len: usize) -> Self {
+        Self {
+            line: Vec::new(),
+            escape: Escape::None,
+            max_len,
+            dropping: false,
+            after_cr: false,
+        }
+    }
+
+    /// Handles the next byte typed, adding what the terminal should show to
+    /// `echo`.
+    fn feed(&mut self, byte: u8, echo: &mut Vec<u8>) -> Edit {
+        let after_cr = std::mem::replace(&mut self.after_cr, false);
+
+        // cursor movement isn't supported, so escape sequences are skipped
+        match (self.escape, byte) {
+            (Escape::None, 0x1b) => {
+                self.escape = Escape::Started;
+                return Edit::Pending;
+            }
+            (Escape::Started, b'[') => {
+                self.escape = Escape::Csi;
+                return Edit::Pending;
+            }
+            (Escape::Csi, 0x40..=0x7e) | (Escape::Started, _) => {
+                self.escape = Escape::None;
+                return Edit::Pending;
+            }
+            (Escape::Csi, _) => return Edit::Pending,
+            (Escape::None, _) => {}
+        }
+
+        match byte {
+            b'\n' if after_cr => Edit::Pending,
+            b'\r' | b'\n' => {
+                self.after_cr = byte == b'\r';
+                echo.extend(b"\r\n");
+                let mut line = std::mem::take(&mut self.line);
+                line.push(b'\n');
+                Edit::Line(line)
+            }
+            // backspace and delete remove the last character, along with all
+            // of the bytes that encode it
+            0x08 | 0x7f => {
+                while let Some(removed) = self.line.pop() {
+                    if removed & 0xc0 != 0x80 {
+                        echo.extend(b"\x08 \x08");
+                        break;
+                    }
+                }
+
+                Edit::Pending
+            }
+            // Ctrl-C discards the line
+            0x03 => {
+                self.line.clear();
+                echo.extend(b"^C\r\n");
+                Edit::Pending
+            }
+            // Ctrl-D ends the session, but only on an empty line
+            0x04 if self.line.is_empty() => Edit::End,
+            // Ctrl-U erases the line
+            0x15 => {
+                while let Some(removed) = self.line.pop() {
+                    if removed & 0xc0 != 0x80 {
+                        echo.extend(b"\x08 \x08");
+                    }
+                }
+
+                Edit::Pending
+            }
+            0x00..=0x1f => Edit::Pending,
+            byte => {
+                // the bytes that continue a character go wherever its first
+                // byte did, so that characters that don't fit aren't split
+                if byte & 0xc0 != 0x80 {
+                    self.dropping = self.line.len() >= self.max_len;
+
+                    if self.dropping {
+                        echo.push(0x07);
+                    }
+                }
+
+                if !self.dropping {
+                    self.line.push(byte);
+                    echo.push(byte);
+                }
+
+                Edit::Pending
+            }
+        }
+    }
+}
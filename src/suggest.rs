@@ -0,0 +1,56 @@
+//! Suggestions for mistyped names.
+
+/// The most edits that a suggestion may be from what was typed.
+pub const MAX_DISTANCE: usize = 2;
+
+/// Counts the single-character insertions, deletions, and substitutions
+/// needed to turn one string into another.
+pub fn distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+    let mut cur = vec![0; b.len() + 1];
+
+    for (i, a) in a.chars().enumerate() {
+        cur[0] = i + 1;
+
+        for (j, b) in b.iter().enumerate() {
+            let substitution = prev[j] + usize::from(a != *b);
+            cur[j + 1] = substitution.min(prev[j + 1] + 1).min(cur[j] + 1);
+        }
+
+        std::mem::swap(&mut prev, &mut cur);
+    }
+
+    prev[b.len()]
+}
+
+/// Finds the candidates closest to a mistyped word, sorted by name.
+///
+/// Only candidates within [MAX_DISTANCE] edits, and fewer edits than the
+/// word is long, are suggested.
+pub fn suggest<'a>(word: &str, candidates: impl IntoIterator<Item = &'a str>) -> Vec<String> {
+    let word = word.to_lowercase();
+    let max = MAX_DISTANCE.min(word.chars().count().saturating_sub(1));
+
+    let mut best = usize::MAX;
+    let mut found: Vec<String> = Vec::new();
+
+    for candidate in candidates {
+        let distance = distance(&word, &candidate.to_lowercase());
+        if distance > max || distance > best {
+            continue;
+        }
+
+        if distance < best {
+            best = distance;
+            found.clear();
+        }
+
+        if !found.iter().any(|name| name == candidate) {
+            found.push(candidate.to_string());
+        }
+    }
+
+    found.sort();
+    found
+}
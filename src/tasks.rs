@@ -0,0 +1,388 @@
+//! The task scheduler, which runs verbs in the background without anyone
+//! connected to drive them.
+//!
+//! Objects whose `npc` field is true have their `heartbeat` verb run every
+//! `heartbeat_interval` seconds, as set on the system object, so that
+//! monsters can wander and shopkeepers can hawk their wares on their own.
+//!
+//! Objects can also be enrolled in turns by setting their `turn_group` field
+//! to the object that groups them, like an arena or a game board. Every
+//! `turn_interval` seconds, as set on the group or else on the system object,
+//! the next of the group's objects in order of their `initiative` has its
+//! `take_turn` verb run, with the group bound as `group`. Once every object
+//! has had its turn, the round starts over.
+//!
+//! Every `server_tick_interval` seconds, the system object's `server_tick`
+//! verb runs too, giving the world a heartbeat of its own for respawning,
+//! decay, and saving from scripts.
+//!
+//! The scheduler also keeps the [world clock](crate::world_clock) running.
+//!
+//! Verbs that `suspend` become tasks, which are listed by `@tasks` until
+//! they finish or are killed with `@kill`. Each player may have only
+//! `max_tasks` of them at once, as set on the system object. Every task
+//! holds a thread while it waits, so there may be no more than
+//! [MAX_TOTAL_TASKS] on the whole server, however many players there are.
+//!
+//! Each background verb runs on a thread of its own, so that one that runs
+//! for a long time holds up only itself. Until it finishes, that object's
+//! verb isn't run again.
+//!
+//! Background verbs run with the permissions of the object's owner. What
+//! they emit and announce reaches players as usual, but nobody is there to
+//! read what they print, so it is dropped.
+
+use std::{
+    cmp::Reverse,
+    collections::{BTreeMap, HashMap, HashSet},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+use tokio_util::sync::CancellationToken;
+
+use crate::{event::Event, script::Host, world_clock, State, Value, SYSTEM_OBJECT};
+
+/// How often heartbeats run if the system object doesn't say.
+pub const DEFAULT_HEARTBEAT_INTERVAL: u64 = 30;
+
+/// How often the system object's `server_tick` runs if it doesn't say.
+pub const DEFAULT_SERVER_TICK_INTERVAL: u64 = 60;
+
+/// How often turns come around if neither the group nor the system object
+/// say.
+pub const DEFAULT_TURN_INTERVAL: u64 = 6;
+
+/// The field that enrolls an object in a group's turns.
+pub const TURN_GROUP_FIELD: &str = "turn_group";
+
+/// How many tasks each player may have if the system object doesn't say.
+pub const DEFAULT_MAX_TASKS: usize = 16;
+
+/// How many tasks there may be across every player, which leaves plenty of
+/// the runtime's blocking threads free for verbs that don't suspend.
+pub const MAX_TOTAL_TASKS: usize = 256;
+
+/// How often the scheduler checks whether anything is due.
+const TICK: Duration = Duration::from_secs(1);
+
+/// Gets a positive number of seconds from a field.
+fn seconds(state: &State, id: usize, field: &str) -> Option<u64> {
+    state
+        .get(id, field)
+        .and_then(|interval| interval.as_integer())
+        .and_then(|interval| interval.try_into().ok())
+        .filter(|interval| *interval > 0)
+}
+
+/// Gets how many seconds pass between heartbeats.
+fn heartbeat_interval(state: &State) -> u64 {
+    seconds(state, SYSTEM_OBJECT, "heartbeat_interval").unwrap_or(DEFAULT_HEARTBEAT_INTERVAL)
+}
+
+/// Gets how many seconds pass between server ticks.
+fn server_tick_interval(state: &State) -> u64 {
+    seconds(state, SYSTEM_OBJECT, "server_tick_interval").unwrap_or(DEFAULT_SERVER_TICK_INTERVAL)
+}
+
+/// Gets how many seconds pass between the turns in a group.
+fn turn_interval(state: &State, group: usize) -> u64 {
+    seconds(state, group, "turn_interval")
+        .or_else(|| seconds(state, SYSTEM_OBJECT, "turn_interval"))
+        .unwrap_or(DEFAULT_TURN_INTERVAL)
+}
+
+/// Lists the groups of objects enrolled in turns, in order of initiative.
+pub fn turn_groups(state: &State) -> BTreeMap<usize, Vec<usize>> {
+    let mut groups: BTreeMap<usize, Vec<usize>> = BTreeMap::new();
+    for id in state.find(TURN_GROUP_FIELD, "*") {
+        let group = state
+            .get(id, TURN_GROUP_FIELD)
+            .and_then(|group| group.as_id());
+        if let Some(group) = group.filter(|group| state.exists(*group)) {
+            groups.entry(group).or_default().push(id);
+        }
+    }
+
+    for enrolled in groups.values_mut() {
+        let initiative = |id: usize| state.get(id, "initiative").and_then(|val| val.as_integer());
+        enrolled.sort_by_key(|id| (Reverse(initiative(*id).unwrap_or(0)), *id));
+    }
+
+    groups
+}
+
+/// Keeps track of whose turn it is in each group.
+#[derive(Default)]
+struct Turns {
+    /// How many seconds have passed since each group's last turn.
+    elapsed: HashMap<usize, u64>,
+
+    /// Which object had each group's last turn.
+    last: HashMap<usize, usize>,
+}
+
+impl Turns {
+    /// Lets a second pass, returning the groups whose turns have come round
+    /// along with whose turn it is.
+    fn tick(&mut self, state: &State) -> Vec<(usize, usize)> {
+        let groups = turn_groups(state);
+        self.elapsed.retain(|group, _| groups.contains_key(group));
+        self.last.retain(|group, _| groups.contains_key(group));
+
+        let mut due = Vec::new();
+        for (group, enrolled) in groups {
+            let elapsed = self.elapsed.entry(group).or_default();
+            *elapsed += 1;
+            if *elapsed < turn_interval(state, group) {
+                continue;
+            }
+
+            *elapsed = 0;
+
+            // objects that leave mid-round start it over
+            let last = self.last.get(&group);
+            let next = match last.and_then(|last| enrolled.iter().position(|id| id == last)) {
+                Some(index) => (index + 1) % enrolled.len(),
+                None => 0,
+            };
+
+            self.last.insert(group, enrolled[next]);
+            due.push((group, enrolled[next]));
+        }
+
+        due
+    }
+}
+
+/// Gets how many tasks each player may have at once.
+pub fn max_tasks(state: &State) -> usize {
+    let max = seconds(state, SYSTEM_OBJECT, "max_tasks");
+    max.map_or(DEFAULT_MAX_TASKS, |max| max as usize)
+}
+
+/// What a task is waiting for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Waiting {
+    /// The UNIX time that the task wakes up at.
+    Until(u64),
+
+    /// A line from the player, for `read`.
+    Input,
+}
+
+/// A verb carrying on in the background after suspending.
+#[derive(Clone, Debug)]
+pub struct Task {
+    /// The player whose permissions the verb runs with.
+    pub player: usize,
+
+    /// The object that the verb is running on.
+    pub object: usize,
+
+    pub verb: String,
+    pub waiting: Waiting,
+
+    /// Cancelled when the task is killed.
+    pub killed: CancellationToken,
+}
+
+/// The tasks that are running in the background, by ID.
+#[derive(Default)]
+pub struct Tasks {
+    next: AtomicU64,
+    tasks: Mutex<BTreeMap<u64, Task>>,
+}
+
+impl Tasks {
+    /// Adds a task, returning its ID, unless its player already has `max`
+    /// tasks or there are already [MAX_TOTAL_TASKS].
+    pub fn add(&self, task: Task, max: Option<usize>) -> Option<u64> {
+        let mut tasks = self.tasks.lock().unwrap();
+        if tasks.len() >= MAX_TOTAL_TASKS {
+            return None;
+        }
+
+        let count = tasks.values().filter(|known| known.player == task.player);
+        if max.is_some_and(|max| count.count() >= max) {
+            return None;
+        }
+
+        let id = self.next.fetch_add(1, Ordering::Relaxed) + 1;
+        tasks.insert(id, task);
+        Some(id)
+    }
+
+    /// Gets a task by ID, if it hasn't finished or been killed.
+    pub fn get(&self, id: u64) -> Option<Task> {
+        self.tasks.lock().unwrap().get(&id).cloned()
+    }
+
+    /// Changes what a task is waiting for.
+    pub fn wait(&self, id: u64, waiting: Waiting) {
+        if let Some(task) = self.tasks.lock().unwrap().get_mut(&id) {
+            task.waiting = waiting;
+        }
+    }
+
+    /// Removes a task that has finished.
+    pub fn remove(&self, id: u64) {
+        self.tasks.lock().unwrap().remove(&id);
+    }
+
+    /// Kills a task, interrupting whatever it is waiting for, and returns
+    /// false if there was no such task.
+    pub fn kill(&self, id: u64) -> bool {
+        let Some(task) = self.tasks.lock().unwrap().remove(&id) else {
+            return false;
+        };
+
+        task.killed.cancel();
+        true
+    }
+
+    /// Lists every task, in the order they started.
+    pub fn list(&self) -> Vec<(u64, Task)> {
+        let tasks = self.tasks.lock().unwrap();
+        tasks.iter().map(|(id, task)| (*id, task.clone())).collect()
+    }
+}
+
+/// Lists the objects that have heartbeats.
+pub fn npcs(state: &State) -> Vec<usize> {
+    let mut npcs = state.find("npc", "true");
+    npcs.retain(|id| matches!(state.get(*id, "npc"), Some(Value::Bool(true))));
+    npcs
+}
+
+/// Runs a verb on an object in the background, with additional objects
+/// bound by name in its scope, returning false if the object has no such
+/// verb.
+pub fn run_verb(state: &Arc<State>, object: usize, verb: &str, bindings: &[(&str, usize)]) -> bool {
+    let mut host = Host::new(state.clone(), object, state.owner(object));
+    for (name, id) in bindings {
+        host.bind(name, *id);
+    }
+
+    // verbs that suspend are left to carry on by themselves
+    let runtime = tokio::runtime::Handle::current();
+    let Some(output) = runtime.block_on(host.spawn(verb)) else {
+        return false;
+    };
+
+    for announcement in &output.announcements {
+        state.announce_from(object, announcement);
+    }
+
+    let room = state.location(object).unwrap_or(object);
+    for message in &output.emits {
+        state.emit_from(room, object, message);
+    }
+
+    true
+}
+
+/// The background verbs that are still running, by object and verb.
+type Running = Arc<Mutex<HashSet<(usize, &'static str)>>>;
+
+/// Starts a verb on an object in the background, unless it is still running
+/// from the last time.
+fn start(
+    state: &Arc<State>,
+    running: &Running,
+    object: usize,
+    verb: &'static str,
+    bindings: Vec<(&'static str, usize)>,
+) {
+    if !running.lock().unwrap().insert((object, verb)) {
+        return;
+    }
+
+    let state = state.clone();
+    let running = running.clone();
+    tokio::task::spawn_blocking(move || {
+        run_verb(&state, object, verb, &bindings);
+        running.lock().unwrap().remove(&(object, verb));
+    });
+}
+
+/// Runs the scheduler until the server shuts down.
+pub async fn run(state: Arc<State>) {
+    let shutdown = state.shutdown_token();
+    let mut interval = tokio::time::interval(TICK);
+    let mut since_heartbeat = 0;
+    let mut since_server_tick = 0;
+    let mut since_world_tick = 0;
+    let mut turns = Turns::default();
+    let running = Running::default();
+    let mut daytime = world_clock::is_daytime(&state);
+
+    loop {
+        tokio::select! {
+            _ = interval.tick() => {}
+            _ = shutdown.cancelled() => break,
+        }
+
+        // the sun keeps moving even while the world is being worked on
+        if world_clock::is_daytime(&state) != daytime {
+            daytime = !daytime;
+            let day = world_clock::day_of(world_clock::world_time(&state));
+            state.publish(match daytime {
+                true => Event::Dawn { day },
+                false => Event::Dusk { day },
+            });
+        }
+
+        // but nothing runs
+        if state.in_maintenance() {
+            continue;
+        }
+
+        since_heartbeat += 1;
+        let heartbeat = since_heartbeat >= heartbeat_interval(&state);
+        if heartbeat {
+            since_heartbeat = 0;
+        }
+
+        since_server_tick += 1;
+        let server_tick = since_server_tick >= server_tick_interval(&state);
+        if server_tick {
+            since_server_tick = 0;
+        }
+
+        since_world_tick += 1;
+        let world_tick = since_world_tick >= world_clock::world_tick_interval(&state);
+        if world_tick {
+            since_world_tick = 0;
+        }
+
+        if server_tick {
+            start(&state, &running, SYSTEM_OBJECT, "server_tick", vec![]);
+        }
+
+        if heartbeat {
+            for npc in npcs(&state) {
+                start(&state, &running, npc, "heartbeat", vec![]);
+            }
+        }
+
+        if world_tick {
+            for object in world_clock::ticking(&state) {
+                start(&state, &running, object, "world_tick", vec![]);
+            }
+        }
+
+        for (group, object) in turns.tick(&state) {
+            start(
+                &state,
+                &running,
+                object,
+                "take_turn",
+                vec![("group", group)],
+            );
+        }
+    }
+}
@@ -0,0 +1,275 @@
+//! The telnet protocol.
+//!
+//! Clients interleave telnet commands with the text that they send. A
+//! [Reader] separates the two, passing the text on to be read as lines and
+//! the commands to the connection that negotiates options with them.
+
+use std::{
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use tokio::{
+    io::{AsyncRead, ReadBuf},
+    sync::mpsc,
+};
+
+/// Interpret as command, which begins every telnet command.
+pub const IAC: u8 = 255;
+pub const DONT: u8 = 254;
+pub const DO: u8 = 253;
+pub const WONT: u8 = 252;
+pub const WILL: u8 = 251;
+
+/// Begins a subnegotiation.
+pub const SB: u8 = 250;
+
+/// Ends a subnegotiation.
+pub const SE: u8 = 240;
+
+/// Go ahead, which marks the end of a prompt for clients without EOR.
+pub const GA: u8 = 249;
+
+/// End of record, which marks the end of a prompt once negotiated.
+pub const EOR: u8 = 239;
+
+/// The Echo option, which the server takes on to stop clients echoing what
+/// is typed, like passwords.
+pub const ECHO: u8 = 1;
+
+/// The End of Record option.
+pub const TELOPT_EOR: u8 = 25;
+
+/// The Negotiate About Window Size option.
+pub const NAWS: u8 = 31;
+
+/// The Generic MUD Communication Protocol option.
+pub const GMCP: u8 = 201;
+
+/// The MUD Client Compression Protocol option, version 2.
+pub const MCCP2: u8 = 86;
+
+/// The MUD eXtension Protocol option.
+pub const MXP: u8 = 91;
+
+/// The MUD Server Status Protocol option.
+pub const MSSP: u8 = 70;
+pub const MSSP_VAR: u8 = 1;
+pub const MSSP_VAL: u8 = 2;
+
+/// A command that a client sent.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Command {
+    Will(u8),
+    Wont(u8),
+    Do(u8),
+    Dont(u8),
+
+    /// The option and the data of a subnegotiation, with escaped `IAC`s
+    /// already unescaped.
+    Subnegotiation(u8, Vec<u8>),
+
+    /// Any other command, like go ahead or no operation.
+    Other(u8),
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+enum ParseState {
+    #[default]
+    Data,
+    Iac,
+    Option(u8),
+    Subnegotiation,
+    SubnegotiationIac,
+}
+
+/// Splits a stream of bytes into text and commands.
+#[derive(Debug, Default)]
+pub struct Parser {
+    state: ParseState,
+    sub: Vec<u8>,
+}
+
+/// The longest subnegotiation that is kept, past which the rest is dropped.
+pub const MAX_SUBNEGOTIATION_LEN: usize = 8192;
+
+impl Parser {
+    /// Parses the next byte, returning it if it is text or the command that
+    /// it completes.
+    pub fn feed(&mut self, byte: u8) -> Option<Result<u8, Command>> {
+        match (self.state, byte) {
+            (ParseState::Data, IAC) => self.state = ParseState::Iac,
+            (ParseState::Data, byte) => return Some(Ok(byte)),
+            (ParseState::Iac, IAC) => {
+                self.state = ParseState::Data;
+                return Some(Ok(IAC));
+            }
+            (ParseState::Iac, WILL | WONT | DO | DONT) => self.state = ParseState::Option(byte),
+            (ParseState::Iac, SB) => {
+                self.sub.clear();
+                self.state = ParseState::Subnegotiation;
+            }
+            (ParseState::Iac, command) => {
+                self.state = ParseState::Data;
+                return Some(Err(Command::Other(command)));
+            }
+            (ParseState::Option(verb), option) => {
+                self.state = ParseState::Data;
+                let command = match verb {
+                    WILL => Command::Will(option),
+                    WONT => Command::Wont(option),
+                    DO => Command::Do(option),
+                    _ => Command::Dont(option),
+                };
+
+                return Some(Err(command));
+            }
+            (ParseState::Subnegotiation, IAC) => self.state = ParseState::SubnegotiationIac,
+            (ParseState::Subnegotiation, byte) => self.push_sub(byte),
+            (ParseState::SubnegotiationIac, SE) => {
+                self.state = ParseState::Data;
+
+                if self.sub.is_empty() {
+                    return None;
+                }
+
+                let option = self.sub.remove(0);
+                let data = std::mem::take(&mut self.sub);
+                return Some(Err(Command::Subnegotiation(option, data)));
+            }
+            (ParseState::SubnegotiationIac, byte) => {
+                self.state = ParseState::Subnegotiation;
+                self.push_sub(byte);
+            }
+        }
+
+        None
+    }
+
+    fn push_sub(&mut self, byte: u8) {
+        if self.sub.len() < MAX_SUBNEGOTIATION_LEN {
+            self.sub.push(byte);
+        }
+    }
+}
+
+/// Reads the text that a client sends, passing the commands mixed into it
+/// to a channel.
+pub struct Reader<R> {
+    inner: R,
+    parser: Parser,
+    commands: mpsc::UnboundedSender<Command>,
+}
+
+impl<R> Reader<R> {
+    /// Wraps a stream, returning the reader and the receiver of commands.
+    pub fn new(inner: R) -> (Self, mpsc::UnboundedReceiver<Command>) {
+        let (commands, rx) = mpsc::unbounded_channel();
+
+        let reader = Self {
+            inner,
+            parser: Parser::default(),
+            commands,
+        };
+
+        (reader, rx)
+    }
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for Reader<R> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        let this = self.get_mut();
+        let mut raw = vec![0; buf.remaining()];
+
+        // a read made only of commands has no text to return, but returning
+        // nothing would look like the end of the stream, so read again
+        loop {
+            let mut raw_buf = ReadBuf::new(&mut raw);
+            match Pin::new(&mut this.inner).poll_read(cx, &mut raw_buf) {
+                Poll::Ready(Ok(())) => {}
+                other => return other,
+            }
+
+            let read = raw_buf.filled();
+            if read.is_empty() {
+                return Poll::Ready(Ok(()));
+            }
+
+            let before = buf.filled().len();
+            for byte in read {
+                match this.parser.feed(*byte) {
+                    Some(Ok(byte)) => buf.put_slice(&[byte]),
+                    Some(Err(command)) => {
+                        let _ = this.commands.send(command);
+                    }
+                    None => {}
+                }
+            }
+
+            if buf.filled().len() > before {
+                return Poll::Ready(Ok(()));
+            }
+        }
+    }
+}
+
+/// The size of a client's window, in characters.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct WindowSize {
+    pub width: u16,
+    pub height: u16,
+}
+
+impl WindowSize {
+    /// Parses the data of a NAWS subnegotiation. Clients report a dimension
+    /// that they don't know as zero.
+    pub fn parse_naws(data: &[u8]) -> Option<Self> {
+        let [w1, w0, h1, h0] = data else {
+            return None;
+        };
+
+        Some(Self {
+            width: u16::from_be_bytes([*w1, *w0]),
+            height: u16::from_be_bytes([*h1, *h0]),
+        })
+    }
+}
+
+/// Encodes a negotiation like `IAC WILL <option>`.
+pub fn negotiate(verb: u8, option: u8) -> Vec<u8> {
+    vec![IAC, verb, option]
+}
+
+/// Encodes a subnegotiation, escaping any `IAC`s in its data.
+pub fn subnegotiate(option: u8, data: &[u8]) -> Vec<u8> {
+    let mut bytes = vec![IAC, SB, option];
+
+    for byte in data {
+        if *byte == IAC {
+            bytes.push(IAC);
+        }
+
+        bytes.push(*byte);
+    }
+
+    bytes.extend([IAC, SE]);
+    bytes
+}
+
+/// Encodes the variables of an MSSP subnegotiation's data.
+pub fn mssp_data(vars: &[(&str, String)]) -> Vec<u8> {
+    let mut data = Vec::new();
+
+    for (name, value) in vars {
+        data.push(MSSP_VAR);
+        data.extend(name.as_bytes());
+        data.push(MSSP_VAL);
+        data.extend(value.as_bytes());
+    }
+
+    data
+}
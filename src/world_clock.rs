@@ -0,0 +1,95 @@
+//! The world clock, which keeps the in-game time of day.
+//!
+//! In-game time runs faster than real time, so that a whole in-game day
+//! passes every `day_length` seconds, as set on the system object. It is day
+//! from `dawn_hour` until `dusk_hour`, and the scheduler publishes
+//! [Event::Dawn](crate::event::Event::Dawn) and
+//! [Event::Dusk](crate::event::Event::Dusk) as day turns to night and back.
+//!
+//! Every `world_tick_interval` seconds, the scheduler also runs the
+//! `world_tick` verb of the system object and of every room whose `outdoors`
+//! field is true, so that weather can change and rooms can keep track of the
+//! time of day between looks.
+
+use crate::{clock, now, State, Value, SYSTEM_OBJECT};
+
+/// How many real seconds an in-game day lasts if the system object doesn't
+/// say.
+pub const DEFAULT_DAY_LENGTH: i64 = clock::HOUR;
+
+/// The in-game hour that day starts at if the system object doesn't say.
+pub const DEFAULT_DAWN_HOUR: i64 = 6;
+
+/// The in-game hour that night starts at if the system object doesn't say.
+pub const DEFAULT_DUSK_HOUR: i64 = 18;
+
+/// How often world ticks run if the system object doesn't say.
+pub const DEFAULT_WORLD_TICK_INTERVAL: u64 = 60;
+
+/// Gets a setting from the system object, if it is in a range.
+fn setting(state: &State, field: &str, range: std::ops::Range<i64>) -> Option<i64> {
+    let setting = state.get(SYSTEM_OBJECT, field)?.as_integer()?;
+    Some(setting).filter(|setting| range.contains(setting))
+}
+
+/// Gets how many real seconds an in-game day lasts.
+pub fn day_length(state: &State) -> i64 {
+    setting(state, "day_length", 1..i64::MAX).unwrap_or(DEFAULT_DAY_LENGTH)
+}
+
+/// Gets how many seconds pass between world ticks.
+pub fn world_tick_interval(state: &State) -> u64 {
+    let interval = setting(state, "world_tick_interval", 1..i64::MAX);
+    interval.map_or(DEFAULT_WORLD_TICK_INTERVAL, |interval| interval as u64)
+}
+
+/// Gets the in-game time, as in-game seconds since the UNIX epoch.
+pub fn world_time(state: &State) -> i64 {
+    let time = now() as i128 * clock::DAY as i128 / day_length(state) as i128;
+    time.try_into().unwrap_or(i64::MAX)
+}
+
+/// Gets the in-game day that an in-game time falls on.
+pub fn day_of(time: i64) -> i64 {
+    time.div_euclid(clock::DAY)
+}
+
+/// Gets the in-game hour of the day that an in-game time falls on.
+pub fn hour_of(time: i64) -> i64 {
+    time.rem_euclid(clock::DAY) / clock::HOUR
+}
+
+/// Tests if it is day in the world, rather than night.
+pub fn is_daytime(state: &State) -> bool {
+    let dawn = setting(state, "dawn_hour", 0..24).unwrap_or(DEFAULT_DAWN_HOUR);
+    let dusk = setting(state, "dusk_hour", 0..24).unwrap_or(DEFAULT_DUSK_HOUR);
+    let hour = hour_of(world_time(state));
+
+    // days may run past midnight if dusk comes before dawn
+    if dawn <= dusk {
+        (dawn..dusk).contains(&hour)
+    } else {
+        hour >= dawn || hour < dusk
+    }
+}
+
+/// Formats an in-game time, like `day 3, 14:05`.
+pub fn format_world_time(time: i64) -> String {
+    let secs = time.rem_euclid(clock::DAY);
+    format!(
+        "day {}, {:02}:{:02}",
+        day_of(time),
+        secs / clock::HOUR,
+        secs % clock::HOUR / clock::MINUTE
+    )
+}
+
+/// Lists the objects that have world ticks.
+pub fn ticking(state: &State) -> Vec<usize> {
+    let mut rooms = state.find("outdoors", "true");
+    rooms.retain(|id| {
+        *id != SYSTEM_OBJECT && matches!(state.get(*id, "outdoors"), Some(Value::Bool(true)))
+    });
+    rooms.insert(0, SYSTEM_OBJECT);
+    rooms
+}
@@ -0,0 +1,52 @@
+//! Wrapping long lines to fit the width of a client's window.
+
+/// Splits a line into lines of at most `width` characters, breaking between
+/// words where it can. Continued lines keep the line's indentation.
+///
+/// Lines with escape sequences in them are left whole, since their
+/// sequences take up no width on screen.
+pub fn wrap(line: &str, width: usize) -> Vec<String> {
+    if width == 0 || line.chars().count() <= width || line.contains('\x1b') {
+        return vec![line.to_string()];
+    }
+
+    let rest = line.trim_start_matches(' ');
+    let indent = &line[..line.len() - rest.len()];
+    let indent = if indent.len() < width / 2 { indent } else { "" };
+
+    let mut lines = Vec::new();
+    let mut current = indent.to_string();
+    let mut used = indent.len();
+    let mut fresh = true;
+
+    for word in rest.split(' ') {
+        let mut word = word;
+
+        if !fresh {
+            if used + 1 + word.chars().count() <= width {
+                current.push(' ');
+                used += 1;
+            } else {
+                lines.push(std::mem::replace(&mut current, indent.to_string()));
+                used = indent.len();
+            }
+        }
+
+        // words longer than a whole line are split wherever they must be
+        while used + word.chars().count() > width {
+            let fits = width - used;
+            let (split, _) = word.char_indices().nth(fits).unwrap();
+            current.push_str(&word[..split]);
+            lines.push(std::mem::replace(&mut current, indent.to_string()));
+            used = indent.len();
+            word = &word[split..];
+        }
+
+        current.push_str(word);
+        used += word.chars().count();
+        fresh = false;
+    }
+
+    lines.push(current);
+    lines
+}